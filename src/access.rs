@@ -0,0 +1,481 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Most denials kept in the receipt
+const MAX_DENIALS: usize = 20;
+
+/// Paths the command may read and write, from --allow-read and --allow-write
+#[derive(Debug, Clone, Default)]
+pub struct PathRules {
+    /// Directories and files the command may read (and execute); reads are
+    /// unrestricted if empty
+    pub read: Vec<PathBuf>,
+    /// Directories and files the command may write, and also read when reads
+    /// are restricted; writes are unrestricted if empty
+    pub write: Vec<PathBuf>,
+}
+
+/// Kernel mechanism enforcing the path rules
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mechanism {
+    /// A Landlock ruleset, enforcing the rules as given
+    Landlock,
+    /// A seccomp filter, used without Landlock: it blocks every filesystem
+    /// change and cannot restrict reads
+    Seccomp,
+}
+
+/// The filesystem access the command was restricted to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccessProfile {
+    /// How the restriction was enforced
+    pub mechanism: Mechanism,
+    /// Landlock ABI version of the kernel
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub landlock_abi: Option<u32>,
+    /// Paths the command could read (absent if reads were unrestricted)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read: Option<Vec<String>>,
+    /// Paths the command could write (absent if writes were unrestricted,
+    /// empty if it could write nowhere)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub write: Option<Vec<String>>,
+    /// Lines of the command's error output reporting a permission error,
+    /// the visible trace of accesses the kernel refused
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub denials: Vec<String>,
+}
+
+impl PathRules {
+    /// The profile enforcing these rules with the best mechanism the kernel
+    /// offers (Linux only)
+    pub fn profile(&self) -> Result<AccessProfile> {
+        let resolve = |paths: &[PathBuf]| -> Result<Option<Vec<String>>> {
+            if paths.is_empty() {
+                return Ok(None);
+            }
+            let mut resolved = Vec::new();
+            for path in paths {
+                let path = fs::canonicalize(path)
+                    .context(format!("Failed to resolve allowed path {}", path.display()))?;
+                let path = path.to_string_lossy().to_string();
+                if !resolved.contains(&path) {
+                    resolved.push(path);
+                }
+            }
+            Ok(Some(resolved))
+        };
+        let read = resolve(&self.read)?;
+        let write = resolve(&self.write)?;
+        if let Some(abi) = imp::landlock_abi() {
+            return Ok(AccessProfile {
+                mechanism: Mechanism::Landlock,
+                landlock_abi: Some(abi),
+                read,
+                write,
+                denials: Vec::new(),
+            });
+        }
+        if !imp::seccomp_available() {
+            bail!("--allow-read and --allow-write require Landlock or seccomp (Linux only)");
+        }
+        if write.is_none() {
+            bail!("--allow-read requires Landlock, which this kernel does not offer");
+        }
+        eprintln!(
+            "rcpt: Landlock is not available, falling back to seccomp: \
+             the command may write nowhere and reads are not restricted"
+        );
+        Ok(AccessProfile {
+            mechanism: Mechanism::Seccomp,
+            landlock_abi: None,
+            read: None,
+            write: Some(Vec::new()),
+            denials: Vec::new(),
+        })
+    }
+}
+
+impl AccessProfile {
+    /// Arrange for the command to restrict itself before exec
+    pub fn apply(&self, command: &mut Command) -> Result<()> {
+        match self.mechanism {
+            Mechanism::Landlock => imp::apply_landlock(self, command),
+            Mechanism::Seccomp => imp::apply_seccomp(command),
+        }
+    }
+
+    /// Collect the denials reported in the command's error output
+    pub fn record_denials(&mut self, output: &str) {
+        self.denials = output
+            .lines()
+            .filter(|line| {
+                line.contains("Permission denied") || line.contains("Read-only file system")
+            })
+            .take(MAX_DENIALS)
+            .map(str::to_string)
+            .collect();
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use anyhow::{Context, Result};
+    use std::ffi::CString;
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::process::CommandExt;
+    use std::path::Path;
+    use std::process::Command;
+    use std::ptr;
+
+    use super::AccessProfile;
+
+    const LANDLOCK_CREATE_RULESET_VERSION: libc::c_uint = 1;
+    const LANDLOCK_RULE_PATH_BENEATH: libc::c_int = 1;
+
+    const ACCESS_EXECUTE: u64 = 1 << 0;
+    const ACCESS_WRITE_FILE: u64 = 1 << 1;
+    const ACCESS_READ_FILE: u64 = 1 << 2;
+    const ACCESS_READ_DIR: u64 = 1 << 3;
+    const ACCESS_REMOVE_DIR: u64 = 1 << 4;
+    const ACCESS_REMOVE_FILE: u64 = 1 << 5;
+    const ACCESS_MAKE_CHAR: u64 = 1 << 6;
+    const ACCESS_MAKE_DIR: u64 = 1 << 7;
+    const ACCESS_MAKE_REG: u64 = 1 << 8;
+    const ACCESS_MAKE_SOCK: u64 = 1 << 9;
+    const ACCESS_MAKE_FIFO: u64 = 1 << 10;
+    const ACCESS_MAKE_BLOCK: u64 = 1 << 11;
+    const ACCESS_MAKE_SYM: u64 = 1 << 12;
+    /// Linking and renaming across directories (ABI 2)
+    const ACCESS_REFER: u64 = 1 << 13;
+    /// Truncating files (ABI 3)
+    const ACCESS_TRUNCATE: u64 = 1 << 14;
+
+    const READ_ACCESS: u64 = ACCESS_EXECUTE | ACCESS_READ_FILE | ACCESS_READ_DIR;
+    const WRITE_ACCESS: u64 = ACCESS_WRITE_FILE
+        | ACCESS_REMOVE_DIR
+        | ACCESS_REMOVE_FILE
+        | ACCESS_MAKE_CHAR
+        | ACCESS_MAKE_DIR
+        | ACCESS_MAKE_REG
+        | ACCESS_MAKE_SOCK
+        | ACCESS_MAKE_FIFO
+        | ACCESS_MAKE_BLOCK
+        | ACCESS_MAKE_SYM
+        | ACCESS_REFER
+        | ACCESS_TRUNCATE;
+    /// Rights that apply to a file itself rather than a directory's contents
+    const FILE_ACCESS: u64 =
+        ACCESS_EXECUTE | ACCESS_WRITE_FILE | ACCESS_READ_FILE | ACCESS_TRUNCATE;
+
+    #[repr(C)]
+    struct RulesetAttr {
+        handled_access_fs: u64,
+    }
+
+    #[repr(C, packed)]
+    struct PathBeneathAttr {
+        allowed_access: u64,
+        parent_fd: libc::c_int,
+    }
+
+    pub fn landlock_abi() -> Option<u32> {
+        // SAFETY: a null attribute with size 0 only queries the version
+        let abi = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                ptr::null::<RulesetAttr>(),
+                0usize,
+                LANDLOCK_CREATE_RULESET_VERSION,
+            )
+        };
+        (abi > 0).then_some(abi as u32)
+    }
+
+    pub fn seccomp_available() -> bool {
+        seccomp::AUDIT_ARCH.is_some()
+    }
+
+    fn check(result: libc::c_long) -> io::Result<libc::c_long> {
+        if result < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(result)
+        }
+    }
+
+    pub fn apply_landlock(profile: &AccessProfile, command: &mut Command) -> Result<()> {
+        let abi = profile.landlock_abi.unwrap_or(1);
+        let mut supported = READ_ACCESS | WRITE_ACCESS;
+        if abi < 2 {
+            supported &= !ACCESS_REFER;
+        }
+        if abi < 3 {
+            supported &= !ACCESS_TRUNCATE;
+        }
+        let mut handled = 0;
+        if profile.read.is_some() {
+            handled |= READ_ACCESS;
+        }
+        if profile.write.is_some() {
+            handled |= WRITE_ACCESS;
+        }
+        handled &= supported;
+
+        let attr = RulesetAttr {
+            handled_access_fs: handled,
+        };
+        // SAFETY: the attribute outlives the call and its size is passed
+        let ruleset = check(unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                &attr as *const RulesetAttr,
+                std::mem::size_of::<RulesetAttr>(),
+                0,
+            )
+        })
+        .context("Failed to create Landlock ruleset")?;
+        // SAFETY: the kernel just returned this descriptor, owned only here
+        let ruleset = unsafe { OwnedFd::from_raw_fd(ruleset as libc::c_int) };
+
+        let read = profile
+            .read
+            .iter()
+            .flatten()
+            .map(|path| (path, READ_ACCESS));
+        let write = profile
+            .write
+            .iter()
+            .flatten()
+            .map(|path| (path, WRITE_ACCESS | READ_ACCESS));
+        for (path, access) in read.chain(write) {
+            let mut access = access & handled;
+            if !Path::new(path).is_dir() {
+                access &= FILE_ACCESS;
+            }
+            let name = CString::new(path.as_bytes())?;
+            // SAFETY: `name` is a valid C string
+            let fd = check(unsafe {
+                libc::open(name.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) as libc::c_long
+            })
+            .context(format!("Failed to open allowed path {}", path))?;
+            // SAFETY: open(2) just returned this descriptor, owned only here
+            let fd = unsafe { OwnedFd::from_raw_fd(fd as libc::c_int) };
+            let rule = PathBeneathAttr {
+                allowed_access: access,
+                parent_fd: fd.as_raw_fd(),
+            };
+            // SAFETY: the rule outlives the call
+            check(unsafe {
+                libc::syscall(
+                    libc::SYS_landlock_add_rule,
+                    ruleset.as_raw_fd(),
+                    LANDLOCK_RULE_PATH_BENEATH,
+                    &rule as *const PathBeneathAttr,
+                    0,
+                )
+            })
+            .context(format!("Failed to add Landlock rule for {}", path))?;
+        }
+
+        // SAFETY: prctl(2) and landlock_restrict_self(2) are async-signal-safe,
+        // and the ruleset stays open as long as the command holds the closure
+        unsafe {
+            command.pre_exec(move || {
+                check(libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) as libc::c_long)?;
+                check(libc::syscall(
+                    libc::SYS_landlock_restrict_self,
+                    ruleset.as_raw_fd(),
+                    0,
+                ))?;
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+
+    pub fn apply_seccomp(command: &mut Command) -> Result<()> {
+        let filter = seccomp::read_only_filter().context("seccomp is not supported here")?;
+        // SAFETY: prctl(2) and seccomp(2) are async-signal-safe, and the
+        // filter stays allocated as long as the command holds the closure
+        unsafe {
+            command.pre_exec(move || {
+                let program = libc::sock_fprog {
+                    len: filter.len() as libc::c_ushort,
+                    filter: filter.as_ptr() as *mut libc::sock_filter,
+                };
+                check(libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) as libc::c_long)?;
+                check(libc::syscall(
+                    libc::SYS_seccomp,
+                    libc::SECCOMP_SET_MODE_FILTER,
+                    0,
+                    &program as *const libc::sock_fprog,
+                ))?;
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+
+    /// A seccomp filter failing every system call that changes the filesystem
+    mod seccomp {
+        const SECCOMP_RET_ALLOW: u32 = 0x7fff_0000;
+        const SECCOMP_RET_ERRNO: u32 = 0x0005_0000;
+
+        /// Offsets into `struct seccomp_data`
+        const NR_OFFSET: u32 = 0;
+        const ARCH_OFFSET: u32 = 4;
+        const ARGS_OFFSET: u32 = 16;
+
+        const BPF_LD_W_ABS: u16 = (libc::BPF_LD | libc::BPF_W | libc::BPF_ABS) as u16;
+        const BPF_JEQ_K: u16 = (libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K) as u16;
+        const BPF_JSET_K: u16 = (libc::BPF_JMP | libc::BPF_JSET | libc::BPF_K) as u16;
+        const BPF_RET_K: u16 = (libc::BPF_RET | libc::BPF_K) as u16;
+
+        /// Open flags that create, truncate or write a file
+        const WRITE_FLAGS: u32 =
+            (libc::O_WRONLY | libc::O_RDWR | libc::O_CREAT | libc::O_TRUNC) as u32;
+
+        #[cfg(target_arch = "x86_64")]
+        pub const AUDIT_ARCH: Option<u32> = Some(0xc000_003e);
+        #[cfg(target_arch = "aarch64")]
+        pub const AUDIT_ARCH: Option<u32> = Some(0xc000_00b7);
+        #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+        pub const AUDIT_ARCH: Option<u32> = None;
+
+        /// System calls that only change the filesystem
+        #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+        const CHANGES: &[libc::c_long] = &[
+            libc::SYS_mkdirat,
+            libc::SYS_mknodat,
+            libc::SYS_unlinkat,
+            libc::SYS_renameat2,
+            libc::SYS_linkat,
+            libc::SYS_symlinkat,
+            libc::SYS_truncate,
+            libc::SYS_fchmod,
+            libc::SYS_fchmodat,
+            libc::SYS_fchown,
+            libc::SYS_fchownat,
+            libc::SYS_utimensat,
+            libc::SYS_setxattr,
+            libc::SYS_lsetxattr,
+            libc::SYS_fsetxattr,
+            libc::SYS_removexattr,
+            libc::SYS_lremovexattr,
+            libc::SYS_fremovexattr,
+            #[cfg(target_arch = "x86_64")]
+            libc::SYS_creat,
+            #[cfg(target_arch = "x86_64")]
+            libc::SYS_mkdir,
+            #[cfg(target_arch = "x86_64")]
+            libc::SYS_mknod,
+            #[cfg(target_arch = "x86_64")]
+            libc::SYS_unlink,
+            #[cfg(target_arch = "x86_64")]
+            libc::SYS_rmdir,
+            #[cfg(target_arch = "x86_64")]
+            libc::SYS_rename,
+            #[cfg(target_arch = "x86_64")]
+            libc::SYS_renameat,
+            #[cfg(target_arch = "x86_64")]
+            libc::SYS_link,
+            #[cfg(target_arch = "x86_64")]
+            libc::SYS_symlink,
+            #[cfg(target_arch = "x86_64")]
+            libc::SYS_chmod,
+            #[cfg(target_arch = "x86_64")]
+            libc::SYS_chown,
+            #[cfg(target_arch = "x86_64")]
+            libc::SYS_lchown,
+            #[cfg(target_arch = "x86_64")]
+            libc::SYS_utime,
+            #[cfg(target_arch = "x86_64")]
+            libc::SYS_utimes,
+            #[cfg(target_arch = "x86_64")]
+            libc::SYS_futimesat,
+        ];
+
+        /// System calls that open a file, with the index of their flags argument
+        #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+        const OPENS: &[(libc::c_long, u32)] = &[
+            (libc::SYS_openat, 2),
+            #[cfg(target_arch = "x86_64")]
+            (libc::SYS_open, 1),
+        ];
+
+        fn statement(code: u16, k: u32) -> libc::sock_filter {
+            jump(code, k, 0, 0)
+        }
+
+        fn jump(code: u16, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+            libc::sock_filter { code, jt, jf, k }
+        }
+
+        #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+        pub fn read_only_filter() -> Option<Vec<libc::sock_filter>> {
+            let deny = SECCOMP_RET_ERRNO | libc::EACCES as u32;
+            let mut program = vec![
+                statement(BPF_LD_W_ABS, ARCH_OFFSET),
+                jump(BPF_JEQ_K, AUDIT_ARCH?, 1, 0),
+                statement(BPF_RET_K, deny),
+                statement(BPF_LD_W_ABS, NR_OFFSET),
+            ];
+            for &nr in CHANGES {
+                program.push(jump(BPF_JEQ_K, nr as u32, 0, 1));
+                program.push(statement(BPF_RET_K, deny));
+            }
+            // Its flags are behind a pointer, out of the filter's reach; C
+            // libraries fall back to openat when it is missing
+            program.push(jump(BPF_JEQ_K, libc::SYS_openat2 as u32, 0, 1));
+            program.push(statement(
+                BPF_RET_K,
+                SECCOMP_RET_ERRNO | libc::ENOSYS as u32,
+            ));
+            for &(nr, flags) in OPENS {
+                program.push(jump(BPF_JEQ_K, nr as u32, 0, 4));
+                // The low half of the argument, on these little-endian targets
+                program.push(statement(BPF_LD_W_ABS, ARGS_OFFSET + 8 * flags));
+                program.push(jump(BPF_JSET_K, WRITE_FLAGS, 0, 1));
+                program.push(statement(BPF_RET_K, deny));
+                program.push(statement(BPF_RET_K, SECCOMP_RET_ALLOW));
+            }
+            program.push(statement(BPF_RET_K, SECCOMP_RET_ALLOW));
+            Some(program)
+        }
+
+        #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+        pub fn read_only_filter() -> Option<Vec<libc::sock_filter>> {
+            None
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use anyhow::{bail, Result};
+    use std::process::Command;
+
+    use super::AccessProfile;
+
+    pub fn landlock_abi() -> Option<u32> {
+        None
+    }
+
+    pub fn seccomp_available() -> bool {
+        false
+    }
+
+    pub fn apply_landlock(_profile: &AccessProfile, _command: &mut Command) -> Result<()> {
+        bail!("Landlock is only supported on Linux")
+    }
+
+    pub fn apply_seccomp(_command: &mut Command) -> Result<()> {
+        bail!("seccomp is only supported on Linux")
+    }
+}
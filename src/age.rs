@@ -0,0 +1,432 @@
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::Path;
+
+use crate::base64;
+use crate::chacha20poly1305::{self, TAG_LEN};
+use crate::digest::hmac_sha256;
+use crate::ed25519::{x25519, X25519_BASE};
+use crate::id;
+
+/// Environment variable naming an age identity file, for reading encrypted
+/// receipts
+pub const IDENTITY_ENV: &str = "RCPT_IDENTITY";
+
+/// First line of every age file
+const VERSION_LINE: &str = "age-encryption.org/v1";
+
+/// Plaintext bytes per payload chunk
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The bech32 alphabet (BIP 173)
+const BECH32: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// An X25519 recipient, as `age1...`
+#[derive(Debug, Clone)]
+pub struct Recipient([u8; 32]);
+
+impl Recipient {
+    /// Parse an `age1...` recipient, as printed by `age-keygen`
+    pub fn parse(s: &str) -> Result<Self> {
+        let key = bech32_decode("age", &s.to_lowercase())
+            .context(format!("'{}' is not an age recipient (age1...)", s))?;
+        Ok(Recipient(key))
+    }
+}
+
+/// An X25519 identity, as `AGE-SECRET-KEY-1...`
+struct Identity([u8; 32]);
+
+/// The identities in an age identity file, one per line, ignoring blank
+/// lines and `#` comments
+fn load_identities(path: &Path) -> Result<Vec<Identity>> {
+    let text = fs::read_to_string(path).context(format!("Failed to read {}", path.display()))?;
+    let identities: Vec<Identity> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            bech32_decode("age-secret-key-", &line.to_lowercase())
+                .map(Identity)
+                .context(format!(
+                    "{} holds a line that is not an age identity",
+                    path.display()
+                ))
+        })
+        .collect::<Result<_>>()?;
+    if identities.is_empty() {
+        bail!("{} holds no age identities", path.display());
+    }
+    Ok(identities)
+}
+
+/// Whether `bytes` are an age file
+pub fn is_encrypted(bytes: &[u8]) -> bool {
+    bytes.starts_with(format!("{}\n", VERSION_LINE).as_bytes())
+}
+
+/// Read the file at `path`, decrypting it with the identities in the file
+/// `$RCPT_IDENTITY` names if it is an age file
+pub fn read(path: &Path) -> Result<Vec<u8>> {
+    let bytes = fs::read(path).context(format!("Failed to read {}", path.display()))?;
+    if !is_encrypted(&bytes) {
+        return Ok(bytes);
+    }
+    let Some(identity_file) = std::env::var_os(IDENTITY_ENV).filter(|file| !file.is_empty()) else {
+        bail!(
+            "{} is encrypted; set {} to an age identity file to read it",
+            path.display(),
+            IDENTITY_ENV
+        );
+    };
+    let identities = load_identities(Path::new(&identity_file))?;
+    decrypt(&bytes, &identities).context(format!("Failed to decrypt {}", path.display()))
+}
+
+/// Encrypt `plaintext` to every recipient, in the age v1 format
+pub fn encrypt(plaintext: &[u8], recipients: &[Recipient]) -> Result<Vec<u8>> {
+    let mut file_key = [0u8; 16];
+    id::secure_random(&mut file_key)?;
+
+    let mut header = format!("{}\n", VERSION_LINE);
+    for recipient in recipients {
+        let mut ephemeral = [0u8; 32];
+        id::secure_random(&mut ephemeral)?;
+        let share = x25519(&ephemeral, &X25519_BASE);
+        let shared = x25519(&ephemeral, &recipient.0);
+        // A low-order recipient makes the shared secret all zeros, which
+        // the age spec forbids
+        if shared == [0; 32] {
+            bail!("age recipient is not a valid X25519 public key");
+        }
+        let body = chacha20poly1305::seal(
+            &wrap_key(&shared, &share, &recipient.0),
+            &[0; 12],
+            &file_key,
+        );
+        header.push_str(&format!("-> X25519 {}\n", unpadded(&share)));
+        header.push_str(&format!("{}\n", unpadded(&body)));
+    }
+    header.push_str("---");
+    let mac = hmac_sha256(&hkdf(&file_key, b"", b"header"), header.as_bytes());
+    header.push_str(&format!(" {}\n", unpadded(&mac)));
+
+    let mut nonce = [0u8; 16];
+    id::secure_random(&mut nonce)?;
+    let payload_key = hkdf(&file_key, &nonce, b"payload");
+    let mut out = header.into_bytes();
+    out.extend_from_slice(&nonce);
+    let chunks: Vec<&[u8]> = match plaintext.is_empty() {
+        true => vec![&[]],
+        false => plaintext.chunks(CHUNK_SIZE).collect(),
+    };
+    for (counter, chunk) in chunks.iter().enumerate() {
+        let last = counter + 1 == chunks.len();
+        out.extend(chacha20poly1305::seal(
+            &payload_key,
+            &chunk_nonce(counter as u64, last),
+            chunk,
+        ));
+    }
+    Ok(out)
+}
+
+/// Decrypt an age file with whichever of `identities` it was encrypted to
+fn decrypt(file: &[u8], identities: &[Identity]) -> Result<Vec<u8>> {
+    // The header is text up to the line starting with "---"
+    let footer = file
+        .windows(4)
+        .position(|window| window == b"\n---")
+        .context("malformed age header")?
+        + 1;
+    let header = std::str::from_utf8(&file[..footer]).context("malformed age header")?;
+    let rest = &file[footer..];
+    let newline = rest
+        .iter()
+        .position(|&byte| byte == b'\n')
+        .context("malformed age header")?;
+    let mac_line = std::str::from_utf8(&rest[..newline]).context("malformed age header")?;
+    let mac = mac_line
+        .strip_prefix("--- ")
+        .and_then(|mac| base64::decode(mac).ok())
+        .context("malformed age header")?;
+    let payload = &rest[newline + 1..];
+
+    let mut lines = header.lines();
+    if lines.next() != Some(VERSION_LINE) {
+        bail!("unsupported age version");
+    }
+    let mut file_key = None;
+    while let Some(line) = lines.next() {
+        let args: Vec<&str> = line
+            .strip_prefix("-> ")
+            .context("malformed age header")?
+            .split(' ')
+            .collect();
+        let mut body = String::new();
+        for line in lines.by_ref() {
+            body.push_str(line);
+            if line.len() < 64 {
+                break;
+            }
+        }
+        if args[0] != "X25519" || file_key.is_some() {
+            continue;
+        }
+        let share: [u8; 32] = args
+            .get(1)
+            .and_then(|share| base64::decode(share).ok())
+            .and_then(|share| share.try_into().ok())
+            .context("malformed X25519 stanza")?;
+        let body = base64::decode(&body).context("malformed X25519 stanza")?;
+        file_key = identities.iter().find_map(|identity| {
+            let shared = x25519(&identity.0, &share);
+            if shared == [0; 32] {
+                return None;
+            }
+            let public = x25519(&identity.0, &X25519_BASE);
+            chacha20poly1305::open(&wrap_key(&shared, &share, &public), &[0; 12], &body)
+        });
+    }
+    let Some(file_key) = file_key else {
+        bail!("not encrypted to any identity in ${}", IDENTITY_ENV);
+    };
+    let expected = hmac_sha256(&hkdf(&file_key, b"", b"header"), &file[..footer + 3]);
+    if mac != expected {
+        bail!("age header MAC does not match");
+    }
+
+    let nonce = payload.get(..16).context("truncated age payload")?;
+    let payload_key = hkdf(&file_key, nonce, b"payload");
+    let chunks: Vec<&[u8]> = payload[16..].chunks(CHUNK_SIZE + TAG_LEN).collect();
+    if chunks.is_empty() {
+        bail!("truncated age payload");
+    }
+    let mut plaintext = Vec::new();
+    for (counter, chunk) in chunks.iter().enumerate() {
+        let last = counter + 1 == chunks.len();
+        let opened =
+            chacha20poly1305::open(&payload_key, &chunk_nonce(counter as u64, last), chunk)
+                .context("age payload is corrupt or truncated")?;
+        if last && opened.is_empty() && counter > 0 {
+            bail!("age payload ends with an empty chunk");
+        }
+        plaintext.extend(opened);
+    }
+    Ok(plaintext)
+}
+
+/// The key wrapping the file key for an X25519 recipient
+fn wrap_key(shared: &[u8; 32], share: &[u8; 32], recipient: &[u8; 32]) -> [u8; 32] {
+    let mut salt = share.to_vec();
+    salt.extend_from_slice(recipient);
+    hkdf(shared, &salt, b"age-encryption.org/v1/X25519")
+}
+
+/// A payload chunk's nonce: its big-endian index, then 1 for the last chunk
+fn chunk_nonce(counter: u64, last: bool) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[3..11].copy_from_slice(&counter.to_be_bytes());
+    nonce[11] = last as u8;
+    nonce
+}
+
+/// 32 bytes of HKDF-SHA256 (RFC 5869)
+fn hkdf(ikm: &[u8], salt: &[u8], info: &[u8]) -> [u8; 32] {
+    let prk = hmac_sha256(salt, ikm);
+    let mut expand = info.to_vec();
+    expand.push(1);
+    hmac_sha256(&prk, &expand)
+}
+
+/// Base64 without padding, as age writes it
+fn unpadded(bytes: &[u8]) -> String {
+    base64::encode(bytes).trim_end_matches('=').to_string()
+}
+
+fn bech32_polymod(values: impl Iterator<Item = u8>) -> u32 {
+    const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+    values.fold(1, |check, value| {
+        let top = check >> 25;
+        let check = (check & 0x1ffffff) << 5 ^ value as u32;
+        (0..5)
+            .filter(|bit| top >> bit & 1 == 1)
+            .fold(check, |check, bit| check ^ GENERATOR[bit])
+    })
+}
+
+/// The human-readable part expanded for the checksum
+fn bech32_hrp(hrp: &str) -> Vec<u8> {
+    let bytes = hrp.bytes();
+    let mut expanded: Vec<u8> = bytes.clone().map(|byte| byte >> 5).collect();
+    expanded.push(0);
+    expanded.extend(bytes.map(|byte| byte & 31));
+    expanded
+}
+
+/// The 32-byte key in a lowercase bech32 string with human-readable part
+/// `hrp`
+fn bech32_decode(hrp: &str, s: &str) -> Option<[u8; 32]> {
+    let data = s.strip_prefix(hrp)?.strip_prefix('1')?;
+    let values: Vec<u8> = data
+        .bytes()
+        .map(|byte| BECH32.iter().position(|&c| c == byte).map(|v| v as u8))
+        .collect::<Option<_>>()?;
+    let mut check = bech32_hrp(hrp);
+    check.extend(&values);
+    if values.len() < 6 || bech32_polymod(check.into_iter()) != 1 {
+        return None;
+    }
+
+    let mut bytes = Vec::new();
+    let (mut acc, mut bits) = (0u32, 0);
+    for value in &values[..values.len() - 6] {
+        acc = (acc << 5 | *value as u32) & 0xffff;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((acc >> bits) as u8);
+        }
+    }
+    if bits >= 5 || acc & ((1 << bits) - 1) != 0 {
+        return None;
+    }
+    bytes.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An identity, its recipient, and a file age-encrypted to it, made
+    // independently of this crate
+    const IDENTITY: &str =
+        "AGE-SECRET-KEY-1QYPQXPQ9QCRSSZG2PVXQ6RS0ZQG3YYC5Z5TPWXQERGD3C8G7RUSQGPQYEE";
+    const RECIPIENT: &str = "age1q73he0q5yzfu3d64msd3p6rvksnrwjk3d2598mgtmlqt9wrdr37q2vrn72";
+    const HEADER: &str = "age-encryption.org/v1
+-> X25519 WGmv9FBUlzLLqu1eXfmzCm2jHLDldCutWtShp2jxpns
+j1RjWWPkVNXdrEAxe9qUxDE+Npdauk+KZcWIHhT1D5I
+--- 0IR6+sVb5ALhCTm7HrpLXYp935SK+9pLZFjrIh8DT4c
+";
+    const PAYLOAD: &str =
+        "c8c9cacbcccdcecfd0d1d2d3d4d5d6d7f1911f119b46cd06d98bfc6311302c5e072819626ef9fe0dbe382b";
+
+    fn identity() -> Identity {
+        Identity(bech32_decode("age-secret-key-", &IDENTITY.to_lowercase()).unwrap())
+    }
+
+    fn known_file() -> Vec<u8> {
+        let mut file = HEADER.as_bytes().to_vec();
+        file.extend(
+            (0..PAYLOAD.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&PAYLOAD[i..i + 2], 16).unwrap()),
+        );
+        file
+    }
+
+    #[test]
+    fn parses_a_recipient_and_its_identity() {
+        let recipient = Recipient::parse(RECIPIENT).unwrap();
+        assert_eq!(recipient.0, x25519(&identity().0, &X25519_BASE));
+        assert_eq!(
+            Recipient::parse(&RECIPIENT.to_uppercase()).unwrap().0,
+            recipient.0
+        );
+        assert!(Recipient::parse(&RECIPIENT.replace("q7", "q8")).is_err());
+        assert!(Recipient::parse("age1").is_err());
+    }
+
+    #[test]
+    fn decrypts_a_known_file() {
+        let file = known_file();
+        assert!(is_encrypted(&file));
+        assert_eq!(decrypt(&file, &[identity()]).unwrap(), b"hello, age\n");
+    }
+
+    #[test]
+    fn round_trips() {
+        let recipient = Recipient::parse(RECIPIENT).unwrap();
+        for len in [
+            0,
+            1,
+            CHUNK_SIZE - 1,
+            CHUNK_SIZE,
+            CHUNK_SIZE + 1,
+            2 * CHUNK_SIZE + 5,
+        ] {
+            let plaintext: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let file = encrypt(&plaintext, std::slice::from_ref(&recipient)).unwrap();
+            assert!(is_encrypted(&file));
+            assert_eq!(
+                decrypt(&file, &[identity()]).unwrap(),
+                plaintext,
+                "{} bytes",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn decrypts_with_any_recipient_or_identity() {
+        let other = Identity([9; 32]);
+        let recipients = [
+            Recipient(x25519(&other.0, &X25519_BASE)),
+            Recipient::parse(RECIPIENT).unwrap(),
+        ];
+        let file = encrypt(b"both", &recipients).unwrap();
+        assert_eq!(decrypt(&file, &[identity()]).unwrap(), b"both");
+        assert_eq!(decrypt(&file, &[other]).unwrap(), b"both");
+        let file = encrypt(b"one", &recipients[1..]).unwrap();
+        assert_eq!(
+            decrypt(&file, &[Identity([9; 32]), identity()]).unwrap(),
+            b"one"
+        );
+    }
+
+    #[test]
+    fn fails_with_the_wrong_identity() {
+        let error = decrypt(&known_file(), &[Identity([9; 32])]).unwrap_err();
+        assert!(
+            error.to_string().contains("not encrypted to any identity"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn fails_on_a_tampered_header() {
+        let file = String::from_utf8_lossy(&known_file()).replace("-> X25519", "-> X25519 extra");
+        assert!(decrypt(file.as_bytes(), &[identity()]).is_err());
+    }
+
+    #[test]
+    fn fails_on_a_truncated_payload() {
+        let file = known_file();
+        for cut in [1, TAG_LEN, PAYLOAD.len() / 2 - 16, PAYLOAD.len() / 2] {
+            let truncated = &file[..file.len() - cut];
+            assert!(
+                decrypt(truncated, &[identity()]).is_err(),
+                "cut {} bytes",
+                cut
+            );
+        }
+
+        // Dropping whole chunks leaves one that was not sealed as the last
+        let recipient = Recipient::parse(RECIPIENT).unwrap();
+        let file = encrypt(&vec![0; 2 * CHUNK_SIZE + 5], &[recipient]).unwrap();
+        let truncated = &file[..file.len() - (5 + TAG_LEN)];
+        let error = decrypt(truncated, &[identity()]).unwrap_err();
+        assert!(
+            error.to_string().contains("corrupt or truncated"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn refuses_a_low_order_recipient() {
+        assert!(encrypt(b"secret", &[Recipient([0; 32])]).is_err());
+        assert!(encrypt(b"secret", &[Recipient(X25519_BASE), Recipient([0; 32])]).is_err());
+    }
+}
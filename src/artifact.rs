@@ -0,0 +1,120 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::digest::{sha256_file, sha256_hex};
+
+/// What a declared artifact path named
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArtifactKind {
+    File,
+    Directory,
+}
+
+/// A file or directory tree declared with `--input` or `--output`, and its
+/// digest
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Artifact {
+    /// Path as given
+    pub path: String,
+    pub kind: ArtifactKind,
+    /// Size of the file, or total size of the files in the tree
+    pub size_bytes: u64,
+    /// Number of files in the tree, for a directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub files: Option<u64>,
+    /// SHA-256 of the file, or of the tree's manifest: one
+    /// `<sha256>  <relative path>` line per file, sorted by path
+    pub sha256: String,
+    /// Whether the file, or the tree's manifest and each of its files, is
+    /// in the object store under its SHA-256, with --objects-above
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stored: bool,
+}
+
+/// Hash the file or directory at `path`, relative to `cwd`
+pub fn hash(cwd: &Path, path: &Path) -> Result<Artifact> {
+    let full = cwd.join(path);
+    let metadata = fs::metadata(&full).context(format!("{} not found", path.display()))?;
+    let (kind, size_bytes, files, sha256) = if metadata.is_dir() {
+        let mut entries = Vec::new();
+        walk(&full, "", &mut entries)?;
+        entries.sort();
+        let size = entries.iter().map(|(_, _, len)| len).sum();
+        let digest = format!("{:x}", Sha256::digest(manifest(&entries)));
+        (
+            ArtifactKind::Directory,
+            size,
+            Some(entries.len() as u64),
+            digest,
+        )
+    } else {
+        let (digest, size) = sha256_file(&full)?;
+        (ArtifactKind::File, size, None, digest)
+    };
+    Ok(Artifact {
+        path: path.to_string_lossy().to_string(),
+        kind,
+        size_bytes,
+        files,
+        sha256,
+        stored: false,
+    })
+}
+
+/// A tree's manifest: one `<sha256>  <relative path>` line for each of the
+/// sorted `entries` from `walk`
+pub fn manifest(entries: &[(String, String, u64)]) -> String {
+    entries
+        .iter()
+        .map(|(name, digest, _)| format!("{}  {}\n", digest, name))
+        .collect()
+}
+
+/// Hash each of `paths` that exists, returning the artifacts and the paths
+/// that were missing
+pub fn hash_existing(cwd: &Path, paths: &[PathBuf]) -> Result<(Vec<Artifact>, Vec<String>)> {
+    let mut artifacts = Vec::new();
+    let mut missing = Vec::new();
+    for path in paths {
+        match fs::symlink_metadata(cwd.join(path)) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                missing.push(path.to_string_lossy().to_string());
+            }
+            _ => artifacts.push(hash(cwd, path)?),
+        }
+    }
+    Ok((artifacts, missing))
+}
+
+/// Collect `(relative path, sha256, size)` for every file under `dir`.
+/// Symlinks are not followed; each is hashed as the path it points to.
+pub fn walk(dir: &Path, prefix: &str, entries: &mut Vec<(String, String, u64)>) -> Result<()> {
+    let listing = fs::read_dir(dir).context(format!("Failed to read {}", dir.display()))?;
+    for entry in listing {
+        let entry = entry.context(format!("Failed to read {}", dir.display()))?;
+        let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+        let path = entry.path();
+        let file_type = entry
+            .file_type()
+            .context(format!("Failed to read {}", path.display()))?;
+        if file_type.is_dir() {
+            walk(&path, &format!("{}/", name), entries)?;
+        } else if file_type.is_symlink() {
+            let target =
+                fs::read_link(&path).context(format!("Failed to read {}", path.display()))?;
+            let target = target.to_string_lossy();
+            entries.push((name, sha256_hex(target.as_bytes()), 0));
+        } else if file_type.is_file() {
+            let (digest, size) = sha256_file(&path)?;
+            entries.push((name, digest, size));
+        } else {
+            bail!("Cannot hash {}: not a regular file", path.display());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,56 @@
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Write `contents` to `path` through a temporary file beside it, renamed
+/// into place, so a reader sees the old file or the new one but never part
+/// of one. Missing parent directories are created. With `fsync`, the file
+/// and then its directory are flushed to disk first, so the write also
+/// survives a crash or power loss
+pub fn write(path: &Path, contents: &[u8], fsync: bool) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    // Hidden and not ending in .json, so nothing looking for receipts
+    // picks it up
+    let mut temp = OsString::from(".");
+    temp.push(name);
+    temp.push(format!(".{}.tmp", std::process::id()));
+    let temp = dir.join(temp);
+    fs::create_dir_all(dir)?;
+
+    let written = (|| {
+        let mut file = File::create(&temp)?;
+        file.write_all(contents)?;
+        if fsync {
+            file.sync_all()?;
+        }
+        drop(file);
+        fs::rename(&temp, path)?;
+        if fsync {
+            sync_dir(dir)?;
+        }
+        Ok(())
+    })();
+    if written.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    written
+}
+
+/// Flush a directory's entries, making a rename in it durable
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+/// Windows makes renames durable with the file's own flush
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,158 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::artifact::Artifact;
+use crate::atomic;
+use crate::receipt::{self, Receipt};
+
+const STATEMENT_TYPE: &str = "https://in-toto.io/Statement/v1";
+const PREDICATE_TYPE: &str = "https://slsa.dev/provenance/v1";
+/// What the build parameters describe: a command run by rcpt
+const BUILD_TYPE: &str = "urn:rcpt:command:v1";
+
+/// An in-toto statement about the products of a run
+#[derive(Serialize)]
+struct Statement {
+    #[serde(rename = "_type")]
+    statement_type: &'static str,
+    subject: Vec<ResourceDescriptor>,
+    #[serde(rename = "predicateType")]
+    predicate_type: &'static str,
+    predicate: Provenance,
+}
+
+/// A file and its digests
+#[derive(Serialize)]
+struct ResourceDescriptor {
+    name: String,
+    digest: BTreeMap<&'static str, String>,
+}
+
+/// SLSA provenance: how the products were made
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Provenance {
+    build_definition: BuildDefinition,
+    run_details: RunDetails,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BuildDefinition {
+    build_type: &'static str,
+    external_parameters: ExternalParameters,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    internal_parameters: Option<InternalParameters>,
+    resolved_dependencies: Vec<ResourceDescriptor>,
+}
+
+/// The invocation: what was run and where
+#[derive(Serialize)]
+struct ExternalParameters {
+    /// The command line, or the shell command string
+    command: Vec<String>,
+    cwd: String,
+}
+
+#[derive(Serialize)]
+struct InternalParameters {
+    env_digest: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RunDetails {
+    builder: Builder,
+    metadata: BuildMetadata,
+    byproducts: Vec<ResourceDescriptor>,
+}
+
+#[derive(Serialize)]
+struct Builder {
+    id: String,
+    version: BTreeMap<&'static str, &'static str>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BuildMetadata {
+    invocation_id: String,
+    started_on: DateTime<Utc>,
+    finished_on: DateTime<Utc>,
+}
+
+fn descriptor(artifact: &Artifact) -> ResourceDescriptor {
+    ResourceDescriptor {
+        name: artifact.path.clone(),
+        digest: BTreeMap::from([("sha256", artifact.sha256.clone())]),
+    }
+}
+
+/// The builder id used without --builder-id: this machine
+pub fn default_builder_id() -> String {
+    let hostname = crate::host::capture().hostname;
+    format!("urn:rcpt:host:{}", hostname.as_deref().unwrap_or("unknown"))
+}
+
+/// Write the run as an in-toto statement with a SLSA provenance predicate
+/// to `path`: products from `outputs`, materials from `inputs` and the
+/// commit checked out, and the receipt itself as a byproduct
+pub fn write(path: &Path, receipt: &Receipt, builder_id: &str, fsync: bool) -> Result<()> {
+    let command = match &receipt.shell {
+        Some(shell) => vec![shell.command.clone()],
+        None => std::iter::once(receipt.command.clone())
+            .chain(receipt.args.iter().cloned())
+            .collect(),
+    };
+    let mut materials: Vec<ResourceDescriptor> = receipt.inputs.iter().map(descriptor).collect();
+    if let Some(commit) = receipt.vcs.as_ref().and_then(|vcs| vcs.commit.clone()) {
+        materials.push(ResourceDescriptor {
+            name: receipt
+                .vcs
+                .as_ref()
+                .map_or_else(String::new, |vcs| vcs.root.clone()),
+            digest: BTreeMap::from([("gitCommit", commit)]),
+        });
+    }
+    let receipt_digest = receipt::receipt_digest(&serde_json::to_value(receipt)?);
+    let statement = Statement {
+        statement_type: STATEMENT_TYPE,
+        subject: receipt.outputs.iter().map(descriptor).collect(),
+        predicate_type: PREDICATE_TYPE,
+        predicate: Provenance {
+            build_definition: BuildDefinition {
+                build_type: BUILD_TYPE,
+                external_parameters: ExternalParameters {
+                    command,
+                    cwd: receipt.cwd.clone(),
+                },
+                internal_parameters: (!receipt.env_digest.is_empty()).then(|| InternalParameters {
+                    env_digest: receipt.env_digest.clone(),
+                }),
+                resolved_dependencies: materials,
+            },
+            run_details: RunDetails {
+                builder: Builder {
+                    id: builder_id.to_string(),
+                    version: BTreeMap::from([("rcpt", env!("CARGO_PKG_VERSION"))]),
+                },
+                metadata: BuildMetadata {
+                    invocation_id: receipt.id.clone(),
+                    started_on: receipt.start_time,
+                    finished_on: receipt.end_time,
+                },
+                byproducts: vec![ResourceDescriptor {
+                    name: "receipt".to_string(),
+                    digest: BTreeMap::from([("sha256", receipt_digest)]),
+                }],
+            },
+        },
+    };
+    let json = serde_json::to_string_pretty(&statement)
+        .context("Failed to serialize attestation to JSON")?;
+    atomic::write(path, json.as_bytes(), fsync)
+        .context(format!("Failed to write attestation to {}", path.display()))
+}
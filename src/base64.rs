@@ -0,0 +1,47 @@
+use anyhow::{bail, Result};
+
+/// The standard base64 alphabet (RFC 4648)
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Standard base64 of `bytes`, with padding
+pub fn encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let value = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |value, (index, &byte)| {
+                value | (byte as u32) << (16 - 8 * index)
+            });
+        for index in 0..4 {
+            if index <= chunk.len() {
+                encoded.push(ALPHABET[(value >> (18 - 6 * index)) as usize & 63] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Decode standard base64, with or without padding
+pub fn decode(encoded: &str) -> Result<Vec<u8>> {
+    let encoded = encoded.trim_end_matches('=').as_bytes();
+    if encoded.len() % 4 == 1 {
+        bail!("invalid base64 length");
+    }
+    let mut bytes = Vec::with_capacity(encoded.len() * 3 / 4);
+    for chunk in encoded.chunks(4) {
+        let mut value = 0u32;
+        for (index, &symbol) in chunk.iter().enumerate() {
+            let Some(digit) = ALPHABET.iter().position(|&c| c == symbol) else {
+                bail!("invalid base64 character '{}'", symbol as char);
+            };
+            value |= (digit as u32) << (18 - 6 * index);
+        }
+        for index in 0..chunk.len() - 1 {
+            bytes.push((value >> (16 - 8 * index)) as u8);
+        }
+    }
+    Ok(bytes)
+}
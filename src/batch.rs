@@ -0,0 +1,200 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+
+use crate::atomic;
+use crate::exec::{execute_with_retries, save_new_receipt, ExecOptions, Invocation, RetryPolicy};
+use crate::receipt::Status;
+use crate::signals;
+use crate::template::{self, Names};
+
+/// Name of the summary file written beside the per-command receipts
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Summary of a batch run
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Number of commands run concurrently
+    pub jobs: usize,
+    /// Number of commands in the batch
+    pub total: usize,
+    /// Number of commands that exited with code 0
+    pub succeeded: usize,
+    /// Number of commands that failed, timed out, or could not be started
+    pub failed: usize,
+    /// Number of commands not started because rcpt was interrupted
+    pub skipped: usize,
+    /// Start time (ISO 8601 timestamp)
+    pub start_time: DateTime<Utc>,
+    /// End time (ISO 8601 timestamp)
+    pub end_time: DateTime<Utc>,
+    /// Duration in milliseconds
+    pub duration_ms: u64,
+    /// One entry per command, in file order
+    pub entries: Vec<ManifestEntry>,
+}
+
+/// Outcome of one command in a batch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Position of the command in the batch, starting at 1
+    pub index: usize,
+    /// Command line as written in the file
+    pub command: String,
+    /// Outcome, absent if the command never ran
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<Status>,
+    /// Exit code of the command (None if terminated by signal)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    /// Duration in milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    /// Path of the command's receipt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub receipt: Option<String>,
+    /// Why the command could not be run or recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Read one shell command per line, skipping blank lines and `#` comments
+pub fn read_commands(path: &Path) -> Result<Vec<String>> {
+    let contents = fs::read_to_string(path)
+        .context(format!("Failed to read commands from {}", path.display()))?;
+    let commands: Vec<String> = contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect();
+    if commands.is_empty() {
+        anyhow::bail!("No commands found in {}", path.display());
+    }
+    Ok(commands)
+}
+
+/// Run `commands` through the shell on a pool of `jobs` workers, writing
+/// each receipt into `out_dir` followed by the manifest
+pub fn run_batch(
+    commands: &[String],
+    jobs: usize,
+    out_dir: &Path,
+    opts: &ExecOptions,
+    policy: &RetryPolicy,
+    report: &mut (impl Write + Send),
+) -> Result<Manifest> {
+    let start_time = Utc::now();
+    let start_instant = Instant::now();
+
+    let next = AtomicUsize::new(0);
+    let entries = Mutex::new(vec![None; commands.len()]);
+    let report = Mutex::new(report);
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, commands.len()) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                let Some(command) = commands.get(index) else {
+                    break;
+                };
+                let entry = run_one(index + 1, command, out_dir, opts, policy);
+                // One that cannot be written is no reason to stop the batch
+                let _ = writeln!(
+                    report.lock().unwrap(),
+                    "[{}/{}] {} {}",
+                    index + 1,
+                    commands.len(),
+                    entry.status.map_or("skipped", Status::as_str),
+                    command
+                );
+                entries.lock().unwrap()[index] = Some(entry);
+            });
+        }
+    });
+
+    let entries: Vec<ManifestEntry> = entries
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.expect("every command is visited by a worker"))
+        .collect();
+    let succeeded = entries
+        .iter()
+        .filter(|e| e.status == Some(Status::Ok))
+        .count();
+    let skipped = entries
+        .iter()
+        .filter(|e| e.status.is_none() && e.error.is_none())
+        .count();
+
+    let manifest = Manifest {
+        jobs,
+        total: entries.len(),
+        succeeded,
+        failed: entries.len() - succeeded - skipped,
+        skipped,
+        start_time,
+        end_time: Utc::now(),
+        duration_ms: start_instant.elapsed().as_millis() as u64,
+        entries,
+    };
+    write_manifest(out_dir, &manifest)?;
+    Ok(manifest)
+}
+
+fn run_one(
+    index: usize,
+    command: &str,
+    out_dir: &Path,
+    opts: &ExecOptions,
+    policy: &RetryPolicy,
+) -> ManifestEntry {
+    let mut entry = ManifestEntry {
+        index,
+        command: command.to_string(),
+        status: None,
+        exit_code: None,
+        duration_ms: None,
+        receipt: None,
+        error: None,
+    };
+    // Once interrupted, let running commands finish but start no new ones
+    if signals::received() {
+        return entry;
+    }
+
+    let result =
+        execute_with_retries(&Invocation::shell(command), opts, policy).and_then(|mut receipt| {
+            let path = out_dir.join(template::render(template::DEFAULT, &Names::of(&receipt))?);
+            save_new_receipt(opts, &mut receipt, &path)?;
+            Ok((receipt, path))
+        });
+    match result {
+        Ok((receipt, path)) => {
+            entry.status = Some(receipt.status);
+            entry.exit_code = receipt.exit_code;
+            entry.duration_ms = Some(receipt.duration_ms);
+            entry.receipt = Some(opts.saved_to(&path).to_string_lossy().to_string());
+        }
+        Err(err) => entry.error = Some(format!("{:#}", err)),
+    }
+    entry
+}
+
+fn write_manifest(out_dir: &Path, manifest: &Manifest) -> Result<()> {
+    let path = out_dir.join(MANIFEST_FILE);
+    let json =
+        serde_json::to_string_pretty(manifest).context("Failed to serialize manifest to JSON")?;
+    fs::create_dir_all(out_dir)
+        .context(format!("Failed to create directory {}", out_dir.display()))?;
+    atomic::write(&path, json.as_bytes(), false)
+        .context(format!("Failed to write manifest to {}", path.display()))?;
+    Ok(())
+}
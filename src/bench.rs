@@ -0,0 +1,135 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+use crate::exec::{execute_command, ExecOptions, Invocation};
+use crate::receipt::{ClockAnomaly, Receipt, Status};
+
+/// Repeated runs of a command and statistics over their durations
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Benchmark {
+    /// Number of warmup runs made before measuring
+    pub warmup: u32,
+    /// Measured runs, in order
+    pub runs: Vec<BenchRun>,
+    /// Statistics over the measured runs' durations, absent if none completed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<DurationStats>,
+}
+
+/// One measured run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchRun {
+    /// Run number, starting at 1
+    pub run: u32,
+    /// Exit code of the run (None if terminated by signal)
+    pub exit_code: Option<i32>,
+    /// Outcome of the run
+    pub status: Status,
+    /// Start time (ISO 8601 timestamp)
+    pub start_time: DateTime<Utc>,
+    /// Duration in milliseconds, with sub-millisecond precision
+    pub duration_ms: f64,
+}
+
+/// Summary of run durations, in milliseconds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DurationStats {
+    pub min_ms: f64,
+    pub max_ms: f64,
+    pub mean_ms: f64,
+    pub median_ms: f64,
+    /// Sample standard deviation (0 for a single run)
+    pub stddev_ms: f64,
+}
+
+impl DurationStats {
+    fn of(durations: &[f64]) -> Option<Self> {
+        if durations.is_empty() {
+            return None;
+        }
+        let mut sorted = durations.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let n = sorted.len();
+        let mean = sorted.iter().sum::<f64>() / n as f64;
+        let median = if n.is_multiple_of(2) {
+            (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0
+        } else {
+            sorted[n / 2]
+        };
+        let variance = if n > 1 {
+            sorted.iter().map(|d| (d - mean).powi(2)).sum::<f64>() / (n - 1) as f64
+        } else {
+            0.0
+        };
+        Some(DurationStats {
+            min_ms: sorted[0],
+            max_ms: sorted[n - 1],
+            mean_ms: mean,
+            median_ms: median,
+            stddev_ms: variance.sqrt(),
+        })
+    }
+}
+
+/// Run the command `warmup` times unmeasured, then `runs` times measured.
+/// Benchmarking stops at the first run that does not succeed. The returned
+/// receipt describes that run, or the last one, and carries the benchmark.
+pub fn run_bench(
+    invocation: &Invocation,
+    opts: &ExecOptions,
+    warmup: u32,
+    runs: u32,
+) -> Result<Receipt> {
+    let start_instant = Instant::now();
+    let mut receipt = execute_command(invocation, opts)?;
+    if opts.dry_run {
+        return Ok(receipt);
+    }
+    let id = receipt.id.clone();
+    let start_time = receipt.start_time;
+
+    let mut measured = Vec::new();
+    for index in 0..warmup + runs {
+        if index > 0 {
+            receipt = execute_command(invocation, opts)?;
+        }
+        if index >= warmup {
+            measured.push(BenchRun {
+                run: index - warmup + 1,
+                exit_code: receipt.exit_code,
+                status: receipt.status,
+                start_time: receipt.start_time,
+                duration_ms: precise_duration_ms(&receipt),
+            });
+        }
+        if !receipt.succeeded() {
+            break;
+        }
+    }
+
+    let durations: Vec<f64> = measured.iter().map(|run| run.duration_ms).collect();
+    // Timing covers the whole benchmark, including warmups
+    receipt.id = id;
+    receipt.start_time = start_time;
+    let duration = start_instant.elapsed();
+    receipt.duration_ms = duration.as_millis() as u64;
+    receipt.clock_anomaly = ClockAnomaly::detect(start_time, receipt.end_time, duration);
+    receipt.benchmark = Some(Benchmark {
+        warmup,
+        stats: DurationStats::of(&durations),
+        runs: measured,
+    });
+    Ok(receipt)
+}
+
+/// The run's duration to the microsecond, from its wall-clock times unless
+/// the clock was stepped during it
+fn precise_duration_ms(receipt: &Receipt) -> f64 {
+    if receipt.clock_anomaly.is_some() {
+        return receipt.duration_ms as f64;
+    }
+    let elapsed = receipt.end_time - receipt.start_time;
+    elapsed.num_microseconds().unwrap_or_default() as f64 / 1000.0
+}
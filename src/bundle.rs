@@ -0,0 +1,276 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::age;
+use crate::atomic;
+use crate::canonical;
+use crate::digest::sha256_hex;
+use crate::gc;
+use crate::receipt::{self, Receipt};
+use crate::signing::{self, detached_path, SignatureRecord, Signer, TrustedSigners};
+use crate::tar;
+use crate::units::format_size;
+use crate::zstd;
+
+/// Version of the bundle format, declared in each manifest
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// Where the manifest is in a bundle
+const MANIFEST: &str = "manifest.json";
+
+/// The first bytes of a zstd frame
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// What a bundle holds, with the digest of each file
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub bundle_version: u32,
+    /// When the bundle was made
+    pub created: DateTime<Utc>,
+    /// Version of rcpt that made it
+    pub rcpt_version: String,
+    /// The receipts in it
+    pub receipts: Vec<BundledReceipt>,
+    /// Every file in it but the manifest: the receipts, their detached
+    /// signatures and sidecars, and the objects they refer to
+    pub files: Vec<BundledFile>,
+    /// Signature over the manifest's canonical JSON without this field,
+    /// with --sign
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<SignatureRecord>,
+}
+
+/// A receipt in a bundle
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BundledReceipt {
+    pub id: String,
+    /// Where it is in the bundle
+    pub path: String,
+}
+
+/// A file in a bundle
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BundledFile {
+    /// Where it is in the bundle
+    pub path: String,
+    /// SHA-256 of its contents, as they are in the bundle
+    pub sha256: String,
+    pub size_bytes: u64,
+}
+
+/// Write `receipts` to `out` as one archive: a tar file, compressed with
+/// zstd if `out` ends in `.zst`, holding each receipt as it is stored under
+/// `receipts/<id>/`, with its detached signature and sidecars beside it,
+/// the objects they refer to under `objects/`, and a manifest of them all,
+/// signed with `signer`
+pub fn create(
+    receipts: &[(PathBuf, Receipt)],
+    out: &Path,
+    signer: Option<&Signer>,
+    report: &mut impl Write,
+) -> Result<()> {
+    let mut files: Vec<(String, Vec<u8>)> = Vec::new();
+    let mut bundled = Vec::new();
+    let mut objects = BTreeMap::new();
+    for (path, receipt) in receipts {
+        // The same receipt may be found in two places
+        if bundled
+            .iter()
+            .any(|entry: &BundledReceipt| entry.id == receipt.id)
+        {
+            continue;
+        }
+        let dir = format!("receipts/{}", receipt.id);
+        let name = format!("{}/{}", dir, file_name(path));
+        files.push((name.clone(), read(path)?));
+        bundled.push(BundledReceipt {
+            id: receipt.id.clone(),
+            path: name,
+        });
+        let sig = detached_path(path);
+        if sig.exists() {
+            files.push((format!("{}/{}", dir, file_name(&sig)), read(&sig)?));
+        }
+        for sidecar in &receipt.sidecars {
+            let file = sidecar.locate(path);
+            files.push((format!("{}/{}", dir, file_name(&file)), read(&file)?));
+        }
+        let Some(store) = receipt.object_dir(path) else {
+            continue;
+        };
+        let mut referenced = BTreeSet::new();
+        gc::references(receipt, &BTreeSet::from([store.clone()]), &mut referenced);
+        for digest in referenced {
+            let file = store.join(&digest);
+            objects.entry(digest).or_insert(file);
+        }
+    }
+    for (digest, file) in objects {
+        files.push((format!("objects/{}", digest), read(&file)?));
+    }
+
+    let mut manifest = Manifest {
+        bundle_version: BUNDLE_VERSION,
+        created: Utc::now(),
+        rcpt_version: env!("CARGO_PKG_VERSION").to_string(),
+        receipts: bundled,
+        files: files
+            .iter()
+            .map(|(path, contents)| BundledFile {
+                path: path.clone(),
+                sha256: sha256_hex(contents),
+                size_bytes: contents.len() as u64,
+            })
+            .collect(),
+        signature: None,
+    };
+    if let Some(signer) = signer {
+        let message = canonical::to_string(&serde_json::to_value(&manifest)?);
+        manifest.signature = Some(signer.signature(message.as_bytes())?);
+    }
+    let json = canonical::to_string(&serde_json::to_value(&manifest)?);
+    files.insert(0, (MANIFEST.to_string(), json.into_bytes()));
+
+    let mut archive = tar::write(&files, manifest.created.timestamp().max(0) as u64)?;
+    if out.extension().is_some_and(|ext| ext == "zst") {
+        archive = zstd::compress(&zstd::program()?, &archive)?;
+    }
+    atomic::write(out, &archive, false).context(format!("Failed to write {}", out.display()))?;
+    writeln!(
+        report,
+        "Bundled {} receipts and {} files into {} ({})",
+        manifest.receipts.len(),
+        manifest.files.len() - manifest.receipts.len(),
+        out.display(),
+        format_size(archive.len() as u64)
+    )?;
+    Ok(())
+}
+
+/// Check the bundle at `path`: that its manifest lists every file in it,
+/// with the digest it has, that the digest of each receipt in it matches,
+/// and that the manifest's signature is valid and, when `trusted` names a
+/// key, allowed signers or identity, made by them. Writes each check that
+/// passed to `report` and fails with the first that did not
+pub fn verify(path: &Path, trusted: &TrustedSigners, report: &mut impl Write) -> Result<()> {
+    let bytes = read(path)?;
+    let archive = match bytes.starts_with(&ZSTD_MAGIC) {
+        true => zstd::decompress(&bytes)?,
+        false => bytes,
+    };
+    let mut entries: BTreeMap<String, Vec<u8>> = tar::read(&archive)
+        .context(format!("{} is not a bundle", path.display()))?
+        .into_iter()
+        .collect();
+    let mut value: serde_json::Value = entries
+        .remove(MANIFEST)
+        .context("manifest: missing")
+        .and_then(|json| serde_json::from_slice(&json).context("manifest: malformed"))?;
+    let signature = value
+        .as_object_mut()
+        .and_then(|object| object.remove("signature"));
+    let manifest: Manifest =
+        serde_json::from_value(value.clone()).context("manifest: malformed")?;
+    if manifest.bundle_version > BUNDLE_VERSION {
+        bail!(
+            "manifest: bundle version {} is newer than this rcpt supports (up to {}); upgrade rcpt to check it",
+            manifest.bundle_version,
+            BUNDLE_VERSION
+        );
+    }
+    writeln!(
+        report,
+        "manifest: ok ({} receipts, made {})",
+        manifest.receipts.len(),
+        manifest.created.format("%Y-%m-%dT%H:%M:%SZ")
+    )?;
+
+    let mut listed = BTreeSet::new();
+    for file in &manifest.files {
+        let contents = entries
+            .get(&file.path)
+            .context(format!("{}: missing", file.path))?;
+        if contents.len() as u64 != file.size_bytes || sha256_hex(contents) != file.sha256 {
+            bail!("{}: does not match its sha256", file.path);
+        }
+        listed.insert(file.path.as_str());
+    }
+    if let Some(extra) = entries.keys().find(|path| !listed.contains(path.as_str())) {
+        bail!("{}: not in the manifest", extra);
+    }
+    let total: u64 = manifest.files.iter().map(|file| file.size_bytes).sum();
+    writeln!(
+        report,
+        "files: ok ({} files, {})",
+        manifest.files.len(),
+        format_size(total)
+    )?;
+
+    let mut encrypted = 0;
+    for bundled in &manifest.receipts {
+        let contents = match listed.contains(bundled.path.as_str()) {
+            true => &entries[&bundled.path],
+            false => bail!("{}: not in the manifest's files", bundled.path),
+        };
+        // Encrypted receipts are covered by the manifest's digests alone
+        if age::is_encrypted(contents) {
+            encrypted += 1;
+            continue;
+        }
+        let value: serde_json::Value =
+            serde_json::from_slice(contents).context(format!("{}: not a receipt", bundled.path))?;
+        let digest = value
+            .get("receipt_digest")
+            .and_then(|digest| digest.as_str());
+        if digest != Some(receipt::receipt_digest(&value).as_str()) {
+            bail!("{}: receipt_digest does not match", bundled.path);
+        }
+        if value.get("id").and_then(|id| id.as_str()) != Some(bundled.id.as_str()) {
+            bail!("{}: is not receipt {}", bundled.path, bundled.id);
+        }
+    }
+    match encrypted {
+        0 => writeln!(
+            report,
+            "receipts: ok ({} digests match)",
+            manifest.receipts.len()
+        )?,
+        _ => writeln!(
+            report,
+            "receipts: ok ({} digests match, {} encrypted)",
+            manifest.receipts.len() - encrypted,
+            encrypted
+        )?,
+    }
+
+    let Some(record) = signature else {
+        if trusted.key.is_some()
+            || trusted.allowed_signers.is_some()
+            || trusted.certificate_identity.is_some()
+        {
+            bail!("signature: missing");
+        }
+        writeln!(report, "signature: none")?;
+        return Ok(());
+    };
+    let record: SignatureRecord = serde_json::from_value(record).context("signature: malformed")?;
+    let message = canonical::to_string(&value);
+    let signer = signing::check_signature(&record, message.as_bytes(), trusted)?;
+    writeln!(report, "signature: ok ({})", signer)?;
+    Ok(())
+}
+
+fn read(path: &Path) -> Result<Vec<u8>> {
+    fs::read(path).context(format!("Failed to read {}", path.display()))
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
@@ -0,0 +1,240 @@
+use serde_json::{Map, Number, Value};
+
+/// The canonical form of `value` (RFC 8785): no whitespace, object keys
+/// sorted by their UTF-16 code units, numbers as JavaScript prints them and
+/// strings with only the escapes JSON requires
+pub fn to_string(value: &Value) -> String {
+    let mut out = String::new();
+    write_value(&mut out, value);
+    out
+}
+
+fn write_value(out: &mut String, value: &Value) {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        Value::Number(n) => write_number(out, n),
+        Value::String(s) => write_string(out, s),
+        Value::Array(items) => {
+            out.push('[');
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                write_value(out, item);
+            }
+            out.push(']');
+        }
+        Value::Object(map) => write_object(out, map),
+    }
+}
+
+fn write_object(out: &mut String, map: &Map<String, Value>) {
+    let mut entries: Vec<(Vec<u16>, &String, &Value)> = map
+        .iter()
+        .map(|(key, value)| (key.encode_utf16().collect(), key, value))
+        .collect();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    out.push('{');
+    for (index, (_, key, value)) in entries.into_iter().enumerate() {
+        if index > 0 {
+            out.push(',');
+        }
+        write_string(out, key);
+        out.push(':');
+        write_value(out, value);
+    }
+    out.push('}');
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\u{8}' => out.push_str("\\b"),
+            '\u{c}' => out.push_str("\\f"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c < ' ' => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Integers are written exactly; other numbers as JavaScript's
+/// `Number.prototype.toString` would
+fn write_number(out: &mut String, n: &Number) {
+    if let Some(n) = n.as_u64() {
+        out.push_str(&n.to_string());
+    } else if let Some(n) = n.as_i64() {
+        out.push_str(&n.to_string());
+    } else if let Some(n) = n.as_f64() {
+        write_float(out, n);
+    }
+}
+
+fn write_float(out: &mut String, n: f64) {
+    if n == 0.0 {
+        out.push('0');
+        return;
+    }
+    if n < 0.0 {
+        out.push('-');
+    }
+    // Shortest round-tripping digits, and the exponent of the first
+    let scientific = format!("{:e}", n.abs());
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    let digits = even_on_ties(n.abs(), mantissa.replace('.', ""), exponent);
+    // The value is 0.<digits> × 10^point
+    let point = exponent + 1;
+    let len = digits.len() as i32;
+    if len <= point && point <= 21 {
+        out.push_str(&digits);
+        out.push_str(&"0".repeat((point - len) as usize));
+    } else if 0 < point && point <= 21 {
+        out.push_str(&digits[..point as usize]);
+        out.push('.');
+        out.push_str(&digits[point as usize..]);
+    } else if -6 < point && point <= 0 {
+        out.push_str("0.");
+        out.push_str(&"0".repeat(-point as usize));
+        out.push_str(&digits);
+    } else {
+        out.push_str(&digits[..1]);
+        if len > 1 {
+            out.push('.');
+            out.push_str(&digits[1..]);
+        }
+        out.push('e');
+        out.push(if exponent < 0 { '-' } else { '+' });
+        out.push_str(&exponent.abs().to_string());
+    }
+}
+
+/// Rust's shortest digits take the upper of two that are equally close to
+/// `n`, where JavaScript takes the even one: 1424953923781206.25 is written
+/// 1424953923781206.2, not .3
+fn even_on_ties(n: f64, digits: String, exponent: i32) -> String {
+    let last = digits.as_bytes()[digits.len() - 1] - b'0';
+    if last.is_multiple_of(2) || digits.len() < 2 {
+        return digits;
+    }
+    let lower = format!("{}{}", &digits[..digits.len() - 1], last - 1);
+    // Every digit of `n`, which has at most 767 significant ones
+    let exact = format!("{:.800e}", n);
+    let (mantissa, exact_exponent) = exact.split_once('e').unwrap_or((&exact, "0"));
+    let tie = exact_exponent == exponent.to_string()
+        && mantissa
+            .replace('.', "")
+            .strip_prefix(lower.as_str())
+            .is_some_and(|rest| rest.starts_with('5') && rest[1..].bytes().all(|b| b == b'0'));
+    let scale = exponent + 1 - lower.len() as i32;
+    match tie && format!("{}e{}", lower, scale).parse() == Ok(n) {
+        true => lower,
+        false => digits,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_numbers_as_javascript_does() {
+        // RFC 8785 appendix B
+        for (bits, expected) in [
+            (0x0000000000000000u64, "0"),
+            (0x8000000000000000, "0"),
+            (0x0000000000000001, "5e-324"),
+            (0x8000000000000001, "-5e-324"),
+            (0x7fefffffffffffff, "1.7976931348623157e+308"),
+            (0xffefffffffffffff, "-1.7976931348623157e+308"),
+            (0x4340000000000000, "9007199254740992"),
+            (0xc340000000000000, "-9007199254740992"),
+            (0x4430000000000000, "295147905179352830000"),
+            (0x44b52d02c7e14af5, "9.999999999999997e+22"),
+            (0x44b52d02c7e14af6, "1e+23"),
+            (0x44b52d02c7e14af7, "1.0000000000000001e+23"),
+            (0x444b1ae4d6e2ef4e, "999999999999999700000"),
+            (0x444b1ae4d6e2ef4f, "999999999999999900000"),
+            (0x444b1ae4d6e2ef50, "1e+21"),
+            (0x3eb0c6f7a0b5ed8c, "9.999999999999997e-7"),
+            (0x3eb0c6f7a0b5ed8d, "0.000001"),
+            (0x41b3de4355555553, "333333333.3333332"),
+            (0x41b3de4355555554, "333333333.33333325"),
+            (0x41b3de4355555555, "333333333.3333333"),
+            (0x41b3de4355555556, "333333333.3333334"),
+            (0x41b3de4355555557, "333333333.33333343"),
+            (0xbecbf647612f3696, "-0.0000033333333333333333"),
+            (0x43143ff3c1cb0959, "1424953923781206.2"),
+        ] {
+            let value = Value::from(f64::from_bits(bits));
+            assert_eq!(to_string(&value), expected, "{:016x}", bits);
+        }
+        assert_eq!(to_string(&Value::from(u64::MAX)), "18446744073709551615");
+        assert_eq!(to_string(&Value::from(i64::MIN)), "-9223372036854775808");
+    }
+
+    #[test]
+    fn sorts_keys_by_utf16_code_units() {
+        // RFC 8785 section 3.2.3
+        let value: Value = serde_json::from_str(
+            r#"{
+                "\u20ac": "Euro Sign",
+                "\r": "Carriage Return",
+                "\ufb33": "Hebrew Letter Dalet With Dagesh",
+                "1": "One",
+                "\ud83d\ude00": "Emoji: Grinning Face",
+                "\u0080": "Control",
+                "\u00f6": "Latin Small Letter O With Diaeresis"
+            }"#,
+        )
+        .unwrap();
+        let keys: Vec<String> = to_string(&value)
+            .split(',')
+            .map(|entry| {
+                entry
+                    .split(':')
+                    .next()
+                    .unwrap()
+                    .trim_start_matches('{')
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                "\"\\r\"",
+                "\"1\"",
+                "\"\u{80}\"",
+                "\"ö\"",
+                "\"€\"",
+                "\"😀\"",
+                "\"\u{fb33}\""
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_the_rfc_example() {
+        // RFC 8785 section 3.2.2; 333333333.33333329 only parses to the
+        // nearest f64 with serde_json's float_roundtrip
+        let value: Value = serde_json::from_str(
+            r#"{
+                "numbers": [333333333.33333329, 1E30, 4.50, 2e-3, 0.000000000000000000000000001],
+                "string": "\u20ac$\u000F\u000aA'\u0042\u0022\u005c\\\"\/",
+                "literals": [null, true, false]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            to_string(&value),
+            r#"{"literals":[null,true,false],"numbers":[333333333.3333333,1e+30,4.5,0.002,1e-27],"string":"€$\u000f\nA'B\"\\\\\"/"}"#
+        );
+    }
+}
@@ -0,0 +1,281 @@
+use clap::ValueEnum;
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::base64;
+use crate::receipt::{Encoding, StreamInfo, TimelineEvent};
+
+/// How often an unfinished reader is checked during a grace period
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Destination that captured output is echoed to as it arrives
+pub type Tee = Box<dyn Write + Send>;
+
+/// Where output that is not valid UTF-8 goes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum BinaryOutput {
+    /// Base64 in the receipt
+    #[default]
+    Base64,
+    /// Raw bytes in a file next to the receipt
+    Sidecar,
+}
+
+/// Bytes of one output stream as seen so far
+#[derive(Default)]
+struct StreamState {
+    /// Leading bytes, up to half the limit (or everything when unbounded)
+    head: Vec<u8>,
+    /// Trailing bytes, up to half the limit
+    tail: VecDeque<u8>,
+    /// Total bytes read from the stream
+    total: u64,
+    /// Digest over the full stream
+    hasher: Sha256,
+}
+
+impl StreamState {
+    fn push(&mut self, chunk: &[u8], limit: Option<usize>) {
+        self.total += chunk.len() as u64;
+        self.hasher.update(chunk);
+
+        let Some(limit) = limit else {
+            self.head.extend_from_slice(chunk);
+            return;
+        };
+        let head_limit = limit - limit / 2;
+        let tail_limit = limit / 2;
+
+        let room = head_limit.saturating_sub(self.head.len());
+        let (to_head, rest) = chunk.split_at(room.min(chunk.len()));
+        self.head.extend_from_slice(to_head);
+
+        // Only keep the last `tail_limit` bytes of what didn't fit in the head
+        let rest = &rest[rest.len().saturating_sub(tail_limit)..];
+        let overflow = (self.tail.len() + rest.len()).saturating_sub(tail_limit);
+        self.tail.drain(..overflow.min(self.tail.len()));
+        self.tail.extend(rest);
+    }
+}
+
+/// Output of every captured stream in the order it was read, for --timeline
+#[derive(Clone)]
+pub struct Timeline {
+    start: Instant,
+    events: Arc<Mutex<Vec<TimelineEvent>>>,
+}
+
+impl Timeline {
+    /// An empty timeline whose offsets count from `start`
+    pub fn new(start: Instant) -> Self {
+        Timeline {
+            start,
+            events: Arc::default(),
+        }
+    }
+
+    /// Where the capture of `stream` (of pipeline stage `stage`, if given)
+    /// records its chunks
+    pub fn stream(&self, stream: &'static str, stage: Option<usize>) -> TimelineStream {
+        TimelineStream {
+            timeline: self.clone(),
+            stream,
+            stage,
+            pending: Vec::new(),
+            recorded: 0,
+        }
+    }
+
+    /// The events recorded so far
+    pub fn finish(self) -> Vec<TimelineEvent> {
+        std::mem::take(&mut *self.events.lock().unwrap())
+    }
+}
+
+/// One stream's side of a timeline
+pub struct TimelineStream {
+    timeline: Timeline,
+    stream: &'static str,
+    stage: Option<usize>,
+    /// Start of a UTF-8 character whose remaining bytes are yet to be read
+    pending: Vec<u8>,
+    /// Bytes recorded, counted against the stream's limit
+    recorded: usize,
+}
+
+impl TimelineStream {
+    fn push(&mut self, chunk: &[u8], limit: Option<usize>) {
+        let room = limit.map_or(usize::MAX, |limit| {
+            limit.saturating_sub(self.recorded + self.pending.len())
+        });
+        if room == 0 {
+            return;
+        }
+        let mut bytes = std::mem::take(&mut self.pending);
+        bytes.extend_from_slice(&chunk[..room.min(chunk.len())]);
+        // Hold back a character split across reads so both halves stay text
+        if let Err(err) = std::str::from_utf8(&bytes) {
+            if err.error_len().is_none() {
+                self.pending = bytes.split_off(err.valid_up_to());
+            }
+        }
+        self.record(bytes);
+    }
+
+    fn finish(&mut self) {
+        let pending = std::mem::take(&mut self.pending);
+        self.record(pending);
+    }
+
+    fn record(&mut self, bytes: Vec<u8>) {
+        if bytes.is_empty() {
+            return;
+        }
+        self.recorded += bytes.len();
+        let mut events = self.timeline.events.lock().unwrap();
+        let (chunk, encoding) = encode(bytes);
+        events.push(TimelineEvent {
+            t_offset_ms: self.timeline.start.elapsed().as_micros() as f64 / 1000.0,
+            stream: self.stream.to_string(),
+            stage: self.stage,
+            chunk,
+            encoding,
+        });
+    }
+}
+
+/// A finished capture of one output stream
+pub struct CapturedStream {
+    /// Stored content; when truncated, head and tail joined by a marker
+    pub content: Vec<u8>,
+    /// Size, digest and truncation details of the full stream
+    pub info: StreamInfo,
+}
+
+/// Background reader accumulating one of the child's output streams
+pub struct Capture {
+    state: Arc<Mutex<StreamState>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Capture {
+    /// Start reading `stream`, keeping at most `limit` bytes of it (head and
+    /// tail), echoing everything to `tee` and recording the first `limit`
+    /// bytes in `timeline` if given
+    pub fn start<R: Read + Send + 'static>(
+        stream: Option<R>,
+        limit: Option<usize>,
+        mut tee: Option<Tee>,
+        mut timeline: Option<TimelineStream>,
+    ) -> Self {
+        let state = Arc::new(Mutex::new(StreamState::default()));
+        let handle = stream.map(|mut stream| {
+            let state = Arc::clone(&state);
+            thread::spawn(move || {
+                let mut chunk = [0u8; 8192];
+                loop {
+                    match stream.read(&mut chunk) {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => {
+                            state.lock().unwrap().push(&chunk[..n], limit);
+                            if let Some(timeline) = &mut timeline {
+                                timeline.push(&chunk[..n], limit);
+                            }
+                            // Losing the echo (e.g. a closed terminal) must not lose the capture
+                            if let Some(out) = &mut tee {
+                                if out
+                                    .write_all(&chunk[..n])
+                                    .and_then(|_| out.flush())
+                                    .is_err()
+                                {
+                                    tee = None;
+                                }
+                            }
+                        }
+                    }
+                }
+                if let Some(timeline) = &mut timeline {
+                    timeline.finish();
+                }
+            })
+        });
+        Capture { state, handle }
+    }
+
+    /// Collect everything read so far. Without a grace period this waits for
+    /// EOF; with one it gives up after the grace period and keeps what it has.
+    pub fn finish(mut self, grace: Option<Duration>) -> CapturedStream {
+        if let Some(handle) = self.handle.take() {
+            match grace {
+                None => {
+                    let _ = handle.join();
+                }
+                Some(grace) => {
+                    let give_up = Instant::now() + grace;
+                    while !handle.is_finished() && Instant::now() < give_up {
+                        thread::sleep(POLL_INTERVAL);
+                    }
+                    if handle.is_finished() {
+                        let _ = handle.join();
+                    }
+                }
+            }
+        }
+
+        let state = std::mem::take(&mut *self.state.lock().unwrap());
+        let mut head = state.head;
+        let mut tail = Vec::from(state.tail);
+        let truncated = state.total > (head.len() + tail.len()) as u64;
+        if truncated {
+            trim_split_chars(&mut head, &mut tail);
+        }
+        let stored = (head.len() + tail.len()) as u64;
+        let mut content = head;
+        if truncated {
+            let marker = format!("\n[... {} bytes truncated ...]\n", state.total - stored);
+            content.extend_from_slice(marker.as_bytes());
+        }
+        content.extend(tail);
+
+        CapturedStream {
+            content,
+            info: StreamInfo {
+                total_bytes: state.total,
+                truncated,
+                sha256: format!("{:x}", state.hasher.finalize()),
+            },
+        }
+    }
+}
+
+/// Drop the pieces of a UTF-8 character cut in two by truncation from the
+/// end of `head` and the start of `tail`, when that leaves both valid text
+fn trim_split_chars(head: &mut Vec<u8>, tail: &mut Vec<u8>) {
+    let head_end = match std::str::from_utf8(head) {
+        Ok(_) => head.len(),
+        Err(err) if err.error_len().is_none() => err.valid_up_to(),
+        Err(_) => return,
+    };
+    let tail_start = tail
+        .iter()
+        .take(3)
+        .take_while(|&&byte| byte & 0xC0 == 0x80)
+        .count();
+    if std::str::from_utf8(&tail[tail_start..]).is_ok() {
+        head.truncate(head_end);
+        tail.drain(..tail_start);
+    }
+}
+
+/// Captured bytes as receipt text: unchanged if they are valid UTF-8,
+/// base64 otherwise
+pub fn encode(content: Vec<u8>) -> (String, Encoding) {
+    match String::from_utf8(content) {
+        Ok(text) => (text, Encoding::Utf8),
+        Err(err) => (base64::encode(err.as_bytes()), Encoding::Base64),
+    }
+}
@@ -0,0 +1,210 @@
+use std::process::Command;
+
+use crate::receipt::ResourceUsage;
+
+/// A transient cgroup the command runs in, removed when dropped
+pub struct Cgroup(imp::Cgroup);
+
+impl Cgroup {
+    /// Create a cgroup below rcpt's own, if cgroup v2 is mounted and rcpt
+    /// may create one there (Linux only)
+    pub fn create() -> Option<Self> {
+        imp::Cgroup::create().map(Cgroup)
+    }
+
+    /// Arrange for the command to move itself into the cgroup before exec,
+    /// so nothing it starts can escape accounting
+    pub fn attach(&self, command: &mut Command) {
+        self.0.attach(command)
+    }
+
+    /// Whether the process `pid` actually joined the cgroup
+    pub fn contains(&self, pid: u32) -> bool {
+        self.0.contains(pid)
+    }
+
+    /// Read the cgroup's usage once everything in it has exited
+    pub fn usage(&self) -> ResourceUsage {
+        self.0.usage()
+    }
+
+    /// Number of processes in the cgroup the OOM killer killed, if the
+    /// memory controller is enabled
+    pub fn oom_kills(&self) -> Option<u64> {
+        self.0.oom_kills()
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::fs::{self, File, OpenOptions};
+    use std::os::unix::io::AsRawFd;
+    use std::os::unix::process::CommandExt;
+    use std::path::PathBuf;
+    use std::process::Command;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use crate::receipt::ResourceUsage;
+
+    /// How long to wait for killed processes to leave the cgroup before
+    /// giving up on removing it
+    const REMOVE_TIMEOUT: Duration = Duration::from_millis(200);
+
+    static NEXT_ID: AtomicU32 = AtomicU32::new(0);
+
+    pub struct Cgroup {
+        /// Path relative to the cgroup root, as in /proc/<pid>/cgroup
+        name: String,
+        dir: PathBuf,
+        procs: File,
+    }
+
+    impl Cgroup {
+        pub fn create() -> Option<Self> {
+            let (root, mount) = cgroup2_mount()?;
+            let own = fs::read_to_string("/proc/self/cgroup").ok()?;
+            let own = own.lines().find_map(|line| line.strip_prefix("0::"))?;
+            let name = format!(
+                "{}/rcpt-{}-{}",
+                own.trim_end_matches('/'),
+                std::process::id(),
+                NEXT_ID.fetch_add(1, Ordering::Relaxed)
+            );
+            let dir = mount.join(name.strip_prefix(root.as_str())?.trim_start_matches('/'));
+            fs::create_dir(&dir).ok()?;
+            match OpenOptions::new()
+                .write(true)
+                .open(dir.join("cgroup.procs"))
+            {
+                Ok(procs) => Some(Cgroup { name, dir, procs }),
+                Err(_) => {
+                    let _ = fs::remove_dir(&dir);
+                    None
+                }
+            }
+        }
+
+        pub fn attach(&self, command: &mut Command) {
+            let fd = self.procs.as_raw_fd();
+            // SAFETY: write(2) is async-signal-safe, and the descriptor stays
+            // open until the cgroup is dropped, after the child has spawned.
+            // A failed move is detected afterwards with `contains`.
+            unsafe {
+                command.pre_exec(move || {
+                    libc::write(fd, b"0".as_ptr().cast(), 1);
+                    Ok(())
+                });
+            }
+        }
+
+        pub fn contains(&self, pid: u32) -> bool {
+            fs::read_to_string(format!("/proc/{}/cgroup", pid)).is_ok_and(|cgroups| {
+                cgroups
+                    .lines()
+                    .any(|line| line.strip_prefix("0::") == Some(self.name.as_str()))
+            })
+        }
+
+        pub fn usage(&self) -> ResourceUsage {
+            let read = |file: &str| fs::read_to_string(self.dir.join(file)).ok();
+            let cpu = read("cpu.stat").unwrap_or_default();
+            let cpu_ms = |key: &str| stat(&cpu, key).unwrap_or(0) as f64 / 1000.0;
+            let io = read("io.stat");
+            let io_bytes = |key: &str| {
+                io.as_deref().map(|io| {
+                    // One line per device: "8:0 rbytes=... wbytes=... rios=..."
+                    io.lines()
+                        .flat_map(str::split_whitespace)
+                        .filter_map(|field| field.strip_prefix(key)?.strip_prefix('='))
+                        .filter_map(|value| value.parse::<u64>().ok())
+                        .sum()
+                })
+            };
+            ResourceUsage {
+                cgroup: Some(self.name.clone()),
+                cpu_user_ms: cpu_ms("user_usec"),
+                cpu_system_ms: cpu_ms("system_usec"),
+                memory_peak_bytes: read("memory.peak").and_then(|peak| peak.trim().parse().ok()),
+                io_read_bytes: io_bytes("rbytes"),
+                io_write_bytes: io_bytes("wbytes"),
+                processes: None,
+                max_rss_bytes: None,
+                minor_page_faults: None,
+                major_page_faults: None,
+                io_syscall_read_bytes: None,
+                io_syscall_write_bytes: None,
+            }
+        }
+
+        pub fn oom_kills(&self) -> Option<u64> {
+            let events = fs::read_to_string(self.dir.join("memory.events")).ok()?;
+            stat(&events, "oom_kill")
+        }
+    }
+
+    impl Drop for Cgroup {
+        fn drop(&mut self) {
+            // Killed processes take a moment to leave
+            let give_up = Instant::now() + REMOVE_TIMEOUT;
+            while fs::remove_dir(&self.dir).is_err() && Instant::now() < give_up {
+                thread::sleep(Duration::from_millis(5));
+            }
+        }
+    }
+
+    /// Value of `key` in a flat-keyed cgroup stat file
+    fn stat(contents: &str, key: &str) -> Option<u64> {
+        contents.lines().find_map(|line| {
+            let (name, value) = line.split_once(' ')?;
+            (name == key).then(|| value.trim().parse().ok())?
+        })
+    }
+
+    /// Root and mount point of the cgroup v2 hierarchy, from mountinfo
+    fn cgroup2_mount() -> Option<(String, PathBuf)> {
+        let mountinfo = fs::read_to_string("/proc/self/mountinfo").ok()?;
+        mountinfo.lines().find_map(|line| {
+            let (mount, filesystem) = line.split_once(" - ")?;
+            if filesystem.split(' ').next() != Some("cgroup2") {
+                return None;
+            }
+            let mut fields = mount.split(' ').skip(3);
+            let root = fields.next()?.to_string();
+            let mount_point = PathBuf::from(fields.next()?);
+            Some((root, mount_point))
+        })
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use std::process::Command;
+
+    use crate::receipt::ResourceUsage;
+
+    pub enum Cgroup {}
+
+    impl Cgroup {
+        pub fn create() -> Option<Self> {
+            None
+        }
+
+        pub fn attach(&self, _command: &mut Command) {
+            match *self {}
+        }
+
+        pub fn contains(&self, _pid: u32) -> bool {
+            match *self {}
+        }
+
+        pub fn usage(&self) -> ResourceUsage {
+            match *self {}
+        }
+
+        pub fn oom_kills(&self) -> Option<u64> {
+            match *self {}
+        }
+    }
+}
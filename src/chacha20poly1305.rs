@@ -0,0 +1,264 @@
+/// Length of the authentication tag after each ciphertext
+pub const TAG_LEN: usize = 16;
+
+/// Low 26 bits of a Poly1305 accumulator limb
+const MASK: u64 = (1 << 26) - 1;
+
+fn load32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// One 64-byte block of the ChaCha20 key stream (RFC 8439)
+fn block(key: &[u8; 32], counter: u32, nonce: &[u8; 12]) -> [u8; 64] {
+    let mut initial = [0u32; 16];
+    initial[..4].copy_from_slice(&[0x61707865, 0x3320646e, 0x79622d32, 0x6b206574]);
+    for i in 0..8 {
+        initial[4 + i] = load32(&key[4 * i..]);
+    }
+    initial[12] = counter;
+    for i in 0..3 {
+        initial[13 + i] = load32(&nonce[4 * i..]);
+    }
+
+    let mut state = initial;
+    let quarter = |s: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize| {
+        s[a] = s[a].wrapping_add(s[b]);
+        s[d] = (s[d] ^ s[a]).rotate_left(16);
+        s[c] = s[c].wrapping_add(s[d]);
+        s[b] = (s[b] ^ s[c]).rotate_left(12);
+        s[a] = s[a].wrapping_add(s[b]);
+        s[d] = (s[d] ^ s[a]).rotate_left(8);
+        s[c] = s[c].wrapping_add(s[d]);
+        s[b] = (s[b] ^ s[c]).rotate_left(7);
+    };
+    for _ in 0..10 {
+        quarter(&mut state, 0, 4, 8, 12);
+        quarter(&mut state, 1, 5, 9, 13);
+        quarter(&mut state, 2, 6, 10, 14);
+        quarter(&mut state, 3, 7, 11, 15);
+        quarter(&mut state, 0, 5, 10, 15);
+        quarter(&mut state, 1, 6, 11, 12);
+        quarter(&mut state, 2, 7, 8, 13);
+        quarter(&mut state, 3, 4, 9, 14);
+    }
+
+    let mut out = [0u8; 64];
+    for i in 0..16 {
+        let word = state[i].wrapping_add(initial[i]);
+        out[4 * i..4 * i + 4].copy_from_slice(&word.to_le_bytes());
+    }
+    out
+}
+
+/// XOR `data` with the key stream starting at block `counter`
+fn chacha20(key: &[u8; 32], counter: u32, nonce: &[u8; 12], data: &mut [u8]) {
+    for (index, chunk) in data.chunks_mut(64).enumerate() {
+        let stream = block(key, counter + index as u32, nonce);
+        for (byte, key_byte) in chunk.iter_mut().zip(stream) {
+            *byte ^= key_byte;
+        }
+    }
+}
+
+/// Poly1305 (RFC 8439) of `message`, whose length must be a multiple of 16,
+/// under the one-time `key`
+fn poly1305(key: &[u8; 32], message: &[u8]) -> [u8; TAG_LEN] {
+    let r = [
+        load32(&key[0..]) as u64 & 0x3ffffff,
+        (load32(&key[3..]) >> 2) as u64 & 0x3ffff03,
+        (load32(&key[6..]) >> 4) as u64 & 0x3ffc0ff,
+        (load32(&key[9..]) >> 6) as u64 & 0x3f03fff,
+        (load32(&key[12..]) >> 8) as u64 & 0x00fffff,
+    ];
+    let s = [r[1] * 5, r[2] * 5, r[3] * 5, r[4] * 5];
+    let mut h = [0u64; 5];
+
+    for chunk in message.chunks(16) {
+        h[0] += load32(&chunk[0..]) as u64 & MASK;
+        h[1] += (load32(&chunk[3..]) >> 2) as u64 & MASK;
+        h[2] += (load32(&chunk[6..]) >> 4) as u64 & MASK;
+        h[3] += (load32(&chunk[9..]) >> 6) as u64 & MASK;
+        h[4] += (load32(&chunk[12..]) >> 8) as u64 | 1 << 24;
+
+        // h *= r, with limbs past the fifth wrapping around times 5 as
+        // 2^130 = 5 modulo 2^130 - 5
+        let d = [
+            h[0] * r[0] + h[1] * s[3] + h[2] * s[2] + h[3] * s[1] + h[4] * s[0],
+            h[0] * r[1] + h[1] * r[0] + h[2] * s[3] + h[3] * s[2] + h[4] * s[1],
+            h[0] * r[2] + h[1] * r[1] + h[2] * r[0] + h[3] * s[3] + h[4] * s[2],
+            h[0] * r[3] + h[1] * r[2] + h[2] * r[1] + h[3] * r[0] + h[4] * s[3],
+            h[0] * r[4] + h[1] * r[3] + h[2] * r[2] + h[3] * r[1] + h[4] * r[0],
+        ];
+        let mut carry = 0;
+        for i in 0..5 {
+            let value = d[i] + carry;
+            h[i] = value & MASK;
+            carry = value >> 26;
+        }
+        h[0] += carry * 5;
+        h[1] += h[0] >> 26;
+        h[0] &= MASK;
+    }
+
+    // Fully reduce h, then subtract the modulus if h is at least 2^130 - 5
+    let mut carry = 0;
+    for limb in &mut h[1..] {
+        *limb += carry;
+        carry = *limb >> 26;
+        *limb &= MASK;
+    }
+    h[0] += carry * 5;
+    h[1] += h[0] >> 26;
+    h[0] &= MASK;
+    let mut g = [0u64; 5];
+    let mut carry = 5;
+    for i in 0..5 {
+        g[i] = h[i] + carry;
+        carry = g[i] >> 26;
+        g[i] &= MASK;
+    }
+    // carry is 1 exactly when h + 5 reached 2^130
+    let use_g = carry.wrapping_neg();
+    for i in 0..5 {
+        h[i] = (h[i] & !use_g) | (g[i] & use_g);
+    }
+
+    // h + s modulo 2^128, s being the key's second half
+    let value = h.iter().enumerate().fold(0u128, |value, (i, &limb)| {
+        value.wrapping_add((limb as u128) << (26 * i))
+    });
+    let value = value.wrapping_add(u128::from_le_bytes(key[16..].try_into().unwrap()));
+    value.to_le_bytes()
+}
+
+/// Poly1305 input: the associated data, then the ciphertext, each padded
+/// to 16 bytes, then both their lengths
+fn mac_input(aad: &[u8], ciphertext: &[u8]) -> Vec<u8> {
+    let mut input = Vec::new();
+    for part in [aad, ciphertext] {
+        input.extend_from_slice(part);
+        input.resize(input.len().div_ceil(16) * 16, 0);
+    }
+    input.extend_from_slice(&(aad.len() as u64).to_le_bytes());
+    input.extend_from_slice(&(ciphertext.len() as u64).to_le_bytes());
+    input
+}
+
+fn one_time_key(key: &[u8; 32], nonce: &[u8; 12]) -> [u8; 32] {
+    let mut otk = [0u8; 32];
+    otk.copy_from_slice(&block(key, 0, nonce)[..32]);
+    otk
+}
+
+/// Encrypt `plaintext`, returning the ciphertext followed by its tag
+pub fn seal(key: &[u8; 32], nonce: &[u8; 12], plaintext: &[u8]) -> Vec<u8> {
+    seal_with(key, nonce, &[], plaintext)
+}
+
+/// `seal`, authenticating `aad` along with the ciphertext
+fn seal_with(key: &[u8; 32], nonce: &[u8; 12], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let mut sealed = plaintext.to_vec();
+    chacha20(key, 1, nonce, &mut sealed);
+    let tag = poly1305(&one_time_key(key, nonce), &mac_input(aad, &sealed));
+    sealed.extend_from_slice(&tag);
+    sealed
+}
+
+/// Decrypt `sealed` as produced by `seal`, or `None` if its tag does not
+/// match
+pub fn open(key: &[u8; 32], nonce: &[u8; 12], sealed: &[u8]) -> Option<Vec<u8>> {
+    open_with(key, nonce, &[], sealed)
+}
+
+/// `open`, for a ciphertext sealed with `aad`
+fn open_with(key: &[u8; 32], nonce: &[u8; 12], aad: &[u8], sealed: &[u8]) -> Option<Vec<u8>> {
+    let split = sealed.len().checked_sub(TAG_LEN)?;
+    let (ciphertext, tag) = sealed.split_at(split);
+    let expected = poly1305(&one_time_key(key, nonce), &mac_input(aad, ciphertext));
+    // Compare in constant time
+    let diff = tag
+        .iter()
+        .zip(expected)
+        .fold(0, |diff, (a, b)| diff | (a ^ b));
+    if diff != 0 {
+        return None;
+    }
+    let mut plaintext = ciphertext.to_vec();
+    chacha20(key, 1, nonce, &mut plaintext);
+    Some(plaintext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(text: &str) -> Vec<u8> {
+        (0..text.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    const PLAINTEXT: &[u8] =
+        b"Ladies and Gentlemen of the class of '99: If I could offer you only \
+        one tip for the future, sunscreen would be it.";
+
+    // RFC 8439 §2.8.2
+    fn rfc_8439() -> ([u8; 32], [u8; 12], Vec<u8>, Vec<u8>) {
+        let mut key = [0u8; 32];
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = 0x80 + i as u8;
+        }
+        let nonce = hex("070000004041424344454647").try_into().unwrap();
+        let aad = hex("50515253c0c1c2c3c4c5c6c7");
+        let sealed = hex(concat!(
+            "d31a8d34648e60db7b86afbc53ef7ec2a4aded51296e08fea9e2b5a736ee62d6",
+            "3dbea45e8ca9671282fafb69da92728b1a71de0a9e060b2905d6a5b67ecd3b36",
+            "92ddbd7f2d778b8c9803aee328091b58fab324e4fad675945585808b4831d7bc",
+            "3ff4def08e4b7a9de576d26586cec64b6116",
+            // The tag
+            "1ae10b594f09e26a7e902ecbd0600691",
+        ));
+        (key, nonce, aad, sealed)
+    }
+
+    #[test]
+    fn seals_the_rfc_8439_vector() {
+        let (key, nonce, aad, sealed) = rfc_8439();
+        assert_eq!(seal_with(&key, &nonce, &aad, PLAINTEXT), sealed);
+    }
+
+    #[test]
+    fn opens_the_rfc_8439_vector() {
+        let (key, nonce, aad, sealed) = rfc_8439();
+        assert_eq!(
+            open_with(&key, &nonce, &aad, &sealed).as_deref(),
+            Some(PLAINTEXT)
+        );
+    }
+
+    #[test]
+    fn rejects_tampering() {
+        let (key, nonce, aad, sealed) = rfc_8439();
+        for i in [0, sealed.len() - TAG_LEN, sealed.len() - 1] {
+            let mut tampered = sealed.clone();
+            tampered[i] ^= 1;
+            assert_eq!(open_with(&key, &nonce, &aad, &tampered), None, "byte {}", i);
+        }
+        assert_eq!(open_with(&key, &nonce, b"", &sealed), None);
+        assert_eq!(open_with(&key, &[0; 12], &aad, &sealed), None);
+        assert_eq!(open_with(&[0; 32], &nonce, &aad, &sealed), None);
+        assert_eq!(open_with(&key, &nonce, &aad, &sealed[..TAG_LEN - 1]), None);
+    }
+
+    #[test]
+    fn round_trips_without_associated_data() {
+        let key = [7u8; 32];
+        for len in [0, 1, 15, 16, 17, 64, 65, 1000] {
+            let plaintext = vec![0x5a; len];
+            let sealed = seal(&key, &[1; 12], &plaintext);
+            assert_eq!(sealed.len(), len + TAG_LEN);
+            assert_eq!(open(&key, &[1; 12], &sealed), Some(plaintext));
+        }
+    }
+}
@@ -0,0 +1,2542 @@
+use anyhow::Result;
+use clap::{Args, Parser, Subcommand};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::{
+    access, age, atomic, attestation, batch, bench, bundle, capture, compare, config, container,
+    cron, detach, diff, env, exec, export, gc, id, import, index, ledger, limits, list, metadata,
+    objects, pipeline, proctree, receipt, redact, remote, replay, report, sandbox, sarif, sched,
+    schedule, schema, search, session, show, signals, signing, sink, stats, stdin, tags, tail,
+    template, timestamp, units, user, watch, zstd,
+};
+
+use access::PathRules;
+use capture::BinaryOutput;
+use config::{Config, PatternConfig, RedactConfig};
+use container::{ContainerSpec, Mount, Runtime};
+use cron::CronSchedule;
+use env::{EnvFile, EnvPolicy, EnvValues};
+use exec::{
+    execute_with_retries, finalize_sinks, save_receipt, with_retries, ExecOptions, Invocation,
+    RetryPolicy,
+};
+use index::Index;
+use ledger::Ledger;
+use limits::ResourceLimits;
+use list::{Filter, StatusFilter};
+use objects::ObjectStore;
+use receipt::{ActorRecord, Receipt, Status};
+use redact::Redactor;
+use sandbox::Sandbox;
+use sched::{CpuList, Scheduling};
+use signing::{Signer, TrustedSigners};
+use sink::{Delivery, FileSink, OnFailure, ReceiptSink, RemoteSink};
+use stdin::StdinMode;
+use template::Names;
+use timestamp::Tsa;
+use user::RunAs;
+
+/// Exit code used when the wrapped command was killed for exceeding its timeout
+const TIMEOUT_EXIT_CODE: i32 = 124;
+
+/// Exit code for a command that succeeded without producing a required
+/// output
+const MISSING_OUTPUTS_EXIT_CODE: i32 = 1;
+
+/// Command wrapper that emits execution receipts as JSON
+#[derive(Parser, Debug)]
+#[command(name = "rcpt")]
+#[command(about = "Execute commands and emit execution receipts as JSON")]
+struct Cli {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Run a command and emit an execution receipt
+    Run(RunArgs),
+    /// Wait for a command started with `run --detach` to finish
+    Wait(WaitArgs),
+    /// Run commands as a pipeline, each feeding its stdout to the next
+    Pipe(PipeArgs),
+    /// Run a command repeatedly and record duration statistics
+    Bench(BenchArgs),
+    /// Run a command again whenever watched files change
+    Watch(WatchArgs),
+    /// Run a command on a cron schedule, emitting a receipt per run
+    Schedule(ScheduleArgs),
+    /// Run many commands concurrently, emitting a receipt for each
+    Batch(BatchArgs),
+    /// Run a command on another machine over ssh, emitting a local receipt
+    Ssh(SshArgs),
+    /// Group the receipts made in a shell under one session id
+    Session(SessionArgs),
+    /// Create an Ed25519 key pair for signing receipts with --sign
+    Keygen(KeygenArgs),
+    /// Check a receipt's digest and signature
+    Verify(VerifyArgs),
+    /// Print the JSON Schema of the receipt format
+    Schema(SchemaArgs),
+    /// Check receipts against the JSON Schema of the version each declares
+    Validate(ValidateArgs),
+    /// Add every receipt in some directories to a SQLite index
+    Index(IndexArgs),
+    /// Delete old receipts, and objects no receipt refers to, by a
+    /// retention policy
+    Gc(GcArgs),
+    /// Delete the receipts picked out by when they ran, status, tag,
+    /// command or session
+    Prune(PruneArgs),
+    /// Mask secrets found in a receipt after it was written, digesting it
+    /// again and, given a key, signing it again
+    Redact(RedactArgs),
+    /// Package receipts, with their output and the objects they refer to,
+    /// into one archive with a signed manifest
+    Bundle(BundleArgs),
+    /// Turn another tool's records of what ran, such as a GitHub workflow
+    /// run or a shell history, into receipts marked as imported
+    Import(ImportArgs),
+    /// Send the receipts a --sink spooled when it could not deliver them
+    Flush(FlushArgs),
+    /// List receipts, oldest first, picked out by when they ran, status,
+    /// tag or command
+    List(ListArgs),
+    /// Print the id, path or JSON of the latest receipt, of those picked out
+    /// by when they ran, status, tag, command or session
+    Last(LastArgs),
+    /// Show a receipt: what ran, how it ended, what it used and the end of
+    /// its output
+    Show(ShowArgs),
+    /// Compare two receipts: what ran, how it ended, how long it took and
+    /// what it wrote
+    Diff(DiffArgs),
+    /// Check a run against a baseline receipt, as a golden file, failing if
+    /// its exit code, output or duration regressed
+    Compare(CompareArgs),
+    /// Find receipts whose stdout or stderr has a line matching a pattern,
+    /// oldest first, with the lines around each match
+    Search(SearchArgs),
+    /// Count runs and failures, and summarize durations, overall or by
+    /// command, tag or day
+    Stats(StatsArgs),
+    /// Print a line for each of the latest receipts, and with --follow for
+    /// each new one as it lands
+    Tail(TailArgs),
+    /// Export receipts for other tools, oldest first
+    Export(ExportArgs),
+    /// Render receipts as a static page to share: a timeline of the runs,
+    /// and each one's details and output
+    Report(ReportArgs),
+    /// Run a receipt's command again, as it was run, emitting a new receipt
+    /// that refers back to it
+    Replay(ReplayArgs),
+}
+
+#[derive(Args, Debug)]
+struct RunArgs {
+    /// Output path for the receipt JSON file, or a directory to name it in
+    /// [default: receipt-<id>.json]
+    #[arg(short, long)]
+    out: Option<PathBuf>,
+
+    /// Name the receipt after this template, in the --out directory if
+    /// given, e.g. '{date}/{tag}/{id}.json', with {id}, {date}, {time},
+    /// {command}, {status}, {exit_code}, {tag} and {tags}
+    #[arg(long, value_name = "TEMPLATE")]
+    out_template: Option<String>,
+
+    #[command(flatten)]
+    exec: ExecArgs,
+
+    /// Start the command in the background and return once it is running,
+    /// leaving a receipt with status "running" to be finalized when it exits
+    #[arg(long, conflicts_with_all = ["dry_run", "pty"])]
+    detach: bool,
+
+    /// Run this command string through the shell ($SHELL -c, or cmd /C on Windows)
+    #[arg(long, value_name = "COMMAND", conflicts_with = "command")]
+    shell: Option<String>,
+
+    /// Command to execute
+    #[arg(required_unless_present = "shell", trailing_var_arg = true)]
+    command: Vec<OsString>,
+}
+
+#[derive(Args, Debug)]
+struct SshArgs {
+    /// Output path for the receipt JSON file, or a directory to name it in
+    /// [default: receipt-<id>.json]
+    #[arg(short, long)]
+    out: Option<PathBuf>,
+
+    /// Name the receipt after this template, in the --out directory if
+    /// given, e.g. '{date}/{tag}/{id}.json', with {id}, {date}, {time},
+    /// {command}, {status}, {exit_code}, {tag} and {tags}
+    #[arg(long, value_name = "TEMPLATE")]
+    out_template: Option<String>,
+
+    /// Machine to run the command on, as [user@]host
+    destination: String,
+
+    /// Pass this option to ssh as -o (repeatable, e.g. Port=2222)
+    #[arg(long, value_name = "OPTION")]
+    ssh_option: Vec<String>,
+
+    #[command(flatten)]
+    exec: ExecArgs,
+
+    /// Run this command string through the remote user's shell as is
+    #[arg(long, value_name = "COMMAND", conflicts_with = "command")]
+    shell: Option<String>,
+
+    /// Command to execute remotely
+    #[arg(required_unless_present = "shell", trailing_var_arg = true)]
+    command: Vec<OsString>,
+}
+
+#[derive(Args, Debug)]
+struct SessionArgs {
+    #[command(subcommand)]
+    command: SessionCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum SessionCommand {
+    /// Start a session, printing a shell command that exports its id; use
+    /// as `eval "$(rcpt session start)"`
+    Start,
+    /// End the current session, printing a shell command that unsets its
+    /// id; use as `eval "$(rcpt session end)"`
+    End,
+    /// Summarize every receipt made in a session
+    Show(SessionShowArgs),
+}
+
+#[derive(Args, Debug)]
+struct SessionShowArgs {
+    /// Session id [default: the current session, from $RCPT_SESSION]
+    id: Option<String>,
+
+    /// Directory to search for receipts, recursively (repeatable)
+    /// [default: .]
+    #[arg(long = "dir", value_name = "DIR")]
+    dirs: Vec<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct KeygenArgs {
+    /// Path for the private key; the public key goes to `<path>.pub`
+    #[arg(short, long, default_value = "rcpt.key")]
+    out: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct IndexArgs {
+    /// Index to update [default: $RCPT_INDEX, else .receipts/index.db]
+    #[arg(long, value_name = "DB")]
+    db: Option<PathBuf>,
+
+    /// Directory to search for receipts, recursively (repeatable)
+    /// [default: .]
+    #[arg(long = "dir", value_name = "DIR")]
+    dirs: Vec<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct ListArgs {
+    #[command(flatten)]
+    select: SelectArgs,
+
+    /// Print a JSON array instead of a table
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Args, Debug)]
+struct LastArgs {
+    #[command(flatten)]
+    select: SelectArgs,
+
+    /// What to print of the receipt
+    #[arg(long, value_enum, default_value_t = list::Print::Path)]
+    print: list::Print,
+}
+
+#[derive(Args, Debug)]
+struct SearchArgs {
+    /// Text to look for in each line of output
+    pattern: String,
+
+    /// Take the pattern as a regular expression
+    #[arg(long)]
+    regex: bool,
+
+    /// Match letters in either case
+    #[arg(short, long)]
+    ignore_case: bool,
+
+    /// How many lines to show before and after each match
+    #[arg(short = 'C', long, value_name = "N", default_value_t = search::DEFAULT_CONTEXT)]
+    context: usize,
+
+    #[command(flatten)]
+    select: SelectArgs,
+}
+
+#[derive(Args, Debug)]
+struct StatsArgs {
+    /// Report on each command, tag or day separately
+    #[arg(long, value_enum, value_name = "KEY")]
+    group_by: Option<stats::GroupBy>,
+
+    /// Print a JSON array instead of a table
+    #[arg(long)]
+    json: bool,
+
+    #[command(flatten)]
+    select: SelectArgs,
+}
+
+#[derive(Args, Debug)]
+struct TailArgs {
+    /// Keep watching, printing each new receipt as it lands, until
+    /// interrupted
+    #[arg(short, long)]
+    follow: bool,
+
+    /// How many of the latest receipts to print first
+    #[arg(short = 'n', long, value_name = "N", default_value_t = tail::DEFAULT_LINES)]
+    lines: usize,
+
+    /// Directory to watch for receipts, recursively (repeatable)
+    /// [default: .]
+    #[arg(long = "dir", value_name = "DIR")]
+    dirs: Vec<PathBuf>,
+
+    /// Watch this ledger for new entries instead of directories
+    #[arg(long, value_name = "PATH", conflicts_with = "dirs")]
+    ledger: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct ExportArgs {
+    /// What to export the receipts as
+    #[arg(long, value_enum)]
+    format: export::Format,
+
+    /// Columns of the CSV, comma-separated, from id, cmd, status,
+    /// exit_code, signal, duration_ms, started_at, ended_at, cwd, tags,
+    /// session_id, parent_id, hostname, cpu_user_ms, cpu_system_ms,
+    /// max_rss_bytes, env_digest, receipt_digest and path
+    #[arg(
+        long,
+        value_name = "FIELDS",
+        value_delimiter = ',',
+        default_value = export::DEFAULT_FIELDS
+    )]
+    fields: Vec<export::Field>,
+
+    /// Write the export to this file instead of stdout
+    #[arg(short, long, value_name = "PATH")]
+    out: Option<PathBuf>,
+
+    #[command(flatten)]
+    select: SelectArgs,
+}
+
+#[derive(Args, Debug)]
+struct ReportArgs {
+    /// Write the report as a self-contained HTML page to this file
+    #[arg(long, value_name = "PATH")]
+    html: PathBuf,
+
+    #[command(flatten)]
+    select: SelectArgs,
+}
+
+#[derive(Args, Debug)]
+struct ReplayArgs {
+    /// The receipt to replay: a path, an id or the start of one, or `last`
+    /// for the latest run
+    #[arg(value_name = "ID|PATH|last")]
+    target: String,
+
+    /// Compare the replay's exit code and output digests with the
+    /// original's, and exit nonzero if they differ, not with the command's
+    /// exit code
+    #[arg(long, conflicts_with = "dry_run")]
+    check: bool,
+
+    /// Output path for the new receipt JSON file, or a directory to name it
+    /// in [default: receipt-<id>.json]
+    #[arg(short, long)]
+    out: Option<PathBuf>,
+
+    /// Directory to search for receipts by id, recursively (repeatable)
+    /// [default: .]
+    #[arg(long = "dir", value_name = "DIR")]
+    dirs: Vec<PathBuf>,
+
+    #[command(flatten)]
+    exec: ExecArgs,
+}
+
+/// Which receipts a command reads, and where it finds them
+#[derive(Args, Debug)]
+struct SelectArgs {
+    /// Only runs that started less than this long ago (e.g. 24h, 7days)
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    since: Option<Duration>,
+
+    /// Only runs with this status, or `fail` for any that finished without
+    /// succeeding (repeatable)
+    #[arg(long, value_name = "STATUS")]
+    status: Vec<StatusFilter>,
+
+    /// Only receipts with this tag (repeatable; all must match)
+    #[arg(long, value_name = "TAG")]
+    tag: Vec<String>,
+
+    /// Only runs whose command line contains this text
+    #[arg(long, value_name = "TEXT")]
+    cmd: Option<String>,
+
+    /// Only runs in this session
+    #[arg(long, value_name = "ID")]
+    session: Option<String>,
+
+    /// Directory to search for receipts, recursively (repeatable)
+    /// [default: .]
+    #[arg(long = "dir", value_name = "DIR")]
+    dirs: Vec<PathBuf>,
+
+    /// Find the receipts through a SQLite index instead of searching
+    /// directories: --index=<DB>, or `.receipts/index.db` if no DB is
+    /// given. Needs sqlite3
+    #[arg(
+        long,
+        value_name = "DB",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = index::DEFAULT_PATH,
+        conflicts_with = "dirs"
+    )]
+    index: Option<PathBuf>,
+}
+
+impl SelectArgs {
+    /// The receipts picked out, oldest first
+    fn receipts(self) -> Result<Vec<(PathBuf, Receipt)>> {
+        let filter = self.filter()?;
+        match self.index()? {
+            Some(index) => {
+                let paths = index.select(&filter)?.into_iter().map(|row| row.path);
+                Ok(list::pick(paths.collect(), &filter))
+            }
+            None => list::find(&self.dirs(), &filter),
+        }
+    }
+
+    /// What is listed of the receipts picked out, oldest first, from the
+    /// index without reading the receipts if there is one
+    fn summaries(self) -> Result<Vec<list::Summary>> {
+        let filter = self.filter()?;
+        match self.index()? {
+            Some(index) => index.select(&filter),
+            None => Ok(list::find(&self.dirs(), &filter)?
+                .iter()
+                .map(|(path, receipt)| list::Summary::of(path, receipt))
+                .collect()),
+        }
+    }
+
+    fn filter(&self) -> Result<Filter> {
+        Ok(Filter {
+            since: match self.since {
+                Some(since) => Some(chrono::Utc::now() - chrono::Duration::from_std(since)?),
+                None => None,
+            },
+            before: None,
+            statuses: self.status.clone(),
+            tags: self.tag.clone(),
+            command: self.cmd.clone(),
+            session: self.session.clone(),
+        })
+    }
+
+    fn index(&self) -> Result<Option<Index>> {
+        let Some(db) = &self.index else {
+            return Ok(None);
+        };
+        if !db.exists() {
+            anyhow::bail!("No index at {}; build one with `rcpt index`", db.display());
+        }
+        Ok(Some(Index::open(db)?))
+    }
+
+    fn dirs(&self) -> Vec<PathBuf> {
+        match self.dirs.is_empty() {
+            true => vec![PathBuf::from(".")],
+            false => self.dirs.clone(),
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+struct ShowArgs {
+    /// The receipt: a path, an id or the start of one, or `last` for the
+    /// latest run
+    #[arg(value_name = "ID|PATH|last")]
+    target: String,
+
+    /// Print the receipt's JSON as it is stored, with its output undecoded
+    #[arg(long)]
+    json: bool,
+
+    /// How many of the last lines of stdout and stderr to show, or 0 for
+    /// all of them
+    #[arg(long, value_name = "N", default_value_t = show::DEFAULT_LINES)]
+    lines: usize,
+
+    /// Print straight to stdout, not through $PAGER
+    #[arg(long)]
+    no_pager: bool,
+
+    /// Directory to search for receipts by id, recursively (repeatable)
+    /// [default: .]
+    #[arg(long = "dir", value_name = "DIR")]
+    dirs: Vec<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct DiffArgs {
+    /// The receipt to compare from: a path, an id or the start of one, or
+    /// `last` for the latest run
+    #[arg(value_name = "A")]
+    a: String,
+
+    /// The receipt to compare to, named the same way
+    #[arg(value_name = "B")]
+    b: String,
+
+    /// Directory to search for receipts by id, recursively (repeatable)
+    /// [default: .]
+    #[arg(long = "dir", value_name = "DIR")]
+    dirs: Vec<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct CompareArgs {
+    /// The run to check: a path, an id or the start of one, or `last` for
+    /// the latest run
+    #[arg(value_name = "ID|PATH|last")]
+    candidate: String,
+
+    /// The receipt to check it against, named the same way
+    #[arg(
+        long,
+        value_name = "ID|PATH",
+        required_unless_present = "baseline_file"
+    )]
+    baseline: Option<String>,
+
+    /// The receipt file to check it against, such as a golden file kept
+    /// in the repository
+    #[arg(long, value_name = "PATH", conflicts_with = "baseline")]
+    baseline_file: Option<PathBuf>,
+
+    /// How much longer than the baseline the run may take, in percent of
+    /// the baseline's duration
+    #[arg(long, value_name = "PERCENT", default_value_t = compare::DEFAULT_TOLERANCE)]
+    tolerance: f64,
+
+    /// How much longer than the baseline the run may take in any case,
+    /// however short the baseline
+    #[arg(long, value_name = "MS", default_value_t = compare::DEFAULT_TOLERANCE_MS)]
+    tolerance_ms: u64,
+
+    /// Leave a check out (repeatable)
+    #[arg(long, value_enum, value_name = "CHECK")]
+    ignore: Vec<compare::Check>,
+
+    /// Print the report as JSON
+    #[arg(long)]
+    json: bool,
+
+    /// Directory to search for receipts by id, recursively (repeatable)
+    /// [default: .]
+    #[arg(long = "dir", value_name = "DIR")]
+    dirs: Vec<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct GcArgs {
+    /// Keep receipts of runs that started less than this many days ago
+    #[arg(long, value_name = "DAYS")]
+    keep_days: Option<u64>,
+
+    /// Keep the receipts of this many of the most recent runs
+    #[arg(long, value_name = "N")]
+    keep_last: Option<usize>,
+
+    /// Keep receipts of runs that did not succeed
+    #[arg(long)]
+    keep_failed: bool,
+
+    /// Directory to search for receipts, recursively (repeatable)
+    /// [default: .]
+    #[arg(long = "dir", value_name = "DIR")]
+    dirs: Vec<PathBuf>,
+
+    /// Ledger whose entries' objects are kept, as `rcpt run --ledger` wrote
+    /// them (repeatable)
+    #[arg(long = "ledger", value_name = "PATH")]
+    ledgers: Vec<PathBuf>,
+
+    /// Object store to delete unreferenced objects from, besides those the
+    /// receipts name (repeatable) [default: .receipts/objects]
+    #[arg(long = "object-store", value_name = "DIR")]
+    object_stores: Vec<PathBuf>,
+
+    /// List what would be deleted without deleting anything
+    #[arg(long)]
+    dry_run: bool,
+}
+
+#[derive(Args, Debug)]
+struct PruneArgs {
+    /// Only runs that started at least this long ago (e.g. 24h, 7days)
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    older_than: Option<Duration>,
+
+    /// Only runs with this status, or `fail` for any that finished without
+    /// succeeding (repeatable)
+    #[arg(long, value_name = "STATUS")]
+    status: Vec<StatusFilter>,
+
+    /// Only receipts with this tag (repeatable; all must match)
+    #[arg(long, value_name = "TAG")]
+    tag: Vec<String>,
+
+    /// Only runs whose command line contains this text
+    #[arg(long, value_name = "TEXT")]
+    cmd: Option<String>,
+
+    /// Only runs in this session
+    #[arg(long, value_name = "ID")]
+    session: Option<String>,
+
+    /// Directory to search for receipts, recursively (repeatable)
+    /// [default: .]
+    #[arg(long = "dir", value_name = "DIR")]
+    dirs: Vec<PathBuf>,
+
+    /// List what would be deleted without deleting anything
+    #[arg(long)]
+    dry_run: bool,
+}
+
+#[derive(Args, Debug)]
+struct RedactArgs {
+    /// The receipt to redact: a path, an id or the start of one, or `last`
+    /// for the latest run
+    #[arg(value_name = "ID|PATH|last")]
+    target: String,
+
+    /// Regular expression matching a secret to mask, replaced by
+    /// `[REDACTED:manual]` (repeatable)
+    #[arg(long, value_name = "REGEX")]
+    pattern: Vec<String>,
+
+    /// Also mask what `rcpt run` would have: the built-in patterns and the
+    /// config file's
+    #[arg(long)]
+    configured: bool,
+
+    /// Read the redaction patterns for --configured from this JSON file
+    #[arg(long, value_name = "PATH", requires = "configured")]
+    config: Option<PathBuf>,
+
+    /// Who is recorded as having redacted the receipt [default: the
+    /// current user]
+    #[arg(long, value_name = "NAME")]
+    by: Option<String>,
+
+    /// Directory to search for receipts by id, recursively (repeatable)
+    /// [default: .]
+    #[arg(long = "dir", value_name = "DIR")]
+    dirs: Vec<PathBuf>,
+
+    /// Sign the rewritten receipt with this Ed25519 private key
+    #[arg(long, value_name = "KEYFILE")]
+    sign: Option<PathBuf>,
+
+    /// Sign the rewritten receipt with this SSH key through ssh-keygen
+    #[arg(long, value_name = "KEYFILE", conflicts_with = "sign")]
+    sign_ssh: Option<PathBuf>,
+
+    /// Sign the rewritten receipt without a long-lived key, through cosign
+    #[arg(long, conflicts_with_all = ["sign", "sign_ssh"])]
+    sign_keyless: bool,
+
+    /// Write the signature to `<receipt>.sig` instead of into the receipt
+    #[arg(long)]
+    sign_detached: bool,
+
+    /// Get a new RFC 3161 timestamp over the rewritten receipt's digest
+    /// from this time-stamping authority
+    #[arg(long, value_name = "TSA")]
+    timestamp_url: Option<String>,
+}
+
+#[derive(Args, Debug)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct BundleArgs {
+    #[command(subcommand)]
+    command: Option<BundleCommand>,
+
+    #[command(flatten)]
+    select: SelectArgs,
+
+    /// Path of the archive: a `.tar.zst` is compressed with zstd, anything
+    /// else is a plain tar file
+    #[arg(short, long, value_name = "PATH", required = true)]
+    out: Option<PathBuf>,
+
+    /// Sign the manifest with this Ed25519 private key
+    #[arg(long, value_name = "KEYFILE")]
+    sign: Option<PathBuf>,
+
+    /// Sign the manifest with this SSH key through ssh-keygen
+    #[arg(long, value_name = "KEYFILE", conflicts_with = "sign")]
+    sign_ssh: Option<PathBuf>,
+
+    /// Sign the manifest without a long-lived key, through cosign
+    #[arg(long, conflicts_with_all = ["sign", "sign_ssh"])]
+    sign_keyless: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum BundleCommand {
+    /// Check a bundle's files against its manifest, and the manifest's
+    /// signature
+    Verify(BundleVerifyArgs),
+}
+
+#[derive(Args, Debug)]
+struct BundleVerifyArgs {
+    /// The bundle to check
+    bundle: PathBuf,
+
+    /// Require an Ed25519 signature made with this PEM public key
+    #[arg(long, value_name = "PUBKEY", conflicts_with = "allowed_signers")]
+    key: Option<PathBuf>,
+
+    /// Require an SSH signature by a signer listed in this allowed_signers
+    /// file
+    #[arg(long, value_name = "FILE", conflicts_with = "certificate_identity")]
+    allowed_signers: Option<PathBuf>,
+
+    /// Require a Sigstore keyless signature certified for this identity
+    #[arg(long, value_name = "IDENTITY", requires = "certificate_oidc_issuer")]
+    certificate_identity: Option<String>,
+
+    /// OIDC issuer that must have vouched for --certificate-identity
+    #[arg(long, value_name = "URL", requires = "certificate_identity")]
+    certificate_oidc_issuer: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct ImportArgs {
+    /// What kind of record the file is
+    #[arg(long, value_enum)]
+    format: import::Format,
+
+    /// The file to import
+    file: PathBuf,
+
+    /// Directory to write the receipts to
+    #[arg(short, long, default_value = ".")]
+    out_dir: PathBuf,
+
+    /// Label every receipt with this tag (repeatable)
+    #[arg(long, value_name = "TAG", value_parser = tags::parse_tag)]
+    tag: Vec<String>,
+
+    /// Read settings such as redaction patterns from this JSON file
+    /// [default: $RCPT_CONFIG, else rcpt/config.json in the user config
+    /// directory]
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Record the imported commands verbatim, without redacting secrets
+    #[arg(long)]
+    no_redact: bool,
+}
+
+#[derive(Args, Debug)]
+struct FlushArgs {
+    /// Where to send them: s3://bucket/prefix, file:///path, an
+    /// http(s):// endpoint, syslog://[host[:port]] or journald://
+    /// (repeatable)
+    #[arg(long, value_name = "URL", required = true)]
+    sink: Vec<String>,
+
+    /// Header to send to an http(s) sink, as `Name: value` (repeatable)
+    #[arg(long = "sink-header", value_name = "HEADER")]
+    sink_headers: Vec<String>,
+
+    /// Log whole receipts to a syslog:// or journald:// sink, not just
+    /// their summaries
+    #[arg(long)]
+    sink_full: bool,
+
+    /// How many times to retry a failed delivery, backing off
+    #[arg(long, value_name = "N", default_value_t = sink::DEFAULT_RETRIES)]
+    sink_retries: u32,
+
+    /// Longest each delivery attempt may take
+    #[arg(
+        long,
+        value_name = "DURATION",
+        default_value = sink::DEFAULT_TIMEOUT,
+        value_parser = humantime::parse_duration
+    )]
+    sink_timeout: Duration,
+
+    /// Spool directory the receipts wait in
+    #[arg(long, value_name = "DIR", default_value = sink::DEFAULT_SPOOL)]
+    spool: PathBuf,
+
+    /// Read sink settings from this JSON file [default: $RCPT_CONFIG, else
+    /// rcpt/config.json in the user config directory]
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct SchemaArgs {
+    /// Receipt version to describe [default: the version rcpt writes]
+    #[arg(long, value_name = "N")]
+    version: Option<u32>,
+}
+
+#[derive(Args, Debug)]
+struct ValidateArgs {
+    /// Receipts to check, or directories to check every receipt under,
+    /// recursively
+    #[arg(required = true, value_name = "PATH")]
+    paths: Vec<PathBuf>,
+
+    /// Count fields the schema does not know as making a receipt invalid
+    #[arg(long)]
+    strict: bool,
+}
+
+#[derive(Args, Debug)]
+struct VerifyArgs {
+    /// Receipts to check
+    #[arg(value_name = "RECEIPT", required_unless_present = "ledger")]
+    receipts: Vec<PathBuf>,
+
+    /// Check the hash chain of this ledger, and the receipt in each entry,
+    /// then that it has an entry for each receipt given
+    #[arg(long, value_name = "PATH")]
+    ledger: Option<PathBuf>,
+
+    /// Require the --ledger to still hold the entry with this SHA-256, the
+    /// head printed by an earlier check
+    #[arg(long, value_name = "SHA256", requires = "ledger")]
+    ledger_head: Option<String>,
+
+    /// Require an Ed25519 signature made with this PEM public key
+    #[arg(
+        long,
+        value_name = "PUBKEY",
+        conflicts_with = "allowed_signers",
+        requires = "receipts"
+    )]
+    key: Option<PathBuf>,
+
+    /// Require an SSH signature by a signer listed in this allowed_signers
+    /// file (as used by `ssh-keygen -Y verify`)
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with = "certificate_identity",
+        requires = "receipts"
+    )]
+    allowed_signers: Option<PathBuf>,
+
+    /// Require a Sigstore keyless signature certified for this identity
+    /// (an email address, or a CI workflow's URL)
+    #[arg(
+        long,
+        value_name = "IDENTITY",
+        requires_all = ["certificate_oidc_issuer", "receipts"]
+    )]
+    certificate_identity: Option<String>,
+
+    /// OIDC issuer that must have vouched for --certificate-identity
+    /// (e.g. https://token.actions.githubusercontent.com)
+    #[arg(long, value_name = "URL", requires = "certificate_identity")]
+    certificate_oidc_issuer: Option<String>,
+
+    /// Require a timestamp from an authority whose certificate chains to
+    /// these PEM CA certificates, instead of to the system's trusted roots
+    #[arg(long, value_name = "PEM", requires = "receipts")]
+    tsa_ca_file: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct WaitArgs {
+    /// Receipt written by `rcpt run --detach`: a path, an id or the start
+    /// of one, or `last` for the latest run
+    #[arg(value_name = "ID|PATH|last")]
+    target: String,
+
+    /// Directory to search for receipts by id, recursively (repeatable)
+    /// [default: .]
+    #[arg(long = "dir", value_name = "DIR")]
+    dirs: Vec<PathBuf>,
+
+    /// Give up (exiting with code 124) if the command is still running after this
+    #[arg(long, value_parser = humantime::parse_duration)]
+    timeout: Option<Duration>,
+}
+
+#[derive(Args, Debug)]
+struct PipeArgs {
+    /// Output path for the receipt JSON file, or a directory to name it in
+    /// [default: receipt-<id>.json]
+    #[arg(short, long)]
+    out: Option<PathBuf>,
+
+    /// Name the receipt after this template, in the --out directory if
+    /// given, e.g. '{date}/{tag}/{id}.json', with {id}, {date}, {time},
+    /// {command}, {status}, {exit_code}, {tag} and {tags}
+    #[arg(long, value_name = "TEMPLATE")]
+    out_template: Option<String>,
+
+    #[command(flatten)]
+    exec: ExecArgs,
+
+    /// Commands to run, separated by -- (e.g. -- cat log -- grep error -- wc -l)
+    #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+    stages: Vec<OsString>,
+}
+
+#[derive(Args, Debug)]
+struct BenchArgs {
+    /// Number of unmeasured runs before measuring
+    #[arg(long, default_value_t = 0)]
+    warmup: u32,
+
+    /// Number of measured runs
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..))]
+    runs: u32,
+
+    #[command(flatten)]
+    run: RunArgs,
+}
+
+#[derive(Args, Debug)]
+struct WatchArgs {
+    /// File or directory to watch for changes (repeatable)
+    #[arg(long = "paths", value_name = "PATH", required = true)]
+    paths: Vec<PathBuf>,
+
+    /// Skip files and directories whose names match this glob (repeatable)
+    #[arg(long, value_name = "PATTERN")]
+    ignore: Vec<String>,
+
+    /// How often to check for changes
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "500ms")]
+    interval: Duration,
+
+    /// Directory for the receipt of each run, named by its id
+    #[arg(short, long, default_value = "receipts")]
+    out_dir: PathBuf,
+
+    #[command(flatten)]
+    exec: ExecArgs,
+
+    /// Run this command string through the shell ($SHELL -c, or cmd /C on Windows)
+    #[arg(long, value_name = "COMMAND", conflicts_with = "command")]
+    shell: Option<String>,
+
+    /// Command to execute
+    #[arg(required_unless_present = "shell", trailing_var_arg = true)]
+    command: Vec<OsString>,
+}
+
+#[derive(Args, Debug)]
+struct ScheduleArgs {
+    /// Five-field cron expression in local time (e.g. '*/15 * * * *') or
+    /// @hourly, @daily, @weekly, @monthly, @yearly
+    #[arg(long, value_name = "EXPR")]
+    cron: CronSchedule,
+
+    /// Directory for the receipt of each run, named receipt-<id>.json
+    #[arg(short, long, default_value = "receipts")]
+    out_dir: PathBuf,
+
+    /// Scheduler state file [default: <OUT_DIR>/heartbeat.json]
+    #[arg(long, value_name = "PATH")]
+    heartbeat: Option<PathBuf>,
+
+    #[command(flatten)]
+    exec: ExecArgs,
+
+    /// Run this command string through the shell ($SHELL -c, or cmd /C on Windows)
+    #[arg(long, value_name = "COMMAND", conflicts_with = "command")]
+    shell: Option<String>,
+
+    /// Command to execute
+    #[arg(required_unless_present = "shell", trailing_var_arg = true)]
+    command: Vec<OsString>,
+}
+
+#[derive(Args, Debug)]
+struct BatchArgs {
+    /// File with one shell command per line (blank lines and # comments are skipped)
+    #[arg(short, long)]
+    file: PathBuf,
+
+    /// Number of commands to run at once
+    #[arg(short, long, default_value_t = 4)]
+    jobs: usize,
+
+    /// Directory for the per-command receipts and the manifest
+    #[arg(short, long, default_value = "receipts")]
+    out_dir: PathBuf,
+
+    #[command(flatten)]
+    exec: ExecArgs,
+}
+
+/// Options shared by every subcommand that executes commands
+#[derive(Args, Debug)]
+struct ExecArgs {
+    /// Kill the command if it runs longer than this (e.g. 30s, 5m, 1h30m)
+    #[arg(long, value_parser = humantime::parse_duration)]
+    timeout: Option<Duration>,
+
+    /// After a timeout or interruption, wait this long for the command to
+    /// exit before sending SIGKILL
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "10s")]
+    kill_after: Duration,
+
+    /// Retry the command up to this many times if it fails
+    #[arg(long, default_value_t = 0)]
+    retries: u32,
+
+    /// Delay before the first retry, doubled after each further failure
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "1s")]
+    retry_backoff: Duration,
+
+    /// Working directory for the command
+    #[arg(long)]
+    cwd: Option<PathBuf>,
+
+    /// Load NAME=value lines from this file into the command's environment
+    /// (repeatable; later files win)
+    #[arg(long, value_name = "PATH")]
+    env_file: Vec<PathBuf>,
+
+    /// Set a variable in the command's environment (repeatable)
+    #[arg(long = "env", value_name = "NAME=VALUE", value_parser = env::parse_assignment)]
+    env_vars: Vec<(String, String)>,
+
+    /// Start the command with an empty environment, so it sees only
+    /// variables set with --env, --env-file or --user
+    #[arg(long)]
+    clear_env: bool,
+
+    /// Record environment variables whose names match this glob (repeatable)
+    #[arg(long, value_name = "PATTERN")]
+    env_allow: Vec<String>,
+
+    /// Never record environment variables whose names match this glob (repeatable)
+    #[arg(long, value_name = "PATTERN")]
+    env_deny: Vec<String>,
+
+    /// How recorded environment variable values are stored
+    #[arg(long, value_enum, value_name = "MODE")]
+    env_values: Option<EnvValues>,
+
+    /// Stdin for the command: inherit, null, capture or file:<path>
+    /// [default: inherit for run, null otherwise]
+    #[arg(long, value_name = "MODE")]
+    stdin: Option<StdinMode>,
+
+    /// Echo the command's output live while still capturing it
+    #[arg(long)]
+    tee: bool,
+
+    /// Keep at most this many bytes of stdout in the receipt (head and tail)
+    #[arg(long, value_name = "BYTES")]
+    max_stdout_bytes: Option<usize>,
+
+    /// Keep at most this many bytes of stderr in the receipt (head and tail)
+    #[arg(long, value_name = "BYTES")]
+    max_stderr_bytes: Option<usize>,
+
+    /// Run the command under a pseudo-terminal, capturing its merged output
+    #[arg(long)]
+    pty: bool,
+
+    /// Limit the command's address space (e.g. 512M, 2G)
+    #[arg(long, value_name = "SIZE", value_parser = units::parse_size)]
+    limit_mem: Option<u64>,
+
+    /// Limit the command's CPU time in seconds
+    #[arg(long, value_name = "SECONDS")]
+    limit_cpu_seconds: Option<u64>,
+
+    /// Limit the number of file descriptors the command may open
+    #[arg(long, value_name = "N")]
+    limit_fds: Option<u64>,
+
+    /// Limit the size of files the command may write (e.g. 100M)
+    #[arg(long, value_name = "SIZE", value_parser = units::parse_size)]
+    limit_fsize: Option<u64>,
+
+    /// Run the command at this niceness (-20 highest priority, 19 lowest)
+    #[arg(long, value_name = "N", allow_negative_numbers = true)]
+    nice: Option<i32>,
+
+    /// Pin the command to these CPUs (e.g. 0-3 or 0,2,4; Linux only)
+    #[arg(long, value_name = "LIST")]
+    cpus: Option<CpuList>,
+
+    /// Run the command as this user (requires root)
+    #[arg(long, value_name = "NAME")]
+    user: Option<String>,
+
+    /// Run the command inside a container from this image, with the working
+    /// directory mounted at the same path
+    #[arg(long, value_name = "IMAGE", conflicts_with = "user")]
+    container: Option<String>,
+
+    /// Container engine for --container (default: docker, else podman)
+    #[arg(long, value_name = "RUNTIME", requires = "container")]
+    container_runtime: Option<Runtime>,
+
+    /// Run the command in new mount, PID and network namespaces with a
+    /// read-only root, a private /tmp and no network (Linux only)
+    #[arg(long, conflicts_with = "container")]
+    sandbox: bool,
+
+    /// Let the sandboxed command write to this directory, besides its
+    /// working directory (repeatable)
+    #[arg(long, value_name = "PATH", requires = "sandbox")]
+    sandbox_write: Vec<PathBuf>,
+
+    /// Bind-mount a host path into the container (repeatable)
+    #[arg(long, value_name = "SOURCE:TARGET[:ro]", value_parser = container::parse_mount, requires = "container")]
+    mount: Vec<Mount>,
+
+    /// Let the command read (and execute) only below this path, plus any
+    /// --allow-write path (repeatable; Linux only)
+    #[arg(long, value_name = "PATH", conflicts_with = "container")]
+    allow_read: Vec<PathBuf>,
+
+    /// Let the command change the filesystem only below this path
+    /// (repeatable; Linux only)
+    #[arg(long, value_name = "PATH", conflicts_with = "container")]
+    allow_write: Vec<PathBuf>,
+
+    /// Run the command without network access: in an empty net namespace on
+    /// Linux, under sandbox-exec on macOS
+    #[arg(long)]
+    no_network: bool,
+
+    /// Move the core dump of a crashed command beside its receipt, as
+    /// `<receipt>.core`
+    #[arg(long)]
+    keep_core: bool,
+
+    /// Store output that is not valid UTF-8 as base64 in the receipt, or
+    /// as raw `<receipt>.stdout.bin` and `<receipt>.stderr.bin` files
+    /// listed in its `sidecars`
+    #[arg(long, value_enum, default_value_t = BinaryOutput::Base64)]
+    binary_output: BinaryOutput,
+
+    /// Write stdout and stderr to `<receipt>.stdout.log` and
+    /// `<receipt>.stderr.log`, as the command wrote them, leaving their
+    /// sizes, digests and previews in the receipt's `sidecars`
+    #[arg(long, conflicts_with_all = ["compress_above", "timeline"])]
+    split_output: bool,
+
+    /// Store stdout or stderr zstd-compressed when it is larger than this
+    /// (e.g. 1MiB): as base64 in the receipt, or as `<receipt>.stdout.zst`
+    /// with `--binary-output sidecar`. Needs zstd
+    #[arg(long, value_name = "SIZE", value_parser = units::parse_size)]
+    compress_above: Option<u64>,
+
+    /// Store stdout or stderr larger than this (e.g. 64KiB), and every
+    /// --output, in a content-addressable object store, each under its
+    /// SHA-256, so identical output across runs is stored once
+    #[arg(long, value_name = "SIZE", value_parser = units::parse_size)]
+    objects_above: Option<u64>,
+
+    /// Directory of the object store for --objects-above
+    #[arg(
+        long,
+        value_name = "DIR",
+        default_value = objects::DEFAULT_DIR,
+        requires = "objects_above"
+    )]
+    object_store: PathBuf,
+
+    /// Add the receipt to a SQLite index, for queries that need not read
+    /// every receipt: --index=<DB>, or `.receipts/index.db` if no DB is
+    /// given [default: $RCPT_INDEX]. Needs sqlite3
+    #[arg(
+        long,
+        value_name = "DB",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = index::DEFAULT_PATH
+    )]
+    index: Option<PathBuf>,
+
+    /// Also append the receipt, as one line of JSON, to this ledger file,
+    /// which rcpt processes can share
+    #[arg(long, value_name = "PATH")]
+    ledger: Option<PathBuf>,
+
+    /// Only append the receipt to the --ledger, writing no receipt file
+    #[arg(long, requires = "ledger")]
+    ledger_only: bool,
+
+    /// Flush the receipt, and the files written with it, to disk before
+    /// reporting it written, so it survives a crash or power loss
+    #[arg(long)]
+    fsync: bool,
+
+    /// Send the receipt once it is written: with its sidecars and detached
+    /// signature to an S3 bucket (s3://bucket/prefix), with credentials from
+    /// AWS_* variables or the config's `s3` section, or a directory
+    /// (file:///path), POSTed as JSON to an http(s):// endpoint, through
+    /// curl, or as a summary to syslog:// (syslog://host[:port] for a remote
+    /// server) or journald:// (repeatable)
+    #[arg(long, value_name = "URL")]
+    sink: Vec<String>,
+
+    /// What to do when a --sink delivery fails: fail the run (fatal), warn
+    /// (log) or warn and spool it (queue); as URL=POLICY for one --sink
+    /// (repeatable) [default: queue]
+    #[arg(
+        long = "sink-on-failure",
+        value_name = "[URL=]POLICY",
+        requires = "sink"
+    )]
+    sink_on_failure: Vec<String>,
+
+    /// Header to POST to an http(s) --sink, as `Name: value` (repeatable)
+    #[arg(long = "sink-header", value_name = "HEADER", requires = "sink")]
+    sink_headers: Vec<String>,
+
+    /// Log the whole receipt to a syslog:// or journald:// --sink, not
+    /// just its summary
+    #[arg(long, requires = "sink")]
+    sink_full: bool,
+
+    /// How many times to retry a --sink delivery that fails for a reason
+    /// that may pass, waiting twice as long each time
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = sink::DEFAULT_RETRIES,
+        requires = "sink"
+    )]
+    sink_retries: u32,
+
+    /// Longest each --sink delivery attempt may take, connecting included,
+    /// before it fails and may be retried
+    #[arg(
+        long,
+        value_name = "DURATION",
+        default_value = sink::DEFAULT_TIMEOUT,
+        value_parser = humantime::parse_duration,
+        requires = "sink"
+    )]
+    sink_timeout: Duration,
+
+    /// Directory keeping the files --sink could not deliver, for the next
+    /// run or `rcpt flush` to send
+    #[arg(
+        long,
+        value_name = "DIR",
+        default_value = sink::DEFAULT_SPOOL,
+        requires = "sink"
+    )]
+    sink_spool: PathBuf,
+
+    /// Record stdout and stderr as one list of chunks in the order they
+    /// were read, each with its time since the start, instead of as two
+    /// strings
+    #[arg(long)]
+    timeline: bool,
+
+    /// Sign the receipt with this Ed25519 private key (PEM, as written by
+    /// `rcpt keygen`)
+    #[arg(long, value_name = "KEYFILE")]
+    sign: Option<PathBuf>,
+
+    /// Sign the receipt with this SSH key through ssh-keygen: a private
+    /// key, or a public key whose private half ssh-agent holds
+    #[arg(long, value_name = "KEYFILE", conflicts_with = "sign")]
+    sign_ssh: Option<PathBuf>,
+
+    /// Sign the receipt without a long-lived key, through cosign: an
+    /// ephemeral key certified by Sigstore's Fulcio for your OIDC identity,
+    /// with the signature logged in the public Rekor transparency log
+    #[arg(long, conflicts_with_all = ["sign", "sign_ssh"])]
+    sign_keyless: bool,
+
+    /// Write the signature to `<receipt>.sig` instead of into the receipt
+    #[arg(long)]
+    sign_detached: bool,
+
+    /// Write the receipt, and any --binary-output or --split-output
+    /// sidecars, encrypted with age to this recipient (`age1...`); repeat
+    /// for several. Read it back with $RCPT_IDENTITY naming a matching
+    /// identity file
+    #[arg(long, value_name = "RECIPIENT", value_parser = age::Recipient::parse)]
+    encrypt_to: Vec<age::Recipient>,
+
+    /// Get an RFC 3161 timestamp over the receipt's digest from this
+    /// time-stamping authority (e.g. https://freetsa.org/tsr), proving the
+    /// receipt existed by then
+    #[arg(long, value_name = "TSA")]
+    timestamp_url: Option<String>,
+
+    /// Also write the run as an in-toto statement with a SLSA provenance
+    /// predicate: --output files as its subjects, --input files and the
+    /// commit checked out as its materials
+    #[arg(long, value_name = "PATH")]
+    attestation: Option<PathBuf>,
+
+    /// Builder id recorded in the --attestation [default: urn:rcpt:host:<hostname>]
+    #[arg(long, value_name = "URI", requires = "attestation")]
+    builder_id: Option<String>,
+
+    /// Record the machine in the receipt: hostname, OS and kernel version,
+    /// architecture, CPU count and memory
+    #[arg(long)]
+    capture_host: bool,
+
+    /// Do not record the git commit, branch and uncommitted changes of the
+    /// working directory
+    #[arg(long)]
+    no_vcs: bool,
+
+    /// Record the processes the command starts, sampling them this often
+    /// (default 100ms; Linux and macOS only)
+    #[arg(
+        long,
+        value_name = "INTERVAL",
+        num_args = 0..=1,
+        default_missing_value = "100ms",
+        value_parser = humantime::parse_duration
+    )]
+    process_tree: Option<Duration>,
+
+    /// Record the files the command and its descendants read and wrote
+    /// (Linux only, using fanotify; requires root)
+    #[arg(long, conflicts_with_all = ["container", "sandbox"])]
+    trace_files: bool,
+
+    /// Hash this file or directory tree before the run and record it in
+    /// `inputs`, relative to the working directory (repeatable)
+    #[arg(long, value_name = "PATH")]
+    input: Vec<PathBuf>,
+
+    /// Hash this file or directory tree after the run and record it in
+    /// `outputs`, relative to the working directory (repeatable)
+    #[arg(long, value_name = "PATH")]
+    output: Vec<PathBuf>,
+
+    /// Mark a run that exited 0 as `missing_outputs` if any --output does
+    /// not exist
+    #[arg(long, requires = "output")]
+    require_outputs: bool,
+
+    /// Fingerprint this directory before and after the run and record the
+    /// files the command created, modified and deleted in it
+    #[arg(long, value_name = "DIR")]
+    snapshot: Option<PathBuf>,
+
+    /// Label the receipt with this tag, e.g. `ci` or `deploy` (repeatable)
+    #[arg(long, value_name = "TAG", value_parser = tags::parse_tag)]
+    tag: Vec<String>,
+
+    /// Link the receipt to the receipt with this id, as its parent
+    /// [default: $RCPT_PARENT, which rcpt sets for the commands it runs]
+    #[arg(long, value_name = "ID")]
+    parent: Option<String>,
+
+    /// Name of the agent running the command [default: $RCPT_AGENT]
+    #[arg(long, value_name = "NAME")]
+    agent: Option<String>,
+
+    /// Id of the agent run the command is part of [default: $RCPT_RUN_ID]
+    #[arg(long, value_name = "ID")]
+    run_id: Option<String>,
+
+    /// Id of the task the command is part of [default: $RCPT_TASK_ID]
+    #[arg(long, value_name = "ID")]
+    task_id: Option<String>,
+
+    /// Read settings such as redaction patterns from this JSON file
+    /// [default: $RCPT_CONFIG, else rcpt/config.json in the user config
+    /// directory]
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Record output and arguments verbatim, without redacting secrets
+    #[arg(long)]
+    no_redact: bool,
+
+    /// Record this key with a string value under `metadata` (repeatable)
+    #[arg(long, value_name = "KEY=VALUE", value_parser = metadata::parse_meta)]
+    meta: Vec<(String, serde_json::Value)>,
+
+    /// Record this key with a JSON value under `metadata`, e.g.
+    /// `build={"number":42}` (repeatable)
+    #[arg(long, value_name = "KEY=JSON", value_parser = metadata::parse_meta_json)]
+    meta_json: Vec<(String, serde_json::Value)>,
+
+    /// Resolve the command and write a planned receipt without running it
+    #[arg(long)]
+    dry_run: bool,
+}
+
+impl RunArgs {
+    fn invocation(&self) -> Invocation {
+        invocation_of(self.shell.as_deref(), &self.command)
+    }
+
+    fn out_path(&self, names: &Names) -> Result<PathBuf> {
+        out_path(self.out.as_deref(), self.out_template.as_deref(), names)
+    }
+}
+
+/// What to execute, given a `--shell` string or a command
+fn invocation_of(shell: Option<&str>, command: &[OsString]) -> Invocation {
+    match shell {
+        Some(script) => Invocation::shell(script),
+        None => Invocation::direct(command.to_vec()),
+    }
+}
+
+impl ExecArgs {
+    /// Options for executing commands, with `default_stdin` used unless
+    /// --stdin was given
+    fn exec_options(&self, default_stdin: StdinMode) -> Result<ExecOptions> {
+        if self.process_tree.is_some() && !proctree::SUPPORTED {
+            anyhow::bail!("--process-tree is only supported on Linux and macOS");
+        }
+        if self.process_tree.is_some_and(|interval| interval.is_zero()) {
+            anyhow::bail!("--process-tree interval must be greater than zero");
+        }
+        Ok(ExecOptions {
+            timeout: self.timeout,
+            kill_after: self.kill_after,
+            cwd: self.cwd.clone(),
+            env_policy: self.env_policy(),
+            env_files: self
+                .env_file
+                .iter()
+                .map(|path| EnvFile::load(path))
+                .collect::<Result<_>>()?,
+            clear_env: self.clear_env,
+            env_vars: self.env_vars.clone(),
+            stdin: self.stdin.clone().unwrap_or(default_stdin),
+            tee: self.tee,
+            max_stdout_bytes: self.max_stdout_bytes,
+            max_stderr_bytes: self.max_stderr_bytes,
+            pty: self.pty,
+            limits: ResourceLimits {
+                memory_bytes: self.limit_mem,
+                cpu_seconds: self.limit_cpu_seconds,
+                open_files: self.limit_fds,
+                file_size_bytes: self.limit_fsize,
+            },
+            scheduling: Scheduling {
+                nice: self.nice,
+                cpus: self.cpus.clone().map(|cpus| cpus.0),
+            },
+            user: self.user.as_deref().map(RunAs::resolve).transpose()?,
+            container: self.container.clone().map(|image| ContainerSpec {
+                image,
+                runtime: self.container_runtime,
+                mounts: self.mount.clone(),
+            }),
+            sandbox: self.sandbox.then(|| Sandbox {
+                writable: self.sandbox_write.clone(),
+            }),
+            access: (!self.allow_read.is_empty() || !self.allow_write.is_empty()).then(|| {
+                PathRules {
+                    read: self.allow_read.clone(),
+                    write: self.allow_write.clone(),
+                }
+            }),
+            no_network: self.no_network,
+            keep_core: self.keep_core,
+            binary_output: self.binary_output,
+            split_output: self.split_output,
+            timeline: self.timeline,
+            compress_above: match self.compress_above {
+                Some(threshold) => Some((threshold, zstd::program()?)),
+                None => None,
+            },
+            objects: match self.objects_above {
+                Some(threshold) => Some((
+                    threshold,
+                    ObjectStore::open(&self.object_store, self.fsync)?,
+                )),
+                None => None,
+            },
+            encrypt_to: self.encrypt_to.clone(),
+            ledger_only: self.ledger.clone().filter(|_| self.ledger_only),
+            fsync: self.fsync,
+            sinks: self.sinks()?,
+            tsa: self.timestamp_url.as_deref().map(Tsa::new).transpose()?,
+            signer: signer(
+                self.sign.as_deref(),
+                self.sign_ssh.as_deref(),
+                self.sign_keyless,
+                self.sign_detached,
+            )?,
+            attestation: self.attestation.clone().map(|path| {
+                let builder_id = self
+                    .builder_id
+                    .clone()
+                    .unwrap_or_else(attestation::default_builder_id);
+                (path, builder_id)
+            }),
+            capture_host: self.capture_host,
+            capture_vcs: !self.no_vcs,
+            process_tree: self.process_tree,
+            trace_files: self.trace_files,
+            inputs: self.input.clone(),
+            outputs: self.output.clone(),
+            require_outputs: self.require_outputs,
+            snapshot: self.snapshot.clone(),
+            tags: tags::dedup(&self.tag),
+            parent_id: flag_or_env(&self.parent, id::PARENT_ENV),
+            session_id: session::current(),
+            redactor: match self.no_redact {
+                true => Redactor::default(),
+                false => Redactor::new(&Config::load(self.config.as_deref())?.redact)?,
+            },
+            actor: Some(ActorRecord {
+                agent: flag_or_env(&self.agent, "RCPT_AGENT"),
+                run_id: flag_or_env(&self.run_id, "RCPT_RUN_ID"),
+                task_id: flag_or_env(&self.task_id, "RCPT_TASK_ID"),
+            })
+            .filter(|actor| *actor != ActorRecord::default()),
+            metadata: metadata::collect(&[self.meta.clone(), self.meta_json.clone()].concat())?,
+            dry_run: self.dry_run,
+            stub: None,
+            receipt_id: None,
+        })
+    }
+
+    /// Where receipts go, in order: the --ledger, the receipt's own file
+    /// unless --ledger-only, the --index, then the --sink
+    fn sinks(&self) -> Result<Vec<Box<dyn ReceiptSink>>> {
+        let mut sinks: Vec<Box<dyn ReceiptSink>> = Vec::new();
+        if let Some(path) = &self.ledger {
+            if !self.encrypt_to.is_empty() {
+                anyhow::bail!("--ledger is not supported with --encrypt-to");
+            }
+            sinks.push(Box::new(Ledger {
+                path: path.clone(),
+                fsync: self.fsync,
+            }));
+        }
+        if !self.ledger_only {
+            sinks.push(Box::new(FileSink {
+                encrypt_to: self.encrypt_to.clone(),
+                fsync: self.fsync,
+            }));
+        }
+        // The index is not encrypted, so encrypted receipts stay out of it
+        let db = self.index.clone().or_else(|| {
+            std::env::var_os(index::INDEX_ENV)
+                .filter(|db| !db.is_empty())
+                .map(PathBuf::from)
+        });
+        if let Some(db) = db.filter(|_| self.encrypt_to.is_empty()) {
+            sinks.push(Box::new(Index::open(&db)?));
+        }
+        if self.sink.is_empty() {
+            return Ok(sinks);
+        }
+        if self.ledger_only {
+            anyhow::bail!("--sink is not supported with --ledger-only");
+        }
+        let (default, policies) = self.sink_policies()?;
+        let config = Config::load(self.config.as_deref())?;
+        for url in &self.sink {
+            if sink::is_system_log(url) && !self.encrypt_to.is_empty() {
+                anyhow::bail!("--sink {} is not supported with --encrypt-to", url);
+            }
+            sinks.push(Box::new(RemoteSink::new(
+                url,
+                &self.sink_headers,
+                self.sink_full,
+                &config,
+                &self.sink_spool,
+                Delivery {
+                    retries: self.sink_retries,
+                    timeout: self.sink_timeout,
+                },
+                policies.get(url.as_str()).copied().unwrap_or(default),
+            )?));
+        }
+        Ok(sinks)
+    }
+
+    /// The --sink-on-failure policy for every --sink, and those for
+    /// particular ones
+    fn sink_policies(&self) -> Result<(OnFailure, HashMap<&str, OnFailure>)> {
+        let mut default = OnFailure::default();
+        let mut policies = HashMap::new();
+        for value in &self.sink_on_failure {
+            match value.rsplit_once('=') {
+                Some((url, policy)) => {
+                    let Some(url) = self.sink.iter().find(|sink| *sink == url) else {
+                        anyhow::bail!("--sink-on-failure {} names no --sink", value);
+                    };
+                    policies.insert(url.as_str(), policy.parse()?);
+                }
+                None => default = value.parse()?,
+            }
+        }
+        Ok((default, policies))
+    }
+
+    /// The environment capture policy, if any environment flag was given
+    fn env_policy(&self) -> Option<EnvPolicy> {
+        if self.env_allow.is_empty() && self.env_deny.is_empty() && self.env_values.is_none() {
+            return None;
+        }
+        Some(EnvPolicy {
+            allow: self.env_allow.clone(),
+            deny: self.env_deny.clone(),
+            values: self.env_values.unwrap_or_default(),
+        })
+    }
+
+    fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            retries: self.retries,
+            backoff: self.retry_backoff,
+        }
+    }
+}
+
+/// Run `rcpt` with the arguments it was given
+pub fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    match cli.command {
+        Commands::Run(args) => run(args),
+        Commands::Wait(args) => wait(args),
+        Commands::Pipe(args) => pipe(args),
+        Commands::Bench(args) => bench(args),
+        Commands::Watch(args) => watch(args),
+        Commands::Schedule(args) => schedule(args),
+        Commands::Batch(args) => batch(args),
+        Commands::Ssh(args) => ssh(args),
+        Commands::Session(args) => session(args),
+        Commands::Keygen(args) => {
+            let key_id = signing::keygen(&args.out)?;
+            println!(
+                "Key {} written to: {} (public key: {}.pub)",
+                key_id,
+                args.out.display(),
+                args.out.display()
+            );
+            Ok(())
+        }
+        Commands::Verify(args) => verify(args),
+        Commands::Index(args) => index(args),
+        Commands::Gc(args) => {
+            if args.keep_days.is_none() && args.keep_last.is_none() && !args.keep_failed {
+                anyhow::bail!("rcpt gc needs a policy: --keep-days, --keep-last or --keep-failed");
+            }
+            let dirs = match args.dirs.is_empty() {
+                true => vec![PathBuf::from(".")],
+                false => args.dirs,
+            };
+            let stores = match args.object_stores.is_empty() {
+                true => vec![PathBuf::from(objects::DEFAULT_DIR)],
+                false => args.object_stores,
+            };
+            let retention = gc::Retention {
+                keep_days: args.keep_days,
+                keep_last: args.keep_last,
+                keep_failed: args.keep_failed,
+            };
+            gc::collect(
+                &dirs,
+                &args.ledgers,
+                &stores,
+                &retention,
+                args.dry_run,
+                &mut io::stdout(),
+            )
+        }
+        Commands::Prune(args) => {
+            let filter = Filter {
+                since: None,
+                before: match args.older_than {
+                    Some(age) => Some(chrono::Utc::now() - chrono::Duration::from_std(age)?),
+                    None => None,
+                },
+                statuses: args.status,
+                tags: args.tag,
+                command: args.cmd,
+                session: args.session,
+            };
+            if filter.before.is_none()
+                && filter.statuses.is_empty()
+                && filter.tags.is_empty()
+                && filter.command.is_none()
+                && filter.session.is_none()
+            {
+                anyhow::bail!(
+                    "rcpt prune needs a filter: --older-than, --status, --tag, --cmd or --session"
+                );
+            }
+            let dirs = match args.dirs.is_empty() {
+                true => vec![PathBuf::from(".")],
+                false => args.dirs,
+            };
+            gc::prune(&dirs, &filter, args.dry_run, &mut io::stdout())
+        }
+        Commands::Bundle(args) => bundle(args),
+        Commands::Import(args) => import(args),
+        Commands::Flush(args) => {
+            let config = Config::load(args.config.as_deref())?;
+            let mut left = 0;
+            for url in &args.sink {
+                let sink = RemoteSink::new(
+                    url,
+                    &args.sink_headers,
+                    args.sink_full,
+                    &config,
+                    &args.spool,
+                    Delivery {
+                        retries: args.sink_retries,
+                        timeout: args.sink_timeout,
+                    },
+                    OnFailure::Queue,
+                )?;
+                let (sent, unsent) = sink.flush_spool()?;
+                println!("Sent {} spooled files to {}", sent, sink.url());
+                left += unsent;
+            }
+            if left > 0 {
+                anyhow::bail!("{} spooled files could not be sent", left);
+            }
+            Ok(())
+        }
+        Commands::List(args) => list(args),
+        Commands::Last(args) => last(args),
+        Commands::Show(args) => show(args),
+        Commands::Diff(args) => diff(args),
+        Commands::Compare(args) => compare(args),
+        Commands::Search(args) => search(args),
+        Commands::Stats(args) => stats(args),
+        Commands::Export(args) => export(args),
+        Commands::Report(args) => report(args),
+        Commands::Replay(args) => replay(args),
+        Commands::Redact(args) => redact(args),
+        Commands::Tail(args) => {
+            let source = match args.ledger {
+                Some(ledger) => tail::Source::Ledger(ledger),
+                None if args.dirs.is_empty() => tail::Source::Dirs(vec![PathBuf::from(".")]),
+                None => tail::Source::Dirs(args.dirs),
+            };
+            signals::install();
+            tail::tail(&source, args.lines, args.follow, &mut io::stdout())
+        }
+        Commands::Schema(args) => {
+            let version = args.version.unwrap_or(receipt::RECEIPT_VERSION);
+            let schema = schema::receipt_schema(version)?;
+            println!("{}", serde_json::to_string_pretty(&schema)?);
+            Ok(())
+        }
+        Commands::Validate(args) => validate(args),
+    }
+}
+
+fn run(args: RunArgs) -> Result<()> {
+    if !args.detach {
+        signals::install();
+        let mut opts = args.exec.exec_options(StdinMode::Inherit)?;
+        check_out(args.out.as_deref(), args.out_template.as_deref())?;
+        announce_id(&mut opts);
+        let mut receipt =
+            execute_with_retries(&args.invocation(), &opts, &args.exec.retry_policy())?;
+        let out = args.out_path(&Names::of(&receipt))?;
+        save_receipt(&opts, &mut receipt, &out)?;
+        finalize_sinks(&opts)?;
+        println!("Receipt written to: {}", opts.saved_to(&out).display());
+        exit_with_status(&receipt)
+    }
+
+    let mut opts = args.exec.exec_options(StdinMode::Null)?;
+    if matches!(opts.stdin, StdinMode::Inherit | StdinMode::Capture) {
+        anyhow::bail!("--detach requires --stdin null or file:<path>");
+    }
+    if !opts.encrypt_to.is_empty() {
+        anyhow::bail!("--encrypt-to is not supported with --detach");
+    }
+    if opts.ledger_only.is_some() {
+        anyhow::bail!("--ledger-only is not supported with --detach");
+    }
+    check_out(args.out.as_deref(), args.out_template.as_deref())?;
+    let invocation = args.invocation();
+    let (program, tags) = (invocation.program(), opts.tags.clone());
+    let names = |id| Names {
+        id,
+        command: &program,
+        tags: &tags,
+        outcome: None,
+    };
+    let Some(id) = detach::supervisor() else {
+        let id = announce_id(&mut opts);
+        let out = args.out_path(&names(&id))?;
+        let receipt = detach::start(&out, &id)?;
+        println!(
+            "Started in the background (pid {}); receipt written to: {}",
+            receipt.pid.unwrap_or_default(),
+            out.display()
+        );
+        println!("Wait for it with: rcpt wait {}", out.display());
+        return Ok(());
+    };
+
+    // The background rcpt: run the command to completion as usual, under
+    // the id its parent already reported
+    signals::install();
+    let out = args.out_path(&names(&id))?;
+    opts.receipt_id = Some(id);
+    opts.stub = Some(out.clone());
+    let mut receipt = execute_with_retries(&invocation, &opts, &args.exec.retry_policy())?;
+    save_receipt(&opts, &mut receipt, &out)?;
+    detach::remove_empty_log(&out);
+    finalize_sinks(&opts)
+}
+
+fn wait(args: WaitArgs) -> Result<()> {
+    let dirs = match args.dirs.is_empty() {
+        true => vec![PathBuf::from(".")],
+        false => args.dirs,
+    };
+    let (path, _) = list::locate(&args.target, &dirs)?;
+    let Some(receipt) = detach::wait(&path, args.timeout)? else {
+        eprintln!("rcpt: {} is still running", path.display());
+        std::process::exit(TIMEOUT_EXIT_CODE);
+    };
+    println!(
+        "Command finished ({}); receipt: {}",
+        receipt.status.as_str(),
+        path.display()
+    );
+    exit_with_status(&receipt)
+}
+
+fn verify(args: VerifyArgs) -> Result<()> {
+    let chain = match &args.ledger {
+        Some(ledger) => {
+            let chain = ledger::verify(ledger, args.ledger_head.as_deref())?;
+            println!(
+                "ledger: ok ({} entries, head {})",
+                chain.digests.len(),
+                chain.head
+            );
+            Some((ledger, chain))
+        }
+        None => None,
+    };
+    let trusted = TrustedSigners {
+        key: args.key,
+        allowed_signers: args.allowed_signers,
+        certificate_identity: args.certificate_identity,
+        certificate_oidc_issuer: args.certificate_oidc_issuer,
+        tsa_ca_file: args.tsa_ca_file,
+    };
+    let several = args.receipts.len() > 1;
+    let mut failed = 0;
+    for (n, path) in args.receipts.iter().enumerate() {
+        if several {
+            if n > 0 {
+                println!();
+            }
+            println!("{}:", path.display());
+        }
+        let checked =
+            signing::verify(path, &trusted, &mut io::stdout()).and_then(|()| match &chain {
+                Some((ledger, chain)) => verify_in_ledger(path, ledger, chain),
+                None => Ok(()),
+            });
+        if let Err(err) = checked {
+            match several {
+                true => eprintln!("rcpt: {}: {:#}", path.display(), err),
+                false => return Err(err),
+            }
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        anyhow::bail!(
+            "{} of {} receipts failed verification",
+            failed,
+            args.receipts.len()
+        );
+    }
+    Ok(())
+}
+
+/// Check `chain`, the checked chain of `ledger`, has an entry for the
+/// receipt at `path`
+fn verify_in_ledger(path: &Path, ledger: &Path, chain: &ledger::Chain) -> Result<()> {
+    let digest = receipt::read_receipt(path)?
+        .receipt_digest
+        .unwrap_or_default();
+    match chain.position(&digest) {
+        Some(line) => println!("ledger: ok (line {} of {})", line, ledger.display()),
+        None => anyhow::bail!("ledger: {} has no entry for this receipt", ledger.display()),
+    }
+    Ok(())
+}
+
+fn validate(args: ValidateArgs) -> Result<()> {
+    let mut paths = Vec::new();
+    for path in args.paths {
+        match path.is_dir() {
+            true => paths.extend(receipt::json_files(&[path])?),
+            false => paths.push(path),
+        }
+    }
+    let mut invalid = 0;
+    for path in &paths {
+        let problems = receipt::read_json(path).and_then(|json| {
+            let value =
+                serde_json::from_str(&json).map_err(|err| anyhow::anyhow!("not JSON: {}", err))?;
+            schema::validate(&value)
+        });
+        match problems {
+            Ok(problems) => {
+                let failing = problems
+                    .iter()
+                    .filter(|problem| args.strict || !problem.unknown)
+                    .count();
+                let unknown = problems.iter().filter(|problem| problem.unknown).count();
+                match (failing, unknown) {
+                    (0, 0) => println!("{}: ok", path.display()),
+                    (0, _) => println!("{}: ok, with {} unknown fields", path.display(), unknown),
+                    _ => println!("{}: {} problems", path.display(), problems.len()),
+                }
+                for problem in problems {
+                    println!("  {}", problem);
+                }
+                if failing > 0 {
+                    invalid += 1;
+                }
+            }
+            Err(err) => {
+                println!("{}: {:#}", path.display(), err);
+                invalid += 1;
+            }
+        }
+    }
+    if invalid > 0 {
+        anyhow::bail!("{} of {} receipts are invalid", invalid, paths.len());
+    }
+    Ok(())
+}
+
+fn index(args: IndexArgs) -> Result<()> {
+    let db = args
+        .db
+        .or_else(|| {
+            std::env::var_os(index::INDEX_ENV)
+                .filter(|db| !db.is_empty())
+                .map(PathBuf::from)
+        })
+        .unwrap_or_else(|| PathBuf::from(index::DEFAULT_PATH));
+    let index = Index::open(&db)?;
+    let dirs = match args.dirs.is_empty() {
+        true => vec![PathBuf::from(".")],
+        false => args.dirs,
+    };
+    // Encrypted receipts stay out of the index, which is not encrypted
+    let receipts: Vec<(PathBuf, Receipt)> = receipt::json_files(&dirs)?
+        .into_iter()
+        .filter(|path| std::fs::read(path).is_ok_and(|bytes| !age::is_encrypted(&bytes)))
+        .filter_map(|path| Some((path.clone(), receipt::read_receipt(&path).ok()?)))
+        .collect();
+    let pruned = index.add_all(&receipts, true)?;
+    println!(
+        "Indexed {} receipts in {} (dropped {} no longer on disk)",
+        receipts.len(),
+        index.path().display(),
+        pruned
+    );
+    Ok(())
+}
+
+fn list(args: ListArgs) -> Result<()> {
+    let receipts = args.select.summaries()?;
+    match args.json {
+        true => println!("{}", list::render_json(&receipts)?),
+        false => print!("{}", list::render_table(&receipts)),
+    }
+    Ok(())
+}
+
+fn last(args: LastArgs) -> Result<()> {
+    let Some((path, receipt)) = args.select.receipts()?.pop() else {
+        anyhow::bail!("No receipts found");
+    };
+    match args.print {
+        list::Print::Id => println!("{}", receipt.id),
+        list::Print::Path => println!("{}", path.display()),
+        list::Print::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&receipt::to_value(&receipt)?)?
+            )
+        }
+    }
+    Ok(())
+}
+
+fn show(args: ShowArgs) -> Result<()> {
+    let dirs = match args.dirs.is_empty() {
+        true => vec![PathBuf::from(".")],
+        false => args.dirs,
+    };
+    let (path, receipt) = list::locate(&args.target, &dirs)?;
+    if args.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&receipt::to_value(&receipt)?)?
+        );
+        return Ok(());
+    }
+    let page = show::render(&path, &receipt, &show::Style::detect(args.lines));
+    if let Some(pager) = show::pager().filter(|_| !args.no_pager) {
+        if show::page(&page, pager)? {
+            return Ok(());
+        }
+    }
+    print!("{}", page);
+    Ok(())
+}
+
+fn diff(args: DiffArgs) -> Result<()> {
+    let dirs = match args.dirs.is_empty() {
+        true => vec![PathBuf::from(".")],
+        false => args.dirs,
+    };
+    let (a_path, a) = list::locate(&args.a, &dirs)?;
+    let (b_path, b) = list::locate(&args.b, &dirs)?;
+    print!(
+        "{}",
+        diff::render(&a_path, &a, &b_path, &b, show::use_color())
+    );
+    Ok(())
+}
+
+fn compare(args: CompareArgs) -> Result<()> {
+    let dirs = match args.dirs.is_empty() {
+        true => vec![PathBuf::from(".")],
+        false => args.dirs,
+    };
+    let (baseline_path, baseline) = match (&args.baseline_file, &args.baseline) {
+        (Some(path), _) => (path.clone(), receipt::read_receipt(path)?),
+        (None, Some(target)) => list::locate(target, &dirs)?,
+        (None, None) => anyhow::bail!("rcpt compare needs --baseline or --baseline-file"),
+    };
+    let (candidate_path, candidate) = list::locate(&args.candidate, &dirs)?;
+    let tolerance = compare::Tolerance {
+        percent: args.tolerance,
+        ms: args.tolerance_ms,
+    };
+    let report = compare::compare(
+        &baseline_path,
+        &baseline,
+        &candidate_path,
+        &candidate,
+        tolerance,
+        &args.ignore,
+    )?;
+    match args.json {
+        true => println!("{}", serde_json::to_string_pretty(&report)?),
+        false => print!("{}", report.render()),
+    }
+    if report.regressed {
+        anyhow::bail!("{} regressed from baseline {}", candidate.id, baseline.id);
+    }
+    Ok(())
+}
+
+fn search(args: SearchArgs) -> Result<()> {
+    let matcher = search::Matcher::new(&args.pattern, args.regex, args.ignore_case)?;
+    let color = show::use_color();
+    let mut found = 0;
+    for (path, receipt) in args.select.receipts()? {
+        match search::search(&path, &receipt, &matcher, args.context, color) {
+            Ok(Some(matches)) => {
+                if found > 0 {
+                    println!();
+                }
+                print!("{}", matches);
+                found += 1;
+            }
+            Ok(None) => {}
+            Err(err) => eprintln!("rcpt: skipping {}: {:#}", path.display(), err),
+        }
+    }
+    if found == 0 {
+        anyhow::bail!("No receipt's output matches {}", args.pattern);
+    }
+    Ok(())
+}
+
+fn stats(args: StatsArgs) -> Result<()> {
+    let groups = stats::aggregate(&args.select.summaries()?, args.group_by);
+    match args.json {
+        true => println!("{}", stats::render_json(&groups)?),
+        false => print!("{}", stats::render_table(&groups, args.group_by)),
+    }
+    Ok(())
+}
+
+fn export(args: ExportArgs) -> Result<()> {
+    let receipts = args.select.receipts()?;
+    let exported = match args.format {
+        export::Format::Csv => export::csv(&receipts, &args.fields),
+        export::Format::Junit => export::junit(&receipts)?,
+        export::Format::Sarif => sarif::render(&receipts)?,
+        export::Format::Md => export::markdown(&receipts)?,
+    };
+    write_export(args.out.as_deref(), &exported)
+}
+
+fn report(args: ReportArgs) -> Result<()> {
+    let receipts = args.select.receipts()?;
+    if receipts.is_empty() {
+        anyhow::bail!("No receipts to report on");
+    }
+    write_export(Some(&args.html), &report::html(&receipts)?)
+}
+
+/// Write `exported` to `out`, or to stdout without one
+fn write_export(out: Option<&Path>, exported: &str) -> Result<()> {
+    match out {
+        Some(out) => atomic::write(out, exported.as_bytes(), false)
+            .map_err(|err| anyhow::anyhow!("Failed to write {}: {}", out.display(), err)),
+        None => {
+            print!("{}", exported);
+            Ok(())
+        }
+    }
+}
+
+/// The signer --sign, --sign-ssh or --sign-keyless asks for, if any
+fn signer(
+    key: Option<&Path>,
+    ssh: Option<&Path>,
+    keyless: bool,
+    detached: bool,
+) -> Result<Option<Signer>> {
+    Ok(match (key, ssh) {
+        (Some(path), _) => Some(Signer::load(path, detached)?),
+        (None, Some(path)) => Some(Signer::load_ssh(path, detached)?),
+        (None, None) if keyless => Some(Signer::keyless(detached)?),
+        (None, None) if detached => {
+            anyhow::bail!("--sign-detached requires --sign, --sign-ssh or --sign-keyless")
+        }
+        (None, None) => None,
+    })
+}
+
+fn bundle(args: BundleArgs) -> Result<()> {
+    if let Some(BundleCommand::Verify(args)) = args.command {
+        let trusted = TrustedSigners {
+            key: args.key,
+            allowed_signers: args.allowed_signers,
+            certificate_identity: args.certificate_identity,
+            certificate_oidc_issuer: args.certificate_oidc_issuer,
+            tsa_ca_file: None,
+        };
+        return bundle::verify(&args.bundle, &trusted, &mut io::stdout());
+    }
+    let Some(out) = args.out else {
+        anyhow::bail!("rcpt bundle needs --out");
+    };
+    let signer = signer(
+        args.sign.as_deref(),
+        args.sign_ssh.as_deref(),
+        args.sign_keyless,
+        false,
+    )?;
+    let receipts = args.select.receipts()?;
+    if receipts.is_empty() {
+        anyhow::bail!("No receipts to bundle");
+    }
+    bundle::create(&receipts, &out, signer.as_ref(), &mut io::stdout())
+}
+
+fn import(args: ImportArgs) -> Result<()> {
+    let redactor = match args.no_redact {
+        true => Redactor::default(),
+        false => Redactor::new(&Config::load(args.config.as_deref())?.redact)?,
+    };
+    let receipts = import::import(args.format, &args.file, &args.tag)?;
+    let count = import::write(receipts, &args.out_dir, &redactor)?;
+    println!(
+        "Imported {} receipts into {}",
+        count,
+        args.out_dir.display()
+    );
+    Ok(())
+}
+
+fn redact(args: RedactArgs) -> Result<()> {
+    let mut config = match args.configured {
+        true => Config::load(args.config.as_deref())?.redact,
+        false => RedactConfig {
+            builtin: false,
+            patterns: Vec::new(),
+        },
+    };
+    config
+        .patterns
+        .extend(args.pattern.iter().map(|pattern| PatternConfig {
+            name: "manual".to_string(),
+            pattern: pattern.clone(),
+        }));
+    if config.patterns.is_empty() && !config.builtin {
+        anyhow::bail!("rcpt redact needs a --pattern or --configured");
+    }
+    let redactor = Redactor::new(&config)?;
+    let signer = signer(
+        args.sign.as_deref(),
+        args.sign_ssh.as_deref(),
+        args.sign_keyless,
+        args.sign_detached,
+    )?;
+    let tsa = args.timestamp_url.as_deref().map(Tsa::new).transpose()?;
+    let dirs = match args.dirs.is_empty() {
+        true => vec![PathBuf::from(".")],
+        false => args.dirs,
+    };
+    let (path, mut receipt) = list::locate(&args.target, &dirs)?;
+    match redact::rewrite(
+        &path,
+        &mut receipt,
+        &redactor,
+        args.by.as_deref(),
+        signer.as_ref(),
+        tsa.as_ref(),
+    )? {
+        0 => println!("No matches in {}", path.display()),
+        count => println!("Masked {} matches in {}", count, path.display()),
+    }
+    Ok(())
+}
+
+fn replay(args: ReplayArgs) -> Result<()> {
+    signals::install();
+    let dirs = match args.dirs.is_empty() {
+        true => vec![PathBuf::from(".")],
+        false => args.dirs,
+    };
+    let (original_path, original) = list::locate(&args.target, &dirs)?;
+    let invocation = replay::invocation(&original)?;
+    let mut opts = args.exec.exec_options(replay::stdin(&original))?;
+    replay::configure(&original, &mut opts)?;
+    announce_id(&mut opts);
+    let mut receipt = execute_with_retries(&invocation, &opts, &args.exec.retry_policy())?;
+    receipt.replay_of = Some(original.id.clone());
+    let out = out_path(args.out.as_deref(), None, &Names::of(&receipt))?;
+    save_receipt(&opts, &mut receipt, &out)?;
+    finalize_sinks(&opts)?;
+    println!("Receipt written to: {}", opts.saved_to(&out).display());
+    if !args.check {
+        exit_with_status(&receipt)
+    }
+    if !replay::check(
+        &original_path,
+        &original,
+        opts.saved_to(&out),
+        &receipt,
+        &mut io::stdout(),
+    )? {
+        anyhow::bail!("The replay diverged from {}", original.id);
+    }
+    Ok(())
+}
+
+fn session(args: SessionArgs) -> Result<()> {
+    match args.command {
+        SessionCommand::Start => {
+            let id = session::start();
+            eprintln!("rcpt: session {} started", id);
+            println!("export {}={}", session::SESSION_ENV, id);
+        }
+        SessionCommand::End => {
+            let Some(id) = session::current() else {
+                anyhow::bail!("No session to end: {} is not set", session::SESSION_ENV);
+            };
+            eprintln!("rcpt: session {} ended", id);
+            println!("unset {}", session::SESSION_ENV);
+        }
+        SessionCommand::Show(args) => {
+            let Some(id) = args.id.or_else(session::current) else {
+                anyhow::bail!("No session given and {} is not set", session::SESSION_ENV);
+            };
+            let dirs = match args.dirs.is_empty() {
+                true => vec![PathBuf::from(".")],
+                false => args.dirs,
+            };
+            print!("{}", session::render(&id, &dirs)?);
+        }
+    }
+    Ok(())
+}
+
+fn pipe(args: PipeArgs) -> Result<()> {
+    signals::install();
+    let stages = pipeline::split_stages(&args.stages)?;
+    let mut opts = args.exec.exec_options(StdinMode::Inherit)?;
+    check_out(args.out.as_deref(), args.out_template.as_deref())?;
+    announce_id(&mut opts);
+    let mut receipt = with_retries(&opts, &args.exec.retry_policy(), || {
+        pipeline::execute_pipeline(&stages, &opts)
+    })?;
+    let out = out_path(
+        args.out.as_deref(),
+        args.out_template.as_deref(),
+        &Names::of(&receipt),
+    )?;
+    save_receipt(&opts, &mut receipt, &out)?;
+    finalize_sinks(&opts)?;
+    println!("Receipt written to: {}", opts.saved_to(&out).display());
+    exit_with_status(&receipt)
+}
+
+fn bench(args: BenchArgs) -> Result<()> {
+    if args.run.exec.retries > 0 {
+        anyhow::bail!("--retries is not supported for benchmarks");
+    }
+    signals::install();
+    let mut opts = args.run.exec.exec_options(StdinMode::Inherit)?;
+    check_out(args.run.out.as_deref(), args.run.out_template.as_deref())?;
+    announce_id(&mut opts);
+    let mut receipt = bench::run_bench(&args.run.invocation(), &opts, args.warmup, args.runs)?;
+    let out = args.run.out_path(&Names::of(&receipt))?;
+    save_receipt(&opts, &mut receipt, &out)?;
+    finalize_sinks(&opts)?;
+    if let Some(stats) = receipt.benchmark.as_ref().and_then(|b| b.stats.as_ref()) {
+        println!(
+            "Time (mean ± σ): {:.1} ms ± {:.1} ms  (median {:.1} ms, range {:.1} … {:.1} ms)",
+            stats.mean_ms, stats.stddev_ms, stats.median_ms, stats.min_ms, stats.max_ms
+        );
+    }
+    println!("Receipt written to: {}", opts.saved_to(&out).display());
+    exit_with_status(&receipt)
+}
+
+fn watch(args: WatchArgs) -> Result<()> {
+    signals::install();
+    let invocation = invocation_of(args.shell.as_deref(), &args.command);
+    let watcher = watch::Watcher {
+        paths: args.paths,
+        ignore: args.ignore,
+        interval: args.interval,
+        out_dir: args.out_dir,
+    };
+    let opts = args.exec.exec_options(StdinMode::Null)?;
+    watcher.run(
+        &invocation,
+        &opts,
+        &args.exec.retry_policy(),
+        &mut io::stdout(),
+    )?;
+    finalize_sinks(&opts)
+}
+
+fn schedule(args: ScheduleArgs) -> Result<()> {
+    signals::install();
+    let invocation = invocation_of(args.shell.as_deref(), &args.command);
+    let scheduler = schedule::Scheduler {
+        schedule: args.cron,
+        heartbeat: args
+            .heartbeat
+            .unwrap_or_else(|| args.out_dir.join("heartbeat.json")),
+        out_dir: args.out_dir,
+    };
+    let opts = args.exec.exec_options(StdinMode::Null)?;
+    scheduler.run(
+        &invocation,
+        &opts,
+        &args.exec.retry_policy(),
+        &mut io::stdout(),
+    )?;
+    finalize_sinks(&opts)
+}
+
+fn ssh(args: SshArgs) -> Result<()> {
+    signals::install();
+    let remote = remote::Remote {
+        destination: args.destination,
+        options: args.ssh_option,
+    };
+    let mut opts = args.exec.exec_options(StdinMode::Inherit)?;
+    if opts.no_network {
+        anyhow::bail!("--no-network cannot be combined with ssh, which needs the network");
+    }
+    check_out(args.out.as_deref(), args.out_template.as_deref())?;
+    announce_id(&mut opts);
+    let command = remote::remote_command(args.shell.as_deref(), &args.command);
+    let mut record = remote.plan(&command)?;
+    if !opts.dry_run {
+        remote.probe(&mut record)?;
+    }
+    let mut receipt = execute_with_retries(
+        &remote.invocation(&command, opts.pty, &record),
+        &opts,
+        &args.exec.retry_policy(),
+    )?;
+    record.exit_code = remote::remote_exit_code(receipt.exit_code);
+    receipt.remote = Some(record);
+    opts.redactor.apply(&mut receipt);
+    let out = out_path(
+        args.out.as_deref(),
+        args.out_template.as_deref(),
+        &Names::of(&receipt),
+    )?;
+    save_receipt(&opts, &mut receipt, &out)?;
+    finalize_sinks(&opts)?;
+    println!("Receipt written to: {}", opts.saved_to(&out).display());
+    exit_with_status(&receipt)
+}
+
+/// A flag's value, or else the non-empty value of an environment variable
+fn flag_or_env(flag: &Option<String>, name: &str) -> Option<String> {
+    flag.clone()
+        .or_else(|| std::env::var(name).ok().filter(|value| !value.is_empty()))
+}
+
+/// Fix a new id for the receipt about to be made, and report it on stderr
+fn announce_id(opts: &mut ExecOptions) -> String {
+    let id = id::ulid(chrono::Utc::now());
+    eprintln!("rcpt: receipt id {}", id);
+    opts.receipt_id = Some(id.clone());
+    id
+}
+
+/// Fail, before anything runs, if `template` is not one `out_path` can
+/// fill in for `out`
+fn check_out(out: Option<&Path>, template: Option<&str>) -> Result<()> {
+    let Some(template) = template else {
+        return Ok(());
+    };
+    if let Some(out) = out.filter(|out| !is_dir(out)) {
+        anyhow::bail!(
+            "--out-template needs --out to be a directory, not {}",
+            out.display()
+        );
+    }
+    template::check(template)
+}
+
+/// Where to write a receipt: `out`, unless it is a directory, in which
+/// the receipt is named after `template`, as it is without `out`
+fn out_path(out: Option<&Path>, template: Option<&str>, names: &Names) -> Result<PathBuf> {
+    let dir = match out {
+        Some(out) if !is_dir(out) => return Ok(out.to_path_buf()),
+        Some(dir) => dir,
+        None => Path::new(""),
+    };
+    Ok(dir.join(template::render(
+        template.unwrap_or(template::DEFAULT),
+        names,
+    )?))
+}
+
+/// Whether `path` is a directory, or names one with a trailing separator
+fn is_dir(path: &Path) -> bool {
+    path.is_dir()
+        || path
+            .as_os_str()
+            .to_string_lossy()
+            .ends_with(std::path::is_separator)
+}
+
+/// Exit the way the receipt's command did
+fn exit_with_status(receipt: &Receipt) -> ! {
+    match receipt.status {
+        Status::TimedOut => std::process::exit(TIMEOUT_EXIT_CODE),
+        // Follow the shell convention for death by signal
+        Status::Interrupted => {
+            std::process::exit(128 + receipt.interrupt_signal.unwrap_or_default())
+        }
+        Status::Planned => std::process::exit(0),
+        Status::MissingOutputs => std::process::exit(MISSING_OUTPUTS_EXIT_CODE),
+        Status::Ok | Status::Failed | Status::OomKilled | Status::Running | Status::Lost => {}
+    }
+
+    // Exit with the same code as the wrapped command
+    // Use 128 as fallback for abnormal termination (e.g., signal termination without exit code)
+    std::process::exit(receipt.exit_code.unwrap_or(128));
+}
+
+fn batch(args: BatchArgs) -> Result<()> {
+    signals::install();
+    if args.exec.attestation.is_some() {
+        anyhow::bail!("--attestation is not supported for batches, which make many receipts");
+    }
+    let commands = batch::read_commands(&args.file)?;
+    let opts = args.exec.exec_options(StdinMode::Null)?;
+    let manifest = batch::run_batch(
+        &commands,
+        args.jobs,
+        &args.out_dir,
+        &opts,
+        &args.exec.retry_policy(),
+        &mut io::stdout(),
+    )?;
+    finalize_sinks(&opts)?;
+    println!(
+        "{} of {} commands succeeded; manifest written to: {}",
+        manifest.succeeded,
+        manifest.total,
+        args.out_dir.join(batch::MANIFEST_FILE).display()
+    );
+
+    if manifest.succeeded < manifest.total {
+        std::process::exit(1);
+    }
+    Ok(())
+}
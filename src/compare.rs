@@ -0,0 +1,188 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::path::Path;
+
+use crate::receipt::Receipt;
+
+/// How much longer than its baseline a run may take, in percent of the
+/// baseline's duration, unless --tolerance says otherwise
+pub const DEFAULT_TOLERANCE: f64 = 20.0;
+
+/// How much longer than its baseline a run may take in any case, however
+/// short the baseline, unless --tolerance-ms says otherwise
+pub const DEFAULT_TOLERANCE_MS: u64 = 100;
+
+/// What a run is compared with its baseline on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Check {
+    /// How it ended: its exit code, or the signal or status that ended it
+    ExitCode,
+    /// The digest of its stdout
+    Stdout,
+    /// The digest of its stderr
+    Stderr,
+    /// How long it took
+    Duration,
+}
+
+impl Check {
+    fn as_str(self) -> &'static str {
+        match self {
+            Check::ExitCode => "exit_code",
+            Check::Stdout => "stdout",
+            Check::Stderr => "stderr",
+            Check::Duration => "duration",
+        }
+    }
+}
+
+/// What a check found
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Ok,
+    Regressed,
+    /// Skipped with --ignore
+    Ignored,
+}
+
+/// How much longer than its baseline a run may take before it counts as
+/// a regression: more than both of these
+#[derive(Debug, Clone, Copy)]
+pub struct Tolerance {
+    pub percent: f64,
+    pub ms: u64,
+}
+
+/// A receipt as the report names it
+#[derive(Debug, Serialize)]
+pub struct Side {
+    pub id: String,
+    pub path: String,
+}
+
+/// The outcome of one check, with what the baseline and the run had
+#[derive(Debug, Serialize)]
+pub struct Finding {
+    pub check: Check,
+    pub verdict: Verdict,
+    pub baseline: String,
+    pub candidate: String,
+}
+
+/// How a run compared with its baseline
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub baseline: Side,
+    pub candidate: Side,
+    /// Whether any check found a regression
+    pub regressed: bool,
+    pub checks: Vec<Finding>,
+}
+
+/// Compare `candidate` with `baseline` on how it ended, the digests of its
+/// stdout and stderr, and how long it took, except for the checks in
+/// `ignore`
+pub fn compare(
+    baseline_path: &Path,
+    baseline: &Receipt,
+    candidate_path: &Path,
+    candidate: &Receipt,
+    tolerance: Tolerance,
+    ignore: &[Check],
+) -> Result<Report> {
+    let mut checks = Vec::new();
+    for check in [
+        Check::ExitCode,
+        Check::Stdout,
+        Check::Stderr,
+        Check::Duration,
+    ] {
+        let (was, now, regressed) = match check {
+            Check::ExitCode => {
+                let (was, now) = (baseline.outcome(), candidate.outcome());
+                let regressed = was != now;
+                (was, now, regressed)
+            }
+            Check::Stdout | Check::Stderr => {
+                let was = baseline.stream_sha256(check.as_str(), baseline_path)?;
+                let now = candidate.stream_sha256(check.as_str(), candidate_path)?;
+                let regressed = was != now;
+                (was, now, regressed)
+            }
+            Check::Duration => {
+                let (was, now) = (baseline.duration_ms, candidate.duration_ms);
+                let slower = now.saturating_sub(was);
+                let regressed =
+                    slower > tolerance.ms && slower as f64 > was as f64 * tolerance.percent / 100.0;
+                (format!("{}ms", was), format!("{}ms", now), regressed)
+            }
+        };
+        let verdict = match (ignore.contains(&check), regressed) {
+            (true, _) => Verdict::Ignored,
+            (false, true) => Verdict::Regressed,
+            (false, false) => Verdict::Ok,
+        };
+        checks.push(Finding {
+            check,
+            verdict,
+            baseline: was,
+            candidate: now,
+        });
+    }
+    Ok(Report {
+        baseline: Side {
+            id: baseline.id.clone(),
+            path: baseline_path.display().to_string(),
+        },
+        candidate: Side {
+            id: candidate.id.clone(),
+            path: candidate_path.display().to_string(),
+        },
+        regressed: checks
+            .iter()
+            .any(|finding| finding.verdict == Verdict::Regressed),
+        checks,
+    })
+}
+
+impl Report {
+    /// A line for each check, after one naming the two receipts
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        let _ = writeln!(
+            text,
+            "{} against baseline {}",
+            self.candidate.id, self.baseline.id
+        );
+        for finding in &self.checks {
+            let (was, now) = match finding.check {
+                Check::Stdout | Check::Stderr => (
+                    format!("sha256 {}", short(&finding.baseline)),
+                    format!("sha256 {}", short(&finding.candidate)),
+                ),
+                _ => (finding.baseline.clone(), finding.candidate.clone()),
+            };
+            let detail = match (finding.verdict, was == now) {
+                (Verdict::Ignored, _) => String::new(),
+                (_, true) => format!(" ({})", now),
+                (_, false) => format!(" ({} → {})", was, now),
+            };
+            let verdict = match finding.verdict {
+                Verdict::Ok => "ok",
+                Verdict::Regressed => "regressed",
+                Verdict::Ignored => "ignored",
+            };
+            let _ = writeln!(text, "{}: {}{}", finding.check.as_str(), verdict, detail);
+        }
+        text
+    }
+}
+
+/// The start of a digest, enough to tell two apart
+fn short(digest: &str) -> &str {
+    &digest[..digest.len().min(12)]
+}
@@ -0,0 +1,114 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Environment variable naming the config file, overriding the default
+pub const CONFIG_ENV: &str = "RCPT_CONFIG";
+
+/// Settings read from rcpt's JSON config file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub redact: RedactConfig,
+    #[serde(default)]
+    pub s3: S3Config,
+    #[serde(default)]
+    pub webhook: WebhookConfig,
+}
+
+/// The `redact` section: which secrets are scrubbed from receipts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RedactConfig {
+    /// Whether the built-in patterns apply
+    #[serde(default = "default_true")]
+    pub builtin: bool,
+    /// Patterns to redact in addition to the built-in ones
+    #[serde(default)]
+    pub patterns: Vec<PatternConfig>,
+}
+
+impl Default for RedactConfig {
+    fn default() -> Self {
+        RedactConfig {
+            builtin: true,
+            patterns: Vec::new(),
+        }
+    }
+}
+
+/// The `s3` section: where `--sink s3://...` uploads to, and as whom. The
+/// AWS_* environment variables take precedence over each setting
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct S3Config {
+    /// URL of an S3-compatible service, instead of AWS for `region`
+    pub endpoint: Option<String>,
+    pub region: Option<String>,
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<String>,
+    /// Token for temporary credentials
+    pub session_token: Option<String>,
+}
+
+/// The `webhook` section: how `--sink https://...` reaches its endpoint
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    /// Headers sent with each receipt, such as `Authorization`, before
+    /// those from --sink-header
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+/// A user-defined secret pattern
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PatternConfig {
+    /// Name shown in place of each match, as `[REDACTED:<name>]`
+    pub name: String,
+    /// Regular expression matching the secret
+    pub pattern: String,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Config {
+    /// Read the config from `path`, else `$RCPT_CONFIG`, else the default
+    /// location if a file exists there
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match std::env::var_os(CONFIG_ENV).filter(|path| !path.is_empty()) {
+                Some(path) => PathBuf::from(path),
+                None => match default_path().filter(|path| path.exists()) {
+                    Some(path) => path,
+                    None => return Ok(Config::default()),
+                },
+            },
+        };
+        let contents = fs::read_to_string(&path)
+            .context(format!("Failed to read config {}", path.display()))?;
+        serde_json::from_str(&contents)
+            .context(format!("Failed to parse config {}", path.display()))
+    }
+}
+
+/// `rcpt/config.json` in the user's config directory: `$XDG_CONFIG_HOME`
+/// or `~/.config` on Unix, `%APPDATA%` on Windows
+fn default_path() -> Option<PathBuf> {
+    let dir = if cfg!(windows) {
+        PathBuf::from(std::env::var_os("APPDATA")?)
+    } else {
+        match std::env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        }
+    };
+    Some(dir.join("rcpt").join("config.json"))
+}
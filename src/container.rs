@@ -0,0 +1,279 @@
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use crate::exec::resolve_executable;
+use crate::limits::ResourceLimits;
+
+static NEXT_ID: AtomicU32 = AtomicU32::new(0);
+
+/// Container engine whose CLI runs the container
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Runtime {
+    Docker,
+    Podman,
+}
+
+impl Runtime {
+    fn program(self) -> &'static str {
+        match self {
+            Runtime::Docker => "docker",
+            Runtime::Podman => "podman",
+        }
+    }
+}
+
+/// A host path bind-mounted into the container
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Mount {
+    /// Absolute path on the host
+    pub source: String,
+    /// Path inside the container
+    pub target: String,
+    /// Whether the container may only read it
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub read_only: bool,
+}
+
+/// Parse a `--mount SOURCE:TARGET[:ro]` argument, resolving SOURCE to an
+/// absolute path
+pub fn parse_mount(s: &str) -> Result<Mount> {
+    let (spec, read_only) = match s.strip_suffix(":ro") {
+        Some(spec) => (spec, true),
+        None => (s.strip_suffix(":rw").unwrap_or(s), false),
+    };
+    let Some((source, target)) = spec.split_once(':') else {
+        bail!("expected SOURCE:TARGET[:ro], got '{}'", s);
+    };
+    if !target.starts_with('/') {
+        bail!("mount target '{}' must be an absolute path", target);
+    }
+    let source = fs::canonicalize(source).context(format!("Mount source {} not found", source))?;
+    Ok(Mount {
+        source: source.to_string_lossy().to_string(),
+        target: target.to_string(),
+        read_only,
+    })
+}
+
+/// What to run the command in, from --container and related flags
+#[derive(Debug, Clone)]
+pub struct ContainerSpec {
+    /// Image reference as given
+    pub image: String,
+    /// Engine to use; the first of docker and podman on PATH if not given
+    pub runtime: Option<Runtime>,
+    /// Mounts in addition to the working directory
+    pub mounts: Vec<Mount>,
+}
+
+/// The container a command ran in
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContainerRecord {
+    /// Engine that ran the container
+    pub runtime: Runtime,
+    /// Image reference as given
+    pub image: String,
+    /// Local id of the image that ran (absent in dry runs of images that
+    /// were not pulled yet)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_id: Option<String>,
+    /// Registry digest of the image, as `repository@sha256:...` (absent
+    /// for images built locally and never pushed)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+    /// Name given to the container
+    pub name: String,
+    /// Working directory inside the container
+    pub workdir: String,
+    /// Host paths mounted into the container, starting with the working
+    /// directory
+    pub mounts: Vec<Mount>,
+}
+
+/// A container ready to run a command, with its image resolved
+pub struct Container {
+    program: PathBuf,
+    pub record: ContainerRecord,
+}
+
+impl ContainerSpec {
+    /// Find the runtime and resolve the image, pulling it first if it is
+    /// not available locally and `pull` is set. The container mounts `cwd`
+    /// at the same path and starts there.
+    pub fn prepare(&self, cwd: &Path, pull: bool) -> Result<Container> {
+        let path = std::env::var_os("PATH");
+        let (runtime, program) = match self.runtime {
+            Some(runtime) => {
+                let program =
+                    resolve_executable(OsStr::new(runtime.program()), path.as_ref(), cwd).context(
+                        format!("Container runtime not found: {}", runtime.program()),
+                    )?;
+                (runtime, program)
+            }
+            None => [Runtime::Docker, Runtime::Podman]
+                .into_iter()
+                .find_map(|runtime| {
+                    let program =
+                        resolve_executable(OsStr::new(runtime.program()), path.as_ref(), cwd)?;
+                    Some((runtime, program))
+                })
+                .context("No container runtime found (install docker or podman)")?,
+        };
+
+        let mut inspected = inspect(&program, &self.image);
+        if inspected.is_none() && pull {
+            let status = Command::new(&program)
+                .args(["pull", "--quiet", &self.image])
+                .stdout(Stdio::null())
+                .status()
+                .context(format!("Failed to run {}", program.display()))?;
+            if !status.success() {
+                bail!("Failed to pull container image {}", self.image);
+            }
+            inspected = inspect(&program, &self.image);
+            if inspected.is_none() {
+                bail!("Failed to inspect container image {}", self.image);
+            }
+        }
+        let (image_id, digest) = match inspected {
+            Some((id, digest)) => (Some(id), digest),
+            None => (None, None),
+        };
+
+        let workdir = cwd.to_string_lossy().to_string();
+        let mut mounts = Vec::new();
+        if !self.mounts.iter().any(|mount| mount.target == workdir) {
+            mounts.push(Mount {
+                source: workdir.clone(),
+                target: workdir.clone(),
+                read_only: false,
+            });
+        }
+        mounts.extend(self.mounts.iter().cloned());
+
+        let name = format!(
+            "rcpt-{}-{}",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        );
+        Ok(Container {
+            program,
+            record: ContainerRecord {
+                runtime,
+                image: self.image.clone(),
+                image_id,
+                digest,
+                name,
+                workdir,
+                mounts,
+            },
+        })
+    }
+}
+
+impl Container {
+    /// The runtime invocation that runs `argv` in the container. Variables
+    /// in `env_names` are passed through from the runtime's own environment,
+    /// so their values never appear in its arguments.
+    pub fn argv(
+        &self,
+        argv: &[OsString],
+        env_names: &[String],
+        limits: &ResourceLimits,
+        no_network: bool,
+        interactive: bool,
+        tty: bool,
+    ) -> Vec<OsString> {
+        let record = &self.record;
+        let mut run: Vec<OsString> = vec![self.program.clone().into()];
+        let mut push = |arg: String| run.push(arg.into());
+        for arg in [
+            "run",
+            "--rm",
+            "--name",
+            &record.name,
+            "--workdir",
+            &record.workdir,
+        ] {
+            push(arg.to_string());
+        }
+        for mount in &record.mounts {
+            let mode = if mount.read_only { ":ro" } else { "" };
+            push("--volume".into());
+            push(format!("{}:{}{}", mount.source, mount.target, mode));
+        }
+        for name in env_names {
+            push("--env".into());
+            push(name.clone());
+        }
+        if let Some(bytes) = limits.memory_bytes {
+            push(format!("--memory={}", bytes));
+        }
+        for (ulimit, value) in [
+            ("cpu", limits.cpu_seconds),
+            ("nofile", limits.open_files),
+            ("fsize", limits.file_size_bytes),
+        ] {
+            if let Some(value) = value {
+                push(format!("--ulimit={}={}", ulimit, value));
+            }
+        }
+        if no_network {
+            push("--network=none".into());
+        }
+        if interactive {
+            push("--interactive".into());
+        }
+        if tty {
+            push("--tty".into());
+        }
+        // Run exactly the image that was recorded, even if the tag moves
+        push(
+            record
+                .image_id
+                .clone()
+                .unwrap_or_else(|| record.image.clone()),
+        );
+        run.extend(argv.iter().cloned());
+        run
+    }
+
+    /// Force-remove the container, for when its runtime client was killed
+    /// before it could clean up
+    pub fn remove(&self) {
+        let _ = Command::new(&self.program)
+            .args(["rm", "--force", &self.record.name])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+    }
+}
+
+/// Local id and registry digest of `image`, if it is available locally
+fn inspect(program: &Path, image: &str) -> Option<(String, Option<String>)> {
+    let output = Command::new(program)
+        .args([
+            "image",
+            "inspect",
+            "--format",
+            "{{.Id}} {{join .RepoDigests \" \"}}",
+            image,
+        ])
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut fields = stdout.split_whitespace();
+    let id = fields.next()?.to_string();
+    Some((id, fields.next().map(str::to_string)))
+}
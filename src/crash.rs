@@ -0,0 +1,228 @@
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::time::SystemTime;
+
+use crate::digest::sha256_file;
+use crate::env::glob_match;
+use crate::receipt::Receipt;
+
+/// How the command crashed, when a fatal signal rcpt did not send killed it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrashRecord {
+    /// Process that crashed
+    pub pid: u32,
+    /// Signal that killed it, e.g. `SIGSEGV`
+    pub signal: String,
+    /// Whether the kernel reported dumping core
+    pub core_dumped: bool,
+    /// Where the kernel wrote the core dump
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub core_path: Option<String>,
+    /// SHA-256 digest of the core dump
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub core_sha256: Option<String>,
+    /// Size of the core dump in bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub core_size_bytes: Option<u64>,
+    /// Program the kernel piped the core dump to instead of writing a file,
+    /// from its core pattern
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub core_handler: Option<String>,
+    /// Copy of the core dump kept beside the receipt, for --keep-core
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stored: Option<String>,
+}
+
+/// What the process `pid`, started in `cwd` at `since`, left behind if
+/// `status` says a signal killed it (Unix only)
+pub fn inspect(
+    status: &ExitStatus,
+    pid: u32,
+    cwd: &Path,
+    since: SystemTime,
+) -> Option<CrashRecord> {
+    let (signal, core_dumped) = imp::fatal_signal(status)?;
+    let mut crash = CrashRecord {
+        pid,
+        signal,
+        core_dumped,
+        core_path: None,
+        core_sha256: None,
+        core_size_bytes: None,
+        core_handler: None,
+        stored: None,
+    };
+    if !core_dumped {
+        return Some(crash);
+    }
+    let pattern = imp::core_pattern();
+    match pattern.strip_prefix('|') {
+        Some(handler) => {
+            crash.core_handler = handler.split_whitespace().next().map(str::to_string);
+        }
+        None => {
+            if let Some(path) = find_core(&pattern, pid, cwd, since) {
+                if let Ok((sha256, size)) = sha256_file(&path) {
+                    crash.core_sha256 = Some(sha256);
+                    crash.core_size_bytes = Some(size);
+                }
+                crash.core_path = Some(path.to_string_lossy().to_string());
+            }
+        }
+    }
+    Some(crash)
+}
+
+/// Move the receipt's core dump to `<out>.core`, or export it there from
+/// systemd-coredump. The command has already run, so failures are reported
+/// but leave the receipt as it is.
+pub fn keep_core(receipt: &mut Receipt, out: &Path) {
+    let Some(crash) = &mut receipt.crash else {
+        return;
+    };
+    let mut dest = OsString::from(out.as_os_str());
+    dest.push(".core");
+    let dest = PathBuf::from(dest);
+    let kept = match (&crash.core_path, &crash.core_handler) {
+        (Some(path), _) => {
+            fs::rename(path, &dest).is_ok()
+                || fs::copy(path, &dest).is_ok_and(|_| fs::remove_file(path).is_ok())
+        }
+        (None, Some(handler)) if handler.ends_with("systemd-coredump") => {
+            let exported = Command::new("coredumpctl")
+                .args([
+                    "--quiet",
+                    "--no-pager",
+                    "dump",
+                    &crash.pid.to_string(),
+                    "--output",
+                ])
+                .arg(&dest)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .is_ok_and(|status| status.success());
+            if exported {
+                if let Ok((sha256, size)) = sha256_file(&dest) {
+                    crash.core_sha256 = Some(sha256);
+                    crash.core_size_bytes = Some(size);
+                }
+            }
+            exported
+        }
+        _ => return,
+    };
+    if kept {
+        crash.stored = Some(dest.to_string_lossy().to_string());
+    } else {
+        eprintln!("rcpt: failed to keep core dump at {}", dest.display());
+    }
+}
+
+/// The newest file written since `since` that `pattern` could have named,
+/// with `%p` as `pid` and every other specifier matching anything
+fn find_core(pattern: &str, pid: u32, cwd: &Path, since: SystemTime) -> Option<PathBuf> {
+    let mut pattern = pattern.trim().to_string();
+    if imp::core_uses_pid() && !pattern.contains("%p") {
+        pattern.push_str(".%p");
+    }
+    let (dir, name) = match pattern.rsplit_once('/') {
+        Some(("", name)) => (PathBuf::from("/"), name),
+        Some((dir, name)) => (cwd.join(dir), name),
+        None => (cwd.to_path_buf(), pattern.as_str()),
+    };
+    if dir.to_string_lossy().contains('%') {
+        return None;
+    }
+    let glob = name_glob(name, pid);
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| glob_match(&glob, &entry.file_name().to_string_lossy()))
+        .filter_map(|entry| {
+            let modified = entry.metadata().ok()?.modified().ok()?;
+            (modified >= since).then(|| (modified, entry.path()))
+        })
+        .max()
+        .map(|(_, path)| path)
+}
+
+/// A glob matching the file names the core `pattern` can produce
+fn name_glob(pattern: &str, pid: u32) -> String {
+    let mut glob = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            glob.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => glob.push('%'),
+            // Linux uses %p, macOS %P
+            Some('p' | 'P') => glob.push_str(&pid.to_string()),
+            _ => glob.push('*'),
+        }
+    }
+    glob
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+
+    use crate::signals;
+
+    pub fn fatal_signal(status: &ExitStatus) -> Option<(String, bool)> {
+        // A broken pipe is how a pipeline stops an early stage, not a crash
+        let signal = status.signal().filter(|&signal| signal != libc::SIGPIPE)?;
+        Some((signals::name(signal), status.core_dumped()))
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn core_pattern() -> String {
+        std::fs::read_to_string("/proc/sys/kernel/core_pattern").unwrap_or_else(|_| "core".into())
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn core_pattern() -> String {
+        std::process::Command::new("sysctl")
+            .args(["-n", "kern.corefile"])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+            .unwrap_or_else(|| "/cores/core.%P".into())
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    pub fn core_pattern() -> String {
+        "%N.core".into()
+    }
+
+    /// Whether Linux appends the pid to core file names without a %p
+    pub fn core_uses_pid() -> bool {
+        std::fs::read_to_string("/proc/sys/kernel/core_uses_pid")
+            .is_ok_and(|value| value.trim() == "1")
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use std::process::ExitStatus;
+
+    pub fn fatal_signal(_status: &ExitStatus) -> Option<(String, bool)> {
+        None
+    }
+
+    pub fn core_pattern() -> String {
+        String::new()
+    }
+
+    pub fn core_uses_pid() -> bool {
+        false
+    }
+}
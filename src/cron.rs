@@ -0,0 +1,398 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, TimeZone, Timelike};
+use std::str::FromStr;
+
+/// How far ahead to look for the next matching minute before giving up
+/// (covers leap days and "30 of February"-style impossible schedules)
+const SEARCH_DAYS: i64 = 5 * 366;
+
+const MONTHS: &[&str] = &[
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: &[&str] = &["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// A standard five-field cron expression (minute hour day-of-month month
+/// day-of-week), evaluated in the time zone of the times it is given
+#[derive(Debug, Clone)]
+pub struct CronSchedule {
+    expression: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether day-of-month and day-of-week were both restricted, in which
+    /// case a day matching either one fires (as in Vixie cron)
+    either_day: bool,
+}
+
+impl FromStr for CronSchedule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let expanded = match s.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            bail!(
+                "expected 5 fields (minute hour day month weekday), got '{}'",
+                s
+            );
+        };
+        let mut weekdays = parse_field(weekday, 0, 7, Some(WEEKDAYS))
+            .context(format!("invalid day-of-week field '{}'", weekday))?;
+        // Both 0 and 7 mean Sunday
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+        Ok(CronSchedule {
+            expression: s.trim().to_string(),
+            minutes: parse_field(minute, 0, 59, None)
+                .context(format!("invalid minute field '{}'", minute))?,
+            hours: parse_field(hour, 0, 23, None)
+                .context(format!("invalid hour field '{}'", hour))?,
+            days: parse_field(day, 1, 31, None)
+                .context(format!("invalid day-of-month field '{}'", day))?,
+            months: parse_field(month, 1, 12, Some(MONTHS))
+                .context(format!("invalid month field '{}'", month))?,
+            weekdays,
+            either_day: !day.starts_with('*') && !weekday.starts_with('*'),
+        })
+    }
+}
+
+/// Parse one field into a bitmask of the values it allows
+fn parse_field(field: &str, min: u32, max: u32, names: Option<&[&str]>) -> Result<u64> {
+    let value = |s: &str| -> Result<u32> {
+        let lower = s.to_ascii_lowercase();
+        if let Some(index) = names.and_then(|names| names.iter().position(|n| *n == lower)) {
+            // Names count from the field's minimum (jan = 1, sun = 0)
+            return Ok(index as u32 + min);
+        }
+        let n: u32 = s.parse().context(format!("'{}' is not a number", s))?;
+        if n < min || n > max {
+            bail!("{} is outside {}-{}", n, min, max);
+        }
+        Ok(n)
+    };
+
+    let mut mask = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step.parse().context(format!("invalid step '{}'", step))?;
+                if step == 0 {
+                    bail!("step must be positive");
+                }
+                (range, step)
+            }
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (value(start)?, value(end)?),
+                // "5/15" means every 15 starting at 5
+                None if step > 1 => (value(range)?, max),
+                None => {
+                    let n = value(range)?;
+                    (n, n)
+                }
+            },
+        };
+        if start > end {
+            bail!("range {}-{} is backwards", start, end);
+        }
+        for n in (start..=end).step_by(step as usize) {
+            mask |= 1 << n;
+        }
+    }
+    Ok(mask)
+}
+
+impl CronSchedule {
+    /// The expression as given
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+
+    /// The first time strictly after `after` that the schedule fires, in
+    /// the same time zone
+    pub fn next_after<Tz: TimeZone>(&self, after: DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let start = after.naive_local().with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let give_up = start + Duration::days(SEARCH_DAYS);
+        let mut t = start;
+        while t < give_up {
+            if !self.allows(self.months, t.month()) {
+                t = first_of_next_month(t)?;
+                continue;
+            }
+            if !self.day_matches(t) {
+                t = t.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+                continue;
+            }
+            if !self.allows(self.hours, t.hour()) {
+                t = t.with_minute(0)? + Duration::hours(1);
+                continue;
+            }
+            if !self.allows(self.minutes, t.minute()) {
+                t += Duration::minutes(1);
+                continue;
+            }
+            // Times skipped by a DST change never happen; fold-back times fire once
+            if let Some(time) = after.timezone().from_local_datetime(&t).earliest() {
+                return Some(time);
+            }
+            t += Duration::minutes(1);
+        }
+        None
+    }
+
+    fn allows(&self, mask: u64, value: u32) -> bool {
+        mask & (1 << value) != 0
+    }
+
+    fn day_matches(&self, t: NaiveDateTime) -> bool {
+        let day = self.allows(self.days, t.day());
+        let weekday = self.allows(self.weekdays, t.weekday().num_days_from_sunday());
+        if self.either_day {
+            day || weekday
+        } else {
+            day && weekday
+        }
+    }
+}
+
+fn first_of_next_month(t: NaiveDateTime) -> Option<NaiveDateTime> {
+    let (year, month) = match t.month() {
+        12 => (t.year() + 1, 1),
+        month => (t.year(), month + 1),
+    };
+    NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, MappedLocalTime, Utc};
+    use std::fmt::Display;
+
+    /// London in 2026: GMT, and BST (+01:00) from 01:00 UTC on 29 March
+    /// until 01:00 UTC on 25 October
+    #[derive(Debug, Clone, Copy)]
+    struct London;
+
+    impl London {
+        fn summer(utc: &NaiveDateTime) -> bool {
+            let start = NaiveDate::from_ymd_opt(2026, 3, 29)
+                .unwrap()
+                .and_hms_opt(1, 0, 0);
+            let end = NaiveDate::from_ymd_opt(2026, 10, 25)
+                .unwrap()
+                .and_hms_opt(1, 0, 0);
+            (start.unwrap()..end.unwrap()).contains(utc)
+        }
+    }
+
+    impl TimeZone for London {
+        type Offset = FixedOffset;
+
+        fn from_offset(_: &FixedOffset) -> Self {
+            London
+        }
+
+        fn offset_from_local_date(&self, local: &NaiveDate) -> MappedLocalTime<FixedOffset> {
+            self.offset_from_local_datetime(&local.and_hms_opt(0, 0, 0).unwrap())
+        }
+
+        fn offset_from_local_datetime(
+            &self,
+            local: &NaiveDateTime,
+        ) -> MappedLocalTime<FixedOffset> {
+            // Summer time first, so that a repeated time's earliest is first
+            let offsets: Vec<FixedOffset> = [3600, 0]
+                .into_iter()
+                .map(|secs| FixedOffset::east_opt(secs).unwrap())
+                .filter(|offset| self.offset_from_utc_datetime(&(*local - *offset)) == *offset)
+                .collect();
+            match offsets[..] {
+                [] => MappedLocalTime::None,
+                [offset] => MappedLocalTime::Single(offset),
+                [earliest, latest] => MappedLocalTime::Ambiguous(earliest, latest),
+                _ => unreachable!(),
+            }
+        }
+
+        fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+            self.offset_from_utc_datetime(&utc.and_hms_opt(0, 0, 0).unwrap())
+        }
+
+        fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+            FixedOffset::east_opt(if London::summer(utc) { 3600 } else { 0 }).unwrap()
+        }
+    }
+
+    fn next<Tz: TimeZone>(expression: &str, after: DateTime<Tz>) -> Option<String>
+    where
+        Tz::Offset: Display,
+    {
+        let schedule: CronSchedule = expression.parse().unwrap();
+        schedule.next_after(after).map(|time| time.to_rfc3339())
+    }
+
+    fn utc(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    fn london(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<London> {
+        London
+            .with_ymd_and_hms(year, month, day, hour, minute, 0)
+            .earliest()
+            .unwrap()
+    }
+
+    #[test]
+    fn fires_on_the_next_matching_minute() {
+        let every_quarter = "*/15 9-17 * jan,jul mon-fri";
+        assert_eq!(
+            next(every_quarter, utc(2026, 1, 5, 8, 50)).as_deref(),
+            Some("2026-01-05T09:00:00+00:00")
+        );
+        assert_eq!(
+            next(every_quarter, utc(2026, 1, 5, 9, 0)).as_deref(),
+            Some("2026-01-05T09:15:00+00:00")
+        );
+        // Friday evening waits for Monday morning
+        assert_eq!(
+            next(every_quarter, utc(2026, 1, 9, 17, 45)).as_deref(),
+            Some("2026-01-12T09:00:00+00:00")
+        );
+        // The end of January waits for July
+        assert_eq!(
+            next(every_quarter, utc(2026, 1, 30, 18, 0)).as_deref(),
+            Some("2026-07-01T09:00:00+00:00")
+        );
+        assert_eq!(
+            next("5/20 * * * *", utc(2026, 1, 5, 8, 50)).as_deref(),
+            Some("2026-01-05T09:05:00+00:00")
+        );
+    }
+
+    #[test]
+    fn expands_shorthands() {
+        let monday = utc(2026, 2, 16, 1, 15);
+        assert_eq!(next("@weekly", monday), next("0 0 * * 0", monday));
+        assert_eq!(next("@daily", monday), next("0 0 * * *", monday));
+        assert_eq!(
+            next("@hourly", monday).as_deref(),
+            Some("2026-02-16T02:00:00+00:00")
+        );
+    }
+
+    #[test]
+    fn counts_sunday_as_0_or_7() {
+        let monday = utc(2026, 2, 16, 1, 15);
+        let sunday = Some("2026-02-22T00:00:00+00:00");
+        assert_eq!(next("0 0 * * 0", monday).as_deref(), sunday);
+        assert_eq!(next("0 0 * * 7", monday).as_deref(), sunday);
+        assert_eq!(next("0 0 * * sun", monday).as_deref(), sunday);
+        assert_eq!(
+            next("0 0 * * 5-7", monday).as_deref(),
+            Some("2026-02-20T00:00:00+00:00")
+        );
+    }
+
+    #[test]
+    fn fires_on_either_day_when_both_are_restricted() {
+        let thirteenth_or_friday = "0 12 13 * fri";
+        assert_eq!(
+            next(thirteenth_or_friday, utc(2026, 4, 1, 0, 0)).as_deref(),
+            Some("2026-04-03T12:00:00+00:00")
+        );
+        // 13 April 2026 is a Monday
+        assert_eq!(
+            next(thirteenth_or_friday, utc(2026, 4, 10, 12, 0)).as_deref(),
+            Some("2026-04-13T12:00:00+00:00")
+        );
+        // A stepped day-of-month restricts alongside day-of-week instead
+        assert_eq!(
+            next("0 12 */2 * fri", utc(2026, 4, 1, 0, 0)).as_deref(),
+            Some("2026-04-03T12:00:00+00:00")
+        );
+        assert_eq!(
+            next("0 12 */2 * fri", utc(2026, 4, 3, 12, 0)).as_deref(),
+            Some("2026-04-17T12:00:00+00:00")
+        );
+    }
+
+    #[test]
+    fn waits_for_leap_days_and_gives_up_on_impossible_dates() {
+        assert_eq!(
+            next("0 0 29 2 *", utc(2026, 3, 1, 0, 0)).as_deref(),
+            Some("2028-02-29T00:00:00+00:00")
+        );
+        assert_eq!(next("0 0 30 2 *", utc(2026, 3, 1, 0, 0)), None);
+    }
+
+    #[test]
+    fn skips_times_that_daylight_saving_skips() {
+        // 01:00 to 01:59 never happen in London on 29 March 2026
+        assert_eq!(
+            next("*/30 * * * *", london(2026, 3, 29, 0, 45)).as_deref(),
+            Some("2026-03-29T02:00:00+01:00")
+        );
+        assert_eq!(
+            next("30 1 * * *", london(2026, 3, 28, 12, 0)).as_deref(),
+            Some("2026-03-30T01:30:00+01:00")
+        );
+        // A weekly job due in the gap waits a week, not a day
+        assert_eq!(
+            next("30 1 * * sun", london(2026, 3, 22, 12, 0)).as_deref(),
+            Some("2026-04-05T01:30:00+01:00")
+        );
+    }
+
+    #[test]
+    fn fires_once_in_times_that_daylight_saving_repeats() {
+        // 01:00 to 01:59 happen twice in London on 25 October 2026
+        let first = next("30 1 * * *", london(2026, 10, 25, 0, 0));
+        assert_eq!(first.as_deref(), Some("2026-10-25T01:30:00+01:00"));
+        let first = DateTime::parse_from_rfc3339(&first.unwrap())
+            .unwrap()
+            .with_timezone(&London);
+        assert_eq!(
+            next("30 1 * * *", first).as_deref(),
+            Some("2026-10-26T01:30:00+00:00")
+        );
+        // Hourly jobs keep to the wall clock, so the repeated hour is skipped
+        assert_eq!(
+            next("0 * * * *", london(2026, 10, 25, 1, 0)).as_deref(),
+            Some("2026-10-25T02:00:00+00:00")
+        );
+    }
+
+    #[test]
+    fn rejects_bad_expressions() {
+        for bad in [
+            "* * * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "* * * 13 *",
+            "* * * * 8",
+            "5-1 * * * *",
+            "*/0 * * * *",
+            "* * * foo *",
+            "@fortnightly",
+        ] {
+            assert!(bad.parse::<CronSchedule>().is_err(), "'{}' parsed", bad);
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::procgroup;
+use crate::receipt::{read_receipt, write_receipt, Receipt, Status};
+
+/// Set in the environment of the background rcpt that supervises a detached
+/// run, to the id of its receipt
+const SUPERVISOR_ENV: &str = "RCPT_DETACHED_SUPERVISOR";
+
+/// How often the receipt is checked while waiting on a detached run
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The receipt id, if this rcpt is the background supervisor of a detached
+/// run. The marker is removed so the command does not inherit it.
+pub fn supervisor() -> Option<String> {
+    let id = std::env::var(SUPERVISOR_ENV).ok();
+    std::env::remove_var(SUPERVISOR_ENV);
+    id
+}
+
+/// Where the supervisor's own diagnostics go
+fn log_path(receipt: &Path) -> PathBuf {
+    let mut path = OsString::from(receipt.as_os_str());
+    path.push(".log");
+    PathBuf::from(path)
+}
+
+/// Start a background rcpt with the same arguments to run the command under
+/// receipt `id`, and return the `running` receipt it writes once the command
+/// has started
+pub fn start(out: &Path, id: &str) -> Result<Receipt> {
+    match fs::remove_file(out) {
+        Err(err) if err.kind() != ErrorKind::NotFound => {
+            return Err(err).context(format!("Failed to remove old receipt {}", out.display()))
+        }
+        _ => {}
+    }
+    if let Some(parent) = out.parent() {
+        fs::create_dir_all(parent).context(format!(
+            "Failed to create parent directories for {}",
+            out.display()
+        ))?;
+    }
+    let log_path = log_path(out);
+    let log = File::create(&log_path)
+        .context(format!("Failed to create log file {}", log_path.display()))?;
+
+    let exe = std::env::current_exe().context("Failed to locate the rcpt executable")?;
+    let mut command = Command::new(exe);
+    command
+        .args(std::env::args_os().skip(1))
+        .env(SUPERVISOR_ENV, id)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(log);
+    procgroup::detach(&mut command);
+    let mut supervisor = command.spawn().context("Failed to start background rcpt")?;
+
+    loop {
+        if let Some(receipt) = read(out) {
+            return Ok(receipt);
+        }
+        if let Some(status) = supervisor
+            .try_wait()
+            .context("Failed to wait for background rcpt")?
+        {
+            // It may have run the whole command between two checks
+            if let Some(receipt) = read(out) {
+                return Ok(receipt);
+            }
+            let log = fs::read_to_string(&log_path).unwrap_or_default();
+            anyhow::bail!(
+                "Background rcpt exited ({}) before starting the command\n{}",
+                status,
+                log.trim_end()
+            );
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Remove the supervisor's log file if nothing was written to it
+pub fn remove_empty_log(out: &Path) {
+    let path = log_path(out);
+    if fs::metadata(&path).is_ok_and(|meta| meta.len() == 0) {
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// Wait for the detached run recorded in `path` to finish, returning its
+/// final receipt, or None if `timeout` passes first. If the supervisor died
+/// without finalizing the receipt, it is finalized here as `lost` once the
+/// command itself has gone.
+pub fn wait(path: &Path, timeout: Option<Duration>) -> Result<Option<Receipt>> {
+    let give_up = timeout.map(|timeout| Instant::now() + timeout);
+    let mut receipt = read_receipt(path)?;
+
+    loop {
+        // A receipt caught mid-write fails to parse; keep the last good one
+        if let Some(latest) = read(path) {
+            receipt = latest;
+        }
+        if receipt.status != Status::Running {
+            return Ok(Some(receipt));
+        }
+        let supervised = receipt.supervisor_pid.is_some_and(procgroup::is_alive);
+        if !supervised && !receipt.pid.is_some_and(procgroup::is_alive) {
+            // The supervisor may have finalized the receipt just before exiting
+            if let Some(latest) = read(path).filter(|r| r.status != Status::Running) {
+                return Ok(Some(latest));
+            }
+            let end_time = Utc::now();
+            receipt.status = Status::Lost;
+            receipt.end_time = end_time;
+            receipt.duration_ms = (end_time - receipt.start_time).num_milliseconds().max(0) as u64;
+            write_receipt(path, &receipt, false)?;
+            return Ok(Some(receipt));
+        }
+        if give_up.is_some_and(|give_up| Instant::now() >= give_up) {
+            return Ok(None);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn read(path: &Path) -> Option<Receipt> {
+    read_receipt(path).ok()
+}
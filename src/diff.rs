@@ -0,0 +1,270 @@
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::path::Path;
+
+use crate::receipt::Receipt;
+use crate::show::paint;
+
+/// Unchanged lines shown around each change in the output's diff
+const CONTEXT: usize = 3;
+
+/// How many edits apart two outputs may be before they are shown as one
+/// replaced by the other, rather than searched for the smallest diff
+const MAX_EDITS: usize = 1024;
+
+/// One step from one list of lines to the other
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edit {
+    /// A line both have
+    Same,
+    /// A line only the first has
+    Delete,
+    /// A line only the second has
+    Insert,
+}
+
+/// What changed from the receipt `a`, at `a_path`, to `b`, at `b_path`:
+/// the command, environment, status, exit code and duration, then a
+/// unified diff of each output stream
+pub fn render(a_path: &Path, a: &Receipt, b_path: &Path, b: &Receipt, color: bool) -> String {
+    let mut page = String::new();
+    let _ = writeln!(
+        page,
+        "{}",
+        paint(color, "1", &format!("--- {} ({})", a.id, a_path.display()))
+    );
+    let _ = writeln!(
+        page,
+        "{}",
+        paint(color, "1", &format!("+++ {} ({})", b.id, b_path.display()))
+    );
+
+    let mut field = |label: &str, before: String, after: String| {
+        let _ = match before == after {
+            true => writeln!(page, "{:<10} {}", format!("{}:", label), before),
+            false => writeln!(
+                page,
+                "{:<10} {} → {}",
+                format!("{}:", label),
+                paint(color, "31", &before),
+                paint(color, "32", &after)
+            ),
+        };
+    };
+    field("Command", a.command_line(), b.command_line());
+    field("Cwd", a.cwd.clone(), b.cwd.clone());
+    field("Env", a.env_digest.clone(), b.env_digest.clone());
+    field(
+        "Status",
+        a.status.as_str().to_string(),
+        b.status.as_str().to_string(),
+    );
+    field("Exit code", exit_code(a), exit_code(b));
+    let mut duration = format!("{}ms", b.duration_ms);
+    if a.duration_ms > 0 && a.duration_ms != b.duration_ms {
+        let change = (b.duration_ms as f64 - a.duration_ms as f64) / a.duration_ms as f64;
+        duration.push_str(&format!(" ({:+.1}%)", change * 100.0));
+    }
+    field("Duration", format!("{}ms", a.duration_ms), duration);
+    if let (Some(a_env), Some(b_env)) = (&a.env, &b.env) {
+        let names: BTreeSet<&String> = a_env.keys().chain(b_env.keys()).collect();
+        let changed: Vec<&str> = names
+            .into_iter()
+            .filter(|name| a_env.get(*name) != b_env.get(*name))
+            .map(String::as_str)
+            .collect();
+        if !changed.is_empty() {
+            let _ = writeln!(page, "{:<10} {}", "Env vars:", changed.join(", "));
+        }
+    }
+
+    for stream in ["stdout", "stderr"] {
+        let _ = writeln!(page);
+        let (before, after) = match (a.written(stream, a_path), b.written(stream, b_path)) {
+            (Ok(before), Ok(after)) => (before, after),
+            (Err(err), _) | (_, Err(err)) => {
+                let _ = writeln!(page, "{}: unavailable: {:#}", stream, err);
+                continue;
+            }
+        };
+        if before == after {
+            let _ = writeln!(page, "{}: identical", stream);
+            continue;
+        }
+        let _ = writeln!(page, "{}", paint(color, "1", &format!("{}:", stream)));
+        let before = String::from_utf8_lossy(&before);
+        let after = String::from_utf8_lossy(&after);
+        page.push_str(&unified(
+            &before.lines().collect::<Vec<_>>(),
+            &after.lines().collect::<Vec<_>>(),
+            color,
+        ));
+    }
+    page
+}
+
+fn exit_code(receipt: &Receipt) -> String {
+    match (receipt.exit_code, &receipt.terminated_by) {
+        (Some(code), _) => code.to_string(),
+        (None, Some(signal)) => signal.name.clone(),
+        (None, None) => "none".to_string(),
+    }
+}
+
+/// A unified diff from `a` to `b`, in hunks with `CONTEXT` lines around
+/// each change
+fn unified(a: &[&str], b: &[&str], color: bool) -> String {
+    let edits = edits(a, b);
+    // Each edit's line in `a` and in `b`
+    let mut positions = Vec::with_capacity(edits.len());
+    let (mut i, mut j) = (0, 0);
+    for edit in &edits {
+        positions.push((i, j));
+        match edit {
+            Edit::Same => (i, j) = (i + 1, j + 1),
+            Edit::Delete => i += 1,
+            Edit::Insert => j += 1,
+        }
+    }
+    let changes: Vec<usize> = (0..edits.len())
+        .filter(|&n| edits[n] != Edit::Same)
+        .collect();
+    let near = |n: usize| {
+        changes
+            .binary_search_by(|&change| {
+                if change + CONTEXT < n {
+                    std::cmp::Ordering::Less
+                } else if change > n + CONTEXT {
+                    std::cmp::Ordering::Greater
+                } else {
+                    std::cmp::Ordering::Equal
+                }
+            })
+            .is_ok()
+    };
+
+    let mut out = String::new();
+    let mut n = 0;
+    while n < edits.len() {
+        if !near(n) {
+            n += 1;
+            continue;
+        }
+        let start = n;
+        while n < edits.len() && near(n) {
+            n += 1;
+        }
+        let hunk = &edits[start..n];
+        let a_len = hunk.iter().filter(|edit| **edit != Edit::Insert).count();
+        let b_len = hunk.iter().filter(|edit| **edit != Edit::Delete).count();
+        let (a_start, b_start) = positions[start];
+        let _ = writeln!(
+            out,
+            "{}",
+            paint(
+                color,
+                "36",
+                &format!(
+                    "@@ -{},{} +{},{} @@",
+                    a_start + usize::from(a_len > 0),
+                    a_len,
+                    b_start + usize::from(b_len > 0),
+                    b_len
+                )
+            )
+        );
+        for (edit, &(i, j)) in hunk.iter().zip(&positions[start..n]) {
+            let _ = match edit {
+                Edit::Same => writeln!(out, " {}", a[i]),
+                Edit::Delete => writeln!(out, "{}", paint(color, "31", &format!("-{}", a[i]))),
+                Edit::Insert => writeln!(out, "{}", paint(color, "32", &format!("+{}", b[j]))),
+            };
+        }
+    }
+    out
+}
+
+/// The shortest way from `a` to `b`, by Myers' algorithm, after the lines
+/// they start and end with alike are set aside. Past `MAX_EDITS`, all of
+/// `a` is deleted and all of `b` inserted
+fn edits(a: &[&str], b: &[&str]) -> Vec<Edit> {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let middle =
+        myers(&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]).unwrap_or_else(|| {
+            let deleted = std::iter::repeat_n(Edit::Delete, a.len() - prefix - suffix);
+            deleted
+                .chain(std::iter::repeat_n(Edit::Insert, b.len() - prefix - suffix))
+                .collect()
+        });
+    std::iter::repeat_n(Edit::Same, prefix)
+        .chain(middle)
+        .chain(std::iter::repeat_n(Edit::Same, suffix))
+        .collect()
+}
+
+fn myers(a: &[&str], b: &[&str]) -> Option<Vec<Edit>> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = n + m;
+    let offset = max + 1;
+    let mut v = vec![0isize; 2 * max as usize + 3];
+    // The furthest reach on each diagonal -d..=d before each round d
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+    'rounds: for d in 0..=max {
+        if d as usize > MAX_EDITS {
+            return None;
+        }
+        trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let at = |k: isize| v[(offset + k) as usize];
+            let mut x = match k == -d || (k != d && at(k - 1) < at(k + 1)) {
+                true => at(k + 1),
+                false => at(k - 1) + 1,
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[(offset + k) as usize] = x;
+            if x >= n && y >= m {
+                break 'rounds;
+            }
+        }
+    }
+
+    let mut edits = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, reach) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        if d == 0 {
+            edits.extend(std::iter::repeat_n(Edit::Same, x as usize));
+            break;
+        }
+        let at = |k: isize| reach[(k + d) as usize];
+        let k = x - y;
+        let prev_k = match k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            true => k + 1,
+            false => k - 1,
+        };
+        let prev_x = at(prev_k);
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            edits.push(Edit::Same);
+            x -= 1;
+            y -= 1;
+        }
+        edits.push(match x == prev_x {
+            true => Edit::Insert,
+            false => Edit::Delete,
+        });
+        (x, y) = (prev_x, prev_y);
+    }
+    edits.reverse();
+    Some(edits)
+}
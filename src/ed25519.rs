@@ -0,0 +1,651 @@
+use sha2::{Digest, Sha512};
+
+/// Low 51 bits of a field element limb
+const MASK: u64 = (1 << 51) - 1;
+
+/// The group order L = 2^252 + 27742317777372353535851937790883648493, as
+/// little-endian 64-bit limbs
+const ORDER: [u64; 5] = [
+    0x5812631a5cf5d3ed,
+    0x14def9dea2f79cd6,
+    0x0000000000000000,
+    0x1000000000000000,
+    0,
+];
+
+/// Encoding of the base point, whose y is 4/5 and x even
+const BASE: [u8; 32] = [
+    0x58, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66,
+    0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66,
+];
+
+/// An element of the field of integers modulo 2^255 - 19, as five 51-bit
+/// limbs
+#[derive(Clone, Copy)]
+struct Fe([u64; 5]);
+
+impl Fe {
+    const ZERO: Fe = Fe([0; 5]);
+    const ONE: Fe = Fe([1, 0, 0, 0, 0]);
+
+    fn from_u64(n: u64) -> Fe {
+        Fe([n & MASK, n >> 51, 0, 0, 0])
+    }
+
+    /// The element encoded little-endian in `bytes`, ignoring the top bit
+    fn from_bytes(bytes: &[u8; 32]) -> Fe {
+        let load = |offset: usize| {
+            let mut word = [0u8; 8];
+            word.copy_from_slice(&bytes[offset..offset + 8]);
+            u64::from_le_bytes(word)
+        };
+        Fe([
+            load(0) & MASK,
+            (load(6) >> 3) & MASK,
+            (load(12) >> 6) & MASK,
+            (load(19) >> 1) & MASK,
+            (load(24) >> 12) & MASK,
+        ])
+    }
+
+    /// The fully reduced little-endian encoding
+    fn to_bytes(self) -> [u8; 32] {
+        let mut fe = self.carry();
+        while fe.0[0] > MASK {
+            fe = fe.carry();
+        }
+        let mut h = fe.0;
+        // Subtract the modulus if the value is at least 2^255 - 19
+        let mut q = (h[0] + 19) >> 51;
+        for limb in &h[1..] {
+            q = (limb + q) >> 51;
+        }
+        h[0] += 19 * q;
+        for i in 0..4 {
+            h[i + 1] += h[i] >> 51;
+            h[i] &= MASK;
+        }
+        h[4] &= MASK;
+
+        let mut bytes = [0u8; 32];
+        let mut acc: u128 = 0;
+        let mut bits = 0;
+        let mut index = 0;
+        for limb in h {
+            acc |= (limb as u128) << bits;
+            bits += 51;
+            while bits >= 8 {
+                bytes[index] = acc as u8;
+                acc >>= 8;
+                bits -= 8;
+                index += 1;
+            }
+        }
+        bytes[index] = acc as u8;
+        bytes
+    }
+
+    /// Bring every limb back to about 51 bits
+    fn carry(self) -> Fe {
+        let mut h = self.0;
+        for i in 0..4 {
+            h[i + 1] += h[i] >> 51;
+            h[i] &= MASK;
+        }
+        h[0] += 19 * (h[4] >> 51);
+        h[4] &= MASK;
+        Fe(h)
+    }
+
+    fn add(self, other: Fe) -> Fe {
+        let (a, b) = (self.0, other.0);
+        Fe([
+            a[0] + b[0],
+            a[1] + b[1],
+            a[2] + b[2],
+            a[3] + b[3],
+            a[4] + b[4],
+        ])
+        .carry()
+    }
+
+    fn sub(self, other: Fe) -> Fe {
+        // Add 4p first so no limb goes negative
+        let (a, b) = (self.0, other.0);
+        Fe([
+            a[0] + 0x1fffffffffffb4 - b[0],
+            a[1] + 0x1ffffffffffffc - b[1],
+            a[2] + 0x1ffffffffffffc - b[2],
+            a[3] + 0x1ffffffffffffc - b[3],
+            a[4] + 0x1ffffffffffffc - b[4],
+        ])
+        .carry()
+    }
+
+    fn neg(self) -> Fe {
+        Fe::ZERO.sub(self)
+    }
+
+    fn mul(self, other: Fe) -> Fe {
+        let a = self.0.map(|limb| limb as u128);
+        let b = other.0.map(|limb| limb as u128);
+        // Limbs past the fifth wrap around times 19, as 2^255 = 19
+        let r = [
+            a[0] * b[0] + 19 * (a[1] * b[4] + a[2] * b[3] + a[3] * b[2] + a[4] * b[1]),
+            a[0] * b[1] + a[1] * b[0] + 19 * (a[2] * b[4] + a[3] * b[3] + a[4] * b[2]),
+            a[0] * b[2] + a[1] * b[1] + a[2] * b[0] + 19 * (a[3] * b[4] + a[4] * b[3]),
+            a[0] * b[3] + a[1] * b[2] + a[2] * b[1] + a[3] * b[0] + 19 * (a[4] * b[4]),
+            a[0] * b[4] + a[1] * b[3] + a[2] * b[2] + a[3] * b[1] + a[4] * b[0],
+        ];
+        let mut h = [0u64; 5];
+        let mut carry: u128 = 0;
+        for i in 0..5 {
+            let value = r[i] + carry;
+            h[i] = value as u64 & MASK;
+            carry = value >> 51;
+        }
+        h[0] += 19 * carry as u64;
+        Fe(h).carry()
+    }
+
+    fn square(self) -> Fe {
+        self.mul(self)
+    }
+
+    /// `self` raised to 2^k - c
+    fn pow(self, k: u32, c: u64) -> Fe {
+        let mut exponent = [0u64; 4];
+        for bit in 0..k as usize {
+            exponent[bit / 64] |= 1 << (bit % 64);
+        }
+        // 2^k - c = (2^k - 1) - (c - 1), and c is small
+        let mut borrow = c - 1;
+        for limb in &mut exponent {
+            let (value, under) = limb.overflowing_sub(borrow);
+            *limb = value;
+            borrow = under as u64;
+        }
+        let mut result = Fe::ONE;
+        for bit in (0..256).rev() {
+            result = result.square();
+            if exponent[bit / 64] >> (bit % 64) & 1 == 1 {
+                result = result.mul(self);
+            }
+        }
+        result
+    }
+
+    fn invert(self) -> Fe {
+        self.pow(255, 21)
+    }
+
+    fn is_zero(self) -> bool {
+        self.to_bytes() == [0; 32]
+    }
+
+    fn equals(self, other: Fe) -> bool {
+        self.to_bytes() == other.to_bytes()
+    }
+
+    fn is_negative(self) -> bool {
+        self.to_bytes()[0] & 1 == 1
+    }
+
+    /// Exchange `a` and `b` if `swap` is 1, without branching on it
+    fn swap(a: &mut Fe, b: &mut Fe, swap: u64) {
+        let mask = swap.wrapping_neg();
+        for (la, lb) in a.0.iter_mut().zip(b.0.iter_mut()) {
+            let diff = (*la ^ *lb) & mask;
+            *la ^= diff;
+            *lb ^= diff;
+        }
+    }
+}
+
+/// The curve constant d = -121665/121666
+fn curve_d() -> Fe {
+    Fe::from_u64(121665)
+        .neg()
+        .mul(Fe::from_u64(121666).invert())
+}
+
+/// A point on the curve in extended coordinates (X:Y:Z:T), with x = X/Z,
+/// y = Y/Z and xy = T/Z
+#[derive(Clone, Copy)]
+struct Point {
+    x: Fe,
+    y: Fe,
+    z: Fe,
+    t: Fe,
+}
+
+impl Point {
+    const IDENTITY: Point = Point {
+        x: Fe::ZERO,
+        y: Fe::ONE,
+        z: Fe::ONE,
+        t: Fe::ZERO,
+    };
+
+    /// Decode a point (RFC 8032 section 5.1.3), if `bytes` encode one
+    fn decode(bytes: &[u8; 32]) -> Option<Point> {
+        let y = Fe::from_bytes(bytes);
+        let mut canonical = *bytes;
+        canonical[31] &= 0x7f;
+        if y.to_bytes() != canonical {
+            return None;
+        }
+        let x_negative = bytes[31] >> 7 == 1;
+        let d = curve_d();
+        let y2 = y.square();
+        let u = y2.sub(Fe::ONE);
+        let v = d.mul(y2).add(Fe::ONE);
+        let v3 = v.square().mul(v);
+        let v7 = v3.square().mul(v);
+        let mut x = u.mul(v3).mul(u.mul(v7).pow(252, 3));
+        let vx2 = v.mul(x.square());
+        if !vx2.equals(u) {
+            if !vx2.equals(u.neg()) {
+                return None;
+            }
+            x = x.mul(Fe::from_u64(2).pow(253, 5));
+        }
+        if x.is_zero() && x_negative {
+            return None;
+        }
+        if x.is_negative() != x_negative {
+            x = x.neg();
+        }
+        Some(Point {
+            x,
+            y,
+            z: Fe::ONE,
+            t: x.mul(y),
+        })
+    }
+
+    fn encode(self) -> [u8; 32] {
+        let z_inv = self.z.invert();
+        let mut bytes = self.y.mul(z_inv).to_bytes();
+        if self.x.mul(z_inv).is_negative() {
+            bytes[31] |= 0x80;
+        }
+        bytes
+    }
+
+    /// Sum of two points; the formula also works for doubling
+    fn add(self, other: Point, d2: Fe) -> Point {
+        let a = self.y.sub(self.x).mul(other.y.sub(other.x));
+        let b = self.y.add(self.x).mul(other.y.add(other.x));
+        let c = self.t.mul(d2).mul(other.t);
+        let d = self.z.add(self.z).mul(other.z);
+        let (e, f, g, h) = (b.sub(a), d.sub(c), d.add(c), b.add(a));
+        Point {
+            x: e.mul(f),
+            y: g.mul(h),
+            z: f.mul(g),
+            t: e.mul(h),
+        }
+    }
+
+    /// `scalar` (little-endian) times the point, with the same sequence of
+    /// operations whatever the scalar
+    fn mul(self, scalar: &[u8; 32]) -> Point {
+        let d2 = curve_d().add(curve_d());
+        let mut r0 = Point::IDENTITY;
+        let mut r1 = self;
+        for bit in (0..256).rev() {
+            let swap = (scalar[bit / 8] >> (bit % 8) & 1) as u64;
+            Point::swap(&mut r0, &mut r1, swap);
+            r1 = r0.add(r1, d2);
+            r0 = r0.add(r0, d2);
+            Point::swap(&mut r0, &mut r1, swap);
+        }
+        r0
+    }
+
+    /// Exchange the points if `swap` is 1, without branching on it
+    fn swap(a: &mut Point, b: &mut Point, swap: u64) {
+        Fe::swap(&mut a.x, &mut b.x, swap);
+        Fe::swap(&mut a.y, &mut b.y, swap);
+        Fe::swap(&mut a.z, &mut b.z, swap);
+        Fe::swap(&mut a.t, &mut b.t, swap);
+    }
+}
+
+fn base_point() -> Point {
+    Point::decode(&BASE).expect("base point decodes")
+}
+
+/// `value` (little-endian 64-bit limbs) reduced modulo the group order
+fn reduce(value: &[u64]) -> [u8; 32] {
+    let mut remainder = [0u64; 5];
+    for bit in (0..value.len() * 64).rev() {
+        // remainder = remainder * 2 + bit
+        for i in (1..5).rev() {
+            remainder[i] = remainder[i] << 1 | remainder[i - 1] >> 63;
+        }
+        remainder[0] = remainder[0] << 1 | value[bit / 64] >> (bit % 64) & 1;
+        if !less_than(&remainder, &ORDER) {
+            let mut borrow = 0;
+            for (limb, order) in remainder.iter_mut().zip(ORDER) {
+                let (value, under1) = limb.overflowing_sub(order);
+                let (value, under2) = value.overflowing_sub(borrow);
+                *limb = value;
+                borrow = (under1 || under2) as u64;
+            }
+        }
+    }
+    let mut bytes = [0u8; 32];
+    for (chunk, limb) in bytes.chunks_mut(8).zip(remainder) {
+        chunk.copy_from_slice(&limb.to_le_bytes());
+    }
+    bytes
+}
+
+fn less_than(a: &[u64; 5], b: &[u64; 5]) -> bool {
+    for i in (0..5).rev() {
+        if a[i] != b[i] {
+            return a[i] < b[i];
+        }
+    }
+    false
+}
+
+fn limbs(bytes: &[u8]) -> Vec<u64> {
+    bytes
+        .chunks(8)
+        .map(|chunk| {
+            let mut word = [0u8; 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+            u64::from_le_bytes(word)
+        })
+        .collect()
+}
+
+/// SHA-512 of the concatenated `parts`, reduced modulo the group order
+fn hash_scalar(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha512::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    reduce(&limbs(&hasher.finalize()))
+}
+
+/// (a + b * c) modulo the group order
+fn mul_add(a: &[u8; 32], b: &[u8; 32], c: &[u8; 32]) -> [u8; 32] {
+    let (a, b, c) = (limbs(a), limbs(b), limbs(c));
+    let mut product = [0u64; 9];
+    for i in 0..4 {
+        let mut carry: u128 = 0;
+        for j in 0..4 {
+            let value = product[i + j] as u128 + b[i] as u128 * c[j] as u128 + carry;
+            product[i + j] = value as u64;
+            carry = value >> 64;
+        }
+        product[i + 4] = carry as u64;
+    }
+    let mut carry = 0;
+    for (i, limb) in product.iter_mut().enumerate() {
+        let value = *limb as u128 + a.get(i).copied().unwrap_or(0) as u128 + carry;
+        *limb = value as u64;
+        carry = value >> 64;
+    }
+    reduce(&product)
+}
+
+/// An Ed25519 private key (RFC 8032), kept as its 32-byte seed
+pub struct SigningKey {
+    seed: [u8; 32],
+    scalar: [u8; 32],
+    prefix: [u8; 32],
+    public: [u8; 32],
+}
+
+impl SigningKey {
+    pub fn from_seed(seed: [u8; 32]) -> Self {
+        let hash = Sha512::digest(seed);
+        let mut scalar = [0u8; 32];
+        scalar.copy_from_slice(&hash[..32]);
+        scalar[0] &= 248;
+        scalar[31] &= 127;
+        scalar[31] |= 64;
+        let mut prefix = [0u8; 32];
+        prefix.copy_from_slice(&hash[32..]);
+        let public = base_point().mul(&scalar).encode();
+        SigningKey {
+            seed,
+            scalar,
+            prefix,
+            public,
+        }
+    }
+
+    pub fn seed(&self) -> &[u8; 32] {
+        &self.seed
+    }
+
+    pub fn public_key(&self) -> [u8; 32] {
+        self.public
+    }
+
+    pub fn sign(&self, message: &[u8]) -> [u8; 64] {
+        let r = hash_scalar(&[&self.prefix, message]);
+        let big_r = base_point().mul(&r).encode();
+        let k = hash_scalar(&[&big_r, &self.public, message]);
+        let s = mul_add(&r, &k, &self.scalar);
+        let mut signature = [0u8; 64];
+        signature[..32].copy_from_slice(&big_r);
+        signature[32..].copy_from_slice(&s);
+        signature
+    }
+}
+
+/// Whether `signature` is `public`'s signature of `message`
+pub fn verify(public: &[u8; 32], message: &[u8], signature: &[u8; 64]) -> bool {
+    let Some(a) = Point::decode(public) else {
+        return false;
+    };
+    let mut big_r = [0u8; 32];
+    big_r.copy_from_slice(&signature[..32]);
+    let mut s = [0u8; 32];
+    s.copy_from_slice(&signature[32..]);
+    let s_limbs = limbs(&s);
+    if !less_than(&[s_limbs[0], s_limbs[1], s_limbs[2], s_limbs[3], 0], &ORDER) {
+        return false;
+    }
+    let k = hash_scalar(&[&big_r, public, message]);
+    let d2 = curve_d().add(curve_d());
+    let expected = base_point().mul(&s);
+    let actual = Point::decode(&big_r).map(|r| r.add(a.mul(&k), d2));
+    actual.is_some_and(|actual| actual.encode() == expected.encode())
+}
+
+/// The Montgomery u-coordinate of the X25519 base point
+pub const X25519_BASE: [u8; 32] = {
+    let mut base = [0u8; 32];
+    base[0] = 9;
+    base
+};
+
+/// X25519 (RFC 7748): `scalar` times the point on the Montgomery form of
+/// the curve with u-coordinate `u`, by the constant-time ladder
+pub fn x25519(scalar: &[u8; 32], u: &[u8; 32]) -> [u8; 32] {
+    let mut k = *scalar;
+    k[0] &= 248;
+    k[31] &= 127;
+    k[31] |= 64;
+    let x1 = Fe::from_bytes(u);
+    let a24 = Fe::from_u64(121665);
+    let (mut x2, mut z2, mut x3, mut z3) = (Fe::ONE, Fe::ZERO, x1, Fe::ONE);
+    let mut swapped = 0u64;
+    for bit in (0..255).rev() {
+        let k_bit = (k[bit / 8] >> (bit % 8) & 1) as u64;
+        swapped ^= k_bit;
+        Fe::swap(&mut x2, &mut x3, swapped);
+        Fe::swap(&mut z2, &mut z3, swapped);
+        swapped = k_bit;
+
+        let a = x2.add(z2);
+        let aa = a.square();
+        let b = x2.sub(z2);
+        let bb = b.square();
+        let e = aa.sub(bb);
+        let da = x3.sub(z3).mul(a);
+        let cb = x3.add(z3).mul(b);
+        x3 = da.add(cb).square();
+        z3 = x1.mul(da.sub(cb).square());
+        x2 = aa.mul(bb);
+        z2 = e.mul(aa.add(a24.mul(e)));
+    }
+    Fe::swap(&mut x2, &mut x3, swapped);
+    Fe::swap(&mut z2, &mut z3, swapped);
+    x2.mul(z2.invert()).to_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex<const N: usize>(text: &str) -> [u8; N] {
+        let mut bytes = [0u8; N];
+        assert_eq!(text.len(), 2 * N);
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&text[2 * i..2 * i + 2], 16).unwrap();
+        }
+        bytes
+    }
+
+    // RFC 8032 §7.1, tests 1 to 3: seed, public key, message, signature
+    const RFC8032: [(&str, &str, &[u8], &str); 3] = [
+        (
+            "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60",
+            "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+            b"",
+            "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+        ),
+        (
+            "4ccd089b28ff96da9db6c346ec114e0f5b8a319f35aba624da8cf6ed4fb8a6fb",
+            "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+            b"\x72",
+            "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00",
+        ),
+        (
+            "c5aa8df43f9f837bedb7442f31dcb7b166d38535076f094b85ce3a2e0b4458f7",
+            "fc51cd8e6218a1a38da47ed00230f0580816ed13ba3303ac5deb911548908025",
+            b"\xaf\x82",
+            "6291d657deec24024827e69c3abe01a30ce548a284743a445e3680d7db5ac3ac18ff9b538d16f290ae67f760984dc6594a7c15e9716ed28dc027beceea1ec40a",
+        ),
+    ];
+
+    #[test]
+    fn signs_the_rfc_8032_vectors() {
+        for (seed, public, message, signature) in RFC8032 {
+            let key = SigningKey::from_seed(hex(seed));
+            assert_eq!(key.public_key(), hex::<32>(public));
+            assert_eq!(key.sign(message), hex::<64>(signature));
+        }
+    }
+
+    #[test]
+    fn verifies_the_rfc_8032_vectors() {
+        for (_, public, message, signature) in RFC8032 {
+            assert!(verify(&hex(public), message, &hex(signature)));
+        }
+    }
+
+    #[test]
+    fn rejects_a_tampered_message() {
+        let (_, public, _, signature) = RFC8032[2];
+        assert!(!verify(&hex(public), b"\xaf\x83", &hex(signature)));
+        assert!(!verify(&hex(public), b"\xaf", &hex(signature)));
+    }
+
+    #[test]
+    fn rejects_a_tampered_signature() {
+        let (_, public, message, signature) = RFC8032[1];
+        for i in [0, 31, 32, 63] {
+            let mut signature = hex::<64>(signature);
+            signature[i] ^= 1;
+            assert!(!verify(&hex(public), message, &signature), "byte {}", i);
+        }
+    }
+
+    #[test]
+    fn rejects_a_non_canonical_s() {
+        // S + L is the same scalar mod L, but only S < L is valid
+        let (_, public, message, signature) = RFC8032[0];
+        let mut signature = hex::<64>(signature);
+        let order: [u8; 32] =
+            hex("edd3f55c1a631258d69cf7a2def9de1400000000000000000000000000000010");
+        let mut carry = 0u16;
+        for (byte, l) in signature[32..].iter_mut().zip(order) {
+            let sum = *byte as u16 + l as u16 + carry;
+            *byte = sum as u8;
+            carry = sum >> 8;
+        }
+        assert_eq!(carry, 0);
+        assert!(!verify(&hex(public), message, &signature));
+    }
+
+    #[test]
+    fn computes_the_rfc_7748_x25519_vectors() {
+        // §5.2
+        assert_eq!(
+            x25519(
+                &hex("a546e36bf0527c9d3b16154b82465edd62144c0ac1fc5a18506a2244ba449ac4"),
+                &hex("e6db6867583030db3594c1a424b15f7c726624ec26b3353b10a903a6d0ab1c4c"),
+            ),
+            hex::<32>("c3da55379de9c6908e94ea4df28d084f32eccf03491c71f754b4075577a28552"),
+        );
+        assert_eq!(
+            x25519(
+                &hex("4b66e9d4d1b4673c5ad22691957d6af5c11b6421e0ea01d42ca4169e7918ba0d"),
+                &hex("e5210f12786811d3f4b7959d0538ae2c31dbe7106fc03c3efc4cd549c715a493"),
+            ),
+            hex::<32>("95cbde9476e8907d7aade45cb4b873f88b595a68799fa152e6f8f7647aac7957"),
+        );
+    }
+
+    #[test]
+    fn iterates_x25519_as_rfc_7748_does() {
+        // §5.2: k and u both start at 9, then k becomes the result and u
+        // the previous k
+        let (mut k, mut u) = (X25519_BASE, X25519_BASE);
+        for _ in 0..1 {
+            (k, u) = (x25519(&k, &u), k);
+        }
+        assert_eq!(
+            k,
+            hex::<32>("422c8e7a6227d7bca1350b3e2bb7279f7897b87bb6854b783c60e80311ae3079"),
+        );
+        for _ in 1..1000 {
+            (k, u) = (x25519(&k, &u), k);
+        }
+        assert_eq!(
+            k,
+            hex::<32>("684cf59ba83309552800ef566f2f4d3c1c3887c49360e3875f2eb94d99532c51"),
+        );
+    }
+
+    #[test]
+    fn agrees_on_the_rfc_7748_shared_secret() {
+        // §6.1
+        let alice: [u8; 32] =
+            hex("77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a");
+        let bob: [u8; 32] = hex("5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb");
+        let alice_public = x25519(&alice, &X25519_BASE);
+        let bob_public = x25519(&bob, &X25519_BASE);
+        assert_eq!(
+            alice_public,
+            hex::<32>("8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a"),
+        );
+        assert_eq!(
+            bob_public,
+            hex::<32>("de9edb7d7b7dc1b4d35b61c2ece435373f8343c85b78674dadfc7e146f882b4f"),
+        );
+        let shared = hex::<32>("4a5d9d5ba4ce2de1728e3bf480350f25e07e21c947d19e3376f09b3c1e161742");
+        assert_eq!(x25519(&alice, &bob_public), shared);
+        assert_eq!(x25519(&bob, &alice_public), shared);
+    }
+}
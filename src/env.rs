@@ -0,0 +1,363 @@
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::digest::{hex, hmac_sha256, sha256_hex};
+use crate::id;
+use crate::receipt::{EnvFileRecord, EnvPolicyReport};
+
+/// Length of the random key each receipt's hashed values are keyed with
+const SALT_BYTES: usize = 16;
+
+/// How recorded environment variable values are stored in the receipt
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum EnvValues {
+    /// Record values verbatim
+    #[default]
+    Plain,
+    /// Record only an HMAC-SHA256 of each value, under a random salt
+    /// recorded beside them
+    Hashed,
+}
+
+/// Which environment variables are recorded in the receipt
+#[derive(Debug, Clone, Default)]
+pub struct EnvPolicy {
+    /// Glob patterns of variable names to record (all names if empty)
+    pub allow: Vec<String>,
+    /// Glob patterns of variable names never recorded, even if allowed
+    pub deny: Vec<String>,
+    /// How recorded values are stored
+    pub values: EnvValues,
+}
+
+impl EnvPolicy {
+    /// Apply the policy to `vars`, returning the recorded variables and a
+    /// description of what was applied
+    pub fn capture<I>(&self, vars: I) -> Result<(BTreeMap<String, String>, EnvPolicyReport)>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let allow = if self.allow.is_empty() {
+            vec!["*".to_string()]
+        } else {
+            self.allow.clone()
+        };
+
+        let salt = match self.values {
+            EnvValues::Plain => None,
+            EnvValues::Hashed => {
+                let mut salt = [0u8; SALT_BYTES];
+                id::secure_random(&mut salt)?;
+                Some(salt)
+            }
+        };
+
+        let mut recorded = BTreeMap::new();
+        let mut denied = Vec::new();
+        let mut unmatched = 0;
+        for (name, value) in vars {
+            if !allow.iter().any(|pattern| glob_match(pattern, &name)) {
+                unmatched += 1;
+            } else if self.deny.iter().any(|pattern| glob_match(pattern, &name)) {
+                denied.push(name);
+            } else {
+                let value = match &salt {
+                    None => value,
+                    Some(salt) => {
+                        format!("hmac-sha256:{}", hex(&hmac_sha256(salt, value.as_bytes())))
+                    }
+                };
+                recorded.insert(name, value);
+            }
+        }
+        denied.sort();
+
+        let report = EnvPolicyReport {
+            allow,
+            deny: self.deny.clone(),
+            values: self.values,
+            salt: salt.map(|salt| hex(&salt)),
+            denied,
+            unmatched,
+        };
+        Ok((recorded, report))
+    }
+}
+
+/// Variables loaded from a dotenv-style file
+#[derive(Debug, Clone)]
+pub struct EnvFile {
+    pub path: PathBuf,
+    /// `path` made absolute, with symlinks resolved
+    pub canonical: Option<PathBuf>,
+    /// SHA-256 digest of the file's contents
+    pub sha256: String,
+    /// Variables in file order
+    pub vars: Vec<(String, String)>,
+}
+
+impl EnvFile {
+    /// Read `NAME=value` lines from `path`. Blank lines and `#` comments are
+    /// skipped, a leading `export` is allowed, and values may be quoted:
+    /// 'single' quotes are literal, "double" quotes allow \n, \t, \" and \\.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents =
+            fs::read(path).context(format!("Failed to read env file {}", path.display()))?;
+        let text = String::from_utf8_lossy(&contents);
+        let mut vars = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let var = parse_env_line(line).context(format!(
+                "{}:{}: expected NAME=value",
+                path.display(),
+                index + 1
+            ))?;
+            vars.push(var);
+        }
+        Ok(EnvFile {
+            path: path.to_path_buf(),
+            canonical: fs::canonicalize(path).ok(),
+            sha256: sha256_hex(&contents),
+            vars,
+        })
+    }
+
+    /// What the receipt records about the file: never the values
+    pub fn record(&self) -> EnvFileRecord {
+        let mut names: Vec<String> = self.vars.iter().map(|(name, _)| name.clone()).collect();
+        names.sort();
+        names.dedup();
+        EnvFileRecord {
+            path: self.path.to_string_lossy().to_string(),
+            canonical_path: self
+                .canonical
+                .as_ref()
+                .map(|path| path.to_string_lossy().to_string()),
+            sha256: self.sha256.clone(),
+            names,
+        }
+    }
+}
+
+/// SHA-256 over the environment's `NAME=VALUE` pairs in sorted order, each
+/// followed by a NUL byte, so environments can be compared without
+/// recording their values
+pub fn digest(env: &BTreeMap<OsString, OsString>) -> String {
+    let mut hasher = Sha256::new();
+    for (name, value) in env {
+        hasher.update(name.as_encoded_bytes());
+        hasher.update(b"=");
+        hasher.update(value.as_encoded_bytes());
+        hasher.update(b"\0");
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Parse a `NAME=VALUE` argument, taking the value verbatim
+pub fn parse_assignment(s: &str) -> Result<(String, String)> {
+    let Some((name, value)) = s.split_once('=') else {
+        bail!("expected NAME=VALUE, got '{}'", s);
+    };
+    if name.is_empty() {
+        bail!("missing variable name in '{}'", s);
+    }
+    Ok((name.to_string(), value.to_string()))
+}
+
+fn parse_env_line(line: &str) -> Result<(String, String)> {
+    let line = line.strip_prefix("export ").unwrap_or(line);
+    let Some((name, value)) = line.split_once('=') else {
+        bail!("missing '='");
+    };
+    let name = name.trim();
+    if name.is_empty() || name.chars().any(char::is_whitespace) {
+        bail!("invalid variable name '{}'", name);
+    }
+    let value = value.trim();
+    let (value, rest) = if let Some(quoted) = value.strip_prefix('\'') {
+        let end = quoted.find('\'').context("unterminated single quote")?;
+        (quoted[..end].to_string(), &quoted[end + 1..])
+    } else if let Some(quoted) = value.strip_prefix('"') {
+        let mut unescaped = String::new();
+        let mut chars = quoted.char_indices();
+        let end = loop {
+            match chars.next().context("unterminated double quote")? {
+                (end, '"') => break end,
+                (_, '\\') => match chars.next() {
+                    Some((_, 'n')) => unescaped.push('\n'),
+                    Some((_, 't')) => unescaped.push('\t'),
+                    Some((_, other)) => unescaped.push(other),
+                    None => bail!("unterminated double quote"),
+                },
+                (_, c) => unescaped.push(c),
+            }
+        };
+        (unescaped, &quoted[end + 1..])
+    } else {
+        // Unquoted values end at a comment
+        match value.find(" #") {
+            Some(comment) => (value[..comment].trim_end().to_string(), ""),
+            None => (value.to_string(), ""),
+        }
+    };
+    let rest = rest.trim_start();
+    if !rest.is_empty() && !rest.starts_with('#') {
+        bail!("unexpected text after closing quote");
+    }
+    Ok((name.to_string(), value))
+}
+
+/// Match `text` against a glob `pattern` where `*` matches any run of
+/// characters and `?` matches exactly one
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` seen and the text position it was matched at
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                // Let the last `*` swallow one more character and retry
+                Some((star, matched)) => {
+                    p = star + 1;
+                    t = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(names: &[&str]) -> Vec<(String, String)> {
+        names
+            .iter()
+            .map(|name| (name.to_string(), format!("{} value", name)))
+            .collect()
+    }
+
+    fn policy(allow: &[&str], deny: &[&str]) -> EnvPolicy {
+        EnvPolicy {
+            allow: allow.iter().map(|s| s.to_string()).collect(),
+            deny: deny.iter().map(|s| s.to_string()).collect(),
+            values: EnvValues::Plain,
+        }
+    }
+
+    #[test]
+    fn matches_globs() {
+        assert!(glob_match("PATH", "PATH"));
+        assert!(!glob_match("PATH", "PATHS"));
+        assert!(!glob_match("PATH", "path"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("AWS_*", "AWS_SECRET_ACCESS_KEY"));
+        assert!(glob_match("AWS_*", "AWS_"));
+        assert!(!glob_match("AWS_*", "MY_AWS_KEY"));
+        assert!(glob_match("*_TOKEN", "GITHUB_TOKEN"));
+        assert!(!glob_match("*_TOKEN", "GITHUB_TOKENS"));
+        assert!(glob_match("*KEY*", "KEY"));
+        assert!(glob_match("*KEY*", "SSH_KEY_PATH"));
+        assert!(glob_match("LC_?", "LC_A"));
+        assert!(!glob_match("LC_?", "LC_"));
+        assert!(!glob_match("LC_?", "LC_AB"));
+        // The last `*` backtracks past an early partial match
+        assert!(glob_match("*_ID_*", "A_IDX_ID_B"));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(!glob_match("a*b*c", "aXbYcZ"));
+    }
+
+    #[test]
+    fn records_allowed_names_not_denied() {
+        let names = [
+            "HOME",
+            "PATH",
+            "AWS_REGION",
+            "AWS_SECRET_ACCESS_KEY",
+            "GITHUB_TOKEN",
+        ];
+        let (recorded, report) = policy(&["AWS_*", "PATH", "*_TOKEN"], &["*SECRET*", "*_TOKEN"])
+            .capture(vars(&names))
+            .unwrap();
+        let recorded: Vec<&str> = recorded.keys().map(String::as_str).collect();
+        assert_eq!(recorded, ["AWS_REGION", "PATH"]);
+        assert_eq!(report.denied, ["AWS_SECRET_ACCESS_KEY", "GITHUB_TOKEN"]);
+        assert_eq!(report.unmatched, 1);
+
+        // No allow patterns allow every name
+        let (recorded, report) = policy(&[], &["HOME"]).capture(vars(&names)).unwrap();
+        assert_eq!(recorded.len(), 4);
+        assert_eq!(report.allow, ["*"]);
+        assert_eq!(report.unmatched, 0);
+    }
+
+    #[test]
+    fn hashes_values() {
+        let policy = EnvPolicy {
+            values: EnvValues::Hashed,
+            ..EnvPolicy::default()
+        };
+        let vars = || [("TOKEN".to_string(), "secret".to_string())];
+        let (recorded, report) = policy.capture(vars()).unwrap();
+        let salt = report.salt.unwrap();
+        assert_eq!(salt.len(), 2 * SALT_BYTES);
+        let salt: Vec<u8> = (0..salt.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&salt[i..i + 2], 16).unwrap())
+            .collect();
+        let expected = hex(&hmac_sha256(&salt, b"secret"));
+        assert_eq!(recorded["TOKEN"], format!("hmac-sha256:{}", expected));
+
+        // Each receipt gets its own salt, so equal values differ between them
+        let (again, _) = policy.capture(vars()).unwrap();
+        assert_ne!(again["TOKEN"], recorded["TOKEN"]);
+        let (plain, report) = EnvPolicy::default().capture(vars()).unwrap();
+        assert_eq!(plain["TOKEN"], "secret");
+        assert_eq!(report.salt, None);
+    }
+
+    #[test]
+    fn parses_env_lines() {
+        let parsed = |line: &str| parse_env_line(line).ok();
+        let var = |name: &str, value: &str| Some((name.to_string(), value.to_string()));
+        assert_eq!(parsed("A=1"), var("A", "1"));
+        assert_eq!(parsed("export A = 1 # one"), var("A", "1"));
+        assert_eq!(parsed("A=x#y"), var("A", "x#y"));
+        assert_eq!(parsed("A='$HOME \\n'"), var("A", "$HOME \\n"));
+        assert_eq!(
+            parsed(r#"A="a\tb\n\"c\" \\" # quoted"#),
+            var("A", "a\tb\n\"c\" \\")
+        );
+        assert_eq!(parsed("A="), var("A", ""));
+        assert_eq!(parsed("A"), None);
+        assert_eq!(parsed("=1"), None);
+        assert_eq!(parsed("A B=1"), None);
+        assert_eq!(parsed("A='open"), None);
+        assert_eq!(parsed("A=\"open\\\""), None);
+        assert_eq!(parsed("A='x' y"), None);
+    }
+}
@@ -0,0 +1,1215 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use std::collections::BTreeMap;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::Read;
+use std::mem;
+use std::path::{Component, Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::access::PathRules;
+use crate::age::{self, Recipient};
+use crate::artifact;
+use crate::atomic;
+use crate::attestation;
+use crate::base64;
+use crate::capture::{self, BinaryOutput, Capture, Tee, Timeline};
+use crate::cgroup::Cgroup;
+use crate::container::ContainerSpec;
+use crate::crash;
+use crate::digest;
+use crate::env::{self, EnvFile, EnvPolicy};
+use crate::host;
+use crate::id;
+use crate::job::Job;
+use crate::limits::ResourceLimits;
+use crate::network::{self, Network};
+use crate::objects::ObjectStore;
+use crate::oom;
+use crate::procgroup::{self, Foreground};
+use crate::proctree::Sampler;
+use crate::pty;
+use crate::receipt::{
+    write_receipt, ActorRecord, Attempt, ClockAnomaly, Compression, CompressionAlgorithm, Encoding,
+    Receipt, ShellInvocation, Sidecar, Status, RECEIPT_VERSION,
+};
+use crate::redact::Redactor;
+use crate::rusage::{self, ChildUsage, Reaper};
+use crate::sandbox::Sandbox;
+use crate::sched::Scheduling;
+use crate::signals;
+use crate::signing::Signer;
+use crate::sink::ReceiptSink;
+use crate::snapshot::Snapshot;
+use crate::stdin::{StdinFeeder, StdinMode};
+use crate::timestamp::Tsa;
+use crate::trace::{RunningTracer, Tracer};
+use crate::user::{self, RunAs};
+use crate::vcs;
+use crate::zstd;
+
+/// How often a running child is polled for exit
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How long to wait for output pipes to drain after the child was killed.
+/// Descendants may still hold the pipes open, so we cannot wait for EOF.
+pub const DRAIN_GRACE: Duration = Duration::from_millis(500);
+
+/// How long the rest of a signalled process group gets to exit before
+/// its remaining members count as orphans
+const ORPHAN_SETTLE: Duration = Duration::from_millis(100);
+
+/// How much of each stream --split-output keeps in the receipt as a
+/// preview
+const PREVIEW_BYTES: usize = 1024;
+
+/// What to execute: an argv, optionally derived from a shell command string
+#[derive(Debug, Clone)]
+pub struct Invocation {
+    argv: Vec<OsString>,
+    shell: Option<ShellInvocation>,
+}
+
+impl Invocation {
+    /// Execute `argv` directly, without a shell
+    pub fn direct(argv: Vec<OsString>) -> Self {
+        Invocation { argv, shell: None }
+    }
+
+    /// Interpret `script` with the user's shell
+    pub fn shell(script: &str) -> Self {
+        let (program, flag) = shell_program();
+        Invocation {
+            argv: vec![program.clone().into(), flag.into(), script.into()],
+            shell: Some(ShellInvocation {
+                program,
+                command: script.to_string(),
+            }),
+        }
+    }
+
+    /// The program run, as it was given
+    pub fn program(&self) -> String {
+        self.argv
+            .first()
+            .map(|program| program.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+
+    /// The same invocation inside a container, where a command string is
+    /// interpreted by the image's /bin/sh rather than the host's shell
+    fn in_container(&self) -> Self {
+        match &self.shell {
+            Some(shell) => Invocation {
+                argv: vec!["/bin/sh".into(), "-c".into(), shell.command.clone().into()],
+                shell: Some(ShellInvocation {
+                    program: "/bin/sh".to_string(),
+                    command: shell.command.clone(),
+                }),
+            },
+            None => self.clone(),
+        }
+    }
+}
+
+/// The shell used for `--shell` and the flag that makes it run a command string
+#[cfg(unix)]
+fn shell_program() -> (String, &'static str) {
+    let shell = std::env::var("SHELL")
+        .ok()
+        .filter(|shell| !shell.is_empty())
+        .unwrap_or_else(|| "/bin/sh".to_string());
+    (shell, "-c")
+}
+
+#[cfg(windows)]
+fn shell_program() -> (String, &'static str) {
+    let shell = std::env::var("COMSPEC")
+        .ok()
+        .filter(|shell| !shell.is_empty())
+        .unwrap_or_else(|| "cmd.exe".to_string());
+    (shell, "/C")
+}
+
+/// Options controlling how a command is executed
+#[derive(Debug, Default)]
+pub struct ExecOptions {
+    /// Kill the command if it runs longer than this
+    pub timeout: Option<Duration>,
+    /// Grace period between SIGTERM and SIGKILL when stopping the command
+    pub kill_after: Duration,
+    /// Working directory for the command (defaults to rcpt's own)
+    pub cwd: Option<PathBuf>,
+    /// Environment variables to record in the receipt, if any
+    pub env_policy: Option<EnvPolicy>,
+    /// Env files loaded into the command's environment, later ones winning
+    pub env_files: Vec<EnvFile>,
+    /// Start the command with an empty environment instead of rcpt's own
+    pub clear_env: bool,
+    /// Variables set with `--env`, applied after env files
+    pub env_vars: Vec<(String, String)>,
+    /// Where the command's stdin comes from
+    pub stdin: StdinMode,
+    /// Echo the command's output to rcpt's own stdout/stderr as it arrives
+    pub tee: bool,
+    /// Keep at most this many bytes of stdout in the receipt (head and tail)
+    pub max_stdout_bytes: Option<usize>,
+    /// Keep at most this many bytes of stderr in the receipt (head and tail)
+    pub max_stderr_bytes: Option<usize>,
+    /// Run the command under a pseudo-terminal, capturing its merged output
+    pub pty: bool,
+    /// Resource limits applied to the command before exec
+    pub limits: ResourceLimits,
+    /// CPU priority and affinity for the command
+    pub scheduling: Scheduling,
+    /// Account to run the command as
+    pub user: Option<RunAs>,
+    /// Container to run the command in, instead of on the host
+    pub container: Option<ContainerSpec>,
+    /// Namespace sandbox to run the command in
+    pub sandbox: Option<Sandbox>,
+    /// Paths the command is restricted to reading and writing
+    pub access: Option<PathRules>,
+    /// Run the command without network access
+    pub no_network: bool,
+    /// Move a crashed command's core dump beside its receipt
+    pub keep_core: bool,
+    /// Where output that is not valid UTF-8 is stored
+    pub binary_output: BinaryOutput,
+    /// Write all output to files beside the receipt, as it was
+    pub split_output: bool,
+    /// Output larger than this many bytes is compressed, with the zstd found
+    /// to do it
+    pub compress_above: Option<(u64, PathBuf)>,
+    /// Output larger than this many bytes, and the declared outputs, go in
+    /// this object store
+    pub objects: Option<(u64, ObjectStore)>,
+    /// Recipients to encrypt the receipt and its sidecars to
+    pub encrypt_to: Vec<Recipient>,
+    /// The ledger the receipt is kept in with --ledger-only, instead of a
+    /// file of its own
+    pub ledger_only: Option<PathBuf>,
+    /// Flush the receipt and the files written with it to disk before
+    /// counting it written
+    pub fsync: bool,
+    /// Where the finished receipt goes, in order, such as a `FileSink`
+    /// writing it to its file
+    pub sinks: Vec<Box<dyn ReceiptSink>>,
+    /// Authority to timestamp the receipt with
+    pub tsa: Option<Tsa>,
+    /// Key to sign the receipt with
+    pub signer: Option<Signer>,
+    /// Where to write an in-toto provenance statement for the run, and the
+    /// builder id it names
+    pub attestation: Option<(PathBuf, String)>,
+    /// Record output as timestamped chunks instead of whole streams
+    pub timeline: bool,
+    /// Record the machine the command runs on
+    pub capture_host: bool,
+    /// Record the version control state of the working directory
+    pub capture_vcs: bool,
+    /// Sample the processes the command starts this often
+    pub process_tree: Option<Duration>,
+    /// Record the files the command opens
+    pub trace_files: bool,
+    /// Files and directories the command reads, hashed before it runs
+    pub inputs: Vec<PathBuf>,
+    /// Files and directories the command should produce, hashed after it
+    /// exits
+    pub outputs: Vec<PathBuf>,
+    /// Fail a successful run that left one of `outputs` missing
+    pub require_outputs: bool,
+    /// Record what the command changes under this directory
+    pub snapshot: Option<PathBuf>,
+    /// Labels recorded in the receipt
+    pub tags: Vec<String>,
+    /// Key/value pairs recorded in the receipt
+    pub metadata: BTreeMap<String, serde_json::Value>,
+    /// Id of the receipt this run is part of
+    pub parent_id: Option<String>,
+    /// Secrets to scrub from the receipt
+    pub redactor: Redactor,
+    /// Id of the session this run belongs to
+    pub session_id: Option<String>,
+    /// Agent, run and task the command is run for
+    pub actor: Option<ActorRecord>,
+    /// Resolve everything and return a planned receipt without running
+    pub dry_run: bool,
+    /// Write a `running` receipt here as soon as the command has started,
+    /// when rcpt is supervising a detached run
+    pub stub: Option<PathBuf>,
+    /// Id for the receipt, when it had to be known before the run started;
+    /// a new one is generated otherwise
+    pub receipt_id: Option<String>,
+}
+
+impl ExecOptions {
+    /// Where the receipt saved for `out` can be found: `out`, or the ledger
+    /// with --ledger-only
+    pub fn saved_to<'a>(&'a self, out: &'a Path) -> &'a Path {
+        self.ledger_only.as_deref().unwrap_or(out)
+    }
+}
+
+/// One of the command's output streams: a pipe, or the pty carrying both
+type Output = Box<dyn Read + Send>;
+
+/// How failed commands are retried
+#[derive(Debug, Default)]
+pub struct RetryPolicy {
+    /// Number of additional attempts after the first failure
+    pub retries: u32,
+    /// Delay before the first retry, doubled for each subsequent retry
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    fn delay_before(&self, retry: u32) -> Duration {
+        self.backoff.saturating_mul(2u32.saturating_pow(retry - 1))
+    }
+}
+
+/// Execute the command, retrying failed attempts according to `policy`.
+/// The returned receipt describes the final attempt and lists every attempt.
+pub fn execute_with_retries(
+    invocation: &Invocation,
+    opts: &ExecOptions,
+    policy: &RetryPolicy,
+) -> Result<Receipt> {
+    with_retries(opts, policy, || execute_command(invocation, opts))
+}
+
+/// Call `execute` until it succeeds or `policy` runs out of retries
+pub fn with_retries(
+    opts: &ExecOptions,
+    policy: &RetryPolicy,
+    mut execute: impl FnMut() -> Result<Receipt>,
+) -> Result<Receipt> {
+    let start_instant = Instant::now();
+    let mut receipt = execute()?;
+    if policy.retries == 0 || opts.dry_run {
+        return Ok(receipt);
+    }
+
+    let id = receipt.id.clone();
+    let start_time = receipt.start_time;
+    let mut attempts = vec![attempt_of(1, &receipt)];
+
+    for retry in 1..=policy.retries {
+        if receipt.succeeded() || receipt.status == Status::Interrupted {
+            break;
+        }
+        let delay = policy.delay_before(retry);
+        eprintln!(
+            "rcpt: attempt {} failed, retrying in {}",
+            retry,
+            humantime::format_duration(delay)
+        );
+        thread::sleep(delay);
+
+        receipt = execute()?;
+        attempts.push(attempt_of(retry + 1, &receipt));
+    }
+
+    // The run keeps its first attempt's id, and timing covers the whole run,
+    // including backoff delays
+    receipt.id = id;
+    receipt.start_time = start_time;
+    let duration = start_instant.elapsed();
+    receipt.duration_ms = duration.as_millis() as u64;
+    receipt.clock_anomaly = ClockAnomaly::detect(start_time, receipt.end_time, duration);
+    receipt.attempts = attempts;
+    Ok(receipt)
+}
+
+fn attempt_of(attempt: u32, receipt: &Receipt) -> Attempt {
+    Attempt {
+        attempt,
+        exit_code: receipt.exit_code,
+        timed_out: receipt.timed_out,
+        start_time: receipt.start_time,
+        duration_ms: receipt.duration_ms,
+    }
+}
+
+/// Execute the command once, with secrets redacted from the receipt
+pub fn execute_command(invocation: &Invocation, opts: &ExecOptions) -> Result<Receipt> {
+    let mut receipt = run_command(invocation, opts)?;
+    opts.redactor.apply(&mut receipt);
+    Ok(receipt)
+}
+
+fn run_command(invocation: &Invocation, opts: &ExecOptions) -> Result<Receipt> {
+    let invocation = match &opts.container {
+        Some(_) => invocation.in_container(),
+        None => invocation.clone(),
+    };
+    let Some((cmd, args)) = invocation.argv.split_first() else {
+        anyhow::bail!("No command specified");
+    };
+
+    let cwd = resolve_cwd(opts.cwd.as_deref())?;
+    let mut child_env = child_environment(opts);
+    let container = match &opts.container {
+        Some(spec) => Some(spec.prepare(&cwd, !opts.dry_run)?),
+        None => None,
+    };
+    // A command in a container is resolved inside its image, not here
+    let executable = match container {
+        Some(_) => None,
+        None => resolve_executable(cmd, child_env.get(OsStr::new("PATH")), &cwd),
+    };
+    if opts.dry_run && container.is_none() && executable.is_none() {
+        anyhow::bail!("Command not found: {}", cmd.to_string_lossy());
+    }
+
+    let start_instant = Instant::now();
+    let mut receipt = planned_receipt(opts, &cwd, &child_env)?;
+    child_env.insert(id::PARENT_ENV.into(), receipt.id.clone().into());
+    receipt.command = cmd.to_string_lossy().to_string();
+    receipt.args = args
+        .iter()
+        .map(|s| s.to_string_lossy().to_string())
+        .collect();
+    receipt.executable = executable.map(|path| path.to_string_lossy().to_string());
+    receipt.shell = invocation.shell.clone();
+    receipt.container = container.as_ref().map(|container| container.record.clone());
+    if opts.dry_run {
+        return Ok(receipt);
+    }
+
+    let mut command = match &container {
+        Some(container) => {
+            let run = container.argv(
+                &invocation.argv,
+                &explicit_env_names(opts),
+                &opts.limits,
+                opts.no_network,
+                !matches!(opts.stdin, StdinMode::Null),
+                opts.pty,
+            );
+            build_command(&run[0], &run[1..], &cwd, &child_env, opts)?
+        }
+        None if opts.no_network => {
+            let argv = network::wrap(invocation.argv.clone());
+            build_command(&argv[0], &argv[1..], &cwd, &child_env, opts)?
+        }
+        None => build_command(cmd, args, &cwd, &child_env, opts)?,
+    };
+    let mut stdin_file = None;
+    let pty = if opts.pty {
+        if matches!(opts.stdin, StdinMode::File(_)) {
+            anyhow::bail!("--pty cannot be combined with --stdin file:<path>");
+        }
+        Some(pty::attach(&mut command)?)
+    } else {
+        procgroup::isolate(&mut command);
+        let (stdin, file) = opts.stdin.stdio()?;
+        stdin_file = file;
+        command
+            .stdin(stdin)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        None
+    };
+    // A container's usage is accounted to the engine, not its client
+    let cgroup = container.is_none().then(Cgroup::create).flatten();
+    if let Some(cgroup) = &cgroup {
+        cgroup.attach(&mut command);
+    }
+    let job = match container {
+        Some(_) => None,
+        None => Job::create(&opts.limits)?,
+    };
+    // The sandbox and a container have no network of their own already
+    if opts.no_network && container.is_none() && receipt.sandbox.is_none() {
+        network::isolate(&mut command)?;
+    }
+    if let Some(sandbox) = &receipt.sandbox {
+        sandbox.apply(&mut command)?;
+    }
+    // After the sandbox, which can no longer mount once restricted
+    if let Some(access) = &receipt.fs_access {
+        access.apply(&mut command)?;
+    }
+    // Registered last so limits and priority are set while still privileged
+    if let Some(user) = &opts.user {
+        user.apply(&mut command);
+    }
+    let snapshot = opts
+        .snapshot
+        .as_deref()
+        .map(|dir| Snapshot::take(&cwd, dir))
+        .transpose()?;
+    // Watching before the spawn catches the command loading its executable
+    let tracer = opts.trace_files.then(Tracer::open).transpose()?;
+    let mut child = command.spawn().context(format!(
+        "Failed to execute command: {}",
+        cmd.to_string_lossy()
+    ))?;
+    // Close our copies of the pty's terminal side so reads see the child exit
+    drop(command);
+    let cgroup = cgroup.filter(|cgroup| cgroup.contains(child.id()));
+    if let Some(job) = &job {
+        if let Err(err) = job.assign(&child) {
+            procgroup::kill(&mut child);
+            let _ = child.wait();
+            return Err(err.context("Failed to assign command to job object"));
+        }
+    }
+    receipt.pid = Some(child.id());
+    write_stub(opts, &receipt);
+    let sampler = opts
+        .process_tree
+        .map(|interval| Sampler::start(&[child.id()], interval));
+    let tracer = tracer.map(|tracer| tracer.start(&[child.id()]));
+
+    let (foreground, feeder, stdout_stream, stderr_stream): (_, _, Option<Output>, Option<Output>) =
+        match pty {
+            Some(master) => {
+                let feeder = match opts.stdin {
+                    StdinMode::Null => None,
+                    _ => Some(StdinFeeder::start(master.try_clone()?)),
+                };
+                (None, feeder, Some(Box::new(master)), None)
+            }
+            None => {
+                // A command in its own process group needs the terminal handed
+                // over before it can read from it
+                let foreground = match opts.stdin {
+                    StdinMode::Inherit => Foreground::acquire(&child),
+                    _ => None,
+                };
+                let feeder = child.stdin.take().map(StdinFeeder::start);
+                let stdout = child.stdout.take().map(|s| Box::new(s) as Output);
+                let stderr = child.stderr.take().map(|s| Box::new(s) as Output);
+                (foreground, feeder, stdout, stderr)
+            }
+        };
+    let (tee_out, tee_err) = tees(opts);
+    let timeline = opts.timeline.then(|| Timeline::new(start_instant));
+    let stdout = Capture::start(
+        stdout_stream,
+        opts.max_stdout_bytes,
+        tee_out,
+        timeline
+            .as_ref()
+            .map(|timeline| timeline.stream("stdout", None)),
+    );
+    let stderr = Capture::start(
+        stderr_stream,
+        opts.max_stderr_bytes,
+        tee_err,
+        timeline
+            .as_ref()
+            .map(|timeline| timeline.stream("stderr", None)),
+    );
+
+    let deadline = opts.timeout.map(|timeout| start_instant + timeout);
+    let WaitOutcome {
+        exits,
+        timed_out,
+        mut interrupt_signal,
+        kill_signal,
+        usage,
+    } = wait_for_group(std::slice::from_mut(&mut child), deadline, opts.kill_after)?;
+    let (status, _) = exits[0];
+    // Before orphans are killed, so the last sample still sees them
+    receipt.process_tree = sampler.map(Sampler::finish);
+    receipt.file_trace = tracer.map(RunningTracer::finish);
+
+    let duration = start_instant.elapsed();
+    let end_time = Utc::now();
+
+    if foreground.is_some() && interrupt_signal.is_none() {
+        interrupt_signal = signals::terminal_interrupt(&status);
+    }
+    drop(foreground);
+
+    let mut orphaned_pids = kill_orphans(&mut child, timed_out || interrupt_signal.is_some());
+    if let Some(container) = &container {
+        // A killed client leaves its container running
+        if timed_out || interrupt_signal.is_some() {
+            container.remove();
+        }
+    }
+    if let Some(job) = &job {
+        orphaned_pids.extend(job.kill_remaining());
+    }
+    let resources = match (&cgroup, &job) {
+        (Some(cgroup), _) => Some(cgroup.usage()),
+        (None, Some(job)) => Some(job.usage()),
+        (None, None) => None,
+    };
+    let resources = rusage::merge(resources, usage);
+
+    // A killed child leaves possibly partial output behind; don't block on it
+    let grace = timed_out.then_some(DRAIN_GRACE);
+    let stdout = stdout.finish(grace);
+    let stderr = stderr.finish(grace);
+    let stdin = opts.stdin.finish(feeder, stdin_file, DRAIN_GRACE);
+
+    receipt.exit_code = status.code();
+    receipt.terminated_by = signals::terminated_by(&status);
+    receipt.status = Status::of(status.code(), timed_out, interrupt_signal.is_some());
+    receipt.interrupt_signal = interrupt_signal;
+    receipt.timed_out = timed_out;
+    receipt.kill_signal = kill_signal.map(signals::name);
+    receipt.orphaned_pids = orphaned_pids;
+    if !timed_out && interrupt_signal.is_none() {
+        receipt.crash = crash::inspect(&status, child.id(), &cwd, receipt.start_time.into());
+        receipt.oom = oom::detect(&status, child.id(), cgroup.as_ref(), resources.as_ref());
+        if receipt.oom.is_some() {
+            receipt.status = Status::OomKilled;
+        }
+    }
+    record_outputs(opts, &cwd, &mut receipt)?;
+    receipt.snapshot = snapshot
+        .map(|snapshot| snapshot.finish(opts.stub.as_deref()))
+        .transpose()?;
+    receipt.stdin = Some(stdin);
+    if let Some(access) = &mut receipt.fs_access {
+        // A pty merges stderr into stdout
+        let merged = if opts.pty { &stdout } else { &stderr };
+        access.record_denials(&String::from_utf8_lossy(&merged.content));
+    }
+    (receipt.stdout, receipt.stdout_encoding) = capture::encode(stdout.content);
+    receipt.stdout_info = Some(stdout.info);
+    (receipt.stderr, receipt.stderr_encoding) = capture::encode(stderr.content);
+    receipt.stderr_info = Some(stderr.info);
+    record_timeline(timeline, &mut receipt);
+    receipt.end_time = end_time;
+    receipt.duration_ms = duration.as_millis() as u64;
+    receipt.clock_anomaly = ClockAnomaly::detect(receipt.start_time, end_time, duration);
+    receipt.resources = resources;
+
+    Ok(receipt)
+}
+
+/// Hash the declared outputs, failing an otherwise successful run that left
+/// one missing if they are required
+pub fn record_outputs(opts: &ExecOptions, cwd: &Path, receipt: &mut Receipt) -> Result<()> {
+    let (outputs, missing) =
+        artifact::hash_existing(cwd, &opts.outputs).context("Failed to hash --output")?;
+    if opts.require_outputs && !missing.is_empty() && receipt.status == Status::Ok {
+        receipt.status = Status::MissingOutputs;
+    }
+    receipt.outputs = outputs;
+    receipt.missing_outputs = missing;
+    if let Some((_, store)) = &opts.objects {
+        for output in &mut receipt.outputs {
+            store
+                .put_artifact(cwd, output, &opts.encrypt_to)
+                .context(format!("Failed to store --output {}", output.path))?;
+            output.stored = true;
+        }
+        receipt.object_store = Some(store.dir().to_string_lossy().to_string());
+    }
+    Ok(())
+}
+
+/// Store the --timeline events in the receipt, in place of the flattened
+/// stdout and stderr
+pub fn record_timeline(timeline: Option<Timeline>, receipt: &mut Receipt) {
+    let Some(timeline) = timeline else {
+        return;
+    };
+    receipt.timeline = timeline.finish();
+    receipt.stdout.clear();
+    receipt.stdout_encoding = Encoding::Utf8;
+    receipt.stderr.clear();
+    receipt.stderr_encoding = Encoding::Utf8;
+}
+
+/// Finish the receipt and write it to `out`: compress output larger than
+/// --compress-above, write the files that go beside it (the core dump with
+/// --keep-core, and with `--binary-output sidecar` output that is not valid
+/// UTF-8 or was compressed, as `<out>.stdout.bin` or `<out>.stdout.zst` and
+/// the same for stderr), timestamp it with --timestamp-url, sign it with
+/// --sign, write its --attestation, then hand it to each of the sinks
+pub fn save_receipt(opts: &ExecOptions, receipt: &mut Receipt, out: &Path) -> Result<()> {
+    if opts.keep_core {
+        crash::keep_core(receipt, out);
+    }
+    store_outputs(opts, receipt, out)?;
+    // The command has already run, so its receipt is saved even when the
+    // timestamp authority or signer fails, and the failure reported after
+    let stamped = match &opts.tsa {
+        Some(tsa) => tsa.stamp(receipt),
+        None => Ok(()),
+    };
+    let signed = match &opts.signer {
+        Some(signer) => signer.sign(receipt, out, opts.fsync),
+        None => Ok(()),
+    };
+    if let Some((path, builder_id)) = &opts.attestation {
+        attestation::write(path, receipt, builder_id, opts.fsync)?;
+    }
+    for sink in &opts.sinks {
+        sink.write(receipt, out)?;
+    }
+    stamped.context(format!("Saved {} without a timestamp", out.display()))?;
+    signed.context(format!("Saved {} unsigned", out.display()))
+}
+
+/// Save `receipt` as `save_receipt` does, but only if nothing is at `out`
+/// yet: the file is created with create_new first, so a receipt another
+/// run wrote there is never replaced
+pub fn save_new_receipt(opts: &ExecOptions, receipt: &mut Receipt, out: &Path) -> Result<()> {
+    // With --ledger-only nothing is written to `out`
+    if opts.ledger_only.is_some() {
+        return save_receipt(opts, receipt, out);
+    }
+    if let Some(parent) = out.parent() {
+        fs::create_dir_all(parent).context(format!("Failed to create {}", parent.display()))?;
+    }
+    match fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(out)
+    {
+        Ok(_) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+            anyhow::bail!("{} already exists; not overwriting it", out.display())
+        }
+        Err(err) => {
+            return Err(err).context(format!("Failed to create {}", out.display()));
+        }
+    }
+    let saved = save_receipt(opts, receipt, out);
+    // Leave nothing behind but a receipt
+    if saved.is_err() && fs::metadata(out).is_ok_and(|meta| meta.len() == 0) {
+        let _ = fs::remove_file(out);
+    }
+    saved
+}
+
+/// Finalize each sink once the last receipt has been saved
+pub fn finalize_sinks(opts: &ExecOptions) -> Result<()> {
+    for sink in &opts.sinks {
+        sink.finalize()?;
+    }
+    Ok(())
+}
+
+/// Store each output stream as --objects-above, --compress-above and
+/// --binary-output ask: in the object store if it is larger than its
+/// threshold, else zstd-compressed if it is larger than that threshold, and
+/// in a sidecar file if it is compressed or not valid UTF-8 with
+/// `--binary-output sidecar`
+fn store_outputs(opts: &ExecOptions, receipt: &mut Receipt, out: &Path) -> Result<()> {
+    if let Some((threshold, store)) = &opts.objects {
+        for (text, encoding) in [
+            (&mut receipt.stdout, &mut receipt.stdout_encoding),
+            (&mut receipt.stderr, &mut receipt.stderr_encoding),
+        ] {
+            let bytes = match *encoding {
+                Encoding::Utf8 => text.as_bytes().to_vec(),
+                Encoding::Base64 => base64::decode(text)?,
+                _ => continue,
+            };
+            if bytes.len() as u64 > *threshold {
+                *text = store.put(&bytes, &opts.encrypt_to)?;
+                *encoding = Encoding::Object;
+                receipt.object_store = Some(store.dir().to_string_lossy().to_string());
+            }
+        }
+    }
+    if opts.split_output {
+        split_outputs(opts, receipt, out)?;
+    }
+    let sidecar = opts.binary_output == BinaryOutput::Sidecar;
+    let over = |len: usize| {
+        opts.compress_above
+            .as_ref()
+            .is_some_and(|(threshold, _)| len as u64 > *threshold)
+    };
+    for (stream, text, encoding, compression) in [
+        (
+            "stdout",
+            &mut receipt.stdout,
+            &mut receipt.stdout_encoding,
+            &mut receipt.stdout_compression,
+        ),
+        (
+            "stderr",
+            &mut receipt.stderr,
+            &mut receipt.stderr_encoding,
+            &mut receipt.stderr_compression,
+        ),
+    ] {
+        let bytes = match *encoding {
+            Encoding::Utf8 if over(text.len()) => mem::take(text).into_bytes(),
+            Encoding::Base64 if sidecar || opts.compress_above.is_some() => base64::decode(text)?,
+            _ => continue,
+        };
+        let sha256 = digest::sha256_hex(&bytes);
+        let size_bytes = bytes.len() as u64;
+        let (contents, extension) = match &opts.compress_above {
+            Some((_, zstd)) if over(bytes.len()) => {
+                let compressed = zstd::compress(zstd, &bytes)?;
+                *compression = Some(Compression {
+                    algorithm: CompressionAlgorithm::Zstd,
+                    original_bytes: size_bytes,
+                    original_sha256: sha256.clone(),
+                    compressed_bytes: compressed.len() as u64,
+                });
+                (compressed, "zst")
+            }
+            _ => (bytes, "bin"),
+        };
+        if !sidecar {
+            if compression.is_none() {
+                // Too small to compress; it stays base64
+                continue;
+            }
+            *text = base64::encode(&contents);
+            *encoding = Encoding::Zstd;
+            continue;
+        }
+
+        let mut path = OsString::from(out.as_os_str());
+        path.push(format!(".{}.{}", stream, extension));
+        let path = PathBuf::from(path);
+        receipt.sidecars.push(Sidecar {
+            stream: stream.to_string(),
+            path: beside(&path),
+            sha256,
+            size_bytes,
+            preview: None,
+        });
+        let contents = match opts.encrypt_to.is_empty() {
+            true => contents,
+            false => age::encrypt(&contents, &opts.encrypt_to)?,
+        };
+        atomic::write(&path, &contents, opts.fsync)
+            .context(format!("Failed to write {}", path.display()))?;
+        text.clear();
+        *encoding = Encoding::Sidecar;
+    }
+    Ok(())
+}
+
+/// The sidecar at `path` as recorded in the receipt: its file name,
+/// relative to the receipt beside it
+fn beside(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Write each output stream still in the receipt, as it was, to
+/// `<out>.stdout.log` and `<out>.stderr.log`, leaving a preview of it in
+/// `sidecars`
+fn split_outputs(opts: &ExecOptions, receipt: &mut Receipt, out: &Path) -> Result<()> {
+    for (stream, text, encoding) in [
+        ("stdout", &mut receipt.stdout, &mut receipt.stdout_encoding),
+        ("stderr", &mut receipt.stderr, &mut receipt.stderr_encoding),
+    ] {
+        let bytes = match *encoding {
+            Encoding::Utf8 => mem::take(text).into_bytes(),
+            Encoding::Base64 => base64::decode(text)?,
+            _ => continue,
+        };
+        let mut path = OsString::from(out.as_os_str());
+        path.push(format!(".{}.log", stream));
+        let path = PathBuf::from(path);
+        let preview = String::from_utf8_lossy(&bytes[..bytes.len().min(PREVIEW_BYTES)])
+            // A character cut in two at the end is dropped
+            .trim_end_matches(char::REPLACEMENT_CHARACTER)
+            .to_string();
+        receipt.sidecars.push(Sidecar {
+            stream: stream.to_string(),
+            path: beside(&path),
+            sha256: digest::sha256_hex(&bytes),
+            size_bytes: bytes.len() as u64,
+            preview: Some(preview),
+        });
+        let contents = match opts.encrypt_to.is_empty() {
+            true => bytes,
+            false => age::encrypt(&bytes, &opts.encrypt_to)?,
+        };
+        atomic::write(&path, &contents, opts.fsync)
+            .context(format!("Failed to write {}", path.display()))?;
+        text.clear();
+        *encoding = Encoding::Sidecar;
+    }
+    Ok(())
+}
+
+/// The environment the command runs with: rcpt's own plus any env files,
+/// adjusted for --user
+pub fn child_environment(opts: &ExecOptions) -> BTreeMap<OsString, OsString> {
+    let mut child_env: BTreeMap<OsString, OsString> = if opts.clear_env {
+        BTreeMap::new()
+    } else {
+        std::env::vars_os().collect()
+    };
+    for file in &opts.env_files {
+        for (name, value) in &file.vars {
+            child_env.insert(name.into(), value.into());
+        }
+    }
+    for (name, value) in &opts.env_vars {
+        child_env.insert(name.into(), value.into());
+    }
+    if let Some(user) = &opts.user {
+        for name in ["USER", "LOGNAME"] {
+            child_env.insert(name.into(), user.name.clone().into());
+        }
+        child_env.insert("HOME".into(), user.home.clone().into());
+    }
+    // Set to the receipt's id once it is known, and left out of its env
+    child_env.remove(OsStr::new(id::PARENT_ENV));
+    child_env
+}
+
+/// Names of the variables set explicitly with --env and --env-file, the
+/// only ones passed into a container
+fn explicit_env_names(opts: &ExecOptions) -> Vec<String> {
+    let mut names: Vec<String> = opts
+        .env_files
+        .iter()
+        .flat_map(|file| &file.vars)
+        .chain(&opts.env_vars)
+        .map(|(name, _)| name.clone())
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// A receipt holding everything known before the command runs, starting
+/// now. The caller fills in what was run and, after running it, the results.
+pub fn planned_receipt(
+    opts: &ExecOptions,
+    cwd: &Path,
+    child_env: &BTreeMap<OsString, OsString>,
+) -> Result<Receipt> {
+    let (env, env_policy) = match &opts.env_policy {
+        Some(policy) => {
+            let vars = child_env.iter().map(|(name, value)| {
+                (
+                    name.to_string_lossy().to_string(),
+                    value.to_string_lossy().to_string(),
+                )
+            });
+            let (env, report) = policy.capture(vars)?;
+            (Some(env), Some(report))
+        }
+        None => (None, None),
+    };
+
+    let start_time = Utc::now();
+    Ok(Receipt {
+        receipt_version: RECEIPT_VERSION,
+        id: opts
+            .receipt_id
+            .clone()
+            .unwrap_or_else(|| id::ulid(start_time)),
+        receipt_digest: None,
+        signature: None,
+        timestamp: None,
+        parent_id: opts.parent_id.clone(),
+        replay_of: None,
+        imported: false,
+        session_id: opts.session_id.clone(),
+        actor: opts.actor.clone(),
+        tags: opts.tags.clone(),
+        metadata: opts.metadata.clone(),
+        command: String::new(),
+        args: Vec::new(),
+        executable: None,
+        shell: None,
+        stages: Vec::new(),
+        pty: opts.pty,
+        cwd: cwd.to_string_lossy().to_string(),
+        host: opts.capture_host.then(host::capture),
+        vcs: opts.capture_vcs.then(|| vcs::capture(cwd)).flatten(),
+        user: match &opts.user {
+            Some(user) => Some(user.record()),
+            None => user::current(),
+        },
+        container: None,
+        remote: None,
+        pid: None,
+        supervisor_pid: opts.stub.as_ref().map(|_| std::process::id()),
+        limits: (!opts.limits.is_empty()).then(|| opts.limits.clone()),
+        scheduling: (!opts.scheduling.is_empty()).then(|| opts.scheduling.clone()),
+        sandbox: opts
+            .sandbox
+            .as_ref()
+            .map(|sandbox| sandbox.profile(cwd))
+            .transpose()?,
+        fs_access: opts.access.as_ref().map(PathRules::profile).transpose()?,
+        network: opts.no_network.then_some(Network::Disabled),
+        env,
+        env_digest: env::digest(child_env),
+        env_policy,
+        env_files: opts.env_files.iter().map(EnvFile::record).collect(),
+        hermetic: opts.clear_env,
+        inputs: opts
+            .inputs
+            .iter()
+            .map(|path| artifact::hash(cwd, path))
+            .collect::<Result<_>>()
+            .context("Failed to hash --input")?,
+        outputs: Vec::new(),
+        missing_outputs: Vec::new(),
+        snapshot: None,
+        exit_code: None,
+        terminated_by: None,
+        status: Status::Planned,
+        interrupt_signal: None,
+        timed_out: false,
+        kill_signal: None,
+        orphaned_pids: Vec::new(),
+        process_tree: None,
+        file_trace: None,
+        crash: None,
+        oom: None,
+        stdin: Some(opts.stdin.record()),
+        stdout: String::new(),
+        stdout_encoding: Encoding::Utf8,
+        stdout_compression: None,
+        stdout_info: None,
+        stderr: String::new(),
+        stderr_encoding: Encoding::Utf8,
+        stderr_compression: None,
+        stderr_info: None,
+        timeline: Vec::new(),
+        sidecars: Vec::new(),
+        object_store: None,
+        redactions: 0,
+        later_redactions: Vec::new(),
+        start_time,
+        end_time: start_time,
+        duration_ms: 0,
+        clock_anomaly: None,
+        resources: None,
+        attempts: Vec::new(),
+        benchmark: None,
+        trigger: None,
+    })
+}
+
+/// Record that the command is running, if supervising a detached run. The
+/// command is already running, so failing to write is reported but not fatal.
+pub fn write_stub(opts: &ExecOptions, receipt: &Receipt) {
+    let Some(path) = &opts.stub else {
+        return;
+    };
+    let mut stub = Receipt {
+        status: Status::Running,
+        ..receipt.clone()
+    };
+    opts.redactor.apply(&mut stub);
+    if let Err(err) = write_receipt(path, &stub, opts.fsync) {
+        eprintln!("rcpt: {:#}", err);
+    }
+}
+
+/// A command for `cmd` with the working directory, environment, limits and
+/// scheduling from `opts`. Stdio, process group and user are up to the caller.
+pub fn build_command(
+    cmd: &OsStr,
+    args: &[OsString],
+    cwd: &Path,
+    child_env: &BTreeMap<OsString, OsString>,
+    opts: &ExecOptions,
+) -> Result<Command> {
+    let mut command = Command::new(cmd);
+    command
+        .args(args)
+        .current_dir(cwd)
+        .env_clear()
+        .envs(child_env);
+    // A container gets its limits from the engine instead
+    if !opts.limits.is_empty() && opts.container.is_none() {
+        opts.limits.apply(&mut command)?;
+    }
+    if !opts.scheduling.is_empty() {
+        opts.scheduling.apply(&mut command)?;
+    }
+    Ok(command)
+}
+
+/// Where captured stdout and stderr are echoed, if --tee was given
+pub fn tees(opts: &ExecOptions) -> (Option<Tee>, Option<Tee>) {
+    if opts.tee {
+        (
+            Some(Box::new(std::io::stdout())),
+            Some(Box::new(std::io::stderr())),
+        )
+    } else {
+        (None, None)
+    }
+}
+
+/// Kill whatever is left in the leader's process group once the command
+/// has exited, returning the pids that were still alive. Anything left
+/// outlived the command; cleaning it up means it neither leaks past
+/// end_time nor holds the output pipes open.
+pub fn kill_orphans(leader: &mut Child, signalled: bool) -> Vec<u32> {
+    if signalled {
+        // Give the rest of the group a moment to act on the signal we sent
+        let give_up = Instant::now() + ORPHAN_SETTLE;
+        while !procgroup::members(leader).is_empty() && Instant::now() < give_up {
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+    let orphaned_pids = procgroup::members(leader);
+    if !orphaned_pids.is_empty() {
+        procgroup::kill(leader);
+    }
+    orphaned_pids
+}
+
+/// Find the file `cmd` refers to: a path relative to `cwd` if it contains a
+/// separator, otherwise the first executable match in `path`
+pub fn resolve_executable(cmd: &OsStr, path: Option<&OsString>, cwd: &Path) -> Option<PathBuf> {
+    let cmd = Path::new(cmd);
+    if cmd.components().count() > 1 {
+        let candidate: PathBuf = cwd
+            .join(cmd)
+            .components()
+            .filter(|component| *component != Component::CurDir)
+            .collect();
+        return is_executable(&candidate).then_some(candidate);
+    }
+    std::env::split_paths(path?)
+        .flat_map(|dir| executable_candidates(&dir.join(cmd)))
+        .find(|candidate| is_executable(candidate))
+}
+
+#[cfg(unix)]
+fn executable_candidates(path: &Path) -> Vec<PathBuf> {
+    vec![path.to_path_buf()]
+}
+
+/// On Windows a bare name may resolve through any of the PATHEXT extensions
+#[cfg(windows)]
+fn executable_candidates(path: &Path) -> Vec<PathBuf> {
+    let extensions = std::env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".into());
+    let mut candidates = vec![path.to_path_buf()];
+    for extension in extensions.split(';').filter(|ext| !ext.is_empty()) {
+        let mut candidate = path.as_os_str().to_owned();
+        candidate.push(extension);
+        candidates.push(candidate.into());
+    }
+    candidates
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(windows)]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Resolve the command's working directory to a canonical absolute path
+pub fn resolve_cwd(cwd: Option<&Path>) -> Result<PathBuf> {
+    let cwd = match cwd {
+        Some(cwd) => cwd.to_path_buf(),
+        None => std::env::current_dir().context("Failed to determine current directory")?,
+    };
+    fs::canonicalize(&cwd).context(format!(
+        "Failed to resolve working directory {}",
+        cwd.display()
+    ))
+}
+
+/// How the wait for a process group ended
+pub struct WaitOutcome {
+    /// Exit status of each child and when it was seen to exit
+    pub exits: Vec<(ExitStatus, Instant)>,
+    pub timed_out: bool,
+    pub interrupt_signal: Option<i32>,
+    /// Last signal rcpt sent to stop the children
+    pub kill_signal: Option<i32>,
+    /// Resource usage of all the children together, from wait4 (Unix only)
+    pub usage: Option<ChildUsage>,
+}
+
+/// Wait for every child to exit, forwarding any termination signal rcpt
+/// receives to their group, which the first child leads. Once `deadline`
+/// passes the group gets SIGTERM. If it is still running `kill_after` after
+/// being signalled either way, it gets SIGKILL.
+pub fn wait_for_group(
+    children: &mut [Child],
+    deadline: Option<Instant>,
+    kill_after: Duration,
+) -> Result<WaitOutcome> {
+    let mut exits = vec![None; children.len()];
+    let mut usage: Option<ChildUsage> = None;
+    let reapers: Vec<Reaper> = children.iter().map(Reaper::new).collect();
+    let mut timed_out = false;
+    let mut interrupt_signal = None;
+    let mut kill_signal = None;
+    let mut escalate_at: Option<Instant> = None;
+    let mut watch = signals::Watch::new();
+
+    loop {
+        for ((child, exit), reaper) in children.iter_mut().zip(exits.iter_mut()).zip(&reapers) {
+            if exit.is_some() {
+                continue;
+            }
+            if let Some((status, child_usage)) = reaper
+                .try_wait(child)
+                .context("Failed to wait for command")?
+            {
+                *exit = Some((status, Instant::now()));
+                if let Some(child_usage) = child_usage {
+                    usage.get_or_insert_with(Default::default).add(&child_usage);
+                }
+            }
+        }
+        if exits.iter().all(Option::is_some) {
+            return Ok(WaitOutcome {
+                exits: exits.into_iter().flatten().collect(),
+                timed_out,
+                interrupt_signal,
+                kill_signal,
+                usage,
+            });
+        }
+        let now = Instant::now();
+        if let Some(signal) = watch.poll() {
+            procgroup::signal(&children[0], signal);
+            interrupt_signal = Some(signal);
+            if kill_signal != Some(signals::SIGKILL) {
+                kill_signal = Some(signal);
+            }
+            escalate_at.get_or_insert(now + kill_after);
+        }
+        if !timed_out && deadline.is_some_and(|deadline| now >= deadline) {
+            timed_out = true;
+            if cfg!(unix) && !kill_after.is_zero() {
+                procgroup::signal(&children[0], signals::SIGTERM);
+                kill_signal = Some(signals::SIGTERM);
+                escalate_at.get_or_insert(now + kill_after);
+            } else {
+                escalate_at = Some(now);
+            }
+        }
+        if escalate_at.is_some_and(|at| now >= at) && kill_signal != Some(signals::SIGKILL) {
+            procgroup::kill_all(children);
+            kill_signal = Some(signals::SIGKILL);
+        }
+        let running: Vec<&Child> = children
+            .iter()
+            .zip(&exits)
+            .filter(|(_, exit)| exit.is_none())
+            .map(|(child, _)| child)
+            .collect();
+        procgroup::sleep_until_exit(&running, POLL_INTERVAL);
+    }
+}
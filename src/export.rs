@@ -0,0 +1,466 @@
+use anyhow::{bail, Result};
+use chrono::SecondsFormat;
+use clap::ValueEnum;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::receipt::{Receipt, Status};
+
+/// What receipts are exported as
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// One row per receipt, of the --fields asked for
+    Csv,
+    /// JUnit XML: a test suite per session, and a test case per receipt
+    Junit,
+    /// SARIF for code-scanning views: a result per error line of each
+    /// failed run, at the file and line it names
+    Sarif,
+    /// Markdown for pull requests and CI job summaries: a table of the
+    /// runs, and each one's output in a collapsed section
+    Md,
+}
+
+/// A column of the CSV export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Id,
+    /// The command line
+    Cmd,
+    Status,
+    ExitCode,
+    /// The name of the signal that ended the command, if one did
+    Signal,
+    DurationMs,
+    StartedAt,
+    EndedAt,
+    Cwd,
+    /// All the tags, separated by `;`
+    Tags,
+    SessionId,
+    ParentId,
+    Hostname,
+    CpuUserMs,
+    CpuSystemMs,
+    MaxRssBytes,
+    EnvDigest,
+    ReceiptDigest,
+    /// Where the receipt is
+    Path,
+}
+
+/// The fields exported when --fields is not given
+pub const DEFAULT_FIELDS: &str = "id,cmd,status,exit_code,duration_ms,started_at";
+
+const FIELDS: &[(&str, Field)] = &[
+    ("id", Field::Id),
+    ("cmd", Field::Cmd),
+    ("status", Field::Status),
+    ("exit_code", Field::ExitCode),
+    ("signal", Field::Signal),
+    ("duration_ms", Field::DurationMs),
+    ("started_at", Field::StartedAt),
+    ("ended_at", Field::EndedAt),
+    ("cwd", Field::Cwd),
+    ("tags", Field::Tags),
+    ("session_id", Field::SessionId),
+    ("parent_id", Field::ParentId),
+    ("hostname", Field::Hostname),
+    ("cpu_user_ms", Field::CpuUserMs),
+    ("cpu_system_ms", Field::CpuSystemMs),
+    ("max_rss_bytes", Field::MaxRssBytes),
+    ("env_digest", Field::EnvDigest),
+    ("receipt_digest", Field::ReceiptDigest),
+    ("path", Field::Path),
+];
+
+impl FromStr for Field {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match FIELDS.iter().find(|(name, _)| *name == s) {
+            Some(&(_, field)) => Ok(field),
+            None => bail!(
+                "expected one of {}, got '{}'",
+                FIELDS
+                    .iter()
+                    .map(|(name, _)| *name)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                s
+            ),
+        }
+    }
+}
+
+impl Field {
+    fn name(self) -> &'static str {
+        FIELDS
+            .iter()
+            .find(|(_, field)| *field == self)
+            .map(|(name, _)| *name)
+            .expect("every field is named")
+    }
+
+    /// This field of `receipt`, at `path`, as text; empty if it has none
+    fn value(self, path: &Path, receipt: &Receipt) -> String {
+        let optional = |value: Option<String>| value.unwrap_or_default();
+        let resources = receipt.resources.as_ref();
+        match self {
+            Field::Id => receipt.id.clone(),
+            Field::Cmd => receipt.command_line(),
+            Field::Status => receipt.status.as_str().to_string(),
+            Field::ExitCode => optional(receipt.exit_code.map(|code| code.to_string())),
+            Field::Signal => optional(receipt.terminated_by.as_ref().map(|by| by.name.clone())),
+            Field::DurationMs => receipt.duration_ms.to_string(),
+            Field::StartedAt => receipt
+                .start_time
+                .to_rfc3339_opts(SecondsFormat::Millis, true),
+            Field::EndedAt => receipt
+                .end_time
+                .to_rfc3339_opts(SecondsFormat::Millis, true),
+            Field::Cwd => receipt.cwd.clone(),
+            Field::Tags => receipt.tags.join(";"),
+            Field::SessionId => optional(receipt.session_id.clone()),
+            Field::ParentId => optional(receipt.parent_id.clone()),
+            Field::Hostname => {
+                optional(receipt.host.as_ref().and_then(|host| host.hostname.clone()))
+            }
+            Field::CpuUserMs => optional(resources.map(|usage| usage.cpu_user_ms.to_string())),
+            Field::CpuSystemMs => optional(resources.map(|usage| usage.cpu_system_ms.to_string())),
+            Field::MaxRssBytes => optional(
+                resources
+                    .and_then(|usage| usage.max_rss_bytes)
+                    .map(|bytes| bytes.to_string()),
+            ),
+            Field::EnvDigest => receipt.env_digest.clone(),
+            Field::ReceiptDigest => optional(receipt.receipt_digest.clone()),
+            Field::Path => path.display().to_string(),
+        }
+    }
+}
+
+/// `receipts` as CSV, RFC 4180 style: a header of the `fields`' names,
+/// then a row for each receipt
+pub fn csv(receipts: &[(PathBuf, Receipt)], fields: &[Field]) -> String {
+    let mut out = String::new();
+    let header: Vec<&str> = fields.iter().map(|field| field.name()).collect();
+    push_row(&mut out, header.into_iter().map(str::to_string));
+    for (path, receipt) in receipts {
+        push_row(
+            &mut out,
+            fields.iter().map(|field| field.value(path, receipt)),
+        );
+    }
+    out
+}
+
+fn push_row(out: &mut String, values: impl Iterator<Item = String>) {
+    let quoted: Vec<String> = values.map(|value| quote(&value)).collect();
+    out.push_str(&quoted.join(","));
+    out.push_str("\r\n");
+}
+
+/// `value`, quoted if it has a comma, quote or line break in it
+fn quote(value: &str) -> String {
+    match value.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => value.to_string(),
+    }
+}
+
+/// How many of the last lines of stderr a failed test case's failure
+/// shows; all of it is in the case's system-err
+const FAILURE_LINES: usize = 20;
+
+/// `receipts` as JUnit XML, for CI test report views: a test suite for each
+/// session, and one of the runs in none, with a test case for each run
+/// that passed if the run succeeded, failed if its command did, errored if
+/// it was stopped, and was skipped if it has not finished, holding its
+/// stdout and stderr
+pub fn junit(receipts: &[(PathBuf, Receipt)]) -> Result<String> {
+    let mut suites: Vec<(&str, Vec<(&Path, &Receipt)>)> = Vec::new();
+    for (path, receipt) in receipts {
+        let name = receipt.session_id.as_deref().unwrap_or("rcpt");
+        match suites.iter_mut().find(|(suite, _)| *suite == name) {
+            Some((_, cases)) => cases.push((path, receipt)),
+            None => suites.push((name, vec![(path, receipt)])),
+        }
+    }
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let (tests, failures, errors, _) = count(receipts.iter().map(|(_, receipt)| receipt));
+    let time: u64 = receipts
+        .iter()
+        .map(|(_, receipt)| receipt.duration_ms)
+        .sum();
+    xml.push_str(&format!(
+        "<testsuites name=\"rcpt\" tests=\"{}\" failures=\"{}\" errors=\"{}\" time=\"{}\">\n",
+        tests,
+        failures,
+        errors,
+        seconds(time)
+    ));
+    for (name, cases) in &suites {
+        let (tests, failures, errors, skipped) = count(cases.iter().map(|(_, receipt)| *receipt));
+        let time: u64 = cases.iter().map(|(_, receipt)| receipt.duration_ms).sum();
+        xml.push_str(&format!(
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"{}\" skipped=\"{}\" time=\"{}\" timestamp=\"{}\">\n",
+            escape(name),
+            tests,
+            failures,
+            errors,
+            skipped,
+            seconds(time),
+            cases[0].1.start_time.format("%Y-%m-%dT%H:%M:%S")
+        ));
+        for (path, receipt) in cases {
+            test_case(&mut xml, path, receipt)?;
+        }
+        xml.push_str("  </testsuite>\n");
+    }
+    xml.push_str("</testsuites>\n");
+    Ok(xml)
+}
+
+fn test_case(xml: &mut String, path: &Path, receipt: &Receipt) -> Result<()> {
+    let program = receipt
+        .command
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default();
+    xml.push_str(&format!(
+        "    <testcase name=\"{}\" classname=\"{}\" time=\"{}\">\n",
+        escape(&receipt.command_line()),
+        escape(program),
+        seconds(receipt.duration_ms)
+    ));
+    xml.push_str(&format!(
+        "      <properties>\n        <property name=\"receipt_id\" value=\"{}\"/>\n        <property name=\"receipt_path\" value=\"{}\"/>\n      </properties>\n",
+        escape(&receipt.id),
+        escape(&path.display().to_string())
+    ));
+    let stdout = String::from_utf8_lossy(&receipt.written("stdout", path)?).to_string();
+    let stderr = String::from_utf8_lossy(&receipt.written("stderr", path)?).to_string();
+    let mut message = receipt.status.as_str().to_string();
+    if let Some(code) = receipt.exit_code {
+        message.push_str(&format!(" with exit code {}", code));
+    } else if let Some(signal) = &receipt.terminated_by {
+        message.push_str(&format!(" by {}", signal.name));
+    }
+    let end: Vec<&str> = stderr.lines().collect();
+    let end = end[end.len().saturating_sub(FAILURE_LINES)..].join("\n");
+    let element = match Outcome::of(receipt.status) {
+        Outcome::Passed => None,
+        Outcome::Skipped => {
+            xml.push_str(&format!(
+                "      <skipped message=\"{}\"/>\n",
+                escape(&message)
+            ));
+            None
+        }
+        Outcome::Failure => Some("failure"),
+        Outcome::Error => Some("error"),
+    };
+    if let Some(element) = element {
+        xml.push_str(&format!(
+            "      <{} message=\"{}\" type=\"{}\">{}</{}>\n",
+            element,
+            escape(&message),
+            receipt.status.as_str(),
+            escape(&end),
+            element
+        ));
+    }
+    if !stdout.is_empty() {
+        xml.push_str(&format!(
+            "      <system-out>{}</system-out>\n",
+            escape(&stdout)
+        ));
+    }
+    if !stderr.is_empty() {
+        xml.push_str(&format!(
+            "      <system-err>{}</system-err>\n",
+            escape(&stderr)
+        ));
+    }
+    xml.push_str("    </testcase>\n");
+    Ok(())
+}
+
+/// How many of the last lines of each stream a run's Markdown section
+/// shows, to keep the export small enough for a pull request
+const MARKDOWN_LINES: usize = 50;
+
+/// `receipts` as Markdown to paste into a pull request or post as a CI job
+/// summary: a line counting the runs, a table with a row for each, then a
+/// collapsed section for each one with output, holding the last
+/// `MARKDOWN_LINES` lines of its stdout and stderr
+pub fn markdown(receipts: &[(PathBuf, Receipt)]) -> Result<String> {
+    let (tests, failures, errors, skipped) = count(receipts.iter().map(|(_, receipt)| receipt));
+    let mut md = match tests {
+        1 => "**1 run**".to_string(),
+        _ => format!("**{} runs**", tests),
+    };
+    for (n, what) in [
+        (failures, "failed"),
+        (errors, "stopped"),
+        (skipped, "unfinished"),
+    ] {
+        if n > 0 {
+            md.push_str(&format!(", {} {}", n, what));
+        }
+    }
+    md.push_str("\n\n| Status | Command | Exit | Duration | Started |\n");
+    md.push_str("| --- | --- | ---: | ---: | --- |\n");
+    for (_, receipt) in receipts {
+        let exit = match (receipt.exit_code, &receipt.terminated_by) {
+            (Some(code), _) => code.to_string(),
+            (None, Some(signal)) => signal.name.clone(),
+            (None, None) => String::new(),
+        };
+        md.push_str(&format!(
+            "| {} {} | {} | {} | {}s | {} |\n",
+            mark(receipt.status),
+            receipt.status.as_str(),
+            code_span(&receipt.command_line()).replace('|', "\\|"),
+            exit,
+            seconds(receipt.duration_ms),
+            receipt.start_time.format("%Y-%m-%d %H:%M:%SZ")
+        ));
+    }
+    for (path, receipt) in receipts {
+        let mut streams = Vec::new();
+        for stream in ["stdout", "stderr"] {
+            let output = receipt.written(stream, path)?;
+            if !output.is_empty() {
+                streams.push((stream, String::from_utf8_lossy(&output).to_string()));
+            }
+        }
+        if streams.is_empty() {
+            continue;
+        }
+        md.push_str(&format!(
+            "\n<details><summary>{} <code>{}</code></summary>\n",
+            mark(receipt.status),
+            escape(&receipt.command_line())
+        ));
+        for (stream, text) in streams {
+            let lines: Vec<&str> = text.lines().collect();
+            let shown = &lines[lines.len().saturating_sub(MARKDOWN_LINES)..];
+            match shown.len() < lines.len() {
+                true => md.push_str(&format!(
+                    "\n**{}** (last {} of {} lines)\n\n",
+                    stream,
+                    shown.len(),
+                    lines.len()
+                )),
+                false => md.push_str(&format!("\n**{}**\n\n", stream)),
+            }
+            let shown = shown.join("\n");
+            // A fence longer than any run of backticks in the output
+            let fence = "`".repeat(longest_run(&shown, '`').max(2) + 1);
+            md.push_str(&format!("{}text\n{}\n{}\n", fence, shown, fence));
+        }
+        md.push_str("\n</details>\n");
+    }
+    Ok(md)
+}
+
+/// The emoji a run's row and section are marked with
+fn mark(status: Status) -> &'static str {
+    match Outcome::of(status) {
+        Outcome::Passed => "✅",
+        Outcome::Failure => "❌",
+        Outcome::Error => "⛔",
+        Outcome::Skipped => "⏳",
+    }
+}
+
+/// `text` on one line as Markdown inline code, between more backticks than
+/// it holds in a row
+fn code_span(text: &str) -> String {
+    let text = text.replace(['\n', '\r'], " ");
+    let ticks = "`".repeat(longest_run(&text, '`') + 1);
+    match text.starts_with('`') || text.ends_with('`') {
+        true => format!("{} {} {}", ticks, text, ticks),
+        false => format!("{}{}{}", ticks, text, ticks),
+    }
+}
+
+/// The most times `c` appears in a row in `text`
+fn longest_run(text: &str, c: char) -> usize {
+    let (mut longest, mut run) = (0, 0);
+    for next in text.chars() {
+        run = match next == c {
+            true => run + 1,
+            false => 0,
+        };
+        longest = longest.max(run);
+    }
+    longest
+}
+
+/// How a run counts in JUnit and is marked in Markdown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Passed,
+    /// The command failed
+    Failure,
+    /// The command was stopped before it could finish
+    Error,
+    /// The command has not finished
+    Skipped,
+}
+
+impl Outcome {
+    fn of(status: Status) -> Self {
+        match status {
+            Status::Ok => Outcome::Passed,
+            Status::Failed | Status::MissingOutputs => Outcome::Failure,
+            Status::TimedOut | Status::Interrupted | Status::OomKilled | Status::Lost => {
+                Outcome::Error
+            }
+            Status::Planned | Status::Running => Outcome::Skipped,
+        }
+    }
+}
+
+/// How many of `receipts` there are, and how many counted as each outcome
+fn count<'a>(receipts: impl Iterator<Item = &'a Receipt>) -> (usize, usize, usize, usize) {
+    let outcomes: Vec<Outcome> = receipts
+        .map(|receipt| Outcome::of(receipt.status))
+        .collect();
+    let of = |outcome| outcomes.iter().filter(|o| **o == outcome).count();
+    (
+        outcomes.len(),
+        of(Outcome::Failure),
+        of(Outcome::Error),
+        of(Outcome::Skipped),
+    )
+}
+
+fn seconds(ms: u64) -> String {
+    format!("{}.{:03}", ms / 1000, ms % 1000)
+}
+
+/// `text` escaped for XML or HTML, in an attribute or between tags, with
+/// the characters XML 1.0 cannot hold at all replaced
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if (c as u32) < 0x20 || c == '\u{FFFE}' || c == '\u{FFFF}' => {
+                escaped.push(char::REPLACEMENT_CHARACTER)
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,273 @@
+use anyhow::{Context, Result};
+use chrono::{Duration, Utc};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::age;
+use crate::artifact::ArtifactKind;
+use crate::ledger;
+use crate::list::{self, Filter};
+use crate::lock::{self, Lock};
+use crate::objects;
+use crate::receipt::{self, Encoding, Receipt, Status};
+use crate::signing;
+use crate::units::format_size;
+
+/// Objects written this recently are kept even if no receipt refers to
+/// them, as a run may have stored them and not yet written its receipt
+const OBJECT_GRACE: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+/// Which receipts `rcpt gc` keeps; a receipt any rule keeps is kept
+#[derive(Debug)]
+pub struct Retention {
+    /// Keep runs that started less than this many days ago
+    pub keep_days: Option<u64>,
+    /// Keep this many of the most recent runs
+    pub keep_last: Option<usize>,
+    /// Keep runs that did not succeed
+    pub keep_failed: bool,
+}
+
+impl Retention {
+    fn keeps(&self, rank: usize, receipt: &Receipt) -> bool {
+        // A receipt still being written to is never old
+        matches!(receipt.status, Status::Planned | Status::Running)
+            || self.keep_last.is_some_and(|last| rank < last)
+            || self
+                .keep_days
+                .is_some_and(|days| receipt.start_time > Utc::now() - Duration::days(days as i64))
+            || (self.keep_failed && receipt.status != Status::Ok)
+    }
+}
+
+/// Delete the receipts under `dirs` that `retention` does not keep, with
+/// their sidecars and detached signatures, then the objects in their object
+/// stores and `stores` that no kept receipt, and no entry in `ledgers`,
+/// refers to, writing what was deleted to `report`. With `dry_run`, only
+/// list what would be deleted. Each directory is locked while it is
+/// collected, so collections sharing one take turns
+pub fn collect(
+    dirs: &[PathBuf],
+    ledgers: &[PathBuf],
+    stores: &[PathBuf],
+    retention: &Retention,
+    dry_run: bool,
+    report: &mut impl Write,
+) -> Result<()> {
+    let (locked, _locks) = lock_dirs(dirs)?;
+
+    let mut receipts = Vec::new();
+    let mut unreadable = 0;
+    for path in receipt::json_files(dirs)? {
+        match receipt::read_receipt(&path) {
+            Ok(receipt) => receipts.push((path, receipt)),
+            // Files that are not receipts are no business of ours, but an
+            // encrypted receipt may refer to objects we cannot see
+            Err(_) if fs::read(&path).is_ok_and(|bytes| age::is_encrypted(&bytes)) => {
+                unreadable += 1;
+            }
+            Err(_) => {}
+        }
+    }
+    receipts.sort_by(|(_, a), (_, b)| (b.start_time, &b.id).cmp(&(a.start_time, &a.id)));
+    // Ledger entries are never deleted, so what they refer to is kept
+    let mut entries = Vec::new();
+    for path in ledgers {
+        entries.extend(ledger::read_from(path, 0)?.0);
+    }
+
+    let verb = if dry_run { "would delete" } else { "deleted" };
+    let stores: BTreeSet<PathBuf> = stores
+        .iter()
+        .cloned()
+        .chain(
+            receipts
+                .iter()
+                .filter_map(|(path, receipt)| receipt.object_dir(path)),
+        )
+        .chain(
+            entries
+                .iter()
+                .filter_map(|entry| entry.object_store.as_ref().map(PathBuf::from)),
+        )
+        // The same store may be named by different paths
+        .filter_map(|store| fs::canonicalize(store).ok())
+        .collect();
+    let mut referenced = BTreeSet::new();
+    for entry in &entries {
+        references(entry, &stores, &mut referenced);
+    }
+    let (mut deleted, mut deleted_bytes) = (0, 0);
+    for (rank, (path, receipt)) in receipts.iter().enumerate() {
+        if retention.keeps(rank, receipt) {
+            references(receipt, &stores, &mut referenced);
+            continue;
+        }
+        deleted_bytes += delete(path, receipt, dry_run)?;
+        if dry_run {
+            writeln!(report, "{} {}", verb, path.display())?;
+        }
+        deleted += 1;
+    }
+
+    let (mut objects, mut object_bytes) = (0, 0);
+    if unreadable > 0 {
+        eprintln!(
+            "rcpt: keeping every object, as {} encrypted receipts could not be read; set {} to read them",
+            unreadable,
+            age::IDENTITY_ENV
+        );
+    } else {
+        for store in &stores {
+            // Runs wait to store objects here until we are done, and one that
+            // stored an object we would delete has marked it recent
+            let _lock = match locked.contains(store) {
+                true => None,
+                false => Some(Lock::exclusive(&lock::dir_lock(store))?),
+            };
+            let listing =
+                fs::read_dir(store).context(format!("Failed to read {}", store.display()))?;
+            for entry in listing.flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                let Ok(metadata) = entry.metadata() else {
+                    continue;
+                };
+                let recent = metadata
+                    .modified()
+                    .ok()
+                    .and_then(|modified| modified.elapsed().ok())
+                    .is_none_or(|age| age < OBJECT_GRACE);
+                if !is_digest(&name) || referenced.contains(&name) || recent {
+                    continue;
+                }
+                if dry_run {
+                    writeln!(report, "{} object {}", verb, entry.path().display())?;
+                } else {
+                    fs::remove_file(entry.path())
+                        .context(format!("Failed to delete {}", entry.path().display()))?;
+                }
+                objects += 1;
+                object_bytes += metadata.len();
+            }
+        }
+    }
+
+    writeln!(
+        report,
+        "{} {} receipts ({}) and {} unreferenced objects ({}); kept {} receipts",
+        if dry_run { "Would delete" } else { "Deleted" },
+        deleted,
+        format_size(deleted_bytes),
+        objects,
+        format_size(object_bytes),
+        receipts.len() - deleted
+    )?;
+    Ok(())
+}
+
+/// Delete the receipts under `dirs` that `filter` picks out, with their
+/// sidecars and detached signatures, except those of runs still going.
+/// Objects they referred to are left for `collect` to delete once nothing
+/// refers to them. Writes what was deleted to `report`, or with `dry_run`
+/// only lists what would be
+pub fn prune(
+    dirs: &[PathBuf],
+    filter: &Filter,
+    dry_run: bool,
+    report: &mut impl Write,
+) -> Result<()> {
+    let (_, _locks) = lock_dirs(dirs)?;
+    let receipts = list::find(dirs, filter)?;
+    let (mut deleted, mut deleted_bytes) = (0, 0);
+    for (path, receipt) in &receipts {
+        if matches!(receipt.status, Status::Planned | Status::Running) {
+            continue;
+        }
+        deleted_bytes += delete(path, receipt, dry_run)?;
+        if dry_run {
+            writeln!(report, "would delete {}", path.display())?;
+        }
+        deleted += 1;
+    }
+    writeln!(
+        report,
+        "{} {} receipts ({})",
+        if dry_run { "Would delete" } else { "Deleted" },
+        deleted,
+        format_size(deleted_bytes)
+    )?;
+    Ok(())
+}
+
+/// Lock each of `dirs`, returning the canonical paths locked and the locks
+fn lock_dirs(dirs: &[PathBuf]) -> Result<(BTreeSet<PathBuf>, Vec<Lock>)> {
+    let locked: BTreeSet<PathBuf> = dirs
+        .iter()
+        .filter_map(|dir| fs::canonicalize(dir).ok())
+        .collect();
+    // Taken in order, so two collections over overlapping directories
+    // cannot each hold what the other waits for
+    let locks = locked
+        .iter()
+        .filter(|dir| dir.is_dir())
+        .map(|dir| Lock::exclusive(&lock::dir_lock(dir)))
+        .collect::<Result<Vec<_>>>()?;
+    Ok((locked, locks))
+}
+
+/// Delete the receipt at `path`, its detached signature and its sidecars,
+/// or with `dry_run` only measure them, returning how many bytes they took
+fn delete(path: &Path, receipt: &Receipt, dry_run: bool) -> Result<u64> {
+    let mut files = vec![path.to_path_buf(), signing::detached_path(path)];
+    files.extend(receipt.sidecars.iter().map(|sidecar| sidecar.locate(path)));
+    let mut bytes = 0;
+    for file in files.iter().filter(|file| file.exists()) {
+        bytes += fs::metadata(file).map_or(0, |metadata| metadata.len());
+        if !dry_run {
+            fs::remove_file(file).context(format!("Failed to delete {}", file.display()))?;
+        }
+    }
+    Ok(bytes)
+}
+
+/// Add the objects `receipt` refers to: its stored streams, and its stored
+/// outputs, with the files listed in each stored directory's manifest as
+/// found in any of `stores`
+pub fn references(
+    receipt: &Receipt,
+    stores: &BTreeSet<PathBuf>,
+    referenced: &mut BTreeSet<String>,
+) {
+    for (text, encoding) in [
+        (&receipt.stdout, receipt.stdout_encoding),
+        (&receipt.stderr, receipt.stderr_encoding),
+    ] {
+        if encoding == Encoding::Object {
+            referenced.insert(text.clone());
+        }
+    }
+    for output in receipt.outputs.iter().filter(|output| output.stored) {
+        referenced.insert(output.sha256.clone());
+        if output.kind != ArtifactKind::Directory {
+            continue;
+        }
+        let Some(manifest) = stores
+            .iter()
+            .find_map(|store| objects::read(store, &output.sha256).ok())
+        else {
+            continue;
+        };
+        for line in String::from_utf8_lossy(&manifest).lines() {
+            if let Some((digest, _)) = line.split_once("  ") {
+                referenced.insert(digest.to_string());
+            }
+        }
+    }
+}
+
+/// Whether `name` is a SHA-256 in hex, as objects are named
+fn is_digest(name: &str) -> bool {
+    name.len() == 64 && name.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
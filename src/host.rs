@@ -0,0 +1,175 @@
+use serde::{Deserialize, Serialize};
+
+/// The machine rcpt ran the command on
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostRecord {
+    /// Hostname of the machine
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    /// Operating system family, e.g. `linux`, `macos` or `windows`
+    pub os: String,
+    /// Name and version of the OS release, e.g. `Ubuntu 24.04.1 LTS`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os_version: Option<String>,
+    /// Version of the running kernel
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kernel_version: Option<String>,
+    /// CPU architecture rcpt was built for, e.g. `x86_64` or `aarch64`
+    pub arch: String,
+    /// Number of logical CPUs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_count: Option<usize>,
+    /// Physical memory installed, in bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_total_bytes: Option<u64>,
+}
+
+/// Describe this machine. Anything that cannot be found out is left out.
+pub fn capture() -> HostRecord {
+    let (hostname, kernel_version) = imp::name_and_kernel();
+    HostRecord {
+        hostname,
+        os: std::env::consts::OS.to_string(),
+        os_version: imp::os_version(),
+        kernel_version,
+        arch: std::env::consts::ARCH.to_string(),
+        cpu_count: std::thread::available_parallelism()
+            .ok()
+            .map(|count| count.get()),
+        memory_total_bytes: imp::memory_total_bytes(),
+    }
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::ffi::CStr;
+
+    /// Hostname and kernel release, from uname
+    pub fn name_and_kernel() -> (Option<String>, Option<String>) {
+        // SAFETY: uname fills the zeroed struct with NUL-terminated strings
+        unsafe {
+            let mut name: libc::utsname = std::mem::zeroed();
+            if libc::uname(&mut name) != 0 {
+                return (None, None);
+            }
+            let field = |chars: &[libc::c_char]| {
+                Some(CStr::from_ptr(chars.as_ptr()).to_string_lossy().to_string())
+                    .filter(|value| !value.is_empty())
+            };
+            (field(&name.nodename), field(&name.release))
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn os_version() -> Option<String> {
+        let release = std::fs::read_to_string("/etc/os-release")
+            .or_else(|_| std::fs::read_to_string("/usr/lib/os-release"))
+            .ok()?;
+        release.lines().find_map(|line| {
+            let value = line.strip_prefix("PRETTY_NAME=")?;
+            Some(value.trim_matches('"').to_string())
+        })
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn os_version() -> Option<String> {
+        let output = std::process::Command::new("sw_vers")
+            .arg("-productVersion")
+            .output()
+            .ok()?;
+        let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        (output.status.success() && !version.is_empty()).then(|| format!("macOS {}", version))
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    pub fn os_version() -> Option<String> {
+        None
+    }
+
+    pub fn memory_total_bytes() -> Option<u64> {
+        // SAFETY: sysconf only reads system configuration
+        let (pages, page_size) = unsafe {
+            (
+                libc::sysconf(libc::_SC_PHYS_PAGES),
+                libc::sysconf(libc::_SC_PAGESIZE),
+            )
+        };
+        (pages > 0 && page_size > 0).then(|| pages as u64 * page_size as u64)
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use std::mem;
+    use std::ptr;
+    use windows_sys::Wdk::System::SystemServices::RtlGetVersion;
+    use windows_sys::Win32::System::SystemInformation::{
+        ComputerNameDnsHostname, GetComputerNameExW, GlobalMemoryStatusEx, MEMORYSTATUSEX,
+        OSVERSIONINFOW,
+    };
+
+    /// DNS hostname and the `major.minor.build` version of the NT kernel
+    pub fn name_and_kernel() -> (Option<String>, Option<String>) {
+        (hostname(), kernel_version())
+    }
+
+    fn hostname() -> Option<String> {
+        let mut size = 0u32;
+        // SAFETY: a null buffer asks only for the size needed
+        unsafe {
+            GetComputerNameExW(ComputerNameDnsHostname, ptr::null_mut(), &mut size);
+        }
+        let mut buffer = vec![0u16; size as usize];
+        // SAFETY: the buffer holds `size` characters
+        let got =
+            unsafe { GetComputerNameExW(ComputerNameDnsHostname, buffer.as_mut_ptr(), &mut size) };
+        (got != 0).then(|| String::from_utf16_lossy(&buffer[..size as usize]))
+    }
+
+    fn kernel_version() -> Option<String> {
+        // RtlGetVersion reports the real version, unlike GetVersionExW,
+        // which depends on the executable's manifest
+        // SAFETY: OSVERSIONINFOW is plain data, and its size is set as the
+        // call requires
+        unsafe {
+            let mut info: OSVERSIONINFOW = mem::zeroed();
+            info.dwOSVersionInfoSize = mem::size_of::<OSVERSIONINFOW>() as u32;
+            if RtlGetVersion(&mut info) != 0 {
+                return None;
+            }
+            Some(format!(
+                "{}.{}.{}",
+                info.dwMajorVersion, info.dwMinorVersion, info.dwBuildNumber
+            ))
+        }
+    }
+
+    pub fn os_version() -> Option<String> {
+        None
+    }
+
+    pub fn memory_total_bytes() -> Option<u64> {
+        // SAFETY: MEMORYSTATUSEX is plain data, and its length is set as
+        // the call requires
+        unsafe {
+            let mut status: MEMORYSTATUSEX = mem::zeroed();
+            status.dwLength = mem::size_of::<MEMORYSTATUSEX>() as u32;
+            (GlobalMemoryStatusEx(&mut status) != 0).then_some(status.ullTotalPhys)
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod imp {
+    pub fn name_and_kernel() -> (Option<String>, Option<String>) {
+        (None, None)
+    }
+
+    pub fn os_version() -> Option<String> {
+        None
+    }
+
+    pub fn memory_total_bytes() -> Option<u64> {
+        None
+    }
+}
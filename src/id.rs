@@ -0,0 +1,128 @@
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+
+/// Environment variable carrying the id of the receipt a command runs
+/// under, so rcpt invocations inside it can link to it as their parent
+pub const PARENT_ENV: &str = "RCPT_PARENT";
+
+/// Crockford's base32 alphabet, as used by ULIDs
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// A new ULID for something that started at `time`: 48 bits of milliseconds
+/// since the Unix epoch followed by 80 random bits, written as 26 characters
+/// that sort in time order
+pub fn ulid(time: DateTime<Utc>) -> String {
+    let mut random = [0u8; 10];
+    if !imp::os_random(&mut random) {
+        fill_fallback(&mut random);
+    }
+    encode(time, &random)
+}
+
+/// The ULID for something that started at `time`, with its 80 bits taken
+/// from the SHA-256 of `seed` rather than at random, so that the same
+/// `seed` always gives the same id
+pub fn ulid_from(time: DateTime<Utc>, seed: &[u8]) -> String {
+    let digest = Sha256::digest(seed);
+    encode(time, &digest[..10])
+}
+
+fn encode(time: DateTime<Utc>, random: &[u8]) -> String {
+    let millis = time.timestamp_millis().max(0) as u128 & ((1 << 48) - 1);
+    let random = random
+        .iter()
+        .fold(0u128, |value, &byte| value << 8 | byte as u128);
+    let value = millis << 80 | random;
+    (0..26)
+        .rev()
+        .map(|digit| ALPHABET[(value >> (digit * 5)) as usize & 31] as char)
+        .collect()
+}
+
+/// When the ULID `id` was made, or None if it is not one
+pub fn time(id: &str) -> Option<DateTime<Utc>> {
+    if id.len() != 26 {
+        return None;
+    }
+    let millis = id[..10].bytes().try_fold(0i64, |value, byte| {
+        let digit = ALPHABET
+            .iter()
+            .position(|&c| c == byte.to_ascii_uppercase())?;
+        Some(value << 5 | digit as i64)
+    })?;
+    DateTime::from_timestamp_millis(millis)
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::fs::File;
+    use std::io::Read;
+
+    pub fn os_random(bytes: &mut [u8]) -> bool {
+        File::open("/dev/urandom")
+            .and_then(|mut random| random.read_exact(bytes))
+            .is_ok()
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use windows_sys::Win32::Security::Cryptography::{
+        BCryptGenRandom, BCRYPT_USE_SYSTEM_PREFERRED_RNG,
+    };
+
+    pub fn os_random(bytes: &mut [u8]) -> bool {
+        // SAFETY: the buffer is writable for the length passed
+        let status = unsafe {
+            BCryptGenRandom(
+                std::ptr::null_mut(),
+                bytes.as_mut_ptr(),
+                bytes.len() as u32,
+                BCRYPT_USE_SYSTEM_PREFERRED_RNG,
+            )
+        };
+        status >= 0
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod imp {
+    pub fn os_random(_bytes: &mut [u8]) -> bool {
+        false
+    }
+}
+
+/// Fill `bytes` from the OS's secure random number generator, for keys
+pub fn secure_random(bytes: &mut [u8]) -> Result<()> {
+    if !imp::os_random(bytes) {
+        bail!("No secure random number source available");
+    }
+    Ok(())
+}
+
+/// Bytes that are unique to this process and moment, if not unpredictable,
+/// for when the OS offers no randomness
+fn fill_fallback(bytes: &mut [u8]) {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hash, Hasher};
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let state = RandomState::new();
+    for (index, chunk) in bytes.chunks_mut(8).enumerate() {
+        let mut hasher = state.build_hasher();
+        (
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed),
+            index,
+        )
+            .hash(&mut hasher);
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .hash(&mut hasher);
+        chunk.copy_from_slice(&hasher.finish().to_le_bytes()[..chunk.len()]);
+    }
+}
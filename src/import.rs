@@ -0,0 +1,494 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use clap::ValueEnum;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::exec::{self, ExecOptions};
+use crate::id;
+use crate::receipt::{self, Receipt, ShellInvocation, Status};
+use crate::redact::Redactor;
+use crate::tags;
+use crate::template::{self, Names};
+
+/// Records of what ran, kept by other tools, that `rcpt import` reads
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// A GitHub Actions workflow run, a list of runs, or the jobs of a run,
+    /// as the REST API returns them
+    GithubWorkflowRun,
+    /// A bash, zsh or fish history file that records when commands ran
+    ShellHistory,
+    /// JSON lines, a task on each, with its command, times and exit code
+    Tasklog,
+}
+
+impl Format {
+    fn as_str(self) -> &'static str {
+        match self {
+            Format::GithubWorkflowRun => "github-workflow-run",
+            Format::ShellHistory => "shell-history",
+            Format::Tasklog => "tasklog",
+        }
+    }
+}
+
+/// A run as another tool recorded it
+struct Record {
+    /// What tells it apart from the other runs in the source, for its id
+    key: String,
+    command: String,
+    args: Vec<String>,
+    shell: Option<ShellInvocation>,
+    cwd: String,
+    start: DateTime<Utc>,
+    end: Option<DateTime<Utc>>,
+    duration_ms: Option<u64>,
+    status: Status,
+    exit_code: Option<i32>,
+    tags: Vec<String>,
+    metadata: BTreeMap<String, Value>,
+    /// Which of the records before it this one ran under
+    parent: Option<usize>,
+}
+
+impl Record {
+    fn new(key: String, command: String, start: DateTime<Utc>, status: Status) -> Self {
+        Record {
+            key,
+            command,
+            args: Vec::new(),
+            shell: None,
+            cwd: String::new(),
+            start,
+            end: None,
+            duration_ms: None,
+            status,
+            exit_code: None,
+            tags: Vec::new(),
+            metadata: BTreeMap::new(),
+            parent: None,
+        }
+    }
+
+    /// A command string run by `program`, as `rcpt run --shell` records one
+    fn shell(key: String, program: &str, script: &str, start: DateTime<Utc>) -> Self {
+        let mut record = Record::new(key, program.to_string(), start, Status::Lost);
+        record.args = vec!["-c".to_string(), script.to_string()];
+        record.shell = Some(ShellInvocation {
+            program: program.to_string(),
+            command: script.to_string(),
+        });
+        record
+    }
+}
+
+/// Receipts for the runs recorded in `file`, read as `format`, each marked
+/// `imported` and tagged with `tags`. Each gets an id made from its time
+/// and what identifies it in the source, so importing the same record
+/// again gives the same receipt. Runs that had not finished, or that the
+/// source does not say the time of, are left out with a warning
+pub fn import(format: Format, file: &Path, tags: &[String]) -> Result<Vec<Receipt>> {
+    let text = fs::read_to_string(file).context(format!("Failed to read {}", file.display()))?;
+    let source = file.display().to_string();
+    let (records, skipped) = match format {
+        Format::GithubWorkflowRun => github(&text).context(format!(
+            "{} is not a GitHub workflow run, a list of runs or the jobs of one",
+            source
+        ))?,
+        Format::ShellHistory => history(&text),
+        Format::Tasklog => tasklog(&text),
+    };
+    if skipped > 0 {
+        eprintln!(
+            "rcpt: skipped {} records in {} that had not finished or did not say when they ran",
+            skipped, source
+        );
+    }
+
+    let mut receipts: Vec<Receipt> = Vec::new();
+    for mut record in records {
+        let mut receipt = exec::planned_receipt(
+            &ExecOptions::default(),
+            Path::new(&record.cwd),
+            &BTreeMap::new(),
+        )?;
+        let seed = format!("{}:{}", format.as_str(), record.key);
+        receipt.id = id::ulid_from(record.start, seed.as_bytes());
+        receipt.parent_id = record.parent.map(|index| receipts[index].id.clone());
+        receipt.imported = true;
+        record.tags.extend_from_slice(tags);
+        receipt.tags = tags::dedup(&record.tags);
+        record
+            .metadata
+            .insert("import_format".to_string(), format.as_str().into());
+        record
+            .metadata
+            .insert("import_source".to_string(), source.as_str().into());
+        receipt.metadata = record.metadata;
+        receipt.command = record.command;
+        receipt.args = record.args;
+        receipt.shell = record.shell;
+        receipt.user = None;
+        receipt.stdin = None;
+        receipt.status = record.status;
+        receipt.exit_code = record.exit_code;
+        receipt.timed_out = record.status == Status::TimedOut;
+        let duration = record
+            .duration_ms
+            .map(|ms| Duration::milliseconds(ms as i64));
+        receipt.start_time = record.start;
+        receipt.end_time = match (record.end, duration) {
+            (Some(end), _) => end,
+            (None, Some(duration)) => record.start + duration,
+            (None, None) => record.start,
+        };
+        receipt.duration_ms = match record.duration_ms {
+            Some(ms) => ms,
+            None => (receipt.end_time - receipt.start_time)
+                .num_milliseconds()
+                .max(0) as u64,
+        };
+        receipts.push(receipt);
+    }
+    Ok(receipts)
+}
+
+/// Write `receipts` into `dir`, each named as `rcpt run` names one, with
+/// secrets masked by `redactor`, returning how many were written
+pub fn write(receipts: Vec<Receipt>, dir: &Path, redactor: &Redactor) -> Result<usize> {
+    fs::create_dir_all(dir).context(format!("Failed to create {}", dir.display()))?;
+    let count = receipts.len();
+    for mut receipt in receipts {
+        redactor.apply(&mut receipt);
+        let name = template::render(template::DEFAULT, &Names::of(&receipt))?;
+        receipt::write_receipt(&dir.join(name), &receipt, false)?;
+    }
+    Ok(count)
+}
+
+/// The runs in GitHub's JSON for a workflow run, a list of runs, or the
+/// jobs of a run: a receipt for each run, or for each job and each of its
+/// steps, under the job's
+fn github(text: &str) -> Result<(Vec<Record>, usize)> {
+    let value: Value = serde_json::from_str(text)?;
+    let mut records = Vec::new();
+    let mut skipped = 0;
+    if let Some(runs) = value.get("workflow_runs").and_then(Value::as_array) {
+        for run in runs {
+            github_run(run, &mut records, &mut skipped);
+        }
+    } else if let Some(jobs) = value.get("jobs").and_then(Value::as_array) {
+        for job in jobs {
+            github_job(job, &mut records, &mut skipped);
+        }
+    } else if value.get("steps").is_some() {
+        github_job(&value, &mut records, &mut skipped);
+    } else if value.get("workflow_id").is_some() {
+        github_run(&value, &mut records, &mut skipped);
+    } else {
+        bail!("it has no workflow_runs, jobs or steps");
+    }
+    Ok((records, skipped))
+}
+
+fn github_run(run: &Value, records: &mut Vec<Record>, skipped: &mut usize) {
+    let start = time(run.get("run_started_at")).or_else(|| time(run.get("created_at")));
+    let (Some(start), Some((status, exit_code))) = (start, github_outcome(run)) else {
+        *skipped += 1;
+        return;
+    };
+    let key = format!("run:{}:{}", text(run, "id"), text(run, "run_attempt"));
+    let mut record = Record::new(key, text(run, "name"), start, status);
+    record.exit_code = exit_code;
+    record.end = time(run.get("updated_at"));
+    let metadata = &mut record.metadata;
+    copy(metadata, "github_run_id", run.get("id"));
+    copy(metadata, "github_attempt", run.get("run_attempt"));
+    copy(metadata, "github_workflow", run.get("name"));
+    copy(
+        metadata,
+        "github_repository",
+        run.pointer("/repository/full_name"),
+    );
+    copy(metadata, "github_event", run.get("event"));
+    copy(metadata, "github_branch", run.get("head_branch"));
+    copy(metadata, "github_sha", run.get("head_sha"));
+    copy(metadata, "github_url", run.get("html_url"));
+    records.push(record);
+}
+
+fn github_job(job: &Value, records: &mut Vec<Record>, skipped: &mut usize) {
+    let mut parent = None;
+    match (time(job.get("started_at")), github_outcome(job)) {
+        (Some(start), Some((status, exit_code))) => {
+            let key = format!("job:{}:{}", text(job, "id"), text(job, "run_attempt"));
+            let mut record = Record::new(key, text(job, "name"), start, status);
+            record.exit_code = exit_code;
+            record.end = time(job.get("completed_at"));
+            let metadata = &mut record.metadata;
+            copy(metadata, "github_run_id", job.get("run_id"));
+            copy(metadata, "github_job_id", job.get("id"));
+            copy(metadata, "github_attempt", job.get("run_attempt"));
+            copy(metadata, "github_workflow", job.get("workflow_name"));
+            copy(metadata, "github_branch", job.get("head_branch"));
+            copy(metadata, "github_sha", job.get("head_sha"));
+            copy(metadata, "github_runner", job.get("runner_name"));
+            copy(metadata, "github_url", job.get("html_url"));
+            parent = Some(records.len());
+            records.push(record);
+        }
+        _ => *skipped += 1,
+    }
+    for step in job
+        .get("steps")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        // Steps that were skipped never ran, so they are not counted
+        if step.get("conclusion").and_then(Value::as_str) == Some("skipped") {
+            continue;
+        }
+        let (Some(start), Some((status, exit_code))) =
+            (time(step.get("started_at")), github_outcome(step))
+        else {
+            *skipped += 1;
+            continue;
+        };
+        let key = format!(
+            "job:{}:{}:step:{}",
+            text(job, "id"),
+            text(job, "run_attempt"),
+            text(step, "number")
+        );
+        let mut record = Record::new(key, text(step, "name"), start, status);
+        record.exit_code = exit_code;
+        record.end = time(step.get("completed_at"));
+        record.parent = parent;
+        let metadata = &mut record.metadata;
+        copy(metadata, "github_run_id", job.get("run_id"));
+        copy(metadata, "github_job_id", job.get("id"));
+        copy(metadata, "github_step", step.get("number"));
+        records.push(record);
+    }
+}
+
+/// How a finished run, job or step of a workflow ended, or None if it has
+/// not finished or did not run
+fn github_outcome(item: &Value) -> Option<(Status, Option<i32>)> {
+    if item.get("status").and_then(Value::as_str) != Some("completed") {
+        return None;
+    }
+    match item.get("conclusion").and_then(Value::as_str)? {
+        "success" => Some((Status::Ok, Some(0))),
+        "failure" | "startup_failure" => Some((Status::Failed, None)),
+        "cancelled" => Some((Status::Interrupted, None)),
+        "timed_out" => Some((Status::TimedOut, None)),
+        _ => None,
+    }
+}
+
+/// The commands in a shell history with the times they ran: bash's with
+/// `HISTTIMEFORMAT` set, zsh's with `EXTENDED_HISTORY`, or fish's. How
+/// each ended is not recorded, so each is `lost`
+fn history(text: &str) -> (Vec<Record>, usize) {
+    let mut records = Vec::new();
+    let mut skipped = 0;
+    let mut lines = text.lines().peekable();
+    while let Some(line) = lines.next() {
+        if let Some(script) = line.strip_prefix("- cmd: ") {
+            // fish: `- cmd: <command>`, then `  when: <seconds>`
+            let script = script.replace("\\n", "\n").replace("\\\\", "\\");
+            let start = lines
+                .peek()
+                .and_then(|line| line.trim_start().strip_prefix("when: "))
+                .and_then(|seconds| seconds.trim().parse::<i64>().ok())
+                .and_then(|seconds| DateTime::from_timestamp(seconds, 0));
+            match start {
+                Some(start) => {
+                    let key = format!("fish:{}:{}", start.timestamp(), script);
+                    records.push(Record::shell(key, "fish", &script, start));
+                }
+                None => skipped += 1,
+            }
+        } else if let Some((start, elapsed, first)) = zsh_entry(line) {
+            // zsh: `: <seconds>:<elapsed>;<command>`, its lines after the
+            // first each following one that ends in a backslash
+            let mut script = first.to_string();
+            while script.ends_with('\\') {
+                let Some(next) = lines.next() else {
+                    break;
+                };
+                script.pop();
+                script.push('\n');
+                script.push_str(next);
+            }
+            let key = format!("zsh:{}:{}", start.timestamp(), script);
+            let mut record = Record::shell(key, "zsh", &script, start);
+            record.duration_ms = Some(elapsed * 1000);
+            records.push(record);
+        } else if let Some(start) = bash_time(line) {
+            // bash: `#<seconds>`, then the command, on as many lines as it
+            // takes until the next time
+            let mut script = Vec::new();
+            while let Some(next) = lines.next_if(|next| bash_time(next).is_none()) {
+                script.push(next);
+            }
+            if script.is_empty() {
+                continue;
+            }
+            let script = script.join("\n");
+            let key = format!("bash:{}:{}", start.timestamp(), script);
+            records.push(Record::shell(key, "bash", &script, start));
+        } else if !line.trim().is_empty() && !line.starts_with(' ') {
+            // Indented lines are fish's `when:` and `paths:` for a command
+            skipped += 1;
+        }
+    }
+    (records, skipped)
+}
+
+/// The start, elapsed seconds and command of a zsh extended history line
+fn zsh_entry(line: &str) -> Option<(DateTime<Utc>, u64, &str)> {
+    let (times, script) = line.strip_prefix(": ")?.split_once(';')?;
+    let (seconds, elapsed) = times.split_once(':')?;
+    let start = DateTime::from_timestamp(seconds.trim().parse().ok()?, 0)?;
+    Some((start, elapsed.trim().parse().ok()?, script))
+}
+
+/// The time in a bash history timestamp line, `#<seconds>`
+fn bash_time(line: &str) -> Option<DateTime<Utc>> {
+    let seconds = line.strip_prefix('#')?;
+    match !seconds.is_empty() && seconds.bytes().all(|byte| byte.is_ascii_digit()) {
+        true => DateTime::from_timestamp(seconds.parse().ok()?, 0),
+        false => None,
+    }
+}
+
+/// The tasks in a JSON lines log, one object on each line with
+/// - `command` (or `cmd`): an array of arguments, or a string that, with
+///   `args`, is the program, and without them a command for `sh`
+/// - `start_time` (or `started_at` or `start`), and `end_time` (or
+///   `ended_at`, `finished_at` or `end`) or `duration_ms`: RFC 3339 times,
+///   or seconds or milliseconds since the Unix epoch
+/// - `exit_code` (or `exit` or `returncode`) and `status`
+/// - optionally `cwd`, `tags`, and `name` (or `task`), kept as metadata
+fn tasklog(text: &str) -> (Vec<Record>, usize) {
+    let mut records = Vec::new();
+    let mut skipped = 0;
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        match task(line) {
+            Some(record) => records.push(record),
+            None => skipped += 1,
+        }
+    }
+    (records, skipped)
+}
+
+fn task(line: &str) -> Option<Record> {
+    let value: Value = serde_json::from_str(line).ok()?;
+    let task = value.as_object()?;
+    let start = time(field(task, &["start_time", "started_at", "start"]))?;
+    let exit_code = field(task, &["exit_code", "exit", "returncode"])
+        .and_then(Value::as_i64)
+        .map(|code| code as i32);
+    let status = match field(task, &["status"])
+        .and_then(Value::as_str)
+        .map(str::to_ascii_lowercase)
+        .as_deref()
+    {
+        Some("running" | "pending" | "queued") => return None,
+        Some("ok" | "success" | "succeeded" | "passed") => Status::Ok,
+        Some("failed" | "failure" | "error" | "errored") => Status::Failed,
+        Some("timed_out" | "timeout") => Status::TimedOut,
+        Some("interrupted" | "cancelled" | "canceled") => Status::Interrupted,
+        _ => match exit_code {
+            Some(0) => Status::Ok,
+            Some(_) => Status::Failed,
+            None => Status::Lost,
+        },
+    };
+    let key = line.to_string();
+    let args = field(task, &["args"]).and_then(Value::as_array);
+    let mut record = match field(task, &["command", "cmd"])? {
+        Value::Array(argv) => {
+            let mut argv = argv.iter().map(Value::as_str);
+            let mut record = Record::new(key, argv.next()??.to_string(), start, status);
+            record.args = argv
+                .map(|arg| arg.map(str::to_string))
+                .collect::<Option<_>>()?;
+            record
+        }
+        Value::String(program) if args.is_some() => {
+            let mut record = Record::new(key, program.clone(), start, status);
+            record.args = args?
+                .iter()
+                .map(|arg| arg.as_str().map(str::to_string))
+                .collect::<Option<_>>()?;
+            record
+        }
+        Value::String(script) => {
+            let mut record = Record::shell(key, "sh", script, start);
+            record.status = status;
+            record
+        }
+        _ => return None,
+    };
+    record.exit_code = exit_code;
+    record.end = time(field(task, &["end_time", "ended_at", "finished_at", "end"]));
+    record.duration_ms = field(task, &["duration_ms"]).and_then(Value::as_u64);
+    if let Some(cwd) = field(task, &["cwd"]).and_then(Value::as_str) {
+        record.cwd = cwd.to_string();
+    }
+    record.tags = field(task, &["tags"])
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|tag| tags::parse_tag(tag.as_str()?).ok())
+        .collect();
+    copy(&mut record.metadata, "task", field(task, &["name", "task"]));
+    Some(record)
+}
+
+/// The first of `names` that `object` has
+fn field<'a>(object: &'a Map<String, Value>, names: &[&str]) -> Option<&'a Value> {
+    names.iter().find_map(|name| object.get(*name))
+}
+
+/// A time given as an RFC 3339 string, or as seconds or, if too many to
+/// be seconds, milliseconds since the Unix epoch
+fn time(value: Option<&Value>) -> Option<DateTime<Utc>> {
+    match value? {
+        Value::String(text) => DateTime::parse_from_rfc3339(text)
+            .ok()
+            .map(|time| time.with_timezone(&Utc)),
+        Value::Number(number) => {
+            let number = number.as_f64()?;
+            let millis = match number.abs() < 1e11 {
+                true => number * 1000.0,
+                false => number,
+            };
+            DateTime::from_timestamp_millis(millis as i64)
+        }
+        _ => None,
+    }
+}
+
+/// `item`'s `name` as text, whether a string or a number, or empty
+fn text(item: &Value, name: &str) -> String {
+    match item.get(name) {
+        Some(Value::String(text)) => text.clone(),
+        Some(Value::Null) | None => String::new(),
+        Some(value) => value.to_string(),
+    }
+}
+
+/// Keep `value` in `metadata` as `key`, if there is one
+fn copy(metadata: &mut BTreeMap<String, Value>, key: &str, value: Option<&Value>) {
+    if let Some(value) = value.filter(|value| !value.is_null()) {
+        metadata.insert(key.to_string(), value.clone());
+    }
+}
@@ -0,0 +1,259 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::ffi::OsStr;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::exec::resolve_executable;
+use crate::list::{Filter, StatusFilter, Summary};
+use crate::lock::{self, Lock};
+use crate::receipt::Receipt;
+use crate::sink::ReceiptSink;
+
+/// Environment variable naming an index to add every receipt written to
+pub const INDEX_ENV: &str = "RCPT_INDEX";
+
+/// Where `--index` keeps the index unless given a path, relative to the
+/// working directory
+pub const DEFAULT_PATH: &str = ".receipts/index.db";
+
+/// Tables of the index; `argv` is the command and its arguments as a JSON
+/// array, and times are RFC 3339 in UTC so they sort as text
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS receipts (
+    id TEXT PRIMARY KEY,
+    path TEXT NOT NULL,
+    argv TEXT NOT NULL,
+    status TEXT NOT NULL,
+    exit_code INTEGER,
+    duration_ms INTEGER NOT NULL,
+    start_time TEXT NOT NULL,
+    end_time TEXT NOT NULL,
+    session_id TEXT
+);
+CREATE TABLE IF NOT EXISTS tags (
+    id TEXT NOT NULL,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (id, tag)
+);
+CREATE INDEX IF NOT EXISTS receipts_start_time ON receipts (start_time);
+CREATE INDEX IF NOT EXISTS receipts_path ON receipts (path);
+CREATE INDEX IF NOT EXISTS tags_tag ON tags (tag);
+PRAGMA user_version = 1;
+";
+
+/// How long to wait for another rcpt to finish writing the index, in
+/// milliseconds
+const BUSY_TIMEOUT_MS: u32 = 10_000;
+
+/// A SQLite index of receipts, written through the sqlite3 shell
+#[derive(Debug, Clone)]
+pub struct Index {
+    db: PathBuf,
+    sqlite: PathBuf,
+}
+
+impl Index {
+    /// The index at `db`, created with its tables if it does not exist
+    pub fn open(db: &Path) -> Result<Self> {
+        let cwd = std::env::current_dir().context("Failed to get current directory")?;
+        let sqlite = resolve_executable(
+            OsStr::new("sqlite3"),
+            std::env::var_os("PATH").as_ref(),
+            &cwd,
+        )
+        .context("sqlite3 not found; install it to index receipts")?;
+        let db = cwd.join(db);
+        if let Some(parent) = db.parent() {
+            fs::create_dir_all(parent).context(format!("Failed to create {}", parent.display()))?;
+        }
+        let index = Index { db, sqlite };
+        let _lock = index.lock()?;
+        index
+            .run(SCHEMA)
+            .context(format!("Failed to create index {}", index.db.display()))?;
+        Ok(index)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.db
+    }
+
+    /// Add or update the receipt written to `path`
+    pub fn add(&self, path: &Path, receipt: &Receipt) -> Result<()> {
+        self.add_all(&[(path.to_path_buf(), receipt.clone())], false)?;
+        Ok(())
+    }
+
+    /// Add or update each receipt in one transaction, first dropping the
+    /// entries of receipts no longer on disk if `prune` is set. Returns how
+    /// many entries were dropped
+    pub fn add_all(&self, receipts: &[(PathBuf, Receipt)], prune: bool) -> Result<usize> {
+        let _lock = self.lock()?;
+        let mut sql = String::from("BEGIN IMMEDIATE;\n");
+        let mut pruned = 0;
+        if prune {
+            let indexed = self.run("SELECT id, path FROM receipts;")?;
+            for line in indexed.lines() {
+                let Some((id, path)) = line.split_once('|') else {
+                    continue;
+                };
+                if !Path::new(path).exists() {
+                    sql.push_str(&format!(
+                        "DELETE FROM receipts WHERE id = {id};\nDELETE FROM tags WHERE id = {id};\n",
+                        id = quote(id)
+                    ));
+                    pruned += 1;
+                }
+            }
+        }
+        for (path, receipt) in receipts {
+            let path = std::path::absolute(path)
+                .context(format!("Failed to resolve {}", path.display()))?;
+            let argv: Vec<&str> = std::iter::once(receipt.command.as_str())
+                .chain(receipt.args.iter().map(String::as_str))
+                .collect();
+            let id = quote(&receipt.id);
+            sql.push_str(&format!(
+                "INSERT OR REPLACE INTO receipts VALUES ({}, {}, {}, {}, {}, {}, {}, {}, {});\n",
+                id,
+                quote(&path.to_string_lossy()),
+                quote(&serde_json::to_string(&argv)?),
+                quote(receipt.status.as_str()),
+                receipt
+                    .exit_code
+                    .map_or("NULL".to_string(), |code| code.to_string()),
+                receipt.duration_ms,
+                quote(
+                    &receipt
+                        .start_time
+                        .to_rfc3339_opts(SecondsFormat::AutoSi, true)
+                ),
+                quote(
+                    &receipt
+                        .end_time
+                        .to_rfc3339_opts(SecondsFormat::AutoSi, true)
+                ),
+                receipt
+                    .session_id
+                    .as_deref()
+                    .map_or("NULL".to_string(), quote),
+            ));
+            sql.push_str(&format!("DELETE FROM tags WHERE id = {};\n", id));
+            for tag in &receipt.tags {
+                sql.push_str(&format!(
+                    "INSERT OR IGNORE INTO tags VALUES ({}, {});\n",
+                    id,
+                    quote(tag)
+                ));
+            }
+        }
+        sql.push_str("COMMIT;\n");
+        self.run(&sql)
+            .context(format!("Failed to update index {}", self.db.display()))?;
+        Ok(pruned)
+    }
+
+    /// The indexed receipts `filter` picks out, oldest first
+    pub fn select(&self, filter: &Filter) -> Result<Vec<Summary>> {
+        let time = |time: DateTime<Utc>| quote(&time.to_rfc3339_opts(SecondsFormat::AutoSi, true));
+        let mut sql = String::from(
+            "SELECT json_object('path', path, 'id', id, 'argv', json(argv), 'status', status, \
+             'exit_code', exit_code, 'duration_ms', duration_ms, 'start_time', start_time, \
+             'tags', (SELECT json_group_array(tag) FROM tags WHERE tags.id = receipts.id)) \
+             FROM receipts WHERE 1",
+        );
+        if let Some(since) = filter.since {
+            sql.push_str(&format!(" AND start_time >= {}", time(since)));
+        }
+        if let Some(before) = filter.before {
+            sql.push_str(&format!(" AND start_time < {}", time(before)));
+        }
+        if !filter.statuses.is_empty() {
+            let statuses: Vec<String> = filter
+                .statuses
+                .iter()
+                .map(|status| match status {
+                    StatusFilter::Is(status) => format!("status = {}", quote(status.as_str())),
+                    StatusFilter::Fail => "status NOT IN ('ok', 'planned', 'running')".to_string(),
+                })
+                .collect();
+            sql.push_str(&format!(" AND ({})", statuses.join(" OR ")));
+        }
+        for tag in &filter.tags {
+            sql.push_str(&format!(
+                " AND id IN (SELECT id FROM tags WHERE tag = {})",
+                quote(tag)
+            ));
+        }
+        if let Some(text) = &filter.command {
+            // The command line is its arguments joined by spaces
+            sql.push_str(&format!(
+                " AND instr((SELECT group_concat(value, ' ') FROM json_each(argv)), {}) > 0",
+                quote(text)
+            ));
+        }
+        if let Some(session) = &filter.session {
+            sql.push_str(&format!(" AND session_id = {}", quote(session)));
+        }
+        sql.push_str(" ORDER BY start_time, id;");
+        let rows = self
+            .run(&sql)
+            .context(format!("Failed to query index {}", self.db.display()))?;
+        rows.lines()
+            .map(|row| {
+                serde_json::from_str(row)
+                    .context(format!("Failed to read index {}", self.db.display()))
+            })
+            .collect()
+    }
+
+    /// Lock the index against other rcpt processes changing it. SQLite
+    /// locks it too, but gives up on a writer busy past its timeout, and
+    /// pruning reads the index before it writes
+    fn lock(&self) -> Result<Lock> {
+        Lock::exclusive(&lock::file_lock(&self.db))
+    }
+
+    /// Run `sql` against the index, returning what sqlite3 printed
+    fn run(&self, sql: &str) -> Result<String> {
+        let mut child = Command::new(&self.sqlite)
+            .args(["-batch", "-bail", "-cmd"])
+            .arg(format!(".timeout {}", BUSY_TIMEOUT_MS))
+            .arg(&self.db)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context(format!("Failed to run {}", self.sqlite.display()))?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        // sqlite3 stops reading at the first error, which its stderr reports
+        let _ = stdin.write_all(sql.as_bytes());
+        drop(stdin);
+        let output = child
+            .wait_with_output()
+            .context(format!("Failed to run {}", self.sqlite.display()))?;
+        if !output.status.success() {
+            bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+}
+
+impl ReceiptSink for Index {
+    /// Add the receipt. It is the receipt that counts, and `rcpt index` can
+    /// catch the index up, so this only warns
+    fn write(&self, receipt: &Receipt, out: &Path) -> Result<()> {
+        if let Err(err) = self.add(out, receipt) {
+            eprintln!("rcpt: failed to index {}: {:#}", out.display(), err);
+        }
+        Ok(())
+    }
+}
+
+/// `value` as an SQL string literal
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
@@ -0,0 +1,227 @@
+use anyhow::Result;
+use std::process::Child;
+
+use crate::limits::ResourceLimits;
+use crate::receipt::ResourceUsage;
+
+/// A Windows job object holding the command and everything it starts, so
+/// the whole tree can be limited, accounted for and killed together.
+/// Anything still in the job when it is dropped is killed.
+pub struct Job(imp::Job);
+
+impl Job {
+    /// Create a job enforcing the memory and CPU limits in `limits`
+    /// (Windows only; elsewhere there is no job)
+    pub fn create(limits: &ResourceLimits) -> Result<Option<Self>> {
+        Ok(imp::Job::create(limits)?.map(Job))
+    }
+
+    /// Put a just-spawned child in the job
+    pub fn assign(&self, child: &Child) -> Result<()> {
+        self.0.assign(child)
+    }
+
+    /// Kill every process still in the job, returning their pids
+    pub fn kill_remaining(&self) -> Vec<u32> {
+        self.0.kill_remaining()
+    }
+
+    /// Accounting totals for every process that ran in the job
+    pub fn usage(&self) -> ResourceUsage {
+        self.0.usage()
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use anyhow::Result;
+    use std::ffi::c_void;
+    use std::io;
+    use std::mem;
+    use std::os::windows::io::AsRawHandle;
+    use std::process::Child;
+    use std::ptr;
+    use windows_sys::Win32::Foundation::{CloseHandle, HANDLE};
+    use windows_sys::Win32::System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW, JobObjectBasicAndIoAccountingInformation,
+        JobObjectBasicProcessIdList, JobObjectExtendedLimitInformation, QueryInformationJobObject,
+        SetInformationJobObject, TerminateJobObject, JOBOBJECTINFOCLASS,
+        JOBOBJECT_BASIC_AND_IO_ACCOUNTING_INFORMATION, JOBOBJECT_BASIC_PROCESS_ID_LIST,
+        JOBOBJECT_EXTENDED_LIMIT_INFORMATION, JOB_OBJECT_LIMIT_JOB_MEMORY,
+        JOB_OBJECT_LIMIT_JOB_TIME, JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+    };
+
+    use crate::limits::ResourceLimits;
+    use crate::receipt::ResourceUsage;
+
+    /// Job times are counted in 100ns ticks
+    const TICKS_PER_MS: f64 = 10_000.0;
+
+    /// Most pids read from the job at once
+    const MAX_PIDS: usize = 1024;
+
+    pub struct Job(HANDLE);
+
+    impl Job {
+        pub fn create(limits: &ResourceLimits) -> Result<Option<Self>> {
+            // SAFETY: both arguments may be null, for default security and
+            // an anonymous job
+            let handle = unsafe { CreateJobObjectW(ptr::null(), ptr::null()) };
+            if handle.is_null() {
+                return Err(io::Error::last_os_error().into());
+            }
+            let job = Job(handle);
+
+            let mut info = JOBOBJECT_EXTENDED_LIMIT_INFORMATION::default();
+            let mut flags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+            if let Some(bytes) = limits.memory_bytes {
+                flags |= JOB_OBJECT_LIMIT_JOB_MEMORY;
+                info.JobMemoryLimit = bytes as usize;
+            }
+            if let Some(seconds) = limits.cpu_seconds {
+                flags |= JOB_OBJECT_LIMIT_JOB_TIME;
+                info.BasicLimitInformation.PerJobUserTimeLimit =
+                    (seconds as f64 * 1000.0 * TICKS_PER_MS) as i64;
+            }
+            info.BasicLimitInformation.LimitFlags = flags;
+            // SAFETY: `info` is the structure this information class expects
+            let set = unsafe {
+                SetInformationJobObject(
+                    job.0,
+                    JobObjectExtendedLimitInformation,
+                    &info as *const _ as *const c_void,
+                    mem::size_of_val(&info) as u32,
+                )
+            };
+            if set == 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+            Ok(Some(job))
+        }
+
+        pub fn assign(&self, child: &Child) -> Result<()> {
+            // SAFETY: both handles are open for the duration of the call
+            let assigned =
+                unsafe { AssignProcessToJobObject(self.0, child.as_raw_handle() as HANDLE) };
+            if assigned == 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+            Ok(())
+        }
+
+        pub fn kill_remaining(&self) -> Vec<u32> {
+            let pids = self.pids();
+            if !pids.is_empty() {
+                // SAFETY: the job handle is open
+                unsafe {
+                    TerminateJobObject(self.0, 1);
+                }
+            }
+            pids
+        }
+
+        pub fn usage(&self) -> ResourceUsage {
+            let accounting: JOBOBJECT_BASIC_AND_IO_ACCOUNTING_INFORMATION =
+                self.query(JobObjectBasicAndIoAccountingInformation);
+            let limits: JOBOBJECT_EXTENDED_LIMIT_INFORMATION =
+                self.query(JobObjectExtendedLimitInformation);
+            let basic = &accounting.BasicInfo;
+            ResourceUsage {
+                cgroup: None,
+                cpu_user_ms: basic.TotalUserTime as f64 / TICKS_PER_MS,
+                cpu_system_ms: basic.TotalKernelTime as f64 / TICKS_PER_MS,
+                memory_peak_bytes: Some(limits.PeakJobMemoryUsed as u64),
+                io_read_bytes: Some(accounting.IoInfo.ReadTransferCount),
+                io_write_bytes: Some(accounting.IoInfo.WriteTransferCount),
+                processes: Some(basic.TotalProcesses as u64),
+                max_rss_bytes: None,
+                minor_page_faults: None,
+                major_page_faults: None,
+                io_syscall_read_bytes: None,
+                io_syscall_write_bytes: None,
+            }
+        }
+
+        /// Processes currently in the job
+        fn pids(&self) -> Vec<u32> {
+            // The list's header is followed by as many ids as fit
+            let header = mem::offset_of!(JOBOBJECT_BASIC_PROCESS_ID_LIST, ProcessIdList)
+                / mem::size_of::<usize>();
+            let mut buffer = vec![0usize; header + MAX_PIDS];
+            // SAFETY: the buffer is writable for the length passed
+            let queried = unsafe {
+                QueryInformationJobObject(
+                    self.0,
+                    JobObjectBasicProcessIdList,
+                    buffer.as_mut_ptr() as *mut c_void,
+                    mem::size_of_val(buffer.as_slice()) as u32,
+                    ptr::null_mut(),
+                )
+            };
+            if queried == 0 {
+                return Vec::new();
+            }
+            // SAFETY: the buffer starts with the list's header and is aligned for it
+            let list = unsafe { &*(buffer.as_ptr() as *const JOBOBJECT_BASIC_PROCESS_ID_LIST) };
+            let count = (list.NumberOfProcessIdsInList as usize).min(MAX_PIDS);
+            buffer[header..header + count]
+                .iter()
+                .map(|&pid| pid as u32)
+                .collect()
+        }
+
+        /// Fixed-size information about the job, zeroed if the query fails
+        fn query<T: Default>(&self, class: JOBOBJECTINFOCLASS) -> T {
+            let mut info = T::default();
+            // SAFETY: callers pair each class with its structure
+            unsafe {
+                QueryInformationJobObject(
+                    self.0,
+                    class,
+                    &mut info as *mut T as *mut c_void,
+                    mem::size_of::<T>() as u32,
+                    ptr::null_mut(),
+                );
+            }
+            info
+        }
+    }
+
+    impl Drop for Job {
+        fn drop(&mut self) {
+            // SAFETY: the handle is owned by this job and closed only here
+            unsafe {
+                CloseHandle(self.0);
+            }
+        }
+    }
+}
+
+#[cfg(not(windows))]
+mod imp {
+    use anyhow::Result;
+    use std::process::Child;
+
+    use crate::limits::ResourceLimits;
+    use crate::receipt::ResourceUsage;
+
+    pub enum Job {}
+
+    impl Job {
+        pub fn create(_limits: &ResourceLimits) -> Result<Option<Self>> {
+            Ok(None)
+        }
+
+        pub fn assign(&self, _child: &Child) -> Result<()> {
+            match *self {}
+        }
+
+        pub fn kill_remaining(&self) -> Vec<u32> {
+            match *self {}
+        }
+
+        pub fn usage(&self) -> ResourceUsage {
+            match *self {}
+        }
+    }
+}
@@ -0,0 +1,359 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::canonical;
+use crate::digest::sha256_hex;
+use crate::receipt::{self, Receipt};
+use crate::sink::ReceiptSink;
+
+/// `prev` of the first entry in a ledger
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// How much of the ledger is read at a time when looking for its last line
+const CHUNK_SIZE: u64 = 64 * 1024;
+
+/// One line of a ledger
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    /// SHA-256 of the previous line, without its newline, chaining each
+    /// entry to everything before it
+    prev: String,
+    receipt: serde_json::Value,
+}
+
+/// A ledger receipts are appended to as they are made
+#[derive(Debug, Clone)]
+pub struct Ledger {
+    pub path: PathBuf,
+    /// Have each line on disk before counting it appended
+    pub fsync: bool,
+}
+
+impl ReceiptSink for Ledger {
+    fn write(&self, receipt: &Receipt, _out: &Path) -> Result<()> {
+        append(&self.path, receipt, self.fsync)
+    }
+}
+
+/// Append `receipt` to the ledger at `path` as one line of canonical JSON,
+/// chained to the line before it. The file is locked while appending, so
+/// lines from rcpt processes sharing a ledger never interleave. With
+/// `fsync`, the line is on disk before this returns
+pub fn append(path: &Path, receipt: &Receipt, fsync: bool) -> Result<()> {
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent).context(format!("Failed to create {}", parent.display()))?;
+    }
+    let mut file = OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(path)
+        .context(format!("Failed to open ledger {}", path.display()))?;
+    file.lock()
+        .context(format!("Failed to lock ledger {}", path.display()))?;
+
+    let last = last_line(&mut file).context(format!("Failed to read ledger {}", path.display()))?;
+    let entry = Entry {
+        prev: match &last {
+            Some((line, _)) => sha256_hex(line),
+            None => GENESIS.to_string(),
+        },
+        receipt: receipt::to_value(receipt)?,
+    };
+    let mut line = canonical::to_string(&serde_json::to_value(&entry)?);
+    line.push('\n');
+    // A line cut short by a crash is left as it is, and chained to like any
+    // other, with this one after it
+    if let Some((_, false)) = last {
+        line.insert(0, '\n');
+    }
+    file.write_all(line.as_bytes())
+        .and_then(|_| file.flush())
+        .and_then(|_| if fsync { file.sync_data() } else { Ok(()) })
+        .context(format!("Failed to append to ledger {}", path.display()))
+}
+
+/// The receipts in the complete lines of the ledger at `path` from byte
+/// `offset` on, and the offset just past the last of those lines. Lines
+/// that are not entries are skipped
+pub fn read_from(path: &Path, offset: u64) -> Result<(Vec<Receipt>, u64)> {
+    let mut file = File::open(path).context(format!("Failed to read ledger {}", path.display()))?;
+    let mut contents = Vec::new();
+    file.seek(SeekFrom::Start(offset))
+        .and_then(|_| file.read_to_end(&mut contents))
+        .context(format!("Failed to read ledger {}", path.display()))?;
+    // A line still being appended is left for the next read
+    let complete = match contents.iter().rposition(|&byte| byte == b'\n') {
+        Some(newline) => &contents[..=newline],
+        None => return Ok((Vec::new(), offset)),
+    };
+    let receipts = complete
+        .split(|&byte| byte == b'\n')
+        .filter_map(|line| serde_json::from_slice::<Entry>(line).ok())
+        .filter_map(|entry| receipt::parse_receipt(&entry.receipt.to_string()).ok())
+        .collect();
+    Ok((receipts, offset + complete.len() as u64))
+}
+
+/// The last line of `file`, without its newline, and whether it ended with
+/// one
+fn last_line(file: &mut File) -> Result<Option<(Vec<u8>, bool)>> {
+    let len = file.seek(SeekFrom::End(0))?;
+    if len == 0 {
+        return Ok(None);
+    }
+    let mut last = [0u8];
+    file.seek(SeekFrom::End(-1))?;
+    file.read_exact(&mut last)?;
+    let terminated = last[0] == b'\n';
+
+    let mut pos = if terminated { len - 1 } else { len };
+    let mut line = Vec::new();
+    while pos > 0 {
+        let start = pos.saturating_sub(CHUNK_SIZE);
+        let mut chunk = vec![0u8; (pos - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut chunk)?;
+        if let Some(newline) = chunk.iter().rposition(|&byte| byte == b'\n') {
+            chunk.drain(..=newline);
+            chunk.extend(line);
+            return Ok(Some((chunk, terminated)));
+        }
+        chunk.extend(line);
+        line = chunk;
+        pos = start;
+    }
+    Ok(Some((line, terminated)))
+}
+
+/// A ledger whose chain checked out
+#[derive(Debug, Clone)]
+pub struct Chain {
+    /// `receipt_digest` of the receipt in each entry, in order
+    pub digests: Vec<String>,
+    /// SHA-256 of the last entry, for a later check to require with `head`
+    pub head: String,
+}
+
+impl Chain {
+    /// The line of the entry holding the receipt whose digest is `digest`
+    pub fn position(&self, digest: &str) -> Option<usize> {
+        self.digests
+            .iter()
+            .position(|entry| entry == digest)
+            .map(|index| index + 1)
+    }
+}
+
+/// Check every entry of the ledger at `path` follows the one before it and
+/// holds a receipt matching its `receipt_digest`, and that the entry whose
+/// SHA-256 is `head`, recorded from an earlier check, is still there
+pub fn verify(path: &Path, head: Option<&str>) -> Result<Chain> {
+    let mut contents = Vec::new();
+    File::open(path)
+        // Wait out an append, so its line is not read half-written
+        .and_then(|mut file| {
+            file.lock_shared()?;
+            file.read_to_end(&mut contents)
+        })
+        .context(format!("Failed to read ledger {}", path.display()))?;
+    let contents = contents.strip_suffix(b"\n").unwrap_or(&contents);
+    if contents.is_empty() {
+        bail!("ledger: {} is empty", path.display());
+    }
+
+    let mut prev = GENESIS.to_string();
+    let mut head_found = false;
+    let mut digests = Vec::new();
+    for (index, line) in contents.split(|&byte| byte == b'\n').enumerate() {
+        let number = index + 1;
+        let entry: Entry = serde_json::from_slice(line)
+            .context(format!("ledger: line {} is not a ledger entry", number))?;
+        if entry.prev != prev {
+            match number {
+                1 => bail!("ledger: line 1 is not the first entry; entries before it were removed"),
+                _ => bail!(
+                    "ledger: line {} does not follow line {}; an entry was inserted, removed or changed",
+                    number,
+                    number - 1
+                ),
+            }
+        }
+        match entry.receipt.get("receipt_digest").and_then(|d| d.as_str()) {
+            Some(digest) if digest == receipt::receipt_digest(&entry.receipt) => {
+                digests.push(digest.to_string())
+            }
+            _ => bail!(
+                "ledger: line {} holds a receipt that does not match its receipt_digest",
+                number
+            ),
+        }
+        prev = sha256_hex(line);
+        head_found |= head == Some(prev.as_str());
+    }
+    if let Some(head) = head.filter(|_| !head_found) {
+        bail!(
+            "ledger: no entry has SHA-256 {}; entries were removed or rewritten",
+            head
+        );
+    }
+    Ok(Chain {
+        digests,
+        head: prev,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn receipt(stdout: &str) -> Receipt {
+        let json = serde_json::json!({
+            "command": "echo",
+            "args": [stdout],
+            "exit_code": 0,
+            "stdout": format!("{}\n", stdout),
+            "stderr": "",
+            "start_time": "2026-02-16T01:00:00.000000000Z",
+            "end_time": "2026-02-16T01:00:00.001000000Z",
+            "duration_ms": 1,
+        });
+        receipt::parse_receipt(&json.to_string()).unwrap()
+    }
+
+    /// A ledger of three receipts, in a directory of its own
+    fn ledger(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rcpt-ledger-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("receipts.jsonl");
+        for stdout in ["one", "two", "three"] {
+            append(&path, &receipt(stdout), false).unwrap();
+        }
+        path
+    }
+
+    fn lines(path: &Path) -> Vec<String> {
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    fn rewrite(path: &Path, lines: &[String]) {
+        fs::write(path, lines.join("\n") + "\n").unwrap();
+    }
+
+    fn failure(path: &Path, head: Option<&str>) -> String {
+        verify(path, head).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn verifies_a_chain() {
+        let path = ledger("chain");
+        let chain = verify(&path, None).unwrap();
+        let lines = lines(&path);
+        assert_eq!(chain.digests.len(), 3);
+        assert_eq!(chain.head, sha256_hex(lines[2].as_bytes()));
+        let first: Entry = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(first.prev, GENESIS);
+        let second: Entry = serde_json::from_str(&lines[1]).unwrap();
+        assert_eq!(second.prev, sha256_hex(lines[0].as_bytes()));
+        assert_eq!(chain.position(&chain.digests[1]), Some(2));
+        assert_eq!(chain.position("missing"), None);
+
+        // A head from an earlier check is found after further appends
+        append(&path, &receipt("four"), false).unwrap();
+        assert_eq!(verify(&path, Some(&chain.head)).unwrap().digests.len(), 4);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn detects_changed_entries() {
+        let path = ledger("changed");
+        let original = lines(&path);
+
+        // Changing a receipt breaks its receipt_digest
+        let mut changed = original.clone();
+        changed[1] = changed[1].replace("two\\n", "2\\n");
+        rewrite(&path, &changed);
+        let err = failure(&path, None);
+        assert!(
+            err.contains("line 2 holds a receipt that does not match"),
+            "{}",
+            err
+        );
+
+        // Recomputing the digest breaks the chain after it instead
+        let mut entry: Entry = serde_json::from_str(&changed[1]).unwrap();
+        let digest = receipt::receipt_digest(&entry.receipt);
+        entry.receipt["receipt_digest"] = digest.into();
+        changed[1] = canonical::to_string(&serde_json::to_value(&entry).unwrap());
+        rewrite(&path, &changed);
+        let err = failure(&path, None);
+        assert!(err.contains("line 3 does not follow line 2"), "{}", err);
+
+        changed[1] = "not json".to_string();
+        rewrite(&path, &changed);
+        let err = failure(&path, None);
+        assert!(err.contains("line 2 is not a ledger entry"), "{}", err);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn detects_removed_entries() {
+        let path = ledger("removed");
+        let original = lines(&path);
+        let head = verify(&path, None).unwrap().head;
+
+        rewrite(&path, &original[1..]);
+        let err = failure(&path, None);
+        assert!(err.contains("line 1 is not the first entry"), "{}", err);
+
+        rewrite(&path, &[original[0].clone(), original[2].clone()]);
+        let err = failure(&path, None);
+        assert!(err.contains("line 2 does not follow line 1"), "{}", err);
+
+        // Dropping the last entries leaves a valid chain, but not the head
+        rewrite(&path, &original[..2]);
+        assert!(verify(&path, None).is_ok());
+        let err = failure(&path, Some(&head));
+        assert!(
+            err.contains(&format!("no entry has SHA-256 {}", head)),
+            "{}",
+            err
+        );
+
+        fs::write(&path, "").unwrap();
+        assert!(failure(&path, None).contains("is empty"));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn appends_after_a_line_cut_short() {
+        let path = ledger("cut");
+        let whole = fs::read(&path).unwrap();
+        fs::write(&path, &whole[..whole.len() - 10]).unwrap();
+        append(&path, &receipt("four"), false).unwrap();
+
+        let lines = lines(&path);
+        assert_eq!(lines.len(), 4);
+        let last: Entry = serde_json::from_str(&lines[3]).unwrap();
+        assert_eq!(last.prev, sha256_hex(lines[2].as_bytes()));
+        let err = failure(&path, None);
+        assert!(err.contains("line 3 is not a ledger entry"), "{}", err);
+
+        // Reading skips it, and leaves nothing half-read
+        let (receipts, offset) = read_from(&path, 0).unwrap();
+        let outputs: Vec<&str> = receipts.iter().map(|r| r.stdout.as_str()).collect();
+        assert_eq!(outputs, ["one\n", "two\n", "four\n"]);
+        assert_eq!(offset, fs::metadata(&path).unwrap().len());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
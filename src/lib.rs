@@ -0,0 +1,86 @@
+mod access;
+mod age;
+mod artifact;
+mod atomic;
+mod attestation;
+mod base64;
+mod batch;
+mod bench;
+mod bundle;
+mod canonical;
+mod capture;
+mod cgroup;
+mod chacha20poly1305;
+/// The `rcpt` binary's entry point, public only so src/main.rs can call it;
+/// not part of the library's API
+#[doc(hidden)]
+pub mod cli;
+mod compare;
+mod config;
+mod container;
+mod crash;
+mod cron;
+mod detach;
+mod diff;
+mod digest;
+mod ed25519;
+mod env;
+pub mod exec;
+mod export;
+mod gc;
+mod host;
+mod id;
+mod import;
+mod index;
+mod job;
+pub mod ledger;
+mod limits;
+mod list;
+mod lock;
+mod metadata;
+mod network;
+mod objects;
+mod oom;
+mod pattern;
+mod pipeline;
+mod procfs;
+mod procgroup;
+mod proctree;
+mod pty;
+pub mod receipt;
+mod redact;
+mod remote;
+mod replay;
+mod report;
+mod rusage;
+mod s3;
+mod sandbox;
+mod sarif;
+mod sched;
+mod schedule;
+mod schema;
+mod search;
+mod session;
+mod show;
+mod signals;
+mod signing;
+mod sigstore;
+pub mod sink;
+mod snapshot;
+mod sshsig;
+mod stats;
+mod stdin;
+mod syslog;
+mod tags;
+mod tail;
+mod tar;
+mod tempfile;
+mod template;
+mod timestamp;
+mod trace;
+mod units;
+mod user;
+mod vcs;
+mod watch;
+mod webhook;
+mod zstd;
@@ -0,0 +1,99 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+
+/// Resource limits applied to the command with setrlimit before exec
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceLimits {
+    /// Maximum address space in bytes (RLIMIT_AS)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_bytes: Option<u64>,
+    /// Maximum CPU time in seconds (RLIMIT_CPU)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_seconds: Option<u64>,
+    /// Maximum number of open file descriptors (RLIMIT_NOFILE)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub open_files: Option<u64>,
+    /// Maximum size of a file the command may write, in bytes (RLIMIT_FSIZE)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_size_bytes: Option<u64>,
+}
+
+impl ResourceLimits {
+    pub fn is_empty(&self) -> bool {
+        *self == ResourceLimits::default()
+    }
+
+    /// Arrange for the limits to be applied in the child before exec
+    pub fn apply(&self, command: &mut Command) -> Result<()> {
+        imp::apply(self, command)
+    }
+}
+
+#[cfg(unix)]
+mod imp {
+    use anyhow::Result;
+    use std::io;
+    use std::os::unix::process::CommandExt;
+    use std::process::Command;
+
+    use super::ResourceLimits;
+
+    pub fn apply(limits: &ResourceLimits, command: &mut Command) -> Result<()> {
+        let limits: Vec<_> = [
+            (libc::RLIMIT_AS, limits.memory_bytes),
+            (libc::RLIMIT_CPU, limits.cpu_seconds),
+            (libc::RLIMIT_NOFILE, limits.open_files),
+            (libc::RLIMIT_FSIZE, limits.file_size_bytes),
+        ]
+        .into_iter()
+        .filter_map(|(resource, value)| Some((resource, value?)))
+        .collect();
+
+        // SAFETY: setrlimit is async-signal-safe and the closure only reads
+        // the already-built vector
+        unsafe {
+            command.pre_exec(move || {
+                for &(resource, value) in &limits {
+                    let limit = libc::rlimit {
+                        rlim_cur: value as libc::rlim_t,
+                        rlim_max: value as libc::rlim_t,
+                    };
+                    if libc::setrlimit(resource, &limit) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+}
+
+/// On Windows the command's job object enforces the memory and CPU limits
+#[cfg(windows)]
+mod imp {
+    use anyhow::{bail, Result};
+    use std::process::Command;
+
+    use super::ResourceLimits;
+
+    pub fn apply(limits: &ResourceLimits, _command: &mut Command) -> Result<()> {
+        if limits.open_files.is_some() || limits.file_size_bytes.is_some() {
+            bail!("--limit-fds and --limit-fsize are only supported on Unix");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod imp {
+    use anyhow::{bail, Result};
+    use std::process::Command;
+
+    use super::ResourceLimits;
+
+    pub fn apply(_limits: &ResourceLimits, _command: &mut Command) -> Result<()> {
+        bail!("--limit-* flags are only supported on Unix and Windows");
+    }
+}
@@ -0,0 +1,251 @@
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::receipt::{json_files, read_receipt, Receipt, Status};
+
+/// What `rcpt last` prints of the receipt it finds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Print {
+    /// Its id
+    Id,
+    /// The path of its file
+    Path,
+    /// Its JSON, as `rcpt show --json` prints it
+    Json,
+}
+
+/// A status to look for: one by name, or `fail` for any run that finished
+/// without succeeding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusFilter {
+    Is(Status),
+    Fail,
+}
+
+impl FromStr for StatusFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s == "fail" {
+            return Ok(StatusFilter::Fail);
+        }
+        match serde_json::from_value(serde_json::Value::String(s.to_string())) {
+            Ok(status) => Ok(StatusFilter::Is(status)),
+            Err(_) => bail!(
+                "expected fail, ok, failed, timed_out, interrupted, oom_killed, missing_outputs, planned, running or lost, got '{}'",
+                s
+            ),
+        }
+    }
+}
+
+impl StatusFilter {
+    fn matches(self, status: Status) -> bool {
+        match self {
+            StatusFilter::Is(wanted) => status == wanted,
+            StatusFilter::Fail => !matches!(status, Status::Ok | Status::Planned | Status::Running),
+        }
+    }
+}
+
+/// Which receipts to pick out. Each condition set must hold; a receipt
+/// needs one of `statuses`, if any, and all of `tags`
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    /// Runs that started at this time or later
+    pub since: Option<DateTime<Utc>>,
+    /// Runs that started before this time
+    pub before: Option<DateTime<Utc>>,
+    pub statuses: Vec<StatusFilter>,
+    pub tags: Vec<String>,
+    /// Text the command line contains
+    pub command: Option<String>,
+    /// The session the run was part of
+    pub session: Option<String>,
+}
+
+impl Filter {
+    pub fn matches(&self, receipt: &Receipt) -> bool {
+        self.since.is_none_or(|since| receipt.start_time >= since)
+            && self.before.is_none_or(|before| receipt.start_time < before)
+            && (self.statuses.is_empty()
+                || self
+                    .statuses
+                    .iter()
+                    .any(|status| status.matches(receipt.status)))
+            && self.tags.iter().all(|tag| receipt.tags.contains(tag))
+            && self
+                .command
+                .as_ref()
+                .is_none_or(|text| receipt.command_line().contains(text.as_str()))
+            && self
+                .session
+                .as_ref()
+                .is_none_or(|session| receipt.session_id.as_ref() == Some(session))
+    }
+}
+
+/// What is listed, and counted in stats, of a receipt: the fields an index
+/// keeps, so those can be shown without reading the receipt's file
+#[derive(Debug, Clone, Deserialize)]
+pub struct Summary {
+    pub path: PathBuf,
+    pub id: String,
+    /// The command and its arguments
+    pub argv: Vec<String>,
+    pub status: Status,
+    pub exit_code: Option<i32>,
+    pub duration_ms: u64,
+    pub start_time: DateTime<Utc>,
+    pub tags: Vec<String>,
+}
+
+impl Summary {
+    pub fn of(path: &Path, receipt: &Receipt) -> Self {
+        Summary {
+            path: path.to_path_buf(),
+            id: receipt.id.clone(),
+            argv: std::iter::once(&receipt.command)
+                .chain(&receipt.args)
+                .cloned()
+                .collect(),
+            status: receipt.status,
+            exit_code: receipt.exit_code,
+            duration_ms: receipt.duration_ms,
+            start_time: receipt.start_time,
+            tags: receipt.tags.clone(),
+        }
+    }
+
+    /// The command and its arguments, joined by spaces
+    pub fn command_line(&self) -> String {
+        self.argv.join(" ")
+    }
+}
+
+/// Every receipt under `dirs` (searched recursively) that `filter` picks
+/// out, in the order the runs started. Files that are not receipts, or
+/// cannot be decrypted, are skipped
+pub fn find(dirs: &[PathBuf], filter: &Filter) -> Result<Vec<(PathBuf, Receipt)>> {
+    Ok(pick(json_files(dirs)?, filter))
+}
+
+/// The receipts at `paths` that `filter` picks out, in the order the runs
+/// started, skipping any that cannot be read
+pub fn pick(paths: Vec<PathBuf>, filter: &Filter) -> Vec<(PathBuf, Receipt)> {
+    let mut receipts: Vec<(PathBuf, Receipt)> = paths
+        .into_iter()
+        .filter_map(|path| {
+            let receipt = read_receipt(&path).ok()?;
+            filter.matches(&receipt).then_some((path, receipt))
+        })
+        .collect();
+    receipts.sort_by(|(_, a), (_, b)| (a.start_time, &a.id).cmp(&(b.start_time, &b.id)));
+    receipts
+}
+
+/// The receipt `target` names: `last` for the latest run under `dirs`, a
+/// path, or an id, or the start of one no other receipt under `dirs`
+/// shares
+pub fn locate(target: &str, dirs: &[PathBuf]) -> Result<(PathBuf, Receipt)> {
+    let path = Path::new(target);
+    if target != "last" && path.is_file() {
+        return Ok((path.to_path_buf(), read_receipt(path)?));
+    }
+    let mut receipts = find(dirs, &Filter::default())?;
+    if target == "last" {
+        return match receipts.pop() {
+            Some(last) => Ok(last),
+            None => bail!("No receipts found under {}", display_dirs(dirs)),
+        };
+    }
+    let prefix = target.to_ascii_uppercase();
+    let mut matching = receipts
+        .into_iter()
+        .filter(|(_, receipt)| receipt.id.starts_with(&prefix));
+    match (matching.next(), matching.next()) {
+        (Some(found), None) => Ok(found),
+        (Some(_), Some(_)) => bail!("More than one receipt has an id starting {}", target),
+        (None, _) => bail!(
+            "No receipt {} found, as a path or an id under {}",
+            target,
+            display_dirs(dirs)
+        ),
+    }
+}
+
+fn display_dirs(dirs: &[PathBuf]) -> String {
+    dirs.iter()
+        .map(|dir| dir.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// What `--json` prints of each receipt
+#[derive(Debug, Serialize)]
+struct Entry<'a> {
+    path: &'a Path,
+    id: &'a str,
+    start_time: DateTime<Utc>,
+    status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exit_code: Option<i32>,
+    duration_ms: u64,
+    argv: &'a [String],
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    tags: &'a [String],
+}
+
+/// A table of `receipts`, one line each
+pub fn render_table(receipts: &[Summary]) -> String {
+    let mut out = format!(
+        "{:<20}  {:<15} {:>4} {:>10}  {:<40}  PATH\n",
+        "START", "STATUS", "EXIT", "DURATION", "COMMAND"
+    );
+    for receipt in receipts {
+        let _ = writeln!(
+            out,
+            "{:<20}  {:<15} {:>4} {:>8}ms  {:<40}  {}",
+            receipt.start_time.format("%Y-%m-%dT%H:%M:%SZ"),
+            receipt.status.as_str(),
+            receipt
+                .exit_code
+                .map_or("-".to_string(), |code| code.to_string()),
+            receipt.duration_ms,
+            truncate(&receipt.command_line(), 40),
+            receipt.path.display()
+        );
+    }
+    out
+}
+
+/// `receipts` as a JSON array of their main fields and paths
+pub fn render_json(receipts: &[Summary]) -> Result<String> {
+    let entries: Vec<Entry> = receipts
+        .iter()
+        .map(|receipt| Entry {
+            path: &receipt.path,
+            id: &receipt.id,
+            start_time: receipt.start_time,
+            status: receipt.status,
+            exit_code: receipt.exit_code,
+            duration_ms: receipt.duration_ms,
+            argv: &receipt.argv,
+            tags: &receipt.tags,
+        })
+        .collect();
+    Ok(serde_json::to_string_pretty(&entries)?)
+}
+
+/// `text` cut to `width` characters, ending in `…` if it was longer
+pub fn truncate(text: &str, width: usize) -> String {
+    match text.chars().count() > width {
+        true => text.chars().take(width - 1).chain(['…']).collect(),
+        false => text.to_string(),
+    }
+}
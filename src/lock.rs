@@ -0,0 +1,58 @@
+use anyhow::{Context, Result};
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+
+/// File locked to coordinate changes to a directory shared by rcpt
+/// processes, such as a receipt directory or object store
+pub const DIR_LOCK: &str = ".rcpt.lock";
+
+/// An advisory lock on a file, held until dropped. Only rcpt processes
+/// take these locks, so they coordinate with each other but hold off
+/// nothing else
+#[derive(Debug)]
+pub struct Lock {
+    _file: File,
+}
+
+impl Lock {
+    /// Take the lock at `path` for changes no one else may make at the same
+    /// time, waiting for every other holder to let go
+    pub fn exclusive(path: &Path) -> Result<Self> {
+        Self::take(path, true)
+    }
+
+    /// Take the lock at `path` alongside other shared holders, waiting for
+    /// an exclusive holder to let go
+    pub fn shared(path: &Path) -> Result<Self> {
+        Self::take(path, false)
+    }
+
+    fn take(path: &Path, exclusive: bool) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .context(format!("Failed to open lock {}", path.display()))?;
+        match exclusive {
+            true => file.lock(),
+            false => file.lock_shared(),
+        }
+        .context(format!("Failed to lock {}", path.display()))?;
+        Ok(Lock { _file: file })
+    }
+}
+
+/// The lock coordinating changes to `dir`
+pub fn dir_lock(dir: &Path) -> PathBuf {
+    dir.join(DIR_LOCK)
+}
+
+/// The lock coordinating changes to the file at `path`, kept beside it
+pub fn file_lock(path: &Path) -> PathBuf {
+    let mut lock = OsString::from(path.as_os_str());
+    lock.push(".lock");
+    PathBuf::from(lock)
+}
@@ -0,0 +1,38 @@
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Parse a `--meta KEY=VALUE` argument, taking the value as a string
+pub fn parse_meta(s: &str) -> Result<(String, Value)> {
+    let (key, value) = split(s)?;
+    Ok((key, Value::String(value.to_string())))
+}
+
+/// Parse a `--meta-json KEY=JSON` argument
+pub fn parse_meta_json(s: &str) -> Result<(String, Value)> {
+    let (key, value) = split(s)?;
+    let value = serde_json::from_str(value).context(format!("invalid JSON for '{}'", key))?;
+    Ok((key, value))
+}
+
+fn split(s: &str) -> Result<(String, &str)> {
+    let Some((key, value)) = s.split_once('=') else {
+        bail!("expected KEY=VALUE, got '{}'", s);
+    };
+    if key.is_empty() {
+        bail!("missing key in '{}'", s);
+    }
+    Ok((key.to_string(), value))
+}
+
+/// The `metadata` object from every `--meta` and `--meta-json` pair,
+/// refusing a key given twice
+pub fn collect(pairs: &[(String, Value)]) -> Result<BTreeMap<String, Value>> {
+    let mut metadata = BTreeMap::new();
+    for (key, value) in pairs {
+        if metadata.insert(key.clone(), value.clone()).is_some() {
+            bail!("metadata key '{}' given more than once", key);
+        }
+    }
+    Ok(metadata)
+}
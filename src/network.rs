@@ -0,0 +1,102 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::process::Command;
+
+/// Network access the command had, for `--no-network`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Network {
+    Disabled,
+}
+
+/// The argv that runs `argv` without network access: unchanged where
+/// `isolate` does the work, wrapped in sandbox-exec on macOS
+pub fn wrap(argv: Vec<OsString>) -> Vec<OsString> {
+    imp::wrap(argv)
+}
+
+/// Arrange for the command to start without network access: in a new net
+/// namespace holding only loopback on Linux. Fails where neither this nor
+/// `wrap` can take the network away.
+pub fn isolate(command: &mut Command) -> Result<()> {
+    imp::isolate(command)
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use anyhow::Result;
+    use std::ffi::OsString;
+    use std::io;
+    use std::os::unix::process::CommandExt;
+    use std::process::Command;
+
+    use crate::sandbox::{is_root, loopback_up, map_user};
+
+    pub fn wrap(argv: Vec<OsString>) -> Vec<OsString> {
+        argv
+    }
+
+    pub fn isolate(command: &mut Command) -> Result<()> {
+        // Without root, a user namespace grants the right to a net namespace
+        let user_ns = !is_root();
+        // SAFETY: getuid(2) and getgid(2) cannot fail
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        let uid_map = format!("{0} {0} 1\n", uid).into_bytes();
+        let gid_map = format!("{0} {0} 1\n", gid).into_bytes();
+        // SAFETY: unshare(2) and the namespace setup only make
+        // async-signal-safe calls, and the maps were formatted before fork
+        unsafe {
+            command.pre_exec(move || {
+                let mut flags = libc::CLONE_NEWNET;
+                if user_ns {
+                    flags |= libc::CLONE_NEWUSER;
+                }
+                if libc::unshare(flags) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                if user_ns {
+                    map_user(&uid_map, &gid_map)?;
+                }
+                loopback_up()
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod imp {
+    use anyhow::Result;
+    use std::ffi::OsString;
+    use std::process::Command;
+
+    /// Seatbelt profile allowing everything but network access
+    const PROFILE: &str = "(version 1) (allow default) (deny network*)";
+
+    pub fn wrap(argv: Vec<OsString>) -> Vec<OsString> {
+        let mut wrapped: Vec<OsString> =
+            vec!["/usr/bin/sandbox-exec".into(), "-p".into(), PROFILE.into()];
+        wrapped.extend(argv);
+        wrapped
+    }
+
+    pub fn isolate(_command: &mut Command) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+mod imp {
+    use anyhow::{bail, Result};
+    use std::ffi::OsString;
+    use std::process::Command;
+
+    pub fn wrap(argv: Vec<OsString>) -> Vec<OsString> {
+        argv
+    }
+
+    pub fn isolate(_command: &mut Command) -> Result<()> {
+        bail!("--no-network is only supported on Linux and macOS");
+    }
+}
@@ -0,0 +1,120 @@
+use anyhow::{bail, Context, Result};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::age::{self, Recipient};
+use crate::artifact::{self, Artifact, ArtifactKind};
+use crate::atomic;
+use crate::digest::sha256_hex;
+use crate::lock::{self, Lock};
+
+/// Where --objects-above stores objects unless --object-store says
+/// otherwise, relative to the working directory
+pub const DEFAULT_DIR: &str = ".receipts/objects";
+
+/// A content-addressable store: a directory of blobs, each named by the
+/// SHA-256 of its contents, so identical output is stored once
+#[derive(Debug, Clone)]
+pub struct ObjectStore {
+    dir: PathBuf,
+    /// Flush each object to disk as it is stored
+    fsync: bool,
+}
+
+impl ObjectStore {
+    /// The store in `dir`, created if it does not exist
+    pub fn open(dir: &Path, fsync: bool) -> Result<Self> {
+        let cwd = std::env::current_dir().context("Failed to get current directory")?;
+        let dir = cwd.join(dir);
+        fs::create_dir_all(&dir)
+            .context(format!("Failed to create object store {}", dir.display()))?;
+        Ok(ObjectStore { dir, fsync })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Store `bytes`, encrypted to `recipients` if there are any, returning
+    /// their SHA-256. Bytes already in the store are not written again, but
+    /// are marked as just stored, so `rcpt gc` leaves them until the
+    /// receipt referring to them is written
+    pub fn put(&self, bytes: &[u8], recipients: &[Recipient]) -> Result<String> {
+        let digest = sha256_hex(bytes);
+        let path = self.dir.join(&digest);
+        // Held off while `rcpt gc` deletes objects
+        let _lock = Lock::shared(&lock::dir_lock(&self.dir))?;
+        if path.exists() {
+            File::open(&path)
+                .and_then(|file| file.set_modified(SystemTime::now()))
+                .context(format!("Failed to update {}", path.display()))?;
+            return Ok(digest);
+        }
+        let contents = match recipients.is_empty() {
+            true => bytes.to_vec(),
+            false => age::encrypt(bytes, recipients)?,
+        };
+        // A concurrent reader or writer never sees part of an object
+        atomic::write(&path, &contents, self.fsync)
+            .context(format!("Failed to write {}", path.display()))?;
+        Ok(digest)
+    }
+
+    /// Store a declared artifact under `cwd`: a file's contents, or a
+    /// tree's manifest and every file in it, checking each still has the
+    /// digest it was recorded with
+    pub fn put_artifact(
+        &self,
+        cwd: &Path,
+        artifact: &Artifact,
+        recipients: &[Recipient],
+    ) -> Result<()> {
+        let full = cwd.join(&artifact.path);
+        let digest = match artifact.kind {
+            ArtifactKind::File => self.put_file(&full, recipients)?,
+            ArtifactKind::Directory => {
+                let mut entries = Vec::new();
+                artifact::walk(&full, "", &mut entries)?;
+                entries.sort();
+                for (name, digest, _) in &entries {
+                    let path = full.join(name);
+                    let stored = match fs::symlink_metadata(&path)?.is_symlink() {
+                        true => self.put(
+                            fs::read_link(&path)?.to_string_lossy().as_bytes(),
+                            recipients,
+                        )?,
+                        false => self.put_file(&path, recipients)?,
+                    };
+                    if stored != *digest {
+                        bail!("{} changed while it was being stored", path.display());
+                    }
+                }
+                self.put(artifact::manifest(&entries).as_bytes(), recipients)?
+            }
+        };
+        if digest != artifact.sha256 {
+            bail!("{} changed while it was being stored", artifact.path);
+        }
+        Ok(())
+    }
+
+    fn put_file(&self, path: &Path, recipients: &[Recipient]) -> Result<String> {
+        let bytes = fs::read(path).context(format!("Failed to read {}", path.display()))?;
+        self.put(&bytes, recipients)
+    }
+}
+
+/// Read the object `digest` from the store in `dir`, decrypting it if it
+/// is encrypted and checking it has that digest
+pub fn read(dir: &Path, digest: &str) -> Result<Vec<u8>> {
+    let path = dir.join(digest);
+    if !path.exists() {
+        bail!("object {} is not in {}", digest, dir.display());
+    }
+    let bytes = age::read(&path)?;
+    if sha256_hex(&bytes) != digest {
+        bail!("object {} in {} is corrupt", digest, dir.display());
+    }
+    Ok(bytes)
+}
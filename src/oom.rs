@@ -0,0 +1,134 @@
+use serde::{Deserialize, Serialize};
+use std::process::ExitStatus;
+
+use crate::cgroup::Cgroup;
+use crate::receipt::ResourceUsage;
+
+/// Where an out-of-memory kill was seen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OomSource {
+    /// The `oom_kill` counter in the command's cgroup's memory.events
+    Cgroup,
+    /// The kernel log's "Killed process" message for the command
+    KernelLog,
+}
+
+/// Evidence that the kernel's out-of-memory killer killed the command
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OomRecord {
+    /// How the kill was detected
+    pub detected_by: OomSource,
+    /// Processes in the command's cgroup the OOM killer killed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oom_kills: Option<u64>,
+    /// Most memory the command's processes used together, in bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_peak_bytes: Option<u64>,
+    /// Resident memory of the killed process when it was killed, from the
+    /// kernel log
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rss_at_kill_bytes: Option<u64>,
+}
+
+/// Whether the OOM killer killed the process `pid`, given its exit
+/// `status` and the cgroup it ran in. Only meaningful when rcpt did not
+/// kill it itself. Linux only.
+pub fn detect(
+    status: &ExitStatus,
+    pid: u32,
+    cgroup: Option<&Cgroup>,
+    resources: Option<&ResourceUsage>,
+) -> Option<OomRecord> {
+    if !imp::killed(status) {
+        return None;
+    }
+    let memory_peak_bytes = resources.and_then(|usage| usage.memory_peak_bytes);
+    if let Some(kills) = cgroup
+        .and_then(Cgroup::oom_kills)
+        .filter(|&kills| kills > 0)
+    {
+        return Some(OomRecord {
+            detected_by: OomSource::Cgroup,
+            oom_kills: Some(kills),
+            memory_peak_bytes,
+            rss_at_kill_bytes: None,
+        });
+    }
+    let message = imp::logged_kill(pid)?;
+    Some(OomRecord {
+        detected_by: OomSource::KernelLog,
+        oom_kills: None,
+        memory_peak_bytes,
+        rss_at_kill_bytes: rss_at_kill(&message),
+    })
+}
+
+/// Total of the `anon-rss`, `file-rss` and `shmem-rss` the kernel logged
+fn rss_at_kill(message: &str) -> Option<u64> {
+    let fields = message
+        .split([' ', ','])
+        .filter_map(|field| field.split_once("-rss:"))
+        .filter_map(|(_, kib)| kib.strip_suffix("kB")?.parse::<u64>().ok());
+    fields
+        .map(|kib| kib * 1024)
+        .reduce(|total, bytes| total + bytes)
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::fs::File;
+    use std::io::{ErrorKind, Read};
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+
+    /// The OOM killer always sends SIGKILL
+    pub fn killed(status: &ExitStatus) -> bool {
+        status.signal() == Some(libc::SIGKILL)
+    }
+
+    /// The kernel log's message about the OOM killer killing `pid`. Reading
+    /// the log may need privileges; without them nothing is found.
+    pub fn logged_kill(pid: u32) -> Option<String> {
+        let Ok(mut kmsg) = File::options()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open("/dev/kmsg")
+        else {
+            return None;
+        };
+        // e.g. "Out of memory: Killed process 1234 (python3) total-vm:..."
+        let needle = format!("Killed process {} (", pid);
+        // Each read returns one record, "priority,sequence,time,flags;message"
+        let mut record = vec![0; 8192];
+        loop {
+            match kmsg.read(&mut record) {
+                Ok(0) => return None,
+                Ok(len) => {
+                    let record = String::from_utf8_lossy(&record[..len]);
+                    if let Some((_, message)) = record.split_once(&needle) {
+                        return Some(message.to_string());
+                    }
+                }
+                // Records overwritten while reading
+                Err(err) if err.raw_os_error() == Some(libc::EPIPE) => {}
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(_) => return None,
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use std::process::ExitStatus;
+
+    pub fn killed(_status: &ExitStatus) -> bool {
+        false
+    }
+
+    pub fn logged_kill(_pid: u32) -> Option<String> {
+        None
+    }
+}
@@ -0,0 +1,549 @@
+use anyhow::{bail, Result};
+
+/// A compiled regular expression over bytes, supporting the subset secret
+/// patterns need: literals, `.`, classes like `[A-Z0-9_-]` and `[^"]`, the
+/// escapes `\d \w \s \D \W \S \b \n \r \t`, groups with `|`, the quantifiers
+/// `* + ? {n} {n,} {n,m}` (lazy with a trailing `?`), and a leading `(?i)`
+/// for case-insensitive matching. Character classes only know ASCII.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    nodes: Vec<Node>,
+    /// Bytes a match can start with, to skip other positions quickly
+    first: Box<[bool; 256]>,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    /// One byte from the set
+    Set(Box<[bool; 256]>),
+    /// A word boundary, `\b`
+    Boundary,
+    /// Alternative sequences, `(a|b)`
+    Group(Vec<Vec<Node>>),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+        lazy: bool,
+    },
+}
+
+/// Matched positions are reported to a continuation, so alternatives and
+/// repeats can backtrack into whatever follows them
+type Continue<'a> = &'a mut dyn FnMut(usize) -> bool;
+
+impl Pattern {
+    pub fn new(source: &str) -> Result<Self> {
+        let (ignore_case, body) = match source.strip_prefix("(?i)") {
+            Some(body) => (true, body),
+            None => (false, source),
+        };
+        let mut parser = Parser {
+            bytes: body.as_bytes(),
+            pos: 0,
+            ignore_case,
+        };
+        let alternatives = parser.alternatives()?;
+        if parser.pos < parser.bytes.len() {
+            bail!("unmatched ')' in pattern '{}'", source);
+        }
+        let nodes = vec![Node::Group(alternatives)];
+        if nullable(&nodes) {
+            bail!("pattern '{}' matches the empty string", source);
+        }
+        let mut first = Box::new([false; 256]);
+        first_bytes(&nodes, &mut first);
+        Ok(Pattern { nodes, first })
+    }
+
+    /// Start and end of the leftmost match at or after `start`
+    pub fn find(&self, input: &[u8], start: usize) -> Option<(usize, usize)> {
+        for pos in start..input.len() {
+            if !self.first[input[pos] as usize] {
+                continue;
+            }
+            let mut end = None;
+            if matches(&self.nodes, input, pos, &mut |found| {
+                end = Some(found);
+                true
+            }) {
+                return end.map(|end| (pos, end));
+            }
+        }
+        None
+    }
+}
+
+fn matches(seq: &[Node], input: &[u8], pos: usize, next: Continue) -> bool {
+    let Some((node, rest)) = seq.split_first() else {
+        return next(pos);
+    };
+    match node {
+        Node::Set(set) => {
+            pos < input.len() && set[input[pos] as usize] && matches(rest, input, pos + 1, next)
+        }
+        Node::Boundary => {
+            let before = pos > 0 && is_word(input[pos - 1]);
+            let after = pos < input.len() && is_word(input[pos]);
+            before != after && matches(rest, input, pos, next)
+        }
+        Node::Group(alternatives) => alternatives.iter().any(|alternative| {
+            matches(alternative, input, pos, &mut |end| {
+                matches(rest, input, end, next)
+            })
+        }),
+        Node::Repeat {
+            node,
+            min,
+            max,
+            lazy,
+        } => match node.as_ref() {
+            // Runs of single bytes are counted rather than recursed into, so
+            // long runs cannot exhaust the stack
+            Node::Set(set) => {
+                let limit = max.unwrap_or(usize::MAX);
+                let run = input[pos.min(input.len())..]
+                    .iter()
+                    .take(limit)
+                    .take_while(|&&byte| set[byte as usize])
+                    .count();
+                if run < *min {
+                    return false;
+                }
+                if *lazy {
+                    (*min..=run).any(|count| matches(rest, input, pos + count, next))
+                } else {
+                    (*min..=run)
+                        .rev()
+                        .any(|count| matches(rest, input, pos + count, next))
+                }
+            }
+            _ => repeat(seq, input, pos, 0, next),
+        },
+    }
+}
+
+/// Match the repeat starting `seq` from `pos`, having matched its node
+/// `count` times already, followed by the rest of `seq`
+fn repeat(seq: &[Node], input: &[u8], pos: usize, count: usize, next: Continue) -> bool {
+    let Some((
+        Node::Repeat {
+            node,
+            min,
+            max,
+            lazy,
+        },
+        rest,
+    )) = seq.split_first()
+    else {
+        return false;
+    };
+    let (min, max, lazy) = (*min, *max, *lazy);
+    let can_stop = count >= min;
+    if lazy && can_stop && matches(rest, input, pos, next) {
+        return true;
+    }
+    let can_continue = max.is_none_or(|max| count < max);
+    // An iteration matching nothing would repeat forever
+    if can_continue
+        && matches(std::slice::from_ref(node), input, pos, &mut |end| {
+            end != pos && repeat(seq, input, end, count + 1, next)
+        })
+    {
+        return true;
+    }
+    !lazy && can_stop && matches(rest, input, pos, next)
+}
+
+fn is_word(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_'
+}
+
+fn nullable(seq: &[Node]) -> bool {
+    seq.iter().all(|node| match node {
+        Node::Set(_) => false,
+        Node::Boundary => true,
+        Node::Group(alternatives) => alternatives.iter().any(|alternative| nullable(alternative)),
+        Node::Repeat { node, min, .. } => *min == 0 || nullable(std::slice::from_ref(node)),
+    })
+}
+
+/// Add the bytes a match of `seq` can start with to `first`
+fn first_bytes(seq: &[Node], first: &mut [bool; 256]) {
+    for node in seq {
+        match node {
+            Node::Set(set) => {
+                for (byte, &member) in set.iter().enumerate() {
+                    first[byte] |= member;
+                }
+            }
+            Node::Boundary => {}
+            Node::Group(alternatives) => {
+                for alternative in alternatives {
+                    first_bytes(alternative, first);
+                }
+            }
+            Node::Repeat { node, .. } => first_bytes(std::slice::from_ref(node), first),
+        }
+        if !nullable(std::slice::from_ref(node)) {
+            return;
+        }
+    }
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    ignore_case: bool,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = self.peek()?;
+        self.pos += 1;
+        Some(byte)
+    }
+
+    /// Sequences separated by `|`, up to a `)` or the end
+    fn alternatives(&mut self) -> Result<Vec<Vec<Node>>> {
+        let mut alternatives = vec![self.sequence()?];
+        while self.peek() == Some(b'|') {
+            self.pos += 1;
+            alternatives.push(self.sequence()?);
+        }
+        Ok(alternatives)
+    }
+
+    fn sequence(&mut self) -> Result<Vec<Node>> {
+        let mut nodes = Vec::new();
+        while let Some(byte) = self.peek() {
+            if byte == b'|' || byte == b')' {
+                break;
+            }
+            let atom = self.atom()?;
+            nodes.push(self.quantified(atom)?);
+        }
+        Ok(nodes)
+    }
+
+    fn atom(&mut self) -> Result<Node> {
+        let byte = self.next().unwrap_or_default();
+        Ok(match byte {
+            b'(' => {
+                if self.bytes[self.pos..].starts_with(b"?:") {
+                    self.pos += 2;
+                }
+                let alternatives = self.alternatives()?;
+                if self.next() != Some(b')') {
+                    bail!("unclosed '(' in pattern");
+                }
+                Node::Group(alternatives)
+            }
+            b'[' => self.class()?,
+            b'.' => {
+                let mut set = Box::new([true; 256]);
+                set[b'\n' as usize] = false;
+                Node::Set(set)
+            }
+            b'\\' => match self.next() {
+                Some(b'b') => Node::Boundary,
+                Some(escape) => Node::Set(self.escape(escape)),
+                None => bail!("pattern ends with '\\'"),
+            },
+            b'*' | b'+' | b'?' | b'{' => bail!("'{}' has nothing to repeat", byte as char),
+            b'^' | b'$' => bail!("anchors ('{}') are not supported", byte as char),
+            literal => Node::Set(self.literal(literal)),
+        })
+    }
+
+    fn quantified(&mut self, atom: Node) -> Result<Node> {
+        let (min, max) = match self.peek() {
+            Some(b'*') => {
+                self.pos += 1;
+                (0, None)
+            }
+            Some(b'+') => {
+                self.pos += 1;
+                (1, None)
+            }
+            Some(b'?') => {
+                self.pos += 1;
+                (0, Some(1))
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                let min = self.number()?;
+                let max = match self.next() {
+                    Some(b'}') => Some(min),
+                    Some(b',') if self.peek() == Some(b'}') => {
+                        self.pos += 1;
+                        None
+                    }
+                    Some(b',') => {
+                        let max = self.number()?;
+                        if self.next() != Some(b'}') {
+                            bail!("unclosed '{{' in pattern");
+                        }
+                        Some(max)
+                    }
+                    _ => bail!("unclosed '{{' in pattern"),
+                };
+                if max.is_some_and(|max| max < min) {
+                    bail!("repeat range {{{},{}}} is backwards", min, max.unwrap());
+                }
+                (min, max)
+            }
+            _ => return Ok(atom),
+        };
+        let lazy = self.peek() == Some(b'?');
+        if lazy {
+            self.pos += 1;
+        }
+        Ok(Node::Repeat {
+            node: Box::new(atom),
+            min,
+            max,
+            lazy,
+        })
+    }
+
+    fn number(&mut self) -> Result<usize> {
+        let start = self.pos;
+        while self.peek().is_some_and(|byte| byte.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        let digits = std::str::from_utf8(&self.bytes[start..self.pos]).unwrap_or_default();
+        match digits.parse() {
+            Ok(number) => Ok(number),
+            Err(_) => bail!("expected a number in '{{...}}'"),
+        }
+    }
+
+    /// A bracketed class, after its `[`
+    fn class(&mut self) -> Result<Node> {
+        let negated = self.peek() == Some(b'^');
+        if negated {
+            self.pos += 1;
+        }
+        let mut set = Box::new([false; 256]);
+        let mut first = true;
+        loop {
+            let Some(byte) = self.next() else {
+                bail!("unclosed '[' in pattern");
+            };
+            if byte == b']' && !first {
+                break;
+            }
+            first = false;
+            let low = match byte {
+                b'\\' => {
+                    let Some(escape) = self.next() else {
+                        bail!("unclosed '[' in pattern");
+                    };
+                    if b"dwsDWS".contains(&escape) {
+                        union(&mut set, &self.escape(escape));
+                        continue;
+                    }
+                    escaped(escape)
+                }
+                byte => byte,
+            };
+            let is_range = self.peek() == Some(b'-')
+                && self.bytes.get(self.pos + 1).is_some_and(|&end| end != b']');
+            let high = if is_range {
+                self.pos += 1;
+                match self.next() {
+                    Some(b'\\') => escaped(self.next().unwrap_or_default()),
+                    Some(high) => high,
+                    None => bail!("unclosed '[' in pattern"),
+                }
+            } else {
+                low
+            };
+            if high < low {
+                bail!("class range {}-{} is backwards", low as char, high as char);
+            }
+            for byte in low..=high {
+                union(&mut set, &self.literal(byte));
+            }
+        }
+        if negated {
+            for member in set.iter_mut() {
+                *member = !*member;
+            }
+        }
+        Ok(Node::Set(set))
+    }
+
+    /// The set for an escape after `\`
+    fn escape(&self, escape: u8) -> Box<[bool; 256]> {
+        let class = |test: fn(u8) -> bool, negate: bool| {
+            let mut set = Box::new([false; 256]);
+            for byte in 0..=255u8 {
+                set[byte as usize] = test(byte) != negate;
+            }
+            set
+        };
+        match escape {
+            b'd' => class(|byte| byte.is_ascii_digit(), false),
+            b'D' => class(|byte| byte.is_ascii_digit(), true),
+            b'w' => class(is_word, false),
+            b'W' => class(is_word, true),
+            b's' => class(|byte| byte.is_ascii_whitespace(), false),
+            b'S' => class(|byte| byte.is_ascii_whitespace(), true),
+            other => self.literal(escaped(other)),
+        }
+    }
+
+    /// The set matching `byte`, in either case if ignoring case
+    fn literal(&self, byte: u8) -> Box<[bool; 256]> {
+        let mut set = Box::new([false; 256]);
+        set[byte as usize] = true;
+        if self.ignore_case {
+            set[byte.to_ascii_lowercase() as usize] = true;
+            set[byte.to_ascii_uppercase() as usize] = true;
+        }
+        set
+    }
+}
+
+/// The byte an escaped character stands for
+fn escaped(escape: u8) -> u8 {
+    match escape {
+        b'n' => b'\n',
+        b'r' => b'\r',
+        b't' => b'\t',
+        other => other,
+    }
+}
+
+fn union(set: &mut [bool; 256], other: &[bool; 256]) {
+    for (member, &other) in set.iter_mut().zip(other.iter()) {
+        *member |= other;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The leftmost match of `pattern` in `input`, as text
+    fn found<'a>(pattern: &str, input: &'a str) -> Option<&'a str> {
+        let pattern = Pattern::new(pattern).unwrap();
+        pattern
+            .find(input.as_bytes(), 0)
+            .map(|(start, end)| &input[start..end])
+    }
+
+    #[test]
+    fn literals_and_dot() {
+        assert_eq!(found("abc", "xxabcxx"), Some("abc"));
+        assert_eq!(found("abc", "xxabxcx"), None);
+        assert_eq!(found("a.c", "abc"), Some("abc"));
+        assert_eq!(found("a.c", "a\nc"), None);
+        assert_eq!(found(r"a\.c", "abc a.c"), Some("a.c"));
+    }
+
+    #[test]
+    fn classes() {
+        assert_eq!(found("[A-Z0-9_-]+", "ab C_9-x"), Some("C_9-"));
+        assert_eq!(found("[^\"]+", "\"quoted\""), Some("quoted"));
+        assert_eq!(found("[]a]+", "x]a]x"), Some("]a]"));
+        assert_eq!(found(r"[\d.]+", "v1.2.3"), Some("1.2.3"));
+        assert_eq!(found(r"[a\-z]+", "b-az"), Some("-az"));
+        assert_eq!(found("[a-]+", "xa-a"), Some("a-a"));
+        assert_eq!(found(r"\d+", "abc123"), Some("123"));
+        assert_eq!(found(r"\D+", "123abc"), Some("abc"));
+        assert_eq!(found(r"\w+", "  foo_1 "), Some("foo_1"));
+        assert_eq!(found(r"\W+", "foo, bar"), Some(", "));
+        assert_eq!(found(r"\s+", "a \t\nb"), Some(" \t\n"));
+        assert_eq!(found(r"\S+", "  ab  "), Some("ab"));
+        assert_eq!(found(r"a\tb", "a\tb"), Some("a\tb"));
+    }
+
+    #[test]
+    fn quantifiers() {
+        assert_eq!(found("ab*c", "ac"), Some("ac"));
+        assert_eq!(found("ab*c", "abbbc"), Some("abbbc"));
+        assert_eq!(found("ab+c", "ac"), None);
+        assert_eq!(found("ab+c", "abbc"), Some("abbc"));
+        assert_eq!(found("colou?r", "color colour"), Some("color"));
+        assert_eq!(found("a{3}", "aa aaaa"), Some("aaa"));
+        assert_eq!(found("a{2,}", "a aaaaa"), Some("aaaaa"));
+        assert_eq!(found("a{2,3}", "aaaaa"), Some("aaa"));
+        assert_eq!(found("x[0-9]{2,3}", "x1 x12345"), Some("x123"));
+        assert_eq!(found("<.+>", "<a><b>"), Some("<a><b>"));
+        assert_eq!(found("<.+?>", "<a><b>"), Some("<a>"));
+        assert_eq!(found("(ab)+", "xababab"), Some("ababab"));
+        assert_eq!(found("(ab)+?", "xababab"), Some("ab"));
+        assert_eq!(found("(a|ab)(c|bcd)", "abcd"), Some("abcd"));
+    }
+
+    #[test]
+    fn long_runs_do_not_exhaust_the_stack() {
+        let input = "a".repeat(1_000_000);
+        assert_eq!(found("a+", &input).map(str::len), Some(input.len()));
+    }
+
+    #[test]
+    fn boundaries() {
+        assert_eq!(found(r"\bkey\b", "monkey key"), Some("key"));
+        assert_eq!(found(r"\bkey\b", "monkey keys"), None);
+        assert_eq!(found(r"\bkey", "key"), Some("key"));
+        assert_eq!(Pattern::new("x").unwrap().find(b"axbx", 2), Some((3, 4)));
+    }
+
+    #[test]
+    fn anchors_are_rejected() {
+        assert!(Pattern::new("^abc").is_err());
+        assert!(Pattern::new("abc$").is_err());
+        // In a class they are literals, or negate it
+        assert_eq!(found("[$^]+", "a$^b"), Some("$^"));
+    }
+
+    #[test]
+    fn alternation() {
+        assert_eq!(found("cat|dog", "hotdog"), Some("dog"));
+        assert_eq!(found("(AKIA|ASIA)[0-9]", "xASIA7"), Some("ASIA7"));
+        assert_eq!(found("(?:foo|bar)baz", "barbaz"), Some("barbaz"));
+        assert_eq!(found("a(b|)c", "ac"), Some("ac"));
+        assert_eq!(found("cat|dog", "bird"), None);
+    }
+
+    #[test]
+    fn ignore_case() {
+        assert_eq!(found("(?i)bearer", "BeArEr x"), Some("BeArEr"));
+        assert_eq!(found("(?i)[a-c]+", "xAbCd"), Some("AbC"));
+        assert_eq!(found("bearer", "BEARER"), None);
+    }
+
+    #[test]
+    fn non_ascii_input() {
+        assert_eq!(found("key=[a-z]+", "ключ key=abc ключ"), Some("key=abc"));
+        assert_eq!(found(r"\w+", "ключ"), None);
+        assert_eq!(found("é+", "café"), Some("é"));
+        assert_eq!(found(r"\bAKIA", "ключAKIA"), Some("AKIA"));
+        // `.` matches bytes, so it can take part of a character
+        assert_eq!(
+            Pattern::new("a.").unwrap().find("aé".as_bytes(), 0),
+            Some((0, 2))
+        );
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        for source in [
+            "(ab", "ab)", "[ab", "a{2", "a{3,2}", "*a", "a\\", "[z-a]", "a*", "(a|)",
+        ] {
+            assert!(
+                Pattern::new(source).is_err(),
+                "{} should not compile",
+                source
+            );
+        }
+    }
+}
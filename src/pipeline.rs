@@ -0,0 +1,396 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use sha2::{Digest, Sha256};
+use std::ffi::{OsStr, OsString};
+use std::io::{Read, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::capture::{self, Capture, Tee, Timeline};
+use crate::cgroup::Cgroup;
+use crate::crash;
+use crate::exec::{self, ExecOptions, WaitOutcome, DRAIN_GRACE};
+use crate::id;
+use crate::job::Job;
+use crate::network;
+use crate::oom;
+use crate::procgroup::{self, Foreground};
+use crate::proctree::Sampler;
+use crate::receipt::{ClockAnomaly, Receipt, Stage, Status};
+use crate::rusage;
+use crate::signals;
+use crate::snapshot::Snapshot;
+use crate::stdin::{StdinFeeder, StdinMode};
+use crate::trace::{RunningTracer, Tracer};
+
+/// Split `argv` into pipeline stages at each `--`
+pub fn split_stages(argv: &[OsString]) -> Result<Vec<Vec<OsString>>> {
+    let stages: Vec<Vec<OsString>> = argv
+        .split(|arg| arg == "--")
+        .map(<[OsString]>::to_vec)
+        .collect();
+    if stages.iter().any(Vec::is_empty) {
+        anyhow::bail!("Empty pipeline stage: separate commands with a single --");
+    }
+    Ok(stages)
+}
+
+/// Run `stages` as a pipeline, each stage's stdout feeding the next stage's
+/// stdin. The pipeline fails if any stage fails (pipefail).
+pub fn execute_pipeline(stages: &[Vec<OsString>], opts: &ExecOptions) -> Result<Receipt> {
+    let mut receipt = run_pipeline(stages, opts)?;
+    opts.redactor.apply(&mut receipt);
+    Ok(receipt)
+}
+
+fn run_pipeline(stages: &[Vec<OsString>], opts: &ExecOptions) -> Result<Receipt> {
+    if opts.pty {
+        anyhow::bail!("--pty is not supported for pipelines");
+    }
+    if opts.container.is_some() {
+        anyhow::bail!("--container is not supported for pipelines");
+    }
+
+    let cwd = exec::resolve_cwd(opts.cwd.as_deref())?;
+    let mut child_env = exec::child_environment(opts);
+    let mut records = Vec::new();
+    for argv in stages {
+        let executable =
+            exec::resolve_executable(&argv[0], child_env.get(OsStr::new("PATH")), &cwd);
+        if opts.dry_run && executable.is_none() {
+            anyhow::bail!("Command not found: {}", argv[0].to_string_lossy());
+        }
+        records.push(Stage {
+            command: argv[0].to_string_lossy().to_string(),
+            args: argv[1..]
+                .iter()
+                .map(|s| s.to_string_lossy().to_string())
+                .collect(),
+            executable: executable.map(|path| path.to_string_lossy().to_string()),
+            exit_code: None,
+            terminated_by: None,
+            status: Status::Planned,
+            stdout_bytes: 0,
+            stderr_bytes: 0,
+            stdout_sha256: None,
+            stderr_sha256: None,
+            duration_ms: 0,
+        });
+    }
+
+    let start_instant = Instant::now();
+    let mut receipt = exec::planned_receipt(opts, &cwd, &child_env)?;
+    child_env.insert(id::PARENT_ENV.into(), receipt.id.clone().into());
+    receipt.command = describe(stages);
+    receipt.stages = records;
+    if opts.dry_run {
+        return Ok(receipt);
+    }
+
+    let cgroup = Cgroup::create();
+    let job = Job::create(&opts.limits)?;
+    let snapshot = opts
+        .snapshot
+        .as_deref()
+        .map(|dir| Snapshot::take(&cwd, dir))
+        .transpose()?;
+    let tracer = opts.trace_files.then(Tracer::open).transpose()?;
+    let mut children: Vec<Child> = Vec::with_capacity(stages.len());
+    let mut stdin_file = None;
+    for (index, argv) in stages.iter().enumerate() {
+        let argv = if opts.no_network {
+            network::wrap(argv.clone())
+        } else {
+            argv.clone()
+        };
+        let mut command = exec::build_command(&argv[0], &argv[1..], &cwd, &child_env, opts)?;
+        // All stages share the first stage's process group, so signals reach them together
+        match children.first() {
+            Some(leader) => procgroup::join(&mut command, leader),
+            None => procgroup::isolate(&mut command),
+        }
+        let stdin = if index == 0 {
+            let (stdin, file) = opts.stdin.stdio()?;
+            stdin_file = file;
+            stdin
+        } else {
+            Stdio::piped()
+        };
+        command
+            .stdin(stdin)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if let Some(cgroup) = &cgroup {
+            cgroup.attach(&mut command);
+        }
+        if opts.no_network && receipt.sandbox.is_none() {
+            network::isolate(&mut command)?;
+        }
+        if let Some(sandbox) = &receipt.sandbox {
+            sandbox.apply(&mut command)?;
+        }
+        if let Some(access) = &receipt.fs_access {
+            access.apply(&mut command)?;
+        }
+        if let Some(user) = &opts.user {
+            user.apply(&mut command);
+        }
+        let spawned = command
+            .spawn()
+            .context(format!(
+                "Failed to execute command: {}",
+                argv[0].to_string_lossy()
+            ))
+            .and_then(|child| {
+                children.push(child);
+                match &job {
+                    Some(job) => job
+                        .assign(children.last().unwrap())
+                        .context("Failed to assign command to job object"),
+                    None => Ok(()),
+                }
+            });
+        if let Err(err) = spawned {
+            procgroup::kill_all(&mut children);
+            for child in &mut children {
+                let _ = child.wait();
+            }
+            return Err(err);
+        }
+    }
+
+    let cgroup = cgroup.filter(|cgroup| children.iter().all(|child| cgroup.contains(child.id())));
+    receipt.pid = Some(children[0].id());
+    exec::write_stub(opts, &receipt);
+    let roots: Vec<u32> = children.iter().map(Child::id).collect();
+    let sampler = opts
+        .process_tree
+        .map(|interval| Sampler::start(&roots, interval));
+    let tracer = tracer.map(|tracer| tracer.start(&roots));
+
+    let foreground = match opts.stdin {
+        StdinMode::Inherit => Foreground::acquire(&children[0]),
+        _ => None,
+    };
+    let feeder = children[0].stdin.take().map(StdinFeeder::start);
+    let relays: Vec<Option<Relay>> = (1..children.len())
+        .map(|index| {
+            let upstream = children[index - 1].stdout.take();
+            let downstream = children[index].stdin.take();
+            upstream
+                .zip(downstream)
+                .map(|(from, to)| Relay::start(from, to))
+        })
+        .collect();
+    let timeline = opts.timeline.then(|| Timeline::new(start_instant));
+    let stdout = Capture::start(
+        children.last_mut().and_then(|child| child.stdout.take()),
+        opts.max_stdout_bytes,
+        opts.tee.then(|| Box::new(std::io::stdout()) as Tee),
+        timeline
+            .as_ref()
+            .map(|timeline| timeline.stream("stdout", None)),
+    );
+    let stderrs: Vec<Capture> = children
+        .iter_mut()
+        .enumerate()
+        .map(|(index, child)| {
+            Capture::start(
+                child.stderr.take(),
+                opts.max_stderr_bytes,
+                opts.tee.then(|| Box::new(std::io::stderr()) as Tee),
+                timeline
+                    .as_ref()
+                    .map(|timeline| timeline.stream("stderr", Some(index))),
+            )
+        })
+        .collect();
+
+    let deadline = opts.timeout.map(|timeout| start_instant + timeout);
+    let WaitOutcome {
+        exits,
+        timed_out,
+        mut interrupt_signal,
+        kill_signal,
+        usage,
+    } = exec::wait_for_group(&mut children, deadline, opts.kill_after)?;
+    // Before orphans are killed, so the last sample still sees them
+    receipt.process_tree = sampler.map(Sampler::finish);
+    receipt.file_trace = tracer.map(RunningTracer::finish);
+
+    let duration = start_instant.elapsed();
+    let end_time = Utc::now();
+
+    if foreground.is_some() && interrupt_signal.is_none() {
+        interrupt_signal = exits
+            .iter()
+            .find_map(|(status, _)| signals::terminal_interrupt(status));
+    }
+    drop(foreground);
+
+    let mut orphaned_pids =
+        exec::kill_orphans(&mut children[0], timed_out || interrupt_signal.is_some());
+    if let Some(job) = &job {
+        orphaned_pids.extend(job.kill_remaining());
+    }
+    let resources = match (&cgroup, &job) {
+        (Some(cgroup), _) => Some(cgroup.usage()),
+        (None, Some(job)) => Some(job.usage()),
+        (None, None) => None,
+    };
+    let resources = rusage::merge(resources, usage);
+
+    let grace = timed_out.then_some(DRAIN_GRACE);
+    let stdout = stdout.finish(grace);
+    let stderrs: Vec<_> = stderrs
+        .into_iter()
+        .map(|stderr| stderr.finish(grace))
+        .collect();
+    let relayed: Vec<(u64, Option<String>)> = relays
+        .into_iter()
+        .map(|relay| relay.map_or((0, None), |relay| relay.finish(DRAIN_GRACE)))
+        .collect();
+    let stdin = opts.stdin.finish(feeder, stdin_file, DRAIN_GRACE);
+
+    let interrupted = interrupt_signal.is_some();
+    for (index, stage) in receipt.stages.iter_mut().enumerate() {
+        let (status, exited_at) = exits[index];
+        let code = status.code();
+        // A stage that finished cleanly before the pipeline was stopped is still ok
+        let stopped = code != Some(0);
+        stage.exit_code = code;
+        stage.terminated_by = signals::terminated_by(&status);
+        stage.status = Status::of(code, timed_out && stopped, interrupted && stopped);
+        (stage.stdout_bytes, stage.stdout_sha256) = match relayed.get(index) {
+            Some((bytes, sha256)) => (*bytes, sha256.clone()),
+            None => (stdout.info.total_bytes, Some(stdout.info.sha256.clone())),
+        };
+        stage.stderr_bytes = stderrs[index].info.total_bytes;
+        stage.stderr_sha256 = Some(stderrs[index].info.sha256.clone());
+        stage.duration_ms = exited_at.duration_since(start_instant).as_millis() as u64;
+    }
+    // As with pipefail, the rightmost failing stage decides the exit code
+    let failed = receipt
+        .stages
+        .iter()
+        .rev()
+        .find(|stage| stage.exit_code != Some(0));
+    let exit_code = failed.map_or(Some(0), |stage| stage.exit_code);
+    receipt.terminated_by = failed.and_then(|stage| stage.terminated_by.clone());
+
+    receipt.exit_code = exit_code;
+    receipt.status = Status::of(exit_code, timed_out, interrupted);
+    receipt.interrupt_signal = interrupt_signal;
+    receipt.timed_out = timed_out;
+    receipt.kill_signal = kill_signal.map(signals::name);
+    receipt.orphaned_pids = orphaned_pids;
+    if !timed_out && !interrupted {
+        receipt.crash = exits
+            .iter()
+            .zip(&children)
+            .find_map(|((status, _), child)| {
+                crash::inspect(status, child.id(), &cwd, receipt.start_time.into())
+            });
+        let oom = exits
+            .iter()
+            .zip(&children)
+            .zip(&mut receipt.stages)
+            .find_map(|(((status, _), child), stage)| {
+                let oom = oom::detect(status, child.id(), cgroup.as_ref(), resources.as_ref())?;
+                stage.status = Status::OomKilled;
+                Some(oom)
+            });
+        if oom.is_some() {
+            receipt.status = Status::OomKilled;
+        }
+        receipt.oom = oom;
+    }
+    exec::record_outputs(opts, &cwd, &mut receipt)?;
+    receipt.snapshot = snapshot
+        .map(|snapshot| snapshot.finish(opts.stub.as_deref()))
+        .transpose()?;
+    receipt.stdin = Some(stdin);
+    let stderr: Vec<u8> = stderrs
+        .iter()
+        .flat_map(|stderr| stderr.content.iter().copied())
+        .collect();
+    if let Some(access) = &mut receipt.fs_access {
+        access.record_denials(&String::from_utf8_lossy(&stderr));
+    }
+    (receipt.stdout, receipt.stdout_encoding) = capture::encode(stdout.content);
+    receipt.stdout_info = Some(stdout.info);
+    (receipt.stderr, receipt.stderr_encoding) = capture::encode(stderr);
+    exec::record_timeline(timeline, &mut receipt);
+    receipt.end_time = end_time;
+    receipt.duration_ms = duration.as_millis() as u64;
+    receipt.clock_anomaly = ClockAnomaly::detect(receipt.start_time, end_time, duration);
+    receipt.resources = resources;
+
+    Ok(receipt)
+}
+
+/// The pipeline written the way a shell would show it
+fn describe(stages: &[Vec<OsString>]) -> String {
+    stages
+        .iter()
+        .map(|argv| {
+            argv.iter()
+                .map(|arg| arg.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect::<Vec<_>>()
+        .join(" | ")
+}
+
+/// Background thread copying one stage's stdout into the next stage's
+/// stdin while counting and hashing the bytes
+struct Relay {
+    bytes: Arc<AtomicU64>,
+    handle: JoinHandle<String>,
+}
+
+impl Relay {
+    fn start(mut from: ChildStdout, mut to: ChildStdin) -> Self {
+        let bytes = Arc::new(AtomicU64::new(0));
+        let handle = {
+            let bytes = Arc::clone(&bytes);
+            thread::spawn(move || {
+                let mut hasher = Sha256::new();
+                let mut chunk = [0u8; 8192];
+                loop {
+                    let n = match from.read(&mut chunk) {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => n,
+                    };
+                    bytes.fetch_add(n as u64, Ordering::SeqCst);
+                    hasher.update(&chunk[..n]);
+                    // Once the next stage stops reading, dropping `from` lets
+                    // this one see a broken pipe, as in a shell pipeline
+                    if to.write_all(&chunk[..n]).is_err() {
+                        break;
+                    }
+                }
+                format!("{:x}", hasher.finalize())
+            })
+        };
+        Relay { bytes, handle }
+    }
+
+    /// Number of bytes relayed and their SHA-256, waiting at most `grace`
+    /// for the copy to end; the digest is None if it did not
+    fn finish(self, grace: Duration) -> (u64, Option<String>) {
+        let give_up = Instant::now() + grace;
+        while !self.handle.is_finished() && Instant::now() < give_up {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let bytes = self.bytes.load(Ordering::SeqCst);
+        match self.handle.is_finished() {
+            true => (bytes, self.handle.join().ok()),
+            false => (bytes, None),
+        }
+    }
+}
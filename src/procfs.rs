@@ -0,0 +1,41 @@
+#![cfg(target_os = "linux")]
+
+use std::fs;
+
+/// The fields of `/proc/<pid>/stat` after the command name, so the first is
+/// the state, the second the parent pid, the third the process group and
+/// the twentieth the start time (fields 3, 4, 5 and 22 in proc(5))
+pub fn stat_fields(pid: u32) -> Option<Vec<String>> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    fields(&stat)
+}
+
+fn fields(stat: &str) -> Option<Vec<String>> {
+    // The command name may contain spaces and parentheses, but nothing
+    // after it does, so it ends at the last ')'
+    let rest = &stat[stat.rfind(')')? + 1..];
+    Some(rest.split_whitespace().map(str::to_string).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_command_names_with_parentheses() {
+        let stat = "4242 (a) (b c) S 1 4242 4242 0 -1 4194560 0 0 0 0 0 0 0 0 20 0 1 0 98765 0 0\n";
+        let fields = fields(stat).unwrap();
+        assert_eq!(fields[0], "S");
+        assert_eq!(fields[1], "1");
+        assert_eq!(fields[2], "4242");
+        assert_eq!(fields[19], "98765");
+        assert_eq!(super::fields("4242 (truncated"), None);
+    }
+
+    #[test]
+    fn reads_this_process() {
+        let fields = stat_fields(std::process::id()).unwrap();
+        assert_eq!(fields[1], std::os::unix::process::parent_id().to_string());
+        assert_eq!(stat_fields(u32::MAX), None);
+    }
+}
@@ -0,0 +1,260 @@
+use std::process::{Child, Command};
+
+#[cfg(unix)]
+mod imp {
+    use std::os::unix::process::CommandExt;
+    use std::process::{Child, Command};
+
+    pub fn isolate(command: &mut Command) {
+        command.process_group(0);
+    }
+
+    pub fn join(command: &mut Command, leader: &Child) {
+        command.process_group(leader.id() as i32);
+    }
+
+    pub fn detach(command: &mut Command) {
+        // SAFETY: setsid(2) is async-signal-safe
+        unsafe {
+            command.pre_exec(|| {
+                if libc::setsid() < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+
+    pub fn is_alive(pid: u32) -> bool {
+        // SAFETY: kill(2) with signal 0 only checks that the process exists
+        let result = unsafe { libc::kill(pid as libc::pid_t, 0) };
+        result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+
+    pub fn signal(child: &Child, signal: i32) {
+        // The child leads its own group, so its pid is the group id.
+        // SAFETY: killpg(2) has no memory-safety preconditions
+        unsafe {
+            libc::killpg(child.id() as libc::pid_t, signal);
+        }
+    }
+
+    pub fn kill(child: &mut Child) {
+        signal(child, libc::SIGKILL);
+    }
+
+    pub fn kill_all(children: &mut [Child]) {
+        // Everything joined the first child's group
+        if let Some(leader) = children.first_mut() {
+            kill(leader);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn members(child: &Child) -> Vec<u32> {
+        let Ok(entries) = std::fs::read_dir("/proc") else {
+            return Vec::new();
+        };
+        let mut pids: Vec<u32> = entries
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+            .filter(|&pid| live_group_of(pid) == Some(child.id()))
+            .collect();
+        pids.sort_unstable();
+        pids
+    }
+
+    /// Process group of `pid` from /proc/<pid>/stat, unless it is a zombie
+    #[cfg(target_os = "linux")]
+    fn live_group_of(pid: u32) -> Option<u32> {
+        let fields = crate::procfs::stat_fields(pid)?;
+        let state = fields.first()?;
+        if state == "Z" || state == "X" {
+            return None;
+        }
+        fields.get(2)?.parse().ok()
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn members(_child: &Child) -> Vec<u32> {
+        Vec::new()
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn sleep_until_exit(children: &[&Child], timeout: std::time::Duration) {
+        // SAFETY: pidfd_open(2) and poll(2) only read the pollfd array we own,
+        // and every descriptor opened here is closed before returning. The
+        // children are unreaped, so their pids cannot have been reused.
+        unsafe {
+            let mut fds: Vec<libc::pollfd> = children
+                .iter()
+                .map(|child| libc::syscall(libc::SYS_pidfd_open, child.id() as libc::pid_t, 0))
+                .filter(|&fd| fd >= 0)
+                .map(|fd| libc::pollfd {
+                    fd: fd as libc::c_int,
+                    events: libc::POLLIN,
+                    revents: 0,
+                })
+                .collect();
+            if fds.len() < children.len() {
+                // No pidfd support (kernels before 5.3): fall back to sleeping
+                std::thread::sleep(timeout);
+            } else {
+                let timeout_ms = timeout.as_millis() as libc::c_int;
+                libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout_ms);
+            }
+            for fd in fds {
+                libc::close(fd.fd);
+            }
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn sleep_until_exit(_children: &[&Child], timeout: std::time::Duration) {
+        std::thread::sleep(timeout);
+    }
+
+    /// Terminal whose foreground process group was handed to the child
+    pub struct Foreground {
+        previous: libc::pid_t,
+    }
+
+    impl Foreground {
+        pub fn acquire(child: &Child) -> Option<Self> {
+            // SAFETY: these calls only query and set terminal state on fd 0;
+            // SIGTTOU is ignored around tcsetpgrp so a background rcpt may call it
+            unsafe {
+                if libc::isatty(libc::STDIN_FILENO) == 0 {
+                    return None;
+                }
+                let previous = libc::tcgetpgrp(libc::STDIN_FILENO);
+                if previous < 0 || previous != libc::getpgrp() {
+                    return None;
+                }
+                set_foreground(child.id() as libc::pid_t);
+                Some(Foreground { previous })
+            }
+        }
+    }
+
+    impl Drop for Foreground {
+        fn drop(&mut self) {
+            // SAFETY: see `Foreground::acquire`
+            unsafe { set_foreground(self.previous) }
+        }
+    }
+
+    unsafe fn set_foreground(pgid: libc::pid_t) {
+        let previous = libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+        libc::tcsetpgrp(libc::STDIN_FILENO, pgid);
+        libc::signal(libc::SIGTTOU, previous);
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use std::process::{Child, Command};
+
+    pub fn isolate(_command: &mut Command) {}
+
+    pub fn join(_command: &mut Command, _leader: &Child) {}
+
+    #[cfg(windows)]
+    pub fn detach(command: &mut Command) {
+        use std::os::windows::process::CommandExt;
+        const DETACHED_PROCESS: u32 = 0x0000_0008;
+        const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+        command.creation_flags(DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP);
+    }
+
+    #[cfg(not(windows))]
+    pub fn detach(_command: &mut Command) {}
+
+    pub fn is_alive(_pid: u32) -> bool {
+        true
+    }
+
+    pub fn signal(_child: &Child, _signal: i32) {}
+
+    pub fn kill(child: &mut Child) {
+        let _ = child.kill();
+    }
+
+    pub fn kill_all(children: &mut [Child]) {
+        for child in children {
+            kill(child);
+        }
+    }
+
+    pub fn members(_child: &Child) -> Vec<u32> {
+        Vec::new()
+    }
+
+    pub fn sleep_until_exit(_children: &[&Child], timeout: std::time::Duration) {
+        std::thread::sleep(timeout);
+    }
+
+    pub struct Foreground;
+
+    impl Foreground {
+        pub fn acquire(_child: &Child) -> Option<Self> {
+            None
+        }
+    }
+
+    /// Nothing to give back, but callers drop it at the point the terminal
+    /// is given back on Unix
+    impl Drop for Foreground {
+        fn drop(&mut self) {}
+    }
+}
+
+pub use imp::Foreground;
+
+/// Start the command in its own process group (Unix only)
+pub fn isolate(command: &mut Command) {
+    imp::isolate(command)
+}
+
+/// Start the command in the process group led by `leader` (Unix only)
+pub fn join(command: &mut Command, leader: &Child) {
+    imp::join(command, leader)
+}
+
+/// Start the command in a new session, detached from rcpt's terminal, so
+/// it outlives rcpt and the terminal closing
+pub fn detach(command: &mut Command) {
+    imp::detach(command)
+}
+
+/// Whether a process with this pid exists (on other platforms than Unix,
+/// always true)
+pub fn is_alive(pid: u32) -> bool {
+    imp::is_alive(pid)
+}
+
+/// Send `signal` to every process in the child's group
+pub fn signal(child: &Child, signal: i32) {
+    imp::signal(child, signal)
+}
+
+/// Forcefully kill the child and, on Unix, everything in its group
+pub fn kill(child: &mut Child) {
+    imp::kill(child)
+}
+
+/// Forcefully kill children started with `isolate` (the first) and `join`
+/// (the rest), and everything else in their group
+pub fn kill_all(children: &mut [Child]) {
+    imp::kill_all(children)
+}
+
+/// Processes still alive in the child's group (Linux only)
+pub fn members(child: &Child) -> Vec<u32> {
+    imp::members(child)
+}
+
+/// Sleep for up to `timeout`, waking as soon as any of `children` exits
+/// (Linux only; elsewhere this sleeps for the whole timeout)
+pub fn sleep_until_exit(children: &[&Child], timeout: std::time::Duration) {
+    imp::sleep_until_exit(children, timeout)
+}
@@ -0,0 +1,299 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Most processes recorded for one run; any beyond are only counted
+const MAX_PROCESSES: usize = 10_000;
+
+/// Whether this platform can sample the process tree
+pub const SUPPORTED: bool = cfg!(any(target_os = "linux", target_os = "macos"));
+
+/// Processes the command started, as seen by periodic sampling
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessTree {
+    /// Time between samples, in milliseconds
+    pub interval_ms: u64,
+    /// Every process seen, in the order they were first seen
+    pub processes: Vec<ProcessRecord>,
+    /// Processes seen beyond the most that are recorded
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub omitted: u64,
+}
+
+fn is_zero(count: &u64) -> bool {
+    *count == 0
+}
+
+/// One process of the tree
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessRecord {
+    pub pid: u32,
+    /// Parent when first seen
+    pub ppid: u32,
+    /// Command line as last seen, after any exec (on macOS, split on
+    /// whitespace; `[name]` for a process only seen after it exited)
+    pub argv: Vec<String>,
+    /// When the process was first and last seen, in milliseconds from the
+    /// start of the command
+    pub first_seen_ms: u64,
+    pub last_seen_ms: u64,
+}
+
+/// A process as one sample saw it
+pub struct Observed {
+    pub pid: u32,
+    pub ppid: u32,
+    /// Process group
+    pub pgid: u32,
+    /// Distinguishes processes that reuse a pid (start time on Linux)
+    pub start: u64,
+}
+
+/// Background sampler of the processes descending from the command's
+pub struct Sampler {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<ProcessTree>,
+}
+
+impl Sampler {
+    /// Start sampling, every `interval`, the processes descending from
+    /// `roots`, which are recorded too. The first sample is taken before
+    /// returning, so even a command that exits at once is seen.
+    pub fn start(roots: &[u32], interval: Duration) -> Self {
+        let mut tracker = Tracker::new(roots, interval);
+        tracker.sample();
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || loop {
+                thread::sleep(interval);
+                // Checked before sampling so the last sample follows the
+                // command's exit
+                let last = stop.load(Ordering::SeqCst);
+                tracker.sample();
+                if last {
+                    return tracker.tree;
+                }
+            })
+        };
+        Sampler { stop, handle }
+    }
+
+    /// Take a last sample and return the tree
+    pub fn finish(self) -> ProcessTree {
+        self.stop.store(true, Ordering::SeqCst);
+        self.handle.join().unwrap_or_else(|_| ProcessTree {
+            interval_ms: 0,
+            processes: Vec::new(),
+            omitted: 0,
+        })
+    }
+}
+
+/// The tree so far, and what is needed to extend it with another sample
+struct Tracker {
+    roots: Vec<u32>,
+    start: Instant,
+    tree: ProcessTree,
+    /// Index into the tree's processes of each process seen, by pid and start
+    seen: HashMap<(u32, u64), Option<usize>>,
+    /// The tree's processes alive at the last sample
+    live: HashMap<u32, u64>,
+}
+
+impl Tracker {
+    fn new(roots: &[u32], interval: Duration) -> Self {
+        Tracker {
+            roots: roots.to_vec(),
+            start: Instant::now(),
+            tree: ProcessTree {
+                interval_ms: interval.as_millis() as u64,
+                processes: Vec::new(),
+                omitted: 0,
+            },
+            seen: HashMap::new(),
+            live: HashMap::new(),
+        }
+    }
+
+    fn sample(&mut self) {
+        let now = self.start.elapsed().as_millis() as u64;
+        let observed = imp::processes();
+        let live = descendants(&self.roots, &self.live, &observed);
+        let tree = &mut self.tree;
+        for process in observed
+            .iter()
+            .filter(|p| live.get(&p.pid) == Some(&p.start))
+        {
+            let index = *self
+                .seen
+                .entry((process.pid, process.start))
+                .or_insert_with(|| {
+                    if tree.processes.len() >= MAX_PROCESSES {
+                        tree.omitted += 1;
+                        return None;
+                    }
+                    tree.processes.push(ProcessRecord {
+                        pid: process.pid,
+                        ppid: process.ppid,
+                        argv: Vec::new(),
+                        first_seen_ms: now,
+                        last_seen_ms: now,
+                    });
+                    Some(tree.processes.len() - 1)
+                });
+            let Some(record) = index.map(|index| &mut tree.processes[index]) else {
+                continue;
+            };
+            record.last_seen_ms = now;
+            // Re-read each time, since a forked child only shows what it
+            // runs once it has exec'd
+            match imp::argv(process.pid) {
+                Some(argv) => record.argv = argv,
+                // Seen only once it had exited: all that is left is its name
+                None if record.argv.is_empty() => {
+                    record.argv = imp::name(process.pid).into_iter().collect();
+                }
+                None => {}
+            }
+        }
+        self.live = live;
+    }
+}
+
+/// The processes in `observed` that belong to the tree: the roots, whatever
+/// was in it at the last sample, anything in the roots' process groups
+/// (which catches orphans reparented between samples) and anything whose
+/// parent belongs
+fn descendants(
+    roots: &[u32],
+    live: &HashMap<u32, u64>,
+    observed: &[Observed],
+) -> HashMap<u32, u64> {
+    let mut tree: HashMap<u32, u64> = observed
+        .iter()
+        .filter(|p| {
+            roots.contains(&p.pid) || roots.contains(&p.pgid) || live.get(&p.pid) == Some(&p.start)
+        })
+        .map(|p| (p.pid, p.start))
+        .collect();
+    loop {
+        let before = tree.len();
+        for process in observed {
+            if !tree.contains_key(&process.pid) && tree.contains_key(&process.ppid) {
+                tree.insert(process.pid, process.start);
+            }
+        }
+        if tree.len() == before {
+            return tree;
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::fs;
+
+    use super::Observed;
+    use crate::procfs;
+
+    pub fn processes() -> Vec<Observed> {
+        let Ok(entries) = fs::read_dir("/proc") else {
+            return Vec::new();
+        };
+        entries
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+            .filter_map(|pid: u32| {
+                let fields = procfs::stat_fields(pid)?;
+                Some(Observed {
+                    pid,
+                    ppid: fields.get(1)?.parse().ok()?,
+                    pgid: fields.get(2)?.parse().ok()?,
+                    start: fields.get(19)?.parse().ok()?,
+                })
+            })
+            .collect()
+    }
+
+    pub fn argv(pid: u32) -> Option<Vec<String>> {
+        let cmdline = fs::read(format!("/proc/{}/cmdline", pid)).ok()?;
+        if cmdline.is_empty() {
+            return None;
+        }
+        Some(
+            cmdline
+                .strip_suffix(b"\0")
+                .unwrap_or(&cmdline)
+                .split(|&byte| byte == 0)
+                .map(|arg| String::from_utf8_lossy(arg).to_string())
+                .collect(),
+        )
+    }
+
+    pub fn name(pid: u32) -> Option<String> {
+        let comm = fs::read_to_string(format!("/proc/{}/comm", pid)).ok()?;
+        Some(format!("[{}]", comm.trim_end()))
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod imp {
+    use std::process::Command;
+
+    use super::Observed;
+
+    pub fn processes() -> Vec<Observed> {
+        let Ok(output) = Command::new("ps")
+            .args(["-A", "-o", "pid=,ppid=,pgid="])
+            .output()
+        else {
+            return Vec::new();
+        };
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                Some(Observed {
+                    pid: fields.next()?.parse().ok()?,
+                    ppid: fields.next()?.parse().ok()?,
+                    pgid: fields.next()?.parse().ok()?,
+                    start: 0,
+                })
+            })
+            .collect()
+    }
+
+    pub fn argv(pid: u32) -> Option<Vec<String>> {
+        let output = Command::new("ps")
+            .args(["-ww", "-o", "command=", "-p", &pid.to_string()])
+            .output()
+            .ok()?;
+        let command = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        (output.status.success() && !command.is_empty())
+            .then(|| command.split_whitespace().map(str::to_string).collect())
+    }
+
+    pub fn name(_pid: u32) -> Option<String> {
+        None
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+mod imp {
+    use super::Observed;
+
+    pub fn processes() -> Vec<Observed> {
+        Vec::new()
+    }
+
+    pub fn argv(_pid: u32) -> Option<Vec<String>> {
+        None
+    }
+
+    pub fn name(_pid: u32) -> Option<String> {
+        None
+    }
+}
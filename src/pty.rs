@@ -0,0 +1,91 @@
+use anyhow::Result;
+use std::fs::File;
+use std::process::Command;
+
+#[cfg(unix)]
+mod imp {
+    use anyhow::{Context, Result};
+    use std::fs::File;
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd};
+    use std::os::unix::process::CommandExt;
+    use std::process::{Command, Stdio};
+
+    /// Terminal size given to the pseudo-terminal when rcpt has none itself
+    const DEFAULT_SIZE: libc::winsize = libc::winsize {
+        ws_row: 24,
+        ws_col: 80,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+
+    pub fn attach(command: &mut Command) -> Result<File> {
+        let mut size = terminal_size().unwrap_or(DEFAULT_SIZE);
+        let (mut master, mut slave) = (0, 0);
+        // SAFETY: openpty writes the two descriptors into the provided ints;
+        // name and termios may be null
+        let rc = unsafe {
+            libc::openpty(
+                &mut master,
+                &mut slave,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                std::ptr::addr_of_mut!(size),
+            )
+        };
+        if rc != 0 {
+            return Err(io::Error::last_os_error()).context("Failed to allocate a pseudo-terminal");
+        }
+        // SAFETY: openpty succeeded, so both descriptors are open and owned by us
+        let (master, slave) = unsafe { (File::from_raw_fd(master), File::from_raw_fd(slave)) };
+
+        command
+            .stdin(Stdio::from(slave.try_clone()?))
+            .stdout(Stdio::from(slave.try_clone()?))
+            .stderr(Stdio::from(slave));
+        // SAFETY: setsid and ioctl are async-signal-safe. The new session
+        // makes the child a group leader, standing in for process_group(0)
+        unsafe {
+            command.pre_exec(|| {
+                if libc::setsid() < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                // stdin is the pty's terminal side by now; make it our controlling tty
+                if libc::ioctl(0, libc::TIOCSCTTY as _, 0) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        Ok(master)
+    }
+
+    fn terminal_size() -> Option<libc::winsize> {
+        let mut size: libc::winsize = DEFAULT_SIZE;
+        for fd in [io::stdout().as_raw_fd(), io::stdin().as_raw_fd()] {
+            // SAFETY: TIOCGWINSZ only writes a winsize into `size`
+            if unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) } == 0 {
+                return Some(size);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use anyhow::{bail, Result};
+    use std::fs::File;
+    use std::process::Command;
+
+    pub fn attach(_command: &mut Command) -> Result<File> {
+        bail!("--pty is only supported on Unix");
+    }
+}
+
+/// Connect the command's stdin, stdout and stderr to a new pseudo-terminal
+/// and make it the command's controlling terminal. Returns the pty's
+/// controlling side, which carries the merged output and accepts input.
+pub fn attach(command: &mut Command) -> Result<File> {
+    imp::attach(command)
+}
@@ -0,0 +1,1087 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::access::AccessProfile;
+use crate::age::{self, Recipient};
+use crate::artifact::Artifact;
+use crate::atomic;
+use crate::base64;
+use crate::bench::Benchmark;
+use crate::canonical;
+use crate::container::ContainerRecord;
+use crate::crash::CrashRecord;
+use crate::digest;
+use crate::env::EnvValues;
+use crate::host::HostRecord;
+use crate::limits::ResourceLimits;
+use crate::network::Network;
+use crate::objects;
+use crate::oom::OomRecord;
+use crate::proctree::ProcessTree;
+use crate::redact::RedactionRecord;
+use crate::remote::RemoteRecord;
+use crate::sandbox::SandboxProfile;
+use crate::sched::Scheduling;
+use crate::signing::SignatureRecord;
+use crate::snapshot::SnapshotRecord;
+use crate::timestamp::TimestampRecord;
+use crate::trace::FileTrace;
+use crate::vcs::VcsRecord;
+use crate::zstd;
+
+/// Version of the receipt format this rcpt writes. It changes when a field
+/// is removed, renamed or changes type or meaning, or an enumerated field
+/// such as `status` gains a value; adding an optional field does not.
+pub const RECEIPT_VERSION: u32 = 8;
+
+/// Values enumerated fields gained after version 1, as the version that
+/// added each, the name of its type and the value
+pub const ADDED_VALUES: &[(u32, &str, &str)] = &[
+    (2, "Status", "missing_outputs"),
+    (3, "Encoding", "base64"),
+    (3, "Encoding", "sidecar"),
+    (4, "SignatureAlgorithm", "ssh"),
+    (5, "SignatureAlgorithm", "sigstore"),
+    (6, "Encoding", "zstd"),
+    (7, "Encoding", "object"),
+];
+
+/// Largest difference between the wall and monotonic clocks over a run not
+/// reported as a clock anomaly
+const CLOCK_TOLERANCE_MS: u64 = 1000;
+
+/// Execution receipt containing command metadata and results
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Receipt {
+    /// Version of the receipt format (receipts written before the field
+    /// existed are version 1)
+    #[serde(default = "first_version")]
+    pub receipt_version: u32,
+    /// Unique id of the run, a ULID that sorts by start time
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    /// SHA-256 of the receipt's canonical JSON without this field,
+    /// `signature` or `timestamp`, filled in when the receipt is written
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub receipt_digest: Option<String>,
+    /// Signature over the receipt without this field, with --sign
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<SignatureRecord>,
+    /// RFC 3161 timestamp over `receipt_digest`, with --timestamp-url
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<TimestampRecord>,
+    /// Id of the receipt whose command ran this one, from `--parent` or the
+    /// `RCPT_PARENT` variable a parent rcpt sets
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<String>,
+    /// Id of the receipt whose command this run ran again, with
+    /// `rcpt replay`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replay_of: Option<String>,
+    /// Whether the receipt was converted by `rcpt import` from another
+    /// tool's record of the run, rather than written by rcpt as it ran
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub imported: bool,
+    /// Session the run belongs to, from `$RCPT_SESSION` as set by
+    /// `rcpt session start`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// Agent, run and task that produced the receipt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actor: Option<ActorRecord>,
+    /// Labels given with `--tag`, in the order given
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Key/value pairs given with `--meta` and `--meta-json`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, serde_json::Value>,
+    /// Command that was executed
+    pub command: String,
+    /// Command arguments
+    pub args: Vec<String>,
+    /// Resolved path of the executable, if it could be found
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub executable: Option<String>,
+    /// Shell and original command string, when run with `--shell`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell: Option<ShellInvocation>,
+    /// Each command of a pipeline, in order, when run with `rcpt pipe`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stages: Vec<Stage>,
+    /// Whether the command ran under a pseudo-terminal, in which case stdout
+    /// holds the merged terminal stream and stderr is empty
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pty: bool,
+    /// Canonical absolute working directory the command ran in
+    #[serde(default)]
+    pub cwd: String,
+    /// Machine the command ran on, with `--capture-host`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<HostRecord>,
+    /// Version control state of the working directory when the command
+    /// started, unless run with `--no-vcs`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vcs: Option<VcsRecord>,
+    /// Effective user the command ran as (Unix only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<UserRecord>,
+    /// Container the command ran in, with its image pinned by id and digest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container: Option<ContainerRecord>,
+    /// Machine the command ran on, for `rcpt ssh`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote: Option<RemoteRecord>,
+    /// Process id of the command (the first stage, for pipelines)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+    /// Process id of the background rcpt that ran the command, for `--detach`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub supervisor_pid: Option<u32>,
+    /// Resource limits applied to the command
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limits: Option<ResourceLimits>,
+    /// CPU priority and affinity the command ran with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scheduling: Option<Scheduling>,
+    /// Namespace sandbox the command ran in, for `--sandbox`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sandbox: Option<SandboxProfile>,
+    /// Filesystem paths the command was restricted to, for `--allow-read`
+    /// and `--allow-write`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fs_access: Option<AccessProfile>,
+    /// Network access the command had, `disabled` for `--no-network`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<Network>,
+    /// Environment variables recorded under the environment policy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<BTreeMap<String, String>>,
+    /// SHA-256 over the command's whole environment, recorded whatever the
+    /// environment policy, for comparing environments without their values
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub env_digest: String,
+    /// Environment capture policy that was applied
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env_policy: Option<EnvPolicyReport>,
+    /// Env files loaded into the command's environment, in load order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub env_files: Vec<EnvFileRecord>,
+    /// Whether the command started from an empty environment, seeing only
+    /// variables passed explicitly with `--env`, `--env-file` or `--user`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub hermetic: bool,
+    /// Files and directories declared with `--input`, hashed before the run
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<Artifact>,
+    /// Files and directories declared with `--output`, hashed after the run
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outputs: Vec<Artifact>,
+    /// Declared outputs that did not exist after the run
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub missing_outputs: Vec<String>,
+    /// Files created, modified and deleted under the `--snapshot` directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<SnapshotRecord>,
+    /// Exit code of the command (None if terminated by signal)
+    pub exit_code: Option<i32>,
+    /// Signal that terminated the command, when it did not exit (Unix only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub terminated_by: Option<TerminatedBy>,
+    /// Overall outcome of the run
+    pub status: Status,
+    /// Signal rcpt received (and forwarded to the command) while it ran
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interrupt_signal: Option<i32>,
+    /// Whether the command was killed for exceeding its timeout
+    #[serde(default)]
+    pub timed_out: bool,
+    /// Last signal (or termination mechanism) rcpt sent to stop the command
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kill_signal: Option<String>,
+    /// Processes left in the command's process group after it exited,
+    /// which rcpt then killed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub orphaned_pids: Vec<u32>,
+    /// Processes the command started, with `--process-tree`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub process_tree: Option<ProcessTree>,
+    /// Files the command read and wrote, with `--trace-files`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_trace: Option<FileTrace>,
+    /// Signal and core dump of a command that crashed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crash: Option<CrashRecord>,
+    /// Evidence of the out-of-memory kill, when `status` is `oom_killed`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oom: Option<OomRecord>,
+    /// Where standard input came from and, when captured, what was sent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stdin: Option<StdinRecord>,
+    /// Standard output (stdout)
+    pub stdout: String,
+    /// How `stdout` is stored
+    #[serde(default, skip_serializing_if = "Encoding::is_utf8")]
+    pub stdout_encoding: Encoding,
+    /// How `stdout` was compressed, when it was larger than --compress-above
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stdout_compression: Option<Compression>,
+    /// Size, digest and truncation of the full stdout stream
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stdout_info: Option<StreamInfo>,
+    /// Standard error (stderr)
+    pub stderr: String,
+    /// How `stderr` is stored
+    #[serde(default, skip_serializing_if = "Encoding::is_utf8")]
+    pub stderr_encoding: Encoding,
+    /// How `stderr` was compressed, when it was larger than --compress-above
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stderr_compression: Option<Compression>,
+    /// Size, digest and truncation of the full stderr stream (absent for
+    /// pipelines, whose stages each record their own)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stderr_info: Option<StreamInfo>,
+    /// With --timeline, stdout and stderr as they were read, in order, in
+    /// place of `stdout` and `stderr`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub timeline: Vec<TimelineEvent>,
+    /// Files holding output that was not valid UTF-8 or was compressed, with
+    /// `--binary-output sidecar`, or all of it, with --split-output
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sidecars: Vec<Sidecar>,
+    /// Object store holding output larger than --objects-above, and the
+    /// declared outputs, each under its SHA-256
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub object_store: Option<String>,
+    /// Number of secrets replaced with `[REDACTED:<name>]` in the command
+    /// line, arguments and output
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub redactions: u64,
+    /// Secrets masked with `rcpt redact` after the receipt was written, in
+    /// the order they were
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub later_redactions: Vec<RedactionRecord>,
+    /// Start time (ISO 8601 timestamp)
+    pub start_time: DateTime<Utc>,
+    /// End time (ISO 8601 timestamp)
+    pub end_time: DateTime<Utc>,
+    /// Duration in milliseconds
+    pub duration_ms: u64,
+    /// How the wall clock disagreed with `duration_ms`, when the host clock
+    /// was stepped during the run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clock_anomaly: Option<ClockAnomaly>,
+    /// CPU, memory and I/O used by the command and all its descendants,
+    /// from its cgroup (Linux, cgroup v2) or job object (Windows)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resources: Option<ResourceUsage>,
+    /// Every attempt made when retries were enabled, in order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attempts: Vec<Attempt>,
+    /// Repeated runs and duration statistics, when run with `rcpt bench`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub benchmark: Option<Benchmark>,
+    /// Why the command was run, for `rcpt watch` and `rcpt schedule`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trigger: Option<Trigger>,
+}
+
+fn first_version() -> u32 {
+    1
+}
+
+impl Receipt {
+    /// Whether the command ran to completion with a zero exit code
+    pub fn succeeded(&self) -> bool {
+        self.status == Status::Ok
+    }
+
+    /// The command and its arguments, joined by spaces
+    pub fn command_line(&self) -> String {
+        std::iter::once(self.command.as_str())
+            .chain(self.args.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// How the run ended: its exit code, the signal that ended it, or else
+    /// its status
+    pub fn outcome(&self) -> String {
+        match (self.exit_code, &self.terminated_by) {
+            (Some(code), _) => format!("exit code {}", code),
+            (None, Some(signal)) => signal.name.clone(),
+            (None, None) => self.status.as_str().to_string(),
+        }
+    }
+
+    /// SHA-256 of the whole of `stdout` or `stderr`, as recorded, or of what
+    /// the receipt at `path` kept of it if it recorded none
+    pub fn stream_sha256(&self, stream: &str, path: &Path) -> Result<String> {
+        let info = match stream {
+            "stdout" => &self.stdout_info,
+            _ => &self.stderr_info,
+        };
+        match info {
+            Some(info) => Ok(info.sha256.clone()),
+            None => Ok(digest::sha256_hex(&self.written(stream, path)?)),
+        }
+    }
+
+    /// The stored bytes of `stdout` or `stderr`, however they are encoded,
+    /// compressed or kept beside the receipt at `path`
+    pub fn output(&self, stream: &str, path: &Path) -> Result<Vec<u8>> {
+        let (text, encoding, compression) = match stream {
+            "stdout" => (&self.stdout, self.stdout_encoding, &self.stdout_compression),
+            _ => (&self.stderr, self.stderr_encoding, &self.stderr_compression),
+        };
+        let stored = match encoding {
+            Encoding::Utf8 => return Ok(text.as_bytes().to_vec()),
+            Encoding::Base64 => return base64::decode(text),
+            Encoding::Zstd => base64::decode(text)?,
+            Encoding::Object => {
+                let store = self.object_dir(path).context(format!(
+                    "{} is in an object store the receipt does not name",
+                    stream
+                ))?;
+                return objects::read(&store, text);
+            }
+            Encoding::Sidecar => {
+                let sidecar = self
+                    .sidecars
+                    .iter()
+                    .find(|sidecar| sidecar.stream == stream)
+                    .context(format!(
+                        "{} is in a sidecar the receipt does not list",
+                        stream
+                    ))?;
+                age::read(&sidecar.locate(path))?
+            }
+        };
+        match compression {
+            Some(_) => zstd::decompress(&stored),
+            None => Ok(stored),
+        }
+    }
+
+    /// The object store of the receipt at `path`: the `object_store` it
+    /// names, relative to its directory unless absolute, or for a receipt
+    /// unpacked from a bundle, the bundle's `objects/`, the store having
+    /// stayed behind on the machine the bundle was made on
+    pub fn object_dir(&self, path: &Path) -> Option<PathBuf> {
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let named = dir.join(self.object_store.as_deref()?);
+        if named.is_dir() {
+            return Some(named);
+        }
+        // A bundle holds each receipt under receipts/<id>/, beside objects/
+        let bundle = Some(dir)
+            .filter(|dir| dir.file_name() == Some(OsStr::new(&self.id)))
+            .and_then(Path::parent)
+            .filter(|receipts| receipts.file_name() == Some(OsStr::new("receipts")))
+            .and_then(Path::parent);
+        match bundle.map(|bundle| bundle.join("objects")) {
+            Some(objects) if objects.is_dir() => Some(objects),
+            _ => Some(named),
+        }
+    }
+
+    /// What the command wrote to `stdout` or `stderr`: its stored bytes, or
+    /// its chunks of the timeline, put back together, if it recorded one
+    pub fn written(&self, stream: &str, path: &Path) -> Result<Vec<u8>> {
+        if self.timeline.is_empty() {
+            return self.output(stream, path);
+        }
+        let mut bytes = Vec::new();
+        for event in self.timeline.iter().filter(|event| event.stream == stream) {
+            match event.encoding {
+                Encoding::Base64 => bytes.extend(base64::decode(&event.chunk)?),
+                _ => bytes.extend_from_slice(event.chunk.as_bytes()),
+            }
+        }
+        Ok(bytes)
+    }
+}
+
+/// Overall outcome of a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    /// The command exited with code 0
+    Ok,
+    /// The command exited nonzero or was terminated by a signal
+    Failed,
+    /// rcpt killed the command for exceeding its timeout
+    TimedOut,
+    /// rcpt was interrupted and forwarded the signal to the command
+    Interrupted,
+    /// The kernel's out-of-memory killer killed the command
+    OomKilled,
+    /// The command exited 0 but left a declared output missing, with
+    /// `--require-outputs`
+    MissingOutputs,
+    /// Dry run: the command was resolved but not executed
+    Planned,
+    /// The command was started with `--detach` and has not finished yet
+    Running,
+    /// The detached rcpt died before the command finished, or the record
+    /// `rcpt import` read did not say how it ended, so its outcome is
+    /// unknown
+    Lost,
+}
+
+impl Status {
+    pub fn of(exit_code: Option<i32>, timed_out: bool, interrupted: bool) -> Self {
+        if interrupted {
+            Status::Interrupted
+        } else if timed_out {
+            Status::TimedOut
+        } else if exit_code == Some(0) {
+            Status::Ok
+        } else {
+            Status::Failed
+        }
+    }
+
+    /// Name of the status as it appears in receipts
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Status::Ok => "ok",
+            Status::Failed => "failed",
+            Status::TimedOut => "timed_out",
+            Status::Interrupted => "interrupted",
+            Status::OomKilled => "oom_killed",
+            Status::MissingOutputs => "missing_outputs",
+            Status::Planned => "planned",
+            Status::Running => "running",
+            Status::Lost => "lost",
+        }
+    }
+}
+
+/// A command string interpreted by a shell
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShellInvocation {
+    /// Shell program the command string was passed to
+    pub program: String,
+    /// Original command string
+    pub command: String,
+}
+
+/// One command of a pipeline
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Stage {
+    /// Command that was executed
+    pub command: String,
+    /// Command arguments
+    pub args: Vec<String>,
+    /// Resolved path of the executable, if it could be found
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub executable: Option<String>,
+    /// Exit code of the stage (None if terminated by signal)
+    pub exit_code: Option<i32>,
+    /// Signal that terminated the stage, when it did not exit (Unix only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub terminated_by: Option<TerminatedBy>,
+    /// Outcome of the stage on its own
+    pub status: Status,
+    /// Number of bytes the stage wrote to stdout
+    pub stdout_bytes: u64,
+    /// Number of bytes the stage wrote to stderr
+    pub stderr_bytes: u64,
+    /// SHA-256 digest of everything the stage wrote to stdout, or for the
+    /// last stage, of the pipeline's stdout
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stdout_sha256: Option<String>,
+    /// SHA-256 digest of everything the stage wrote to stderr
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stderr_sha256: Option<String>,
+    /// Time from the start of the pipeline until the stage exited, in milliseconds
+    pub duration_ms: u64,
+}
+
+/// Account a command ran as
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserRecord {
+    /// User name, if the uid has a passwd entry
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Effective user id
+    pub uid: u32,
+    /// Effective primary group id
+    pub gid: u32,
+}
+
+/// Description of the environment capture policy applied to a receipt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnvPolicyReport {
+    /// Glob patterns of variable names that were eligible for recording
+    pub allow: Vec<String>,
+    /// Glob patterns of variable names that were never recorded
+    pub deny: Vec<String>,
+    /// How recorded values are stored
+    pub values: EnvValues,
+    /// With `hashed` values, the random key they were HMAC'd under, as hex.
+    /// Anyone holding the receipt can check a guessed value with it, so
+    /// hashing only hides values too long to guess
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub salt: Option<String>,
+    /// Names of allowed variables withheld by a deny pattern
+    pub denied: Vec<String>,
+    /// Number of variables not matched by any allow pattern
+    pub unmatched: usize,
+}
+
+/// An env file loaded into the command's environment
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnvFileRecord {
+    /// Path of the file as given
+    pub path: String,
+    /// Absolute path of the file, with symlinks resolved, so it can be
+    /// found again from any directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub canonical_path: Option<String>,
+    /// SHA-256 digest of the file's contents
+    pub sha256: String,
+    /// Names of the variables it set (never their values)
+    pub names: Vec<String>,
+}
+
+/// Resources used by the command and every process it started, from the
+/// cgroup (Linux) or job object (Windows) it ran in
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResourceUsage {
+    /// Cgroup the command ran in, relative to the cgroup v2 root
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cgroup: Option<String>,
+    /// CPU time spent in user mode, in milliseconds
+    pub cpu_user_ms: f64,
+    /// CPU time spent in the kernel, in milliseconds
+    pub cpu_system_ms: f64,
+    /// Highest memory usage, in bytes (on Linux, needs the memory controller)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_peak_bytes: Option<u64>,
+    /// Bytes read: from storage on Linux and macOS (from the io controller,
+    /// else the command's own counters), by any I/O on Windows
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub io_read_bytes: Option<u64>,
+    /// Bytes written: to storage on Linux and macOS (from the io controller,
+    /// else the command's own counters), by any I/O on Windows
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub io_write_bytes: Option<u64>,
+    /// Number of processes started, including the command (Windows only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub processes: Option<u64>,
+    /// Largest resident set size of any single process, in bytes, from
+    /// wait4 (Unix only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_rss_bytes: Option<u64>,
+    /// Page faults served without I/O, from wait4 (Unix only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minor_page_faults: Option<u64>,
+    /// Page faults that needed I/O, from wait4 (Unix only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub major_page_faults: Option<u64>,
+    /// Bytes passed through read calls, including those served from the
+    /// page cache, pipes and sockets (Linux only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub io_syscall_read_bytes: Option<u64>,
+    /// Bytes passed through write calls (Linux only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub io_syscall_write_bytes: Option<u64>,
+}
+
+/// Standard input provided to the command
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StdinRecord {
+    /// Stdin mode: inherit, null, capture or file
+    pub mode: String,
+    /// File stdin was read from, in file mode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Size of the stdin file, in file mode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_size: Option<u64>,
+    /// Number of bytes forwarded to the command (in file mode, the number
+    /// it read)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<u64>,
+    /// SHA-256 digest of the bytes forwarded to the command
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Leading bytes of the forwarded input
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preview: Option<String>,
+}
+
+fn is_zero(count: &u64) -> bool {
+    *count == 0
+}
+
+/// How a captured output stream is stored in the receipt
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    /// As text
+    #[default]
+    Utf8,
+    /// Base64 of the raw bytes, which were not valid UTF-8
+    Base64,
+    /// Base64 of the zstd-compressed bytes, which were larger than
+    /// --compress-above
+    Zstd,
+    /// In a sidecar file listed in `sidecars`; the field is empty
+    Sidecar,
+    /// In the `object_store`, under the SHA-256 the field holds
+    Object,
+}
+
+/// How a stored output stream was compressed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Compression {
+    /// Compression format
+    pub algorithm: CompressionAlgorithm,
+    /// Size of the output before compression
+    pub original_bytes: u64,
+    /// SHA-256 digest of the output before compression
+    pub original_sha256: String,
+    /// Size of the compressed output
+    pub compressed_bytes: u64,
+}
+
+/// A compression format for stored output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompressionAlgorithm {
+    /// A zstd frame, as the `zstd` tool reads and writes
+    Zstd,
+}
+
+impl Encoding {
+    fn is_utf8(&self) -> bool {
+        *self == Encoding::Utf8
+    }
+}
+
+/// A chunk of output as it was read, for --timeline
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelineEvent {
+    /// Milliseconds since the command started
+    pub t_offset_ms: f64,
+    /// Which stream it was read from, `stdout` or `stderr`
+    pub stream: String,
+    /// Index of the pipeline stage that wrote it, for stderr of `rcpt pipe`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stage: Option<usize>,
+    /// The bytes read
+    pub chunk: String,
+    /// How `chunk` is stored
+    #[serde(default, skip_serializing_if = "Encoding::is_utf8")]
+    pub encoding: Encoding,
+}
+
+/// Raw output written next to the receipt instead of into it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sidecar {
+    /// Which stream it holds, `stdout` or `stderr`
+    pub stream: String,
+    /// Path of the file, relative to the receipt's directory
+    pub path: String,
+    /// SHA-256 digest of the output in the file (before compression, and
+    /// encryption with --encrypt-to)
+    pub sha256: String,
+    /// Size of the output in bytes
+    pub size_bytes: u64,
+    /// The start of the output, as text, with --split-output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preview: Option<String>,
+}
+
+impl Sidecar {
+    /// Where the file is, for the receipt at `receipt`: always beside it,
+    /// by file name, so it resolves the same from any directory (older
+    /// receipts recorded the path relative to where `rcpt run` ran)
+    pub fn locate(&self, receipt: &Path) -> PathBuf {
+        let name = Path::new(&self.path).file_name().unwrap_or_default();
+        receipt.with_file_name(name)
+    }
+}
+
+/// Which agent produced a receipt, and the run and task it was part of
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActorRecord {
+    /// Name of the agent, from `--agent` or `$RCPT_AGENT`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent: Option<String>,
+    /// Id of the agent's run, from `--run-id` or `$RCPT_RUN_ID`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<String>,
+    /// Id of the task within the run, from `--task-id` or `$RCPT_TASK_ID`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task_id: Option<String>,
+}
+
+/// The signal a process died from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TerminatedBy {
+    /// Signal number
+    pub signal: i32,
+    /// Conventional name of the signal, e.g. `SIGSEGV`
+    pub name: String,
+    /// Whether the kernel reported dumping core
+    pub core_dumped: bool,
+}
+
+/// Details of a captured output stream, describing the full stream even
+/// when only part of it is stored in the receipt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamInfo {
+    /// Total number of bytes the command wrote to the stream
+    pub total_bytes: u64,
+    /// Whether the stored content omits part of the stream
+    pub truncated: bool,
+    /// SHA-256 digest of the full stream
+    pub sha256: String,
+}
+
+/// Disagreement between the wall clock, which `start_time` and `end_time`
+/// come from, and the monotonic clock `duration_ms` is measured with, as
+/// when NTP or an administrator steps the host clock during a run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClockAnomaly {
+    /// `end_time` minus `start_time`, negative if the clock went back past
+    /// the start
+    pub wall_clock_ms: i64,
+    /// Time that actually passed, by the monotonic clock
+    pub monotonic_ms: u64,
+    /// How far the wall clock was stepped: forward if positive, back if
+    /// negative
+    pub step_ms: i64,
+    /// Whether `end_time` is before `start_time`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub end_before_start: bool,
+}
+
+impl ClockAnomaly {
+    /// Compare the wall clock's account of a run from `start` to `end` with
+    /// its `duration` by the monotonic clock, allowing for the time between
+    /// reading the two clocks
+    pub fn detect(start: DateTime<Utc>, end: DateTime<Utc>, duration: Duration) -> Option<Self> {
+        let wall_clock_ms = (end - start).num_milliseconds();
+        let monotonic_ms = duration.as_millis() as u64;
+        let step_ms = wall_clock_ms - monotonic_ms as i64;
+        let end_before_start = end < start;
+        if step_ms.unsigned_abs() < CLOCK_TOLERANCE_MS && !end_before_start {
+            return None;
+        }
+        Some(ClockAnomaly {
+            wall_clock_ms,
+            monotonic_ms,
+            step_ms,
+            end_before_start,
+        })
+    }
+}
+
+/// Outcome of a single attempt of a retried command
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attempt {
+    /// Attempt number, starting at 1
+    pub attempt: u32,
+    /// Exit code of the attempt (None if terminated by signal)
+    pub exit_code: Option<i32>,
+    /// Whether the attempt was killed for exceeding its timeout
+    #[serde(default)]
+    pub timed_out: bool,
+    /// Start time (ISO 8601 timestamp)
+    pub start_time: DateTime<Utc>,
+    /// Duration in milliseconds
+    pub duration_ms: u64,
+}
+
+/// Why a command was run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Trigger {
+    /// What started the run
+    pub reason: TriggerReason,
+    /// Files that were added, modified or removed, for change triggers
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub paths: Vec<String>,
+    /// Time the run was scheduled for, for schedule triggers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scheduled_time: Option<DateTime<Utc>>,
+    /// Scheduled times since the previous run that passed without a run
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub missed: Vec<DateTime<Utc>>,
+}
+
+impl Trigger {
+    pub fn new(reason: TriggerReason) -> Self {
+        Trigger {
+            reason,
+            paths: Vec::new(),
+            scheduled_time: None,
+            missed: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TriggerReason {
+    /// The first run, when watching began
+    Start,
+    /// Watched files changed
+    Change,
+    /// The run's scheduled time arrived
+    Schedule,
+}
+
+/// Read a receipt written by this or an earlier version of rcpt, upgraded
+/// to the current version
+pub fn read_receipt(path: &Path) -> Result<Receipt> {
+    let contents = read_json(path)?;
+    parse_receipt(&contents).context(format!("Failed to parse receipt {}", path.display()))
+}
+
+/// Directories rcpt keeps files in that are not receipts of their own: the
+/// spool of `sink::DEFAULT_SPOOL`, holding copies of receipts waiting to be
+/// sent, and the object store of `objects::DEFAULT_DIR`
+const SKIPPED_DIRS: [&str; 2] = ["spool", "objects"];
+
+/// Every `.json` file under `dirs`, searched recursively, outside the
+/// spool and object store
+pub fn json_files(dirs: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for dir in dirs {
+        let listing = fs::read_dir(dir).context(format!("Failed to read {}", dir.display()))?;
+        collect_json(listing, &mut paths);
+    }
+    Ok(paths)
+}
+
+/// Add the `.json` files in `listing` and below to `paths`. A directory
+/// that cannot be read is skipped with a warning, rather than hiding every
+/// other receipt
+fn collect_json(listing: fs::ReadDir, paths: &mut Vec<PathBuf>) {
+    for entry in listing.flatten() {
+        let path = entry.path();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            let name = entry.file_name();
+            if SKIPPED_DIRS.iter().any(|skipped| name == *skipped) {
+                continue;
+            }
+            match fs::read_dir(&path) {
+                Ok(listing) => collect_json(listing, paths),
+                Err(err) => eprintln!("rcpt: skipping {}: {}", path.display(), err),
+            }
+        } else if path.extension().is_some_and(|ext| ext == "json") {
+            paths.push(path);
+        }
+    }
+}
+
+/// The JSON text of the receipt at `path`, decrypted if it was written with
+/// --encrypt-to
+pub fn read_json(path: &Path) -> Result<String> {
+    let bytes = age::read(path).context(format!("Failed to read receipt {}", path.display()))?;
+    String::from_utf8(bytes).context(format!("Receipt {} is not UTF-8", path.display()))
+}
+
+/// Parse a receipt of any version up to the current one
+pub fn parse_receipt(json: &str) -> Result<Receipt> {
+    let value = upgrade(serde_json::from_str(json)?)?;
+    let mut receipt: Receipt = serde_json::from_value(value)?;
+    receipt.receipt_version = RECEIPT_VERSION;
+    Ok(receipt)
+}
+
+/// A receipt's JSON, of any version up to the current one, changed to read
+/// as the current version
+pub fn upgrade(mut value: serde_json::Value) -> Result<serde_json::Value> {
+    let object = value
+        .as_object_mut()
+        .context("receipt is not a JSON object")?;
+    let version = match object.get("receipt_version") {
+        Some(version) => version
+            .as_u64()
+            .filter(|&version| version >= 1)
+            .context("receipt_version is not a positive integer")?,
+        None => {
+            // Receipts from before the field existed may lack `status`,
+            // which follows from how the command ended
+            if !object.contains_key("status") {
+                let field = |name: &str| object.get(name).unwrap_or(&serde_json::Value::Null);
+                let status = Status::of(
+                    field("exit_code").as_i64().map(|code| code as i32),
+                    field("timed_out").as_bool() == Some(true),
+                    !field("interrupt_signal").is_null(),
+                );
+                object.insert("status".into(), status.as_str().into());
+            }
+            first_version() as u64
+        }
+    };
+    if version > RECEIPT_VERSION as u64 {
+        bail!(
+            "receipt version {} is newer than this rcpt supports (up to {}); upgrade rcpt to read it",
+            version,
+            RECEIPT_VERSION
+        );
+    }
+    // Upgrades from each older version to the next go here; so far later
+    // versions only added values to enumerated fields, which older receipts
+    // do not hold
+    Ok(value)
+}
+
+/// SHA-256 of the canonical form of a receipt's JSON, leaving out its
+/// `receipt_digest`, `signature` and `timestamp`
+pub fn receipt_digest(value: &serde_json::Value) -> String {
+    let mut value = value.clone();
+    if let Some(object) = value.as_object_mut() {
+        object.remove("receipt_digest");
+        object.remove("signature");
+        object.remove("timestamp");
+    }
+    digest::sha256_hex(canonical::to_string(&value).as_bytes())
+}
+
+/// The receipt's JSON, with its `receipt_digest`
+pub fn to_value(receipt: &Receipt) -> Result<serde_json::Value> {
+    let mut value = serde_json::to_value(receipt).context("Failed to serialize receipt to JSON")?;
+    let digest = receipt_digest(&value);
+    if let Some(object) = value.as_object_mut() {
+        object.insert("receipt_digest".into(), digest.into());
+    }
+    Ok(value)
+}
+
+/// Write a receipt as canonical JSON, with its `receipt_digest`. The file
+/// is replaced whole, never left half-written; with `fsync` it is on disk
+/// before this returns
+pub fn write_receipt(path: &Path, receipt: &Receipt, fsync: bool) -> Result<()> {
+    let json = canonical::to_string(&to_value(receipt)?);
+    write_file(path, json.as_bytes(), fsync)
+}
+
+/// Write a receipt as `write_receipt` does, but encrypted with age to
+/// `recipients`
+pub fn write_encrypted_receipt(
+    path: &Path,
+    receipt: &Receipt,
+    recipients: &[Recipient],
+    fsync: bool,
+) -> Result<()> {
+    let json = canonical::to_string(&to_value(receipt)?);
+    write_file(path, &age::encrypt(json.as_bytes(), recipients)?, fsync)
+}
+
+fn write_file(path: &Path, contents: &[u8], fsync: bool) -> Result<()> {
+    // Ensure the parent directory exists
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context(format!(
+            "Failed to create parent directories for {}",
+            path.display()
+        ))?;
+    }
+
+    atomic::write(path, contents, fsync)
+        .context(format!("Failed to write receipt to {}", path.display()))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A receipt as rcpt wrote them before `receipt_version` and `status`
+    /// existed, from the original README
+    const BASELINE: &str = r#"{
+  "command": "echo",
+  "args": ["Hello, world!"],
+  "exit_code": 0,
+  "stdout": "Hello, world!\n",
+  "stderr": "",
+  "start_time": "2026-02-16T01:00:00.000000000Z",
+  "end_time": "2026-02-16T01:00:00.001000000Z",
+  "duration_ms": 1
+}"#;
+
+    #[test]
+    fn reads_baseline_receipts() {
+        let receipt = parse_receipt(BASELINE).unwrap();
+        assert_eq!(receipt.receipt_version, RECEIPT_VERSION);
+        assert_eq!(receipt.status, Status::Ok);
+        assert_eq!(receipt.command_line(), "echo Hello, world!");
+        assert_eq!(receipt.stdout, "Hello, world!\n");
+        assert_eq!(receipt.duration_ms, 1);
+    }
+
+    #[test]
+    fn derives_the_status_of_baseline_receipts() {
+        let status = |fields: &str| {
+            let json = BASELINE.replace("\"exit_code\": 0", fields);
+            parse_receipt(&json).unwrap().status
+        };
+        assert_eq!(status("\"exit_code\": 2"), Status::Failed);
+        assert_eq!(status("\"exit_code\": null"), Status::Failed);
+        assert_eq!(
+            status("\"exit_code\": null, \"timed_out\": true"),
+            Status::TimedOut
+        );
+        assert_eq!(
+            status("\"exit_code\": 130, \"interrupt_signal\": 2"),
+            Status::Interrupted
+        );
+        // A recorded status is kept
+        assert_eq!(
+            status("\"exit_code\": 0, \"status\": \"missing_outputs\""),
+            Status::MissingOutputs
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn skips_unreadable_directories() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("rcpt-unreadable-{}", std::process::id()));
+        let locked = dir.join("locked");
+        fs::create_dir_all(&locked).unwrap();
+        fs::write(dir.join("a.json"), "{}").unwrap();
+        fs::write(locked.join("b.json"), "{}").unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+        // Root reads it regardless
+        let readable = fs::read_dir(&locked).is_ok();
+        let found = json_files(std::slice::from_ref(&dir));
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+        match readable {
+            true => assert_eq!(found.unwrap().len(), 2),
+            false => assert_eq!(found.unwrap(), vec![dir.join("a.json")]),
+        }
+        assert!(json_files(&[dir.join("missing")]).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        let versioned = |version: &str| {
+            let fields = format!("{{\"receipt_version\": {}, \"status\": \"ok\",", version);
+            parse_receipt(&BASELINE.replacen('{', &fields, 1))
+        };
+        assert!(versioned("1").is_ok());
+        assert!(versioned("0").is_err());
+        assert!(versioned("\"1\"").is_err());
+        let newer = versioned(&(RECEIPT_VERSION + 1).to_string()).unwrap_err();
+        assert!(newer.to_string().contains("newer than this rcpt supports"));
+    }
+}
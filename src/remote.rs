@@ -0,0 +1,196 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::process::{Command, Stdio};
+
+use crate::exec::Invocation;
+
+/// Exit code ssh uses for its own errors, indistinguishable from a remote
+/// command exiting 255
+const SSH_ERROR_EXIT_CODE: i32 = 255;
+
+/// Where and what an `rcpt ssh` command ran
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteRecord {
+    /// Destination as given, e.g. `deploy@web1`
+    pub destination: String,
+    /// User ssh logged in as, after applying ssh_config
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// Address ssh connected to, after applying ssh_config
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    /// Port ssh connected to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    /// Hostname reported by the remote machine
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    /// Algorithm of the host key the server presented
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host_key_algorithm: Option<String>,
+    /// Fingerprint of the host key the server presented, as `SHA256:...`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host_key_fingerprint: Option<String>,
+    /// Command line run by the remote shell
+    pub command: String,
+    /// Exit code of the remote command (None if ssh itself failed, or the
+    /// command exited 255, which ssh cannot tell apart)
+    pub exit_code: Option<i32>,
+}
+
+/// A host to run commands on over ssh
+pub struct Remote {
+    /// Destination passed to ssh, `[user@]host`
+    pub destination: String,
+    /// `-o` options passed to every ssh connection
+    pub options: Vec<String>,
+}
+
+impl Remote {
+    /// What is known about running `command` on the destination without
+    /// connecting: ssh_config resolution only
+    pub fn plan(&self, command: &str) -> Result<RemoteRecord> {
+        let output = self
+            .ssh()
+            .arg("-G")
+            .arg(&self.destination)
+            .stdin(Stdio::null())
+            .output()
+            .context("Failed to run ssh")?;
+        if !output.status.success() {
+            bail!(
+                "ssh could not resolve {}: {}",
+                self.destination,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        let config = String::from_utf8_lossy(&output.stdout);
+        let setting = |key: &str| {
+            config.lines().find_map(|line| {
+                let (name, value) = line.split_once(' ')?;
+                (name == key).then(|| value.to_string())
+            })
+        };
+        Ok(RemoteRecord {
+            destination: self.destination.clone(),
+            user: setting("user"),
+            address: setting("hostname"),
+            port: setting("port").and_then(|port| port.parse().ok()),
+            hostname: None,
+            host_key_algorithm: None,
+            host_key_fingerprint: None,
+            command: command.to_string(),
+            exit_code: None,
+        })
+    }
+
+    /// Connect once to learn the remote hostname and the host key the
+    /// server presents, which `invocation` then holds the command's own
+    /// connection to
+    pub fn probe(&self, record: &mut RemoteRecord) -> Result<()> {
+        let output = self
+            .ssh()
+            .arg("-v")
+            .arg(&self.destination)
+            .arg("uname -n")
+            .stdin(Stdio::null())
+            .output()
+            .context("Failed to run ssh")?;
+        let debug = String::from_utf8_lossy(&output.stderr);
+        if !output.status.success() {
+            let reason = debug
+                .lines()
+                .rfind(|line| !line.starts_with("debug"))
+                .unwrap_or("ssh failed");
+            bail!("Failed to connect to {}: {}", self.destination, reason);
+        }
+        record.hostname = Some(String::from_utf8_lossy(&output.stdout).trim().to_string());
+        // e.g. "debug1: Server host key: ssh-ed25519 SHA256:..."
+        if let Some(key) = debug
+            .lines()
+            .find_map(|line| line.split_once("Server host key: ").map(|(_, key)| key))
+        {
+            let mut fields = key.split_whitespace();
+            record.host_key_algorithm = fields.next().map(str::to_string);
+            record.host_key_fingerprint = fields.next().map(str::to_string);
+        }
+        Ok(())
+    }
+
+    /// The local ssh invocation that runs `command` remotely, with a
+    /// terminal allocated there if `tty` is set. Once `probe` has recorded
+    /// the host key, the connection only accepts a key of that type already
+    /// in known_hosts, as the recorded one was, unless the `-o` options say
+    /// otherwise (ssh takes the first value given)
+    pub fn invocation(&self, command: &str, tty: bool, record: &RemoteRecord) -> Invocation {
+        let mut argv: Vec<OsString> = vec!["ssh".into()];
+        let pinned = record.host_key_algorithm.as_deref().map(|algorithm| {
+            [
+                "StrictHostKeyChecking=yes".to_string(),
+                format!("HostKeyAlgorithms={}", host_key_algorithms(algorithm)),
+            ]
+        });
+        for option in self.options.iter().chain(pinned.iter().flatten()) {
+            argv.push("-o".into());
+            argv.push(option.into());
+        }
+        if tty {
+            argv.push("-t".into());
+        }
+        argv.push(self.destination.clone().into());
+        argv.push(command.into());
+        Invocation::direct(argv)
+    }
+
+    fn ssh(&self) -> Command {
+        let mut ssh = Command::new("ssh");
+        for option in &self.options {
+            ssh.arg("-o").arg(option);
+        }
+        ssh
+    }
+}
+
+/// The `HostKeyAlgorithms` that verify a host key of type `key_type`: RSA
+/// keys sign with SHA-2 algorithms named apart from the key type
+fn host_key_algorithms(key_type: &str) -> String {
+    match key_type {
+        "ssh-rsa" => "rsa-sha2-512,rsa-sha2-256".to_string(),
+        "ssh-rsa-cert-v01@openssh.com" => {
+            "rsa-sha2-512-cert-v01@openssh.com,rsa-sha2-256-cert-v01@openssh.com".to_string()
+        }
+        other => other.to_string(),
+    }
+}
+
+/// The remote exit code given ssh's own exit code
+pub fn remote_exit_code(ssh_exit_code: Option<i32>) -> Option<i32> {
+    ssh_exit_code.filter(|&code| code != SSH_ERROR_EXIT_CODE)
+}
+
+/// The command line for the remote shell: a `--shell` string verbatim, or
+/// `command` with every argument quoted so the remote side sees the same argv
+pub fn remote_command(shell: Option<&str>, command: &[OsString]) -> String {
+    match shell {
+        Some(script) => script.to_string(),
+        None => command
+            .iter()
+            .map(|arg| shell_quote(&arg.to_string_lossy()))
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
+
+/// Quote `arg` for a POSIX shell, leaving plain words as they are
+fn shell_quote(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,@%+".contains(c));
+    if plain {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
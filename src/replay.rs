@@ -0,0 +1,165 @@
+use anyhow::{bail, Result};
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::container::ContainerSpec;
+use crate::env::{EnvFile, EnvPolicy, EnvValues};
+use crate::exec::{ExecOptions, Invocation};
+use crate::receipt::Receipt;
+use crate::stdin::StdinMode;
+
+/// The command `original` ran, argument for argument, as it was recorded
+pub fn invocation(original: &Receipt) -> Result<Invocation> {
+    if !original.stages.is_empty() {
+        bail!(
+            "Receipt {} is of a pipeline, which cannot be replayed",
+            original.id
+        );
+    }
+    if original.remote.is_some() {
+        bail!(
+            "Receipt {} is of an `rcpt ssh` run, which cannot be replayed",
+            original.id
+        );
+    }
+    if original.redactions > 0 && original.command_line().contains("[REDACTED:") {
+        bail!(
+            "Receipt {} had secrets redacted from its command line, so it cannot be replayed",
+            original.id
+        );
+    }
+    let mut argv = vec![OsString::from(&original.command)];
+    argv.extend(original.args.iter().map(OsString::from));
+    Ok(Invocation::direct(argv))
+}
+
+/// Where the replay's stdin comes from unless --stdin says otherwise: the
+/// file `original` read, if it read one, or else nothing
+pub fn stdin(original: &Receipt) -> StdinMode {
+    match &original.stdin {
+        Some(record) if record.mode == "file" => match &record.path {
+            Some(path) => StdinMode::File(PathBuf::from(path)),
+            None => StdinMode::Null,
+        },
+        _ => StdinMode::Null,
+    }
+}
+
+/// Set up `opts` to run as `original` did, where no option given to
+/// `rcpt replay` says otherwise: in its working directory and container,
+/// with its env files loaded again, under its environment policy and,
+/// for a run from an empty environment, with the variables it recorded
+pub fn configure(original: &Receipt, opts: &mut ExecOptions) -> Result<()> {
+    if opts.cwd.is_none() && !original.cwd.is_empty() {
+        opts.cwd = Some(PathBuf::from(&original.cwd));
+    }
+    let cwd = Path::new(&original.cwd);
+    if opts.env_policy.is_none() {
+        opts.env_policy = original.env_policy.as_ref().map(|report| EnvPolicy {
+            allow: report.allow.clone(),
+            deny: report.deny.clone(),
+            values: report.values,
+        });
+    }
+    if opts.env_files.is_empty() {
+        for record in &original.env_files {
+            // Where it was loaded from, if that was recorded, or else as
+            // given, from the recorded working directory
+            let path = match &record.canonical_path {
+                Some(path) => PathBuf::from(path),
+                None => cwd.join(&record.path),
+            };
+            let file = EnvFile::load(&path)?;
+            if file.sha256 != record.sha256 {
+                eprintln!(
+                    "rcpt: env file {} has changed since {} ran",
+                    record.path, original.id
+                );
+            }
+            opts.env_files.push(file);
+        }
+    }
+    if original.hermetic && !opts.clear_env {
+        opts.clear_env = true;
+        if let (Some(env), Some(report)) = (&original.env, &original.env_policy) {
+            if report.values == EnvValues::Hashed {
+                eprintln!(
+                    "rcpt: {} recorded only digests of its environment, so the replay starts without it",
+                    original.id
+                );
+            } else {
+                let given: Vec<String> =
+                    opts.env_vars.iter().map(|(name, _)| name.clone()).collect();
+                opts.env_vars.splice(
+                    0..0,
+                    env.iter()
+                        .filter(|(name, _)| !given.contains(name))
+                        .map(|(name, value)| (name.clone(), value.clone())),
+                );
+            }
+            if !report.denied.is_empty() {
+                eprintln!(
+                    "rcpt: {} did not record {}, so the replay starts without them",
+                    original.id,
+                    report.denied.join(", ")
+                );
+            }
+        }
+    }
+    if opts.container.is_none() {
+        if let Some(container) = &original.container {
+            // The image that ran, by digest if it had one, and the mounts
+            // other than the working directory's
+            let image = container
+                .digest
+                .clone()
+                .or_else(|| container.image_id.clone())
+                .unwrap_or_else(|| container.image.clone());
+            opts.container = Some(ContainerSpec {
+                image,
+                runtime: Some(container.runtime),
+                mounts: container.mounts.iter().skip(1).cloned().collect(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Whether `replay`, at `replay_path`, ended as `original` did and wrote the
+/// same stdout and stderr, writing each comparison to `report`
+pub fn check(
+    original_path: &Path,
+    original: &Receipt,
+    replay_path: &Path,
+    replay: &Receipt,
+    report: &mut impl Write,
+) -> Result<bool> {
+    let mut same = true;
+    let (was, now) = (original.outcome(), replay.outcome());
+    match was == now {
+        true => writeln!(report, "outcome: ok ({})", now)?,
+        false => {
+            writeln!(report, "outcome: differs ({} → {})", was, now)?;
+            same = false;
+        }
+    }
+    for stream in ["stdout", "stderr"] {
+        let was = original.stream_sha256(stream, original_path)?;
+        let now = replay.stream_sha256(stream, replay_path)?;
+        match was == now {
+            true => writeln!(report, "{}: ok (sha256 {})", stream, &now[..12])?,
+            false => {
+                writeln!(
+                    report,
+                    "{}: differs (sha256 {} → {})",
+                    stream,
+                    &was[..12],
+                    &now[..12]
+                )?;
+                same = false;
+            }
+        }
+    }
+    Ok(same)
+}
@@ -0,0 +1,217 @@
+use anyhow::Result;
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use crate::export::escape;
+use crate::receipt::{Receipt, Status};
+use crate::units::format_size;
+
+/// How many of the last lines of each stream the page holds
+const OUTPUT_LINES: usize = 500;
+
+/// The narrowest a run's bar is drawn on the timeline, in percent of its
+/// width, so that short runs can still be seen and clicked
+const MIN_BAR: f64 = 0.4;
+
+const STYLE: &str = "\
+body { font: 14px/1.4 system-ui, sans-serif; margin: 2em auto; max-width: 72em; padding: 0 1em; color: #1f2328; }
+h1 { font-size: 1.5em; margin-bottom: 0.2em; }
+.summary { color: #59636e; margin-top: 0; }
+.timeline { border: 1px solid #d1d9e0; border-radius: 6px; padding: 0.5em; margin: 1em 0 2em; }
+.row { display: flex; align-items: center; height: 1.6em; }
+.label { flex: 0 0 18em; overflow: hidden; text-overflow: ellipsis; white-space: nowrap; font-family: ui-monospace, monospace; font-size: 12px; padding-right: 0.5em; }
+.track { position: relative; flex: 1; height: 1em; background: #f6f8fa; }
+.bar { position: absolute; top: 0; bottom: 0; border-radius: 2px; }
+details { border: 1px solid #d1d9e0; border-left-width: 6px; border-radius: 6px; margin: 0.5em 0; }
+summary { cursor: pointer; padding: 0.5em; font-family: ui-monospace, monospace; }
+summary .meta { color: #59636e; float: right; }
+.status { display: inline-block; min-width: 7em; font-weight: bold; }
+dl { display: grid; grid-template-columns: max-content 1fr; gap: 0.2em 1em; margin: 0.5em 1em; }
+dt { color: #59636e; }
+dd { margin: 0; font-family: ui-monospace, monospace; word-break: break-all; }
+h4 { margin: 1em 1em 0.3em; }
+pre { background: #f6f8fa; margin: 0 1em 1em; padding: 0.5em; overflow-x: auto; max-height: 40em; font-size: 12px; }
+.ok { border-left-color: #1a7f37; } .bar.ok { background: #1a7f37; } .ok .status { color: #1a7f37; }
+.failed { border-left-color: #d1242f; } .bar.failed { background: #d1242f; } .failed .status { color: #d1242f; }
+.stopped { border-left-color: #bc4c00; } .bar.stopped { background: #bc4c00; } .stopped .status { color: #bc4c00; }
+.pending { border-left-color: #9a6700; } .bar.pending { background: #d4a72c; } .pending .status { color: #9a6700; }
+";
+
+/// The class a run that ended with `status` is colored by
+fn class(status: Status) -> &'static str {
+    match status {
+        Status::Ok => "ok",
+        Status::Failed | Status::MissingOutputs => "failed",
+        Status::TimedOut | Status::Interrupted | Status::OomKilled | Status::Lost => "stopped",
+        Status::Planned | Status::Running => "pending",
+    }
+}
+
+/// Whether a run that ended with `status` failed or was stopped
+fn failed(status: Status) -> bool {
+    !matches!(status, Status::Ok | Status::Planned | Status::Running)
+}
+
+/// `receipts` as one static HTML page needing nothing else to be viewed: a
+/// summary, a timeline of when each run started and how long it took, and
+/// a section for each run, colored by its status, that expands to show its
+/// details and the end of its output. Failed runs' sections start expanded
+pub fn html(receipts: &[(PathBuf, Receipt)]) -> Result<String> {
+    let sessions: BTreeSet<&str> = receipts
+        .iter()
+        .filter_map(|(_, receipt)| receipt.session_id.as_deref())
+        .collect();
+    let title = match (sessions.len(), sessions.first()) {
+        (1, Some(session)) => format!("Session {}", session),
+        _ => "Receipts".to_string(),
+    };
+    let failures = receipts
+        .iter()
+        .filter(|(_, receipt)| failed(receipt.status))
+        .count();
+    let start = receipts.iter().map(|(_, receipt)| receipt.start_time).min();
+    let end = receipts.iter().map(|(_, receipt)| receipt.end_time).max();
+
+    let mut page = String::new();
+    let _ = writeln!(page, "<!DOCTYPE html>");
+    let _ = writeln!(page, "<html lang=\"en\">");
+    let _ = writeln!(page, "<head>");
+    let _ = writeln!(page, "<meta charset=\"utf-8\">");
+    let _ = writeln!(page, "<title>rcpt: {}</title>", escape(&title));
+    let _ = writeln!(page, "<style>\n{}</style>", STYLE);
+    let _ = writeln!(page, "</head>");
+    let _ = writeln!(page, "<body>");
+    let _ = writeln!(page, "<h1>{}</h1>", escape(&title));
+    let mut summary = match receipts.len() {
+        1 => format!("1 run, {} failed", failures),
+        runs => format!("{} runs, {} failed", runs, failures),
+    };
+    if let (Some(start), Some(end)) = (start, end) {
+        summary.push_str(&format!(
+            ", from {} to {}",
+            start.format("%Y-%m-%d %H:%M:%S UTC"),
+            end.format("%Y-%m-%d %H:%M:%S UTC")
+        ));
+    }
+    let _ = writeln!(page, "<p class=\"summary\">{}</p>", summary);
+
+    if let (Some(start), Some(end)) = (start, end) {
+        let span = (end - start).num_milliseconds().max(1) as f64;
+        let _ = writeln!(page, "<div class=\"timeline\">");
+        for (_, receipt) in receipts {
+            let offset = (receipt.start_time - start).num_milliseconds() as f64;
+            let left = offset / span * 100.0;
+            let width = (receipt.duration_ms as f64 / span * 100.0)
+                .max(MIN_BAR)
+                .min(100.0 - left);
+            let command = escape(&receipt.command_line());
+            let _ = writeln!(
+                page,
+                "<div class=\"row\"><span class=\"label\" title=\"{command}\">{command}</span>\
+                 <span class=\"track\"><a class=\"bar {}\" href=\"#r-{}\" \
+                 style=\"left: {:.2}%; width: {:.2}%\" title=\"{} {}ms\"></a></span></div>",
+                class(receipt.status),
+                escape(&receipt.id),
+                left,
+                width,
+                receipt.status.as_str(),
+                receipt.duration_ms,
+            );
+        }
+        let _ = writeln!(page, "</div>");
+    }
+
+    for (path, receipt) in receipts {
+        section(&mut page, path, receipt)?;
+    }
+    let _ = writeln!(page, "</body>");
+    let _ = writeln!(page, "</html>");
+    Ok(page)
+}
+
+/// The expandable section for the receipt at `path`
+fn section(page: &mut String, path: &Path, receipt: &Receipt) -> Result<()> {
+    let open = match failed(receipt.status) {
+        true => " open",
+        false => "",
+    };
+    let _ = writeln!(
+        page,
+        "<details id=\"r-{}\" class=\"{}\"{}>",
+        escape(&receipt.id),
+        class(receipt.status),
+        open
+    );
+    let _ = writeln!(
+        page,
+        "<summary><span class=\"status\">{}</span> {} \
+         <span class=\"meta\">{} · {}ms</span></summary>",
+        receipt.status.as_str(),
+        escape(&receipt.command_line()),
+        receipt.start_time.format("%Y-%m-%d %H:%M:%S UTC"),
+        receipt.duration_ms
+    );
+
+    let mut outcome = receipt.status.as_str().to_string();
+    if let Some(code) = receipt.exit_code {
+        outcome.push_str(&format!(" (exit code {})", code));
+    } else if let Some(signal) = &receipt.terminated_by {
+        outcome.push_str(&format!(" ({})", signal.name));
+    }
+    let mut fields = vec![
+        ("Receipt", receipt.id.clone()),
+        ("Path", path.display().to_string()),
+        ("Command", receipt.command_line()),
+        ("Status", outcome),
+        (
+            "Started",
+            receipt
+                .start_time
+                .format("%Y-%m-%dT%H:%M:%S%.3fZ")
+                .to_string(),
+        ),
+        ("Duration", format!("{}ms", receipt.duration_ms)),
+        ("Cwd", receipt.cwd.clone()),
+    ];
+    if let Some(hostname) = receipt.host.as_ref().and_then(|host| host.hostname.clone()) {
+        fields.push(("Host", hostname));
+    }
+    if !receipt.tags.is_empty() {
+        fields.push(("Tags", receipt.tags.join(", ")));
+    }
+    if let Some(session) = &receipt.session_id {
+        fields.push(("Session", session.clone()));
+    }
+    if let Some(parent) = &receipt.parent_id {
+        fields.push(("Parent", parent.clone()));
+    }
+    let _ = writeln!(page, "<dl>");
+    for (label, value) in fields {
+        let _ = writeln!(page, "<dt>{}</dt><dd>{}</dd>", label, escape(&value));
+    }
+    let _ = writeln!(page, "</dl>");
+
+    for stream in ["stdout", "stderr"] {
+        let output = receipt.written(stream, path)?;
+        if output.is_empty() {
+            continue;
+        }
+        let text = String::from_utf8_lossy(&output);
+        let lines: Vec<&str> = text.lines().collect();
+        let skipped = lines.len().saturating_sub(OUTPUT_LINES);
+        let _ = writeln!(
+            page,
+            "<h4>{} ({})</h4>",
+            stream,
+            format_size(output.len() as u64)
+        );
+        let _ = write!(page, "<pre>");
+        if skipped > 0 {
+            let _ = writeln!(page, "… {} earlier lines", skipped);
+        }
+        let _ = writeln!(page, "{}</pre>", escape(&lines[skipped..].join("\n")));
+    }
+    let _ = writeln!(page, "</details>");
+    Ok(())
+}
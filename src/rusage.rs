@@ -0,0 +1,249 @@
+use std::io;
+use std::process::{Child, ExitStatus};
+
+use crate::receipt::ResourceUsage;
+
+/// What the kernel accounted to reaped children: each child and the
+/// descendants it waited for
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChildUsage {
+    pub cpu_user_ms: f64,
+    pub cpu_system_ms: f64,
+    /// Largest resident set of any single process, in bytes
+    pub max_rss_bytes: u64,
+    pub minor_page_faults: u64,
+    pub major_page_faults: u64,
+    /// Bytes read from and written to storage
+    pub io_read_bytes: Option<u64>,
+    pub io_write_bytes: Option<u64>,
+    /// Bytes passed through read and write calls, whatever served them:
+    /// the page cache, pipes, sockets or terminals (Linux only)
+    pub io_syscall_read_bytes: Option<u64>,
+    pub io_syscall_write_bytes: Option<u64>,
+}
+
+impl ChildUsage {
+    /// Account another child's usage
+    pub fn add(&mut self, other: &ChildUsage) {
+        self.cpu_user_ms += other.cpu_user_ms;
+        self.cpu_system_ms += other.cpu_system_ms;
+        self.max_rss_bytes = self.max_rss_bytes.max(other.max_rss_bytes);
+        self.minor_page_faults += other.minor_page_faults;
+        self.major_page_faults += other.major_page_faults;
+        let sum = |mine: &mut Option<u64>, theirs: Option<u64>| {
+            if let Some(theirs) = theirs {
+                *mine = Some(mine.unwrap_or(0) + theirs);
+            }
+        };
+        sum(&mut self.io_read_bytes, other.io_read_bytes);
+        sum(&mut self.io_write_bytes, other.io_write_bytes);
+        sum(&mut self.io_syscall_read_bytes, other.io_syscall_read_bytes);
+        sum(
+            &mut self.io_syscall_write_bytes,
+            other.io_syscall_write_bytes,
+        );
+    }
+}
+
+/// A child being waited for, holding what is needed to read its counters
+/// once it has exited
+pub struct Reaper(imp::Reaper);
+
+impl Reaper {
+    /// Start watching a running child
+    pub fn new(child: &Child) -> Self {
+        Reaper(imp::Reaper::new(child))
+    }
+
+    /// Reap the child if it has exited, returning its status and, on Unix,
+    /// its resource usage from wait4 and its I/O counters
+    pub fn try_wait(
+        &self,
+        child: &mut Child,
+    ) -> io::Result<Option<(ExitStatus, Option<ChildUsage>)>> {
+        self.0.try_wait(child)
+    }
+}
+
+/// Add rusage figures to the resources measured by a cgroup or job object,
+/// or make them the measurement when there is neither. The cgroup's CPU
+/// times and I/O are kept, since they also count descendants that were
+/// never waited for.
+pub fn merge(resources: Option<ResourceUsage>, usage: Option<ChildUsage>) -> Option<ResourceUsage> {
+    let Some(usage) = usage else {
+        return resources;
+    };
+    let mut resources = resources.unwrap_or(ResourceUsage {
+        cgroup: None,
+        cpu_user_ms: usage.cpu_user_ms,
+        cpu_system_ms: usage.cpu_system_ms,
+        memory_peak_bytes: None,
+        io_read_bytes: None,
+        io_write_bytes: None,
+        processes: None,
+        max_rss_bytes: None,
+        minor_page_faults: None,
+        major_page_faults: None,
+        io_syscall_read_bytes: None,
+        io_syscall_write_bytes: None,
+    });
+    resources.io_read_bytes = resources.io_read_bytes.or(usage.io_read_bytes);
+    resources.io_write_bytes = resources.io_write_bytes.or(usage.io_write_bytes);
+    resources.io_syscall_read_bytes = usage.io_syscall_read_bytes;
+    resources.io_syscall_write_bytes = usage.io_syscall_write_bytes;
+    resources.max_rss_bytes = Some(usage.max_rss_bytes);
+    resources.minor_page_faults = Some(usage.minor_page_faults);
+    resources.major_page_faults = Some(usage.major_page_faults);
+    Some(resources)
+}
+
+#[cfg(unix)]
+mod imp {
+    #[cfg(target_os = "linux")]
+    use std::fs::File;
+    use std::io;
+    #[cfg(target_os = "linux")]
+    use std::io::Read;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::{Child, ExitStatus};
+
+    use super::ChildUsage;
+
+    /// ru_maxrss is in kilobytes, except on Apple platforms where it is bytes
+    #[cfg(target_vendor = "apple")]
+    const MAX_RSS_UNIT: u64 = 1;
+    #[cfg(not(target_vendor = "apple"))]
+    const MAX_RSS_UNIT: u64 = 1024;
+
+    pub struct Reaper {
+        pid: libc::pid_t,
+        #[cfg(target_os = "linux")]
+        io: Option<File>,
+    }
+
+    impl Reaper {
+        pub fn new(child: &Child) -> Self {
+            let pid = child.id() as libc::pid_t;
+            Reaper {
+                pid,
+                #[cfg(target_os = "linux")]
+                io: File::open(format!("/proc/{}/io", pid)).ok(),
+            }
+        }
+
+        pub fn try_wait(
+            &self,
+            _child: &mut Child,
+        ) -> io::Result<Option<(ExitStatus, Option<ChildUsage>)>> {
+            let pid = self.pid;
+            // Check for an exit without reaping, so the I/O counters of the
+            // zombie can still be read
+            // SAFETY: waitid writes only to the siginfo we own
+            let exited = unsafe {
+                let mut info: libc::siginfo_t = std::mem::zeroed();
+                let flags = libc::WEXITED | libc::WNOHANG | libc::WNOWAIT;
+                if libc::waitid(libc::P_PID, pid as libc::id_t, &mut info, flags) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                info.si_pid() != 0
+            };
+            if !exited {
+                return Ok(None);
+            }
+            let io = self.io_counters();
+
+            let mut status = 0;
+            // SAFETY: wait4 writes only to the status and rusage we own. Once
+            // it reaps the child, callers stop waiting on it through `Child`.
+            let (reaped, usage) = unsafe {
+                let mut usage: libc::rusage = std::mem::zeroed();
+                let reaped = libc::wait4(pid, &mut status, 0, &mut usage);
+                (reaped, usage)
+            };
+            if reaped < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let ms =
+                |time: libc::timeval| time.tv_sec as f64 * 1000.0 + time.tv_usec as f64 / 1000.0;
+            let usage = ChildUsage {
+                cpu_user_ms: ms(usage.ru_utime),
+                cpu_system_ms: ms(usage.ru_stime),
+                max_rss_bytes: usage.ru_maxrss as u64 * MAX_RSS_UNIT,
+                minor_page_faults: usage.ru_minflt as u64,
+                major_page_faults: usage.ru_majflt as u64,
+                ..io
+            };
+            Ok(Some((ExitStatus::from_raw(status), Some(usage))))
+        }
+
+        /// Counters from /proc/<pid>/io, which for a process that has exited
+        /// include the children it reaped. The file is opened while the child
+        /// runs, since only root may open it once the child is a zombie.
+        #[cfg(target_os = "linux")]
+        fn io_counters(&self) -> ChildUsage {
+            let mut io = String::new();
+            if let Some(mut file) = self.io.as_ref() {
+                let _ = file.read_to_string(&mut io);
+            }
+            let counter = |key: &str| {
+                io.lines().find_map(|line| {
+                    let (name, value) = line.split_once(": ")?;
+                    (name == key).then(|| value.trim().parse().ok())?
+                })
+            };
+            ChildUsage {
+                io_read_bytes: counter("read_bytes"),
+                io_write_bytes: counter("write_bytes"),
+                io_syscall_read_bytes: counter("rchar"),
+                io_syscall_write_bytes: counter("wchar"),
+                ..Default::default()
+            }
+        }
+
+        /// Disk I/O of the process itself, from proc_pid_rusage
+        #[cfg(target_os = "macos")]
+        fn io_counters(&self) -> ChildUsage {
+            // SAFETY: the buffer is the structure this flavor fills
+            unsafe {
+                let mut info: libc::rusage_info_v2 = std::mem::zeroed();
+                let buffer = &mut info as *mut libc::rusage_info_v2 as *mut libc::rusage_info_t;
+                if libc::proc_pid_rusage(self.pid, libc::RUSAGE_INFO_V2, buffer) != 0 {
+                    return ChildUsage::default();
+                }
+                ChildUsage {
+                    io_read_bytes: Some(info.ri_diskio_bytesread),
+                    io_write_bytes: Some(info.ri_diskio_byteswritten),
+                    ..Default::default()
+                }
+            }
+        }
+
+        #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+        fn io_counters(&self) -> ChildUsage {
+            ChildUsage::default()
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use std::io;
+    use std::process::{Child, ExitStatus};
+
+    use super::ChildUsage;
+
+    pub struct Reaper;
+
+    impl Reaper {
+        pub fn new(_child: &Child) -> Self {
+            Reaper
+        }
+
+        pub fn try_wait(
+            &self,
+            child: &mut Child,
+        ) -> io::Result<Option<(ExitStatus, Option<ChildUsage>)>> {
+            Ok(child.try_wait()?.map(|status| (status, None)))
+        }
+    }
+}
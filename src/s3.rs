@@ -0,0 +1,122 @@
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::S3Config;
+use crate::digest::sha256_hex;
+use crate::sink::{self, Delivery};
+
+/// An S3 bucket, or a prefix in one, that receipts are uploaded to through
+/// curl
+#[derive(Debug, Clone)]
+pub struct Bucket {
+    bucket: String,
+    /// Prefix of every key, without a trailing `/`
+    prefix: String,
+    endpoint: String,
+    region: String,
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+    curl: PathBuf,
+}
+
+impl Bucket {
+    /// The bucket at `url`, `s3://bucket/prefix`, with the endpoint, region
+    /// and credentials from the AWS_* environment variables, else `config`
+    pub fn new(url: &str, config: &S3Config) -> Result<Self> {
+        let Some(location) = url.strip_prefix("s3://") else {
+            bail!("--sink must be an s3://bucket/prefix URL, not {}", url);
+        };
+        let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+        if bucket.is_empty() {
+            bail!("--sink {} names no bucket", url);
+        }
+        let setting = |names: &[&str], configured: &Option<String>| {
+            names
+                .iter()
+                .find_map(|name| std::env::var(name).ok().filter(|value| !value.is_empty()))
+                .or_else(|| configured.clone())
+        };
+        let region = setting(&["AWS_REGION", "AWS_DEFAULT_REGION"], &config.region)
+            .unwrap_or_else(|| "us-east-1".to_string());
+        let endpoint = setting(
+            &["AWS_ENDPOINT_URL_S3", "AWS_ENDPOINT_URL"],
+            &config.endpoint,
+        )
+        .unwrap_or_else(|| format!("https://s3.{}.amazonaws.com", region));
+        let Some(access_key_id) = setting(&["AWS_ACCESS_KEY_ID"], &config.access_key_id) else {
+            bail!("--sink needs credentials: set AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY, or the s3 section of the config");
+        };
+        let Some(secret_access_key) =
+            setting(&["AWS_SECRET_ACCESS_KEY"], &config.secret_access_key)
+        else {
+            bail!("--sink needs AWS_SECRET_ACCESS_KEY, or secret_access_key in the s3 section of the config");
+        };
+
+        Ok(Bucket {
+            bucket: bucket.to_string(),
+            prefix: prefix.trim_matches('/').to_string(),
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            region,
+            access_key_id,
+            secret_access_key,
+            session_token: setting(&["AWS_SESSION_TOKEN"], &config.session_token),
+            curl: sink::find_curl("upload receipts to S3")?,
+        })
+    }
+
+    /// PUT `file` to the key `name` under the prefix, signed with SigV4.
+    /// Credentials reach curl through its stdin, not its command line
+    pub fn upload(&self, file: &Path, name: &str, delivery: Delivery) -> Result<()> {
+        let key = match self.prefix.is_empty() {
+            true => name.to_string(),
+            false => format!("{}/{}", self.prefix, name),
+        };
+        let target = format!("{}/{}/{}", self.endpoint, self.bucket, encode_key(&key));
+        let contents = fs::read(file).context(format!("Failed to read {}", file.display()))?;
+        // S3 wants the payload's digest as a header, as well as signed
+        let mut config = format!(
+            "user = {}\naws-sigv4 = {}\nheader = {}\n",
+            sink::quote(&format!(
+                "{}:{}",
+                self.access_key_id, self.secret_access_key
+            )),
+            sink::quote(&format!("aws:amz:{}:s3", self.region)),
+            sink::quote(&format!("x-amz-content-sha256: {}", sha256_hex(&contents)))
+        );
+        if let Some(token) = &self.session_token {
+            config.push_str(&format!(
+                "header = {}\n",
+                sink::quote(&format!("x-amz-security-token: {}", token))
+            ));
+        }
+
+        sink::run_curl(
+            &self.curl,
+            &["--upload-file".into(), file.into(), target.into()],
+            &config,
+            delivery,
+        )
+        .context(format!(
+            "failed to upload {} to s3://{}/{}",
+            file.display(),
+            self.bucket,
+            key
+        ))
+    }
+}
+
+/// `key` percent-encoded for a URL path, keeping its `/`s
+fn encode_key(key: &str) -> String {
+    let mut encoded = String::new();
+    for byte in key.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
@@ -0,0 +1,399 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// An opt-in namespace sandbox, from --sandbox and --sandbox-write
+#[derive(Debug, Clone, Default)]
+pub struct Sandbox {
+    /// Directories the command may write to besides its working directory
+    pub writable: Vec<PathBuf>,
+}
+
+/// How the command was sandboxed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SandboxProfile {
+    /// Namespaces the command ran in: mount, pid and net, plus user when
+    /// rcpt was not root
+    pub namespaces: Vec<String>,
+    /// Whether everything outside `writable` was mounted read-only
+    pub read_only_root: bool,
+    /// Directories the command could write to, starting with its working
+    /// directory
+    pub writable: Vec<String>,
+    /// Whether /tmp was an empty tmpfs of the command's own
+    pub private_tmp: bool,
+    /// Whether the command could reach the network (only loopback is
+    /// available in the net namespace)
+    pub network: bool,
+}
+
+impl Sandbox {
+    /// The profile for a command running in `cwd`
+    pub fn profile(&self, cwd: &Path) -> Result<SandboxProfile> {
+        let mut writable = vec![cwd.to_string_lossy().to_string()];
+        for path in &self.writable {
+            let path = fs::canonicalize(path)
+                .context(format!("Failed to resolve sandbox path {}", path.display()))?;
+            if !path.is_dir() {
+                bail!("Sandbox path {} is not a directory", path.display());
+            }
+            let path = path.to_string_lossy().to_string();
+            if !writable.contains(&path) {
+                writable.push(path);
+            }
+        }
+        let mut namespaces = vec!["mount".to_string(), "pid".to_string(), "net".to_string()];
+        if !imp::is_root() {
+            namespaces.push("user".to_string());
+        }
+        Ok(SandboxProfile {
+            namespaces,
+            read_only_root: true,
+            writable,
+            private_tmp: true,
+            network: false,
+        })
+    }
+}
+
+impl SandboxProfile {
+    /// Arrange for the command to enter the sandbox before exec (Linux only)
+    pub fn apply(&self, command: &mut Command) -> Result<()> {
+        imp::apply(self, command)
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub use imp::{is_root, loopback_up, map_user};
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use anyhow::Result;
+    use std::ffi::CString;
+    use std::io;
+    use std::os::unix::process::CommandExt;
+    use std::path::Path;
+    use std::process::Command;
+    use std::ptr;
+
+    use super::SandboxProfile;
+
+    /// Signals the stand-in processes leave to the command, which gets them
+    /// from rcpt directly as a member of the same process group
+    const PASSED_SIGNALS: [libc::c_int; 4] =
+        [libc::SIGINT, libc::SIGTERM, libc::SIGHUP, libc::SIGQUIT];
+
+    pub fn is_root() -> bool {
+        // SAFETY: geteuid(2) cannot fail
+        unsafe { libc::geteuid() == 0 }
+    }
+
+    /// Everything needed to enter the sandbox, prepared before fork so the
+    /// child does not allocate
+    struct Plan {
+        user_ns: bool,
+        uid_map: Vec<u8>,
+        gid_map: Vec<u8>,
+        writable: Vec<CString>,
+        /// Directories to create in the private /tmp as mount points for
+        /// writable paths below it
+        tmp_dirs: Vec<CString>,
+        /// Detached copies of the writable trees, taken before the root
+        /// becomes read-only
+        trees: Vec<libc::c_int>,
+    }
+
+    pub fn apply(profile: &SandboxProfile, command: &mut Command) -> Result<()> {
+        // SAFETY: getuid(2) and getgid(2) cannot fail
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        let mut tmp_dirs = Vec::new();
+        for path in &profile.writable {
+            if let Ok(below) = Path::new(path).strip_prefix("/tmp") {
+                let mut dir = Path::new("/tmp").to_path_buf();
+                for component in below.components() {
+                    dir.push(component);
+                    tmp_dirs.push(CString::new(dir.to_string_lossy().as_bytes())?);
+                }
+            }
+        }
+        let mut plan = Plan {
+            user_ns: profile.namespaces.iter().any(|ns| ns == "user"),
+            uid_map: format!("{0} {0} 1\n", uid).into_bytes(),
+            gid_map: format!("{0} {0} 1\n", gid).into_bytes(),
+            writable: profile
+                .writable
+                .iter()
+                .map(|path| CString::new(path.as_bytes()))
+                .collect::<Result<_, _>>()?,
+            tmp_dirs,
+            trees: vec![-1; profile.writable.len()],
+        };
+        // SAFETY: `enter` only makes async-signal-safe calls and does not
+        // allocate
+        unsafe {
+            command.pre_exec(move || enter(&mut plan));
+        }
+        Ok(())
+    }
+
+    fn check(result: libc::c_long) -> io::Result<libc::c_long> {
+        if result < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(result)
+        }
+    }
+
+    /// Runs in the child between fork and exec
+    fn enter(plan: &mut Plan) -> io::Result<()> {
+        let mut flags = libc::CLONE_NEWNS | libc::CLONE_NEWPID | libc::CLONE_NEWNET;
+        if plan.user_ns {
+            flags |= libc::CLONE_NEWUSER;
+        }
+        // SAFETY: unshare(2) has no memory-safety preconditions
+        check(unsafe { libc::unshare(flags) } as libc::c_long)?;
+        if plan.user_ns {
+            map_user(&plan.uid_map, &plan.gid_map)?;
+        }
+
+        // Only children enter the new PID namespace. The first becomes its
+        // init, which sets up the mounts and starts the command as its own
+        // child, since init would ignore the signals rcpt sends.
+        let mut status_pipe = [0; 2];
+        // SAFETY: the array has room for both descriptors
+        check(unsafe { libc::pipe2(status_pipe.as_mut_ptr(), libc::O_CLOEXEC) } as libc::c_long)?;
+        let [signal_reader, signal_writer] = status_pipe;
+        // SAFETY: fork(2) in a single-threaded child
+        match check(unsafe { libc::fork() } as libc::c_long)? {
+            0 => {}
+            init => stand_in(init as libc::pid_t, Some(signal_reader), None),
+        }
+        mount_sandbox(plan)?;
+        // SAFETY: fork(2) in a single-threaded child
+        match check(unsafe { libc::fork() } as libc::c_long)? {
+            0 => {}
+            command => stand_in(command as libc::pid_t, None, Some(signal_writer)),
+        }
+        Ok(())
+    }
+
+    fn mount_sandbox(plan: &mut Plan) -> io::Result<()> {
+        mount(None, c"/", None, libc::MS_REC | libc::MS_PRIVATE, None)?;
+        for (path, tree) in plan.writable.iter().zip(plan.trees.iter_mut()) {
+            // SAFETY: `path` is a valid C string
+            *tree = check(unsafe {
+                libc::syscall(
+                    libc::SYS_open_tree,
+                    libc::AT_FDCWD,
+                    path.as_ptr(),
+                    libc::OPEN_TREE_CLONE
+                        | libc::O_CLOEXEC as libc::c_uint
+                        | libc::AT_RECURSIVE as libc::c_uint,
+                )
+            })? as libc::c_int;
+        }
+
+        let read_only = libc::mount_attr {
+            attr_set: libc::MOUNT_ATTR_RDONLY,
+            attr_clr: 0,
+            propagation: 0,
+            userns_fd: 0,
+        };
+        // SAFETY: the attribute structure outlives the call
+        check(unsafe {
+            libc::syscall(
+                libc::SYS_mount_setattr,
+                libc::AT_FDCWD,
+                c"/".as_ptr(),
+                libc::AT_RECURSIVE as libc::c_uint,
+                &read_only as *const libc::mount_attr,
+                std::mem::size_of::<libc::mount_attr>(),
+            )
+        })?;
+
+        mount(
+            Some(c"tmpfs"),
+            c"/tmp",
+            Some(c"tmpfs"),
+            libc::MS_NOSUID | libc::MS_NODEV,
+            Some(c"mode=1777"),
+        )?;
+        for dir in &plan.tmp_dirs {
+            // SAFETY: `dir` is a valid C string; existing directories are fine
+            unsafe {
+                libc::mkdir(dir.as_ptr(), 0o755);
+            }
+        }
+        for (path, &tree) in plan.writable.iter().zip(&plan.trees) {
+            // SAFETY: `tree` is a detached mount and `path` a valid C string
+            check(unsafe {
+                libc::syscall(
+                    libc::SYS_move_mount,
+                    tree,
+                    c"".as_ptr(),
+                    libc::AT_FDCWD,
+                    path.as_ptr(),
+                    libc::MOVE_MOUNT_F_EMPTY_PATH,
+                )
+            })?;
+        }
+
+        // The working directory still refers to the old, read-only tree
+        // SAFETY: the working directory is the first writable path
+        check(unsafe { libc::chdir(plan.writable[0].as_ptr()) } as libc::c_long)?;
+
+        mount(
+            Some(c"proc"),
+            c"/proc",
+            Some(c"proc"),
+            libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC,
+            None,
+        )?;
+        loopback_up()
+    }
+
+    fn mount(
+        source: Option<&std::ffi::CStr>,
+        target: &std::ffi::CStr,
+        fstype: Option<&std::ffi::CStr>,
+        flags: libc::c_ulong,
+        data: Option<&std::ffi::CStr>,
+    ) -> io::Result<()> {
+        let ptr_of = |s: Option<&std::ffi::CStr>| s.map_or(ptr::null(), |s| s.as_ptr());
+        // SAFETY: every pointer is null or a valid C string
+        check(unsafe {
+            libc::mount(
+                ptr_of(source),
+                target.as_ptr(),
+                ptr_of(fstype),
+                flags,
+                ptr_of(data).cast(),
+            )
+        } as libc::c_long)?;
+        Ok(())
+    }
+
+    /// Map rcpt's own uid and gid into a just-created user namespace, given
+    /// the maps' contents
+    pub fn map_user(uid_map: &[u8], gid_map: &[u8]) -> io::Result<()> {
+        write_file(c"/proc/self/setgroups", b"deny")?;
+        write_file(c"/proc/self/uid_map", uid_map)?;
+        write_file(c"/proc/self/gid_map", gid_map)
+    }
+
+    fn write_file(path: &std::ffi::CStr, contents: &[u8]) -> io::Result<()> {
+        // SAFETY: `path` is a valid C string and `contents` a valid buffer
+        unsafe {
+            let fd =
+                check(libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC) as libc::c_long)?
+                    as libc::c_int;
+            let written = libc::write(fd, contents.as_ptr().cast(), contents.len());
+            libc::close(fd);
+            check(written as libc::c_long)?;
+        }
+        Ok(())
+    }
+
+    /// Bring up the new net namespace's loopback interface
+    pub fn loopback_up() -> io::Result<()> {
+        // SAFETY: the ifreq is zeroed and named before each ioctl
+        unsafe {
+            let socket = check(
+                libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0)
+                    as libc::c_long,
+            )? as libc::c_int;
+            let mut request: libc::ifreq = std::mem::zeroed();
+            request.ifr_name[..2].copy_from_slice(&[b'l' as libc::c_char, b'o' as libc::c_char]);
+            let mut result = libc::ioctl(socket, libc::SIOCGIFFLAGS, &mut request);
+            if result == 0 {
+                request.ifr_ifru.ifru_flags |= libc::IFF_UP as libc::c_short;
+                result = libc::ioctl(socket, libc::SIOCSIFFLAGS, &request);
+            }
+            libc::close(socket);
+            check(result as libc::c_long)?;
+        }
+        Ok(())
+    }
+
+    /// Wait for `child` and exit the way it did, leaving signals to it. The
+    /// namespace's init cannot die by its own signal, so it reports the
+    /// command's fatal signal over `signal_writer` for the process outside
+    /// to re-raise instead.
+    fn stand_in(
+        child: libc::pid_t,
+        signal_reader: Option<libc::c_int>,
+        signal_writer: Option<libc::c_int>,
+    ) -> ! {
+        // SAFETY: only async-signal-safe calls, and this process never
+        // returns into the code that forked it
+        unsafe {
+            // Let the exec status pipe and output pipes belong to the command
+            let keep = signal_reader.or(signal_writer).unwrap_or(-1);
+            for fd in 3..1024 {
+                if fd != keep {
+                    libc::close(fd);
+                }
+            }
+            for signal in PASSED_SIGNALS {
+                libc::signal(signal, libc::SIG_IGN);
+            }
+            let mut status = 0;
+            loop {
+                // As init, also reap whatever else the command left behind
+                let pid = libc::waitpid(-1, &mut status, 0);
+                if pid == child {
+                    break;
+                }
+                if pid < 0 && io::Error::last_os_error().raw_os_error() != Some(libc::EINTR) {
+                    libc::_exit(1);
+                }
+            }
+            let mut signal = if libc::WIFSIGNALED(status) {
+                libc::WTERMSIG(status)
+            } else {
+                0
+            };
+            if let Some(writer) = signal_writer {
+                if signal != 0 {
+                    let byte = signal as u8;
+                    libc::write(writer, (&byte as *const u8).cast(), 1);
+                }
+                libc::_exit(libc::WEXITSTATUS(status));
+            }
+            if let Some(reader) = signal_reader {
+                let mut byte = 0u8;
+                if libc::read(reader, (&mut byte as *mut u8).cast(), 1) == 1 {
+                    signal = byte as libc::c_int;
+                }
+            }
+            if signal != 0 {
+                let no_core = libc::rlimit {
+                    rlim_cur: 0,
+                    rlim_max: 0,
+                };
+                libc::setrlimit(libc::RLIMIT_CORE, &no_core);
+                libc::signal(signal, libc::SIG_DFL);
+                libc::kill(libc::getpid(), signal);
+            }
+            libc::_exit(libc::WEXITSTATUS(status));
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use anyhow::{bail, Result};
+    use std::process::Command;
+
+    use super::SandboxProfile;
+
+    pub fn is_root() -> bool {
+        false
+    }
+
+    pub fn apply(_profile: &SandboxProfile, _command: &mut Command) -> Result<()> {
+        bail!("--sandbox is only supported on Linux");
+    }
+}
@@ -0,0 +1,301 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use crate::receipt::{Receipt, Status};
+
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// The most results reported for one failed run
+const MAX_RESULTS: usize = 20;
+
+/// How many lines after, then before, an error line are looked at for the
+/// file and line it is about when it names none itself, as with rustc's
+/// `--> src/main.rs:12:5` below an error and the `File "app.py", line 7`
+/// above a Python exception
+const NEARBY: usize = 3;
+
+/// Words, in any case, that make a line of output an error line
+const ERROR_WORDS: &[&str] = &[
+    "error",
+    "fatal",
+    "failed",
+    "failure",
+    "panicked",
+    "exception",
+];
+
+/// A SARIF 2.1.0 log
+#[derive(Debug, Serialize)]
+struct Log {
+    #[serde(rename = "$schema")]
+    schema: &'static str,
+    version: &'static str,
+    runs: Vec<Run>,
+}
+
+#[derive(Debug, Serialize)]
+struct Run {
+    tool: Tool,
+    results: Vec<SarifResult>,
+}
+
+#[derive(Debug, Serialize)]
+struct Tool {
+    driver: Driver,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Driver {
+    name: &'static str,
+    version: &'static str,
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Rule {
+    id: &'static str,
+    short_description: Message,
+}
+
+#[derive(Debug, Serialize)]
+struct Message {
+    text: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifResult {
+    rule_id: &'static str,
+    level: &'static str,
+    message: Message,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    locations: Vec<Location>,
+    properties: Properties,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Location {
+    physical_location: PhysicalLocation,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PhysicalLocation {
+    artifact_location: ArtifactLocation,
+    region: Region,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct ArtifactLocation {
+    uri: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Region {
+    start_line: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    start_column: Option<u64>,
+}
+
+/// What ties a result back to its receipt
+#[derive(Debug, Serialize)]
+struct Properties {
+    receipt_id: String,
+    receipt_path: String,
+    command: String,
+    status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exit_code: Option<i32>,
+}
+
+/// The rule a result of a run that ended with `status` is reported under,
+/// or None if it did not fail
+fn rule(status: Status) -> Option<&'static str> {
+    match status {
+        Status::Failed | Status::MissingOutputs => Some("command-failed"),
+        Status::TimedOut | Status::Interrupted | Status::OomKilled | Status::Lost => {
+            Some("command-stopped")
+        }
+        Status::Ok | Status::Planned | Status::Running => None,
+    }
+}
+
+/// The failed runs among `receipts` as a SARIF log, for code-scanning
+/// views. Each error line in a run's stderr and stdout about a file and
+/// line, such as `src/main.rs:12:5` or `File "app.py", line 7`, is a
+/// result at that place, up to `MAX_RESULTS` a run. A run with none is one
+/// result, without a location, quoting its first error line
+pub fn render(receipts: &[(PathBuf, Receipt)]) -> Result<String> {
+    let mut results = Vec::new();
+    for (path, receipt) in receipts {
+        let Some(rule_id) = rule(receipt.status) else {
+            continue;
+        };
+        let mut outcome = format!("`{}` {}", receipt.command_line(), receipt.status.as_str());
+        if let Some(code) = receipt.exit_code {
+            outcome.push_str(&format!(" with exit code {}", code));
+        }
+        let mut errors = Vec::new();
+        for stream in ["stderr", "stdout"] {
+            let output = receipt.written(stream, path)?;
+            let output = String::from_utf8_lossy(&output);
+            let lines: Vec<&str> = output.lines().collect();
+            for (n, line) in lines.iter().enumerate() {
+                if !is_error(line) {
+                    continue;
+                }
+                let after = lines.iter().skip(n + 1).take(NEARBY);
+                let before = lines[n.saturating_sub(NEARBY)..n].iter().rev();
+                let location = [line]
+                    .into_iter()
+                    .chain(after)
+                    .chain(before)
+                    .find_map(|nearby| locate(nearby, &receipt.cwd));
+                errors.push((line.trim().to_string(), location));
+            }
+        }
+        let properties = || Properties {
+            receipt_id: receipt.id.clone(),
+            receipt_path: path.display().to_string(),
+            command: receipt.command_line(),
+            status: receipt.status,
+            exit_code: receipt.exit_code,
+        };
+        // Only the first error line about each place is reported
+        let mut located: Vec<(String, Location)> = Vec::new();
+        for (line, location) in &errors {
+            let Some(location) = location else {
+                continue;
+            };
+            if located.len() < MAX_RESULTS && !located.iter().any(|(_, seen)| seen == location) {
+                located.push((line.clone(), location.clone()));
+            }
+        }
+        if located.is_empty() {
+            let text = match errors.first() {
+                Some((line, _)) => format!("{}: {}", outcome, line),
+                None => outcome,
+            };
+            results.push(SarifResult {
+                rule_id,
+                level: "error",
+                message: Message { text },
+                locations: Vec::new(),
+                properties: properties(),
+            });
+            continue;
+        }
+        for (line, location) in located {
+            results.push(SarifResult {
+                rule_id,
+                level: "error",
+                message: Message {
+                    text: format!("{}: {}", outcome, line),
+                },
+                locations: vec![location],
+                properties: properties(),
+            });
+        }
+    }
+
+    let log = Log {
+        schema: SCHEMA,
+        version: "2.1.0",
+        runs: vec![Run {
+            tool: Tool {
+                driver: Driver {
+                    name: "rcpt",
+                    version: env!("CARGO_PKG_VERSION"),
+                    rules: vec![
+                        Rule {
+                            id: "command-failed",
+                            short_description: Message {
+                                text: "A command failed".to_string(),
+                            },
+                        },
+                        Rule {
+                            id: "command-stopped",
+                            short_description: Message {
+                                text: "A command was stopped before it finished".to_string(),
+                            },
+                        },
+                    ],
+                },
+            },
+            results,
+        }],
+    };
+    let mut json = serde_json::to_string_pretty(&log)?;
+    json.push('\n');
+    Ok(json)
+}
+
+fn is_error(line: &str) -> bool {
+    let line = line.to_ascii_lowercase();
+    ERROR_WORDS.iter().any(|word| line.contains(word))
+}
+
+/// The file and line `line` refers to, its path made relative to `cwd`,
+/// where the command ran, if it is inside it
+fn locate(line: &str, cwd: &str) -> Option<Location> {
+    let (file, start_line, start_column) = python_reference(line).or_else(|| {
+        line.split_whitespace()
+            .find_map(|word| reference(word.trim_matches(|c: char| "()[]<>'\",;".contains(c))))
+    })?;
+    let uri = match Path::new(&file).strip_prefix(cwd) {
+        Ok(relative) => relative.to_string_lossy().replace('\\', "/"),
+        Err(_) if Path::new(&file).is_absolute() => format!("file://{}", file.replace('\\', "/")),
+        Err(_) => file.replace('\\', "/"),
+    };
+    Some(Location {
+        physical_location: PhysicalLocation {
+            artifact_location: ArtifactLocation { uri },
+            region: Region {
+                start_line,
+                start_column,
+            },
+        },
+    })
+}
+
+/// A reference in Python's traceback style: `File "app.py", line 7`
+fn python_reference(line: &str) -> Option<(String, u64, Option<u64>)> {
+    let (_, rest) = line.split_once("File \"")?;
+    let (file, rest) = rest.split_once('"')?;
+    let digits: String = rest
+        .strip_prefix(", line ")?
+        .chars()
+        .take_while(char::is_ascii_digit)
+        .collect();
+    Some((file.to_string(), digits.parse().ok()?, None))
+}
+
+/// A reference like `src/main.rs:12` or `src/main.rs:12:5`, as compilers,
+/// linters and test runners print them. The file must look like a path,
+/// with a `.` or a separator in it, and not be a URL
+fn reference(word: &str) -> Option<(String, u64, Option<u64>)> {
+    if word.contains("://") {
+        return None;
+    }
+    let parts: Vec<&str> = word.split(':').collect();
+    let number = |part: &str| -> Option<u64> {
+        match !part.is_empty() && part.bytes().all(|byte| byte.is_ascii_digit()) {
+            true => part.parse().ok(),
+            false => None,
+        }
+    };
+    (1..parts.len()).find_map(|i| {
+        let line = number(parts[i]).filter(|&line| line > 0)?;
+        let file = parts[..i].join(":");
+        if !file.contains(['.', '/', '\\']) || file.bytes().all(|byte| byte.is_ascii_digit()) {
+            return None;
+        }
+        let column = parts.get(i + 1).and_then(|part| number(part));
+        Some((file, line, column))
+    })
+}
@@ -0,0 +1,145 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+use std::str::FromStr;
+
+/// CPU scheduling settings applied to the command before exec
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Scheduling {
+    /// Niceness the command runs at (-20 highest priority, 19 lowest)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nice: Option<i32>,
+    /// CPUs the command is pinned to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpus: Option<Vec<usize>>,
+}
+
+impl Scheduling {
+    pub fn is_empty(&self) -> bool {
+        *self == Scheduling::default()
+    }
+
+    /// Arrange for the settings to be applied in the child before exec
+    pub fn apply(&self, command: &mut Command) -> Result<()> {
+        imp::apply(self, command)
+    }
+}
+
+/// A set of CPUs given on the command line, e.g. `0-3`, `1,3,5` or `0-1,4-5`
+#[derive(Debug, Clone)]
+pub struct CpuList(pub Vec<usize>);
+
+impl FromStr for CpuList {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        parse_cpu_list(s).map(CpuList)
+    }
+}
+
+fn parse_cpu_list(s: &str) -> Result<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for part in s.split(',') {
+        let part = part.trim();
+        let parse = |n: &str| {
+            n.trim()
+                .parse::<usize>()
+                .context(format!("invalid CPU number '{}'", n))
+        };
+        match part.split_once('-') {
+            Some((first, last)) => {
+                let (first, last) = (parse(first)?, parse(last)?);
+                if first > last {
+                    bail!("invalid CPU range '{}'", part);
+                }
+                cpus.extend(first..=last);
+            }
+            None => cpus.push(parse(part)?),
+        }
+    }
+    cpus.sort_unstable();
+    cpus.dedup();
+    Ok(cpus)
+}
+
+#[cfg(unix)]
+mod imp {
+    use anyhow::Result;
+    use std::io;
+    use std::os::unix::process::CommandExt;
+    use std::process::Command;
+
+    use super::Scheduling;
+
+    pub fn apply(scheduling: &Scheduling, command: &mut Command) -> Result<()> {
+        let nice = scheduling.nice;
+        let affinity = match &scheduling.cpus {
+            Some(cpus) => Some(cpu_set(cpus)?),
+            None => None,
+        };
+
+        // SAFETY: setpriority and sched_setaffinity are plain syscalls and
+        // the closure only reads values captured before fork
+        unsafe {
+            command.pre_exec(move || {
+                if let Some(nice) = nice {
+                    if libc::setpriority(libc::PRIO_PROCESS, 0, nice) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                if let Some(set) = &affinity {
+                    set_affinity(set)?;
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    fn cpu_set(cpus: &[usize]) -> Result<libc::cpu_set_t> {
+        let max = libc::CPU_SETSIZE as usize;
+        if let Some(cpu) = cpus.iter().find(|&&cpu| cpu >= max) {
+            anyhow::bail!("CPU {} is out of range (maximum {})", cpu, max - 1);
+        }
+        // SAFETY: cpu_set_t is a plain bitmask, valid when zeroed, and every
+        // CPU index was checked against CPU_SETSIZE above
+        unsafe {
+            let mut set: libc::cpu_set_t = std::mem::zeroed();
+            for &cpu in cpus {
+                libc::CPU_SET(cpu, &mut set);
+            }
+            Ok(set)
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    unsafe fn set_affinity(set: &libc::cpu_set_t) -> io::Result<()> {
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), set) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn cpu_set(_cpus: &[usize]) -> Result<()> {
+        anyhow::bail!("--cpus is only supported on Linux");
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    unsafe fn set_affinity(_set: &()) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use anyhow::{bail, Result};
+    use std::process::Command;
+
+    use super::Scheduling;
+
+    pub fn apply(_scheduling: &Scheduling, _command: &mut Command) -> Result<()> {
+        bail!("--nice and --cpus are only supported on Unix");
+    }
+}
@@ -0,0 +1,247 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use crate::atomic;
+use crate::cron::CronSchedule;
+use crate::exec::{execute_with_retries, save_new_receipt, ExecOptions, Invocation, RetryPolicy};
+use crate::receipt::{Status, Trigger, TriggerReason};
+use crate::signals;
+use crate::template::{self, Names};
+
+/// How often rcpt checks for a signal while waiting for the next run
+const SIGNAL_CHECK: Duration = Duration::from_millis(50);
+
+/// How often the heartbeat file is refreshed while waiting
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Most missed times listed in a single receipt; the heartbeat counts them all
+const MAX_MISSED_LISTED: usize = 100;
+
+/// State of a running scheduler, rewritten on every change and at least every
+/// 30 seconds so a stale file means the scheduler is gone
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Heartbeat {
+    /// Process id of the scheduler
+    pub pid: u32,
+    /// Cron expression being followed
+    pub cron: String,
+    /// When the scheduler started
+    pub started_at: DateTime<Utc>,
+    /// When this file was last written
+    pub updated_at: DateTime<Utc>,
+    /// Next scheduled run, absent once the scheduler has stopped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_run: Option<DateTime<Utc>>,
+    /// Whether a run is in progress
+    #[serde(default)]
+    pub running: bool,
+    /// Number of runs made by this scheduler
+    pub runs: u64,
+    /// Number of failed runs made by this scheduler
+    pub failures: u64,
+    /// Number of scheduled times that passed without a run, including any
+    /// while no scheduler was running
+    pub missed_runs: u64,
+    /// Most recent run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_run: Option<LastRun>,
+    /// Most recent error that prevented a run from being recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+/// Summary of the most recent scheduled run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LastRun {
+    pub scheduled_time: DateTime<Utc>,
+    pub start_time: DateTime<Utc>,
+    pub status: Status,
+    pub exit_code: Option<i32>,
+    /// Path of the run's receipt
+    pub receipt: String,
+}
+
+/// A command run on a cron schedule
+pub struct Scheduler {
+    pub schedule: CronSchedule,
+    /// Directory receiving one receipt per run, named by its id
+    pub out_dir: PathBuf,
+    /// Path of the heartbeat file
+    pub heartbeat: PathBuf,
+}
+
+impl Scheduler {
+    /// Run the command at every scheduled time until rcpt is interrupted.
+    /// A scheduled time that passes while the previous run is still going is
+    /// skipped and recorded as missed.
+    pub fn run(
+        &self,
+        invocation: &Invocation,
+        opts: &ExecOptions,
+        policy: &RetryPolicy,
+        report: &mut impl Write,
+    ) -> Result<()> {
+        let started_at = Utc::now();
+        let mut heartbeat = Heartbeat {
+            pid: std::process::id(),
+            cron: self.schedule.expression().to_string(),
+            started_at,
+            updated_at: started_at,
+            next_run: None,
+            running: false,
+            runs: 0,
+            failures: 0,
+            missed_runs: 0,
+            last_run: None,
+            last_error: None,
+        };
+        // Runs that came due while no scheduler was running
+        let mut missed = match read_heartbeat(&self.heartbeat).and_then(|prev| prev.next_run) {
+            Some(next_run) => {
+                let due = next_run.with_timezone(&Local) - chrono::Duration::minutes(1);
+                self.times_between(due, Local::now())
+            }
+            None => Vec::new(),
+        };
+        heartbeat.missed_runs = missed.len() as u64;
+
+        loop {
+            let Some(next) = self.schedule.next_after(Local::now()) else {
+                anyhow::bail!(
+                    "Cron expression '{}' never fires",
+                    self.schedule.expression()
+                );
+            };
+            heartbeat.next_run = Some(next.with_timezone(&Utc));
+            self.write_heartbeat(&mut heartbeat)?;
+
+            if !self.sleep_until(next, &mut heartbeat)? {
+                heartbeat.next_run = None;
+                return self.write_heartbeat(&mut heartbeat);
+            }
+
+            heartbeat.running = true;
+            self.write_heartbeat(&mut heartbeat)?;
+            let scheduled_time = next.with_timezone(&Utc);
+            let listed = missed.len().min(MAX_MISSED_LISTED);
+            let trigger = Trigger {
+                scheduled_time: Some(scheduled_time),
+                missed: missed.drain(..).take(listed).collect(),
+                ..Trigger::new(TriggerReason::Schedule)
+            };
+            match self.run_once(invocation, opts, policy, trigger) {
+                Ok(last_run) => {
+                    heartbeat.runs += 1;
+                    if last_run.status != Status::Ok {
+                        heartbeat.failures += 1;
+                    }
+                    writeln!(
+                        report,
+                        "[{}] {}; receipt written to: {}",
+                        next.format("%Y-%m-%d %H:%M"),
+                        last_run.status.as_str(),
+                        last_run.receipt
+                    )?;
+                    heartbeat.last_run = Some(last_run);
+                }
+                Err(err) => {
+                    eprintln!("rcpt: scheduled run failed: {:#}", err);
+                    heartbeat.last_error = Some(format!("{:#}", err));
+                }
+            }
+            heartbeat.running = false;
+
+            let skipped = self.times_between(next, Local::now());
+            heartbeat.missed_runs += skipped.len() as u64;
+            missed.extend(skipped);
+            if signals::received() {
+                heartbeat.next_run = None;
+                return self.write_heartbeat(&mut heartbeat);
+            }
+        }
+    }
+
+    fn run_once(
+        &self,
+        invocation: &Invocation,
+        opts: &ExecOptions,
+        policy: &RetryPolicy,
+        trigger: Trigger,
+    ) -> Result<LastRun> {
+        let scheduled_time = trigger.scheduled_time.unwrap_or_else(Utc::now);
+        let mut receipt = execute_with_retries(invocation, opts, policy)?;
+        receipt.trigger = Some(trigger);
+        let path = self
+            .out_dir
+            .join(template::render(template::DEFAULT, &Names::of(&receipt))?);
+        save_new_receipt(opts, &mut receipt, &path)?;
+        Ok(LastRun {
+            scheduled_time,
+            start_time: receipt.start_time,
+            status: receipt.status,
+            exit_code: receipt.exit_code,
+            receipt: opts.saved_to(&path).to_string_lossy().to_string(),
+        })
+    }
+
+    /// Sleep until `time`, refreshing the heartbeat along the way. Returns
+    /// false if rcpt was interrupted first.
+    fn sleep_until(&self, time: DateTime<Local>, heartbeat: &mut Heartbeat) -> Result<bool> {
+        let mut last_beat = Local::now();
+        loop {
+            if signals::received() {
+                return Ok(false);
+            }
+            let now = Local::now();
+            if now >= time {
+                return Ok(true);
+            }
+            if (now - last_beat).to_std().unwrap_or_default() >= HEARTBEAT_INTERVAL {
+                self.write_heartbeat(heartbeat)?;
+                last_beat = now;
+            }
+            let remaining = (time - now).to_std().unwrap_or_default();
+            thread::sleep(remaining.min(SIGNAL_CHECK));
+        }
+    }
+
+    /// Scheduled times strictly after `after` and before `until`
+    fn times_between(&self, after: DateTime<Local>, until: DateTime<Local>) -> Vec<DateTime<Utc>> {
+        let mut times = Vec::new();
+        let mut time = after;
+        while let Some(next) = self.schedule.next_after(time) {
+            if next >= until {
+                break;
+            }
+            times.push(next.with_timezone(&Utc));
+            time = next;
+        }
+        times
+    }
+
+    fn write_heartbeat(&self, heartbeat: &mut Heartbeat) -> Result<()> {
+        heartbeat.updated_at = Utc::now();
+        let json = serde_json::to_string_pretty(heartbeat)
+            .context("Failed to serialize heartbeat to JSON")?;
+        if let Some(parent) = self.heartbeat.parent() {
+            fs::create_dir_all(parent).context(format!(
+                "Failed to create parent directories for {}",
+                self.heartbeat.display()
+            ))?;
+        }
+        atomic::write(&self.heartbeat, json.as_bytes(), false).context(format!(
+            "Failed to write heartbeat to {}",
+            self.heartbeat.display()
+        ))
+    }
+}
+
+fn read_heartbeat(path: &Path) -> Option<Heartbeat> {
+    serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+}
@@ -0,0 +1,911 @@
+use anyhow::{bail, Result};
+use serde::de::{
+    self, DeserializeSeed, Deserializer, EnumAccess, Expected, IntoDeserializer, MapAccess,
+    SeqAccess, VariantAccess, Visitor,
+};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::receipt::{self, Receipt, ADDED_VALUES, RECEIPT_VERSION};
+
+/// The JSON Schema dialect the schema is written in
+const DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// The shape of a value, as its type's `Deserialize` impl asked for it
+#[derive(Debug, Clone)]
+enum Shape {
+    /// Anything at all, e.g. a `serde_json::Value`
+    Any,
+    Null,
+    Boolean,
+    Integer,
+    Unsigned,
+    Number,
+    String,
+    DateTime,
+    Optional(Box<Shape>),
+    Array(Box<Shape>),
+    Tuple(Vec<Shape>),
+    Map(Box<Shape>),
+    /// A struct or enum, defined by name under `$defs`
+    Named(&'static str),
+}
+
+/// A struct or enum found while tracing
+#[derive(Debug)]
+enum Definition {
+    /// Fields in declaration order, with whether each is required
+    Struct(Vec<(&'static str, Shape, bool)>),
+    /// Unit variants, serialized as strings
+    Enum(&'static [&'static str]),
+}
+
+/// What a trace has found so far
+struct Context {
+    definitions: RefCell<BTreeMap<&'static str, Definition>>,
+    /// A struct field to leave out, to find whether it is required
+    omit: Option<(&'static str, &'static str)>,
+}
+
+#[derive(Debug)]
+struct TraceError {
+    message: String,
+    /// The field a struct could not do without
+    missing: Option<&'static str>,
+}
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for TraceError {}
+
+impl de::Error for TraceError {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        TraceError {
+            message: message.to_string(),
+            missing: None,
+        }
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        TraceError {
+            message: format!("missing field `{}`", field),
+            missing: Some(field),
+        }
+    }
+}
+
+/// A deserializer that hands a type's `Deserialize` impl the smallest value
+/// of whatever it asks for, writing down what it asked for into `shape`
+struct Trace<'a> {
+    context: &'a Context,
+    shape: &'a mut Shape,
+}
+
+impl<'a> Trace<'a> {
+    fn mark(self, shape: Shape) -> &'a Context {
+        *self.shape = shape;
+        self.context
+    }
+}
+
+/// Deserialize through a fresh `Trace`, returning the value and its shape
+fn trace<'de, T: DeserializeSeed<'de>>(
+    context: &Context,
+    seed: T,
+) -> Result<(T::Value, Shape), TraceError> {
+    let mut shape = Shape::Any;
+    let value = seed.deserialize(Trace {
+        context,
+        shape: &mut shape,
+    })?;
+    Ok((value, shape))
+}
+
+impl<'de> Deserializer<'de> for Trace<'_> {
+    type Error = TraceError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        self.mark(Shape::Any);
+        visitor.visit_unit()
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        self.mark(Shape::Boolean);
+        visitor.visit_bool(false)
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        self.mark(Shape::Integer);
+        visitor.visit_i64(0)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        self.deserialize_u64(visitor)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        self.deserialize_u64(visitor)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        self.deserialize_u64(visitor)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        self.mark(Shape::Unsigned);
+        visitor.visit_u64(0)
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        self.deserialize_f64(visitor)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        self.mark(Shape::Number);
+        visitor.visit_f64(0.0)
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        self.mark(Shape::String);
+        visitor.visit_char('a')
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        // chrono's timestamps are strings too; they only say what they are
+        // in what they expect
+        let expecting = format!("{}", &visitor as &dyn Expected);
+        match expecting.contains("date and time") {
+            true => self.mark(Shape::DateTime),
+            false => self.mark(Shape::String),
+        };
+        visitor.visit_str("1970-01-01T00:00:00Z")
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        let mut inner = Shape::Any;
+        let value = visitor.visit_some(Trace {
+            context: self.context,
+            shape: &mut inner,
+        })?;
+        *self.shape = Shape::Optional(Box::new(inner));
+        Ok(value)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        self.mark(Shape::Null);
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        let mut elements = Elements {
+            context: self.context,
+            remaining: 1,
+            shapes: Vec::new(),
+        };
+        let value = visitor.visit_seq(&mut elements)?;
+        let element = elements.shapes.pop().unwrap_or(Shape::Any);
+        *self.shape = Shape::Array(Box::new(element));
+        Ok(value)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        let mut elements = Elements {
+            context: self.context,
+            remaining: len,
+            shapes: Vec::new(),
+        };
+        let value = visitor.visit_seq(&mut elements)?;
+        *self.shape = Shape::Tuple(elements.shapes);
+        Ok(value)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        let mut entry = Entry {
+            context: self.context,
+            done: false,
+            shape: Shape::Any,
+        };
+        let value = visitor.visit_map(&mut entry)?;
+        *self.shape = Shape::Map(Box::new(entry.shape));
+        Ok(value)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        let omit = self.context.omit;
+        let mut access = Fields {
+            context: self.context,
+            fields: fields
+                .iter()
+                .copied()
+                .filter(|field| omit != Some((name, field)))
+                .collect(),
+            next: 0,
+            shapes: Vec::new(),
+        };
+        let value = visitor.visit_map(&mut access)?;
+        let definition = Definition::Struct(
+            access
+                .shapes
+                .into_iter()
+                .map(|(field, shape)| (field, shape, false))
+                .collect(),
+        );
+        let context = self.mark(Shape::Named(name));
+        context
+            .definitions
+            .borrow_mut()
+            .entry(name)
+            .or_insert(definition);
+        Ok(value)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        let context = self.mark(Shape::Named(name));
+        context
+            .definitions
+            .borrow_mut()
+            .insert(name, Definition::Enum(variants));
+        visitor.visit_enum(Variant(variants[0]))
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        visitor.visit_unit()
+    }
+}
+
+/// The elements of a sequence or tuple, each traced once
+struct Elements<'a> {
+    context: &'a Context,
+    remaining: usize,
+    shapes: Vec<Shape>,
+}
+
+impl<'de> SeqAccess<'de> for Elements<'_> {
+    type Error = TraceError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, TraceError> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        let (value, shape) = trace(self.context, seed)?;
+        self.shapes.push(shape);
+        Ok(Some(value))
+    }
+}
+
+/// A map with one entry, whose value's shape stands for all of them
+struct Entry<'a> {
+    context: &'a Context,
+    done: bool,
+    shape: Shape,
+}
+
+impl<'de> MapAccess<'de> for Entry<'_> {
+    type Error = TraceError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, TraceError> {
+        if self.done {
+            return Ok(None);
+        }
+        self.done = true;
+        Ok(Some(trace(self.context, seed)?.0))
+    }
+
+    fn next_value_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<T::Value, TraceError> {
+        let (value, shape) = trace(self.context, seed)?;
+        self.shape = shape;
+        Ok(value)
+    }
+}
+
+/// A struct's fields, each traced once
+struct Fields<'a> {
+    context: &'a Context,
+    fields: Vec<&'static str>,
+    next: usize,
+    shapes: Vec<(&'static str, Shape)>,
+}
+
+impl<'de> MapAccess<'de> for Fields<'_> {
+    type Error = TraceError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, TraceError> {
+        let Some(field) = self.fields.get(self.next) else {
+            return Ok(None);
+        };
+        seed.deserialize(field.into_deserializer()).map(Some)
+    }
+
+    fn next_value_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<T::Value, TraceError> {
+        let field = self.fields[self.next];
+        self.next += 1;
+        let (value, shape) = trace(self.context, seed)?;
+        self.shapes.push((field, shape));
+        Ok(value)
+    }
+}
+
+/// An enum value, always its first variant
+struct Variant(&'static str);
+
+impl<'de> EnumAccess<'de> for Variant {
+    type Error = TraceError;
+    type Variant = Self;
+
+    fn variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<(T::Value, Self), TraceError> {
+        let value = seed.deserialize(self.0.into_deserializer())?;
+        Ok((value, self))
+    }
+}
+
+impl<'de> VariantAccess<'de> for Variant {
+    type Error = TraceError;
+
+    fn unit_variant(self) -> Result<(), TraceError> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        _seed: T,
+    ) -> Result<T::Value, TraceError> {
+        Err(de::Error::custom(
+            "only unit enum variants can be described",
+        ))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        _visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        Err(de::Error::custom(
+            "only unit enum variants can be described",
+        ))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        Err(de::Error::custom(
+            "only unit enum variants can be described",
+        ))
+    }
+}
+
+/// Trace `T`, returning the definitions it used and its own shape
+fn trace_type<T: for<'de> Deserialize<'de>>(
+    omit: Option<(&'static str, &'static str)>,
+) -> Result<(BTreeMap<&'static str, Definition>, Shape), TraceError> {
+    let context = Context {
+        definitions: RefCell::new(BTreeMap::new()),
+        omit,
+    };
+    let (_, shape) = trace(&context, std::marker::PhantomData::<T>)?;
+    Ok((context.definitions.into_inner(), shape))
+}
+
+/// The JSON Schema of `shape`
+fn schema_of(shape: &Shape) -> Value {
+    let mut schema = Map::new();
+    let mut typed = |name: &str| {
+        schema.insert("type".to_string(), Value::from(name));
+    };
+    match shape {
+        Shape::Any => {}
+        Shape::Null => typed("null"),
+        Shape::Boolean => typed("boolean"),
+        Shape::Integer => typed("integer"),
+        Shape::Unsigned => {
+            typed("integer");
+            schema.insert("minimum".to_string(), Value::from(0));
+        }
+        Shape::Number => typed("number"),
+        Shape::String => typed("string"),
+        Shape::DateTime => {
+            typed("string");
+            schema.insert("format".to_string(), Value::from("date-time"));
+        }
+        Shape::Optional(inner) => {
+            let null = schema_of(&Shape::Null);
+            schema.insert(
+                "anyOf".to_string(),
+                Value::from(vec![schema_of(inner), null]),
+            );
+        }
+        Shape::Array(element) => {
+            typed("array");
+            schema.insert("items".to_string(), schema_of(element));
+        }
+        Shape::Tuple(elements) => {
+            typed("array");
+            let items: Vec<Value> = elements.iter().map(schema_of).collect();
+            schema.insert("prefixItems".to_string(), Value::from(items));
+            schema.insert("minItems".to_string(), Value::from(elements.len()));
+            schema.insert("maxItems".to_string(), Value::from(elements.len()));
+        }
+        Shape::Map(value) => {
+            typed("object");
+            schema.insert("additionalProperties".to_string(), schema_of(value));
+        }
+        Shape::Named(name) => {
+            schema.insert("$ref".to_string(), Value::from(format!("#/$defs/{}", name)));
+        }
+    }
+    Value::Object(schema)
+}
+
+fn definition_schema(definition: &Definition) -> Value {
+    let mut schema = Map::new();
+    match definition {
+        Definition::Struct(fields) => {
+            schema.insert("type".to_string(), Value::from("object"));
+            let properties: Map<String, Value> = fields
+                .iter()
+                .map(|(field, shape, _)| (field.to_string(), schema_of(shape)))
+                .collect();
+            schema.insert("properties".to_string(), Value::Object(properties));
+            let required: Vec<&str> = fields
+                .iter()
+                .filter(|(_, _, required)| *required)
+                .map(|(field, _, _)| *field)
+                .collect();
+            schema.insert("required".to_string(), Value::from(required));
+        }
+        Definition::Enum(variants) => {
+            schema.insert("type".to_string(), Value::from("string"));
+            schema.insert("enum".to_string(), Value::from(variants.to_vec()));
+        }
+    }
+    Value::Object(schema)
+}
+
+/// The JSON Schema for receipts of `version`, traced from the `Receipt`
+/// types so it cannot drift from what rcpt reads
+pub fn receipt_schema(version: u32) -> Result<Value> {
+    if !(1..=RECEIPT_VERSION).contains(&version) {
+        bail!(
+            "no schema for receipt version {}; this rcpt knows versions 1 to {}",
+            version,
+            RECEIPT_VERSION
+        );
+    }
+    let (mut definitions, shape) = trace_type::<Receipt>(None)?;
+    // A field is required when its struct fails to deserialize without it
+    let fields: Vec<(&'static str, &'static str)> = definitions
+        .iter()
+        .filter_map(|(name, definition)| match definition {
+            Definition::Struct(fields) => Some(fields.iter().map(|(field, _, _)| (*name, *field))),
+            Definition::Enum(_) => None,
+        })
+        .flatten()
+        .collect();
+    for (name, field) in fields {
+        let required = match trace_type::<Receipt>(Some((name, field))) {
+            Ok(_) => false,
+            Err(error) if error.missing == Some(field) => true,
+            Err(error) => return Err(error.into()),
+        };
+        if let Some(Definition::Struct(fields)) = definitions.get_mut(name) {
+            for entry in fields.iter_mut().filter(|entry| entry.0 == field) {
+                entry.2 = required;
+            }
+        }
+    }
+
+    let mut schema = Map::new();
+    schema.insert("$schema".to_string(), Value::from(DIALECT));
+    schema.insert(
+        "title".to_string(),
+        Value::from(format!("rcpt receipt, version {}", version)),
+    );
+    if let Value::Object(root) = schema_of(&shape) {
+        schema.extend(root);
+    }
+    let mut definitions: Map<String, Value> = definitions
+        .iter()
+        .map(|(name, definition)| (name.to_string(), definition_schema(definition)))
+        .collect();
+    // Older versions lack the values enumerated fields gained since
+    for (_, name, value) in ADDED_VALUES.iter().filter(|(added, _, _)| *added > version) {
+        let values = definitions
+            .get_mut(*name)
+            .and_then(|schema| schema.get_mut("enum"));
+        if let Some(Value::Array(values)) = values {
+            values.retain(|known| known != value);
+        }
+    }
+    schema.insert("$defs".to_string(), Value::Object(definitions));
+    Ok(Value::Object(schema))
+}
+
+/// Something wrong with a receipt, at a JSON Pointer to where in it
+#[derive(Debug, Clone)]
+pub struct Problem {
+    pub pointer: String,
+    pub message: String,
+    /// Whether it is only a field the schema does not know, which readers
+    /// ignore, as it may have been added later in the same version
+    pub unknown: bool,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.pointer.is_empty() {
+            true => write!(f, "{}", self.message),
+            false => write!(f, "{}: {}", self.pointer, self.message),
+        }
+    }
+}
+
+/// Check `value`, a receipt's JSON, against the schema for the version it
+/// declares, returning everything wrong with it: values of the wrong type,
+/// required fields that are missing and fields the schema does not know
+pub fn validate(value: &Value) -> Result<Vec<Problem>> {
+    let problem = |pointer: &str, message: String| Problem {
+        pointer: pointer.to_string(),
+        message,
+        unknown: false,
+    };
+    let version = match value.get("receipt_version") {
+        None => 1,
+        Some(version) => match version.as_u64().and_then(|v| u32::try_from(v).ok()) {
+            Some(version) => version,
+            None => {
+                return Ok(vec![problem(
+                    "/receipt_version",
+                    format!("expected a positive integer, found {}", version),
+                )])
+            }
+        },
+    };
+    if !(1..=RECEIPT_VERSION).contains(&version) {
+        return Ok(vec![problem(
+            "/receipt_version",
+            format!(
+                "no schema for receipt version {}; this rcpt knows versions 1 to {}",
+                version, RECEIPT_VERSION
+            ),
+        )]);
+    }
+    // Receipts from before receipt_version are checked as rcpt reads them
+    let value = match value.is_object() {
+        true => &receipt::upgrade(value.clone())?,
+        false => value,
+    };
+    let schema = receipt_schema(version)?;
+    let definitions = match schema.get("$defs") {
+        Some(Value::Object(definitions)) => definitions.clone(),
+        _ => Map::new(),
+    };
+    let mut problems = Vec::new();
+    check(&schema, &definitions, value, "", &mut problems);
+    // What the schema cannot say rcpt still has to be able to read
+    if problems.iter().all(|problem| problem.unknown) {
+        if let Err(error) = serde_json::from_value::<Receipt>(value.clone()) {
+            problems.push(problem("", error.to_string()));
+        }
+    }
+    Ok(problems)
+}
+
+/// Add what is wrong with `value`, at `pointer`, by `schema` to `problems`
+fn check(
+    schema: &Value,
+    definitions: &Map<String, Value>,
+    value: &Value,
+    pointer: &str,
+    problems: &mut Vec<Problem>,
+) {
+    let mut problem = |message: String| {
+        problems.push(Problem {
+            pointer: pointer.to_string(),
+            message,
+            unknown: false,
+        })
+    };
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        let name = reference.trim_start_matches("#/$defs/");
+        match definitions.get(name) {
+            Some(definition) => check(definition, definitions, value, pointer, problems),
+            None => problem(format!("schema has no definition {}", name)),
+        }
+        return;
+    }
+    if let Some(Value::Array(alternatives)) = schema.get("anyOf") {
+        let mut first = None;
+        for alternative in alternatives {
+            let mut found = Vec::new();
+            check(alternative, definitions, value, pointer, &mut found);
+            if found.is_empty() {
+                return;
+            }
+            first.get_or_insert(found);
+        }
+        problems.extend(first.unwrap_or_default());
+        return;
+    }
+    if let Some(expected) = schema.get("type").and_then(Value::as_str) {
+        let found = kind(value);
+        let matches = expected == found || (expected == "number" && found == "integer");
+        if !matches {
+            problem(format!(
+                "expected {}, found {}",
+                article(expected),
+                article(found)
+            ));
+            return;
+        }
+    }
+    if let (Some(minimum), Some(number)) = (
+        schema.get("minimum").and_then(Value::as_f64),
+        value.as_f64(),
+    ) {
+        if number < minimum {
+            problem(format!("expected at least {}, found {}", minimum, value));
+        }
+    }
+    if schema.get("format").and_then(Value::as_str) == Some("date-time") {
+        if let Some(text) = value.as_str() {
+            if chrono::DateTime::parse_from_rfc3339(text).is_err() {
+                problem(format!("expected an RFC 3339 date-time, found {}", value));
+            }
+        }
+    }
+    if let Some(Value::Array(variants)) = schema.get("enum") {
+        if !variants.contains(value) {
+            let names: Vec<String> = variants.iter().map(Value::to_string).collect();
+            problem(format!(
+                "expected one of {}, found {}",
+                names.join(", "),
+                value
+            ));
+        }
+    }
+    if let Value::Array(elements) = value {
+        if let Some(Value::Array(items)) = schema.get("prefixItems") {
+            if items.len() != elements.len() {
+                problem(format!(
+                    "expected {} items, found {}",
+                    items.len(),
+                    elements.len()
+                ));
+            }
+            for (n, (item, element)) in items.iter().zip(elements).enumerate() {
+                check(
+                    item,
+                    definitions,
+                    element,
+                    &format!("{}/{}", pointer, n),
+                    problems,
+                );
+            }
+        } else if let Some(items) = schema.get("items") {
+            for (n, element) in elements.iter().enumerate() {
+                check(
+                    items,
+                    definitions,
+                    element,
+                    &format!("{}/{}", pointer, n),
+                    problems,
+                );
+            }
+        }
+    }
+    if let Value::Object(object) = value {
+        let field_pointer = |field: &str| {
+            format!(
+                "{}/{}",
+                pointer,
+                field.replace('~', "~0").replace('/', "~1")
+            )
+        };
+        if let Some(Value::Object(properties)) = schema.get("properties") {
+            for (field, field_value) in object {
+                match properties.get(field) {
+                    Some(property) => check(
+                        property,
+                        definitions,
+                        field_value,
+                        &field_pointer(field),
+                        problems,
+                    ),
+                    None => problems.push(Problem {
+                        pointer: field_pointer(field),
+                        message: "unknown field".to_string(),
+                        unknown: true,
+                    }),
+                }
+            }
+        } else if let Some(values) = schema.get("additionalProperties") {
+            for (field, field_value) in object {
+                check(
+                    values,
+                    definitions,
+                    field_value,
+                    &field_pointer(field),
+                    problems,
+                );
+            }
+        }
+        if let Some(Value::Array(required)) = schema.get("required") {
+            for field in required.iter().filter_map(Value::as_str) {
+                if !object.contains_key(field) {
+                    problems.push(Problem {
+                        pointer: field_pointer(field),
+                        message: "missing required field".to_string(),
+                        unknown: false,
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// The JSON Schema type of `value`
+fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(number) if number.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// `kind` with its indefinite article, for messages
+fn article(kind: &str) -> String {
+    match kind {
+        "null" => "null".to_string(),
+        "array" | "integer" | "object" => format!("an {}", kind),
+        _ => format!("a {}", kind),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The values the schema of `version` allows for the enum `name`
+    fn values(version: u32, name: &str) -> Vec<Value> {
+        let schema = receipt_schema(version).unwrap();
+        match &schema["$defs"][name]["enum"] {
+            Value::Array(values) => values.clone(),
+            other => panic!("{} is not an enum: {}", name, other),
+        }
+    }
+
+    #[test]
+    fn older_versions_lack_values_added_since() {
+        for (added, name, value) in ADDED_VALUES {
+            let value = Value::from(*value);
+            assert!(!values(added - 1, name).contains(&value));
+            assert!(values(*added, name).contains(&value));
+            assert!(values(RECEIPT_VERSION, name).contains(&value));
+        }
+        assert!(receipt_schema(0).is_err());
+        assert!(receipt_schema(RECEIPT_VERSION + 1).is_err());
+    }
+
+    #[test]
+    fn validates_against_the_declared_version() {
+        let receipt = |version: u32, status: &str| {
+            serde_json::json!({
+                "receipt_version": version,
+                "command": "make",
+                "args": [],
+                "exit_code": 0,
+                "status": status,
+                "stdout": "",
+                "stderr": "",
+                "start_time": "2026-02-16T01:00:00Z",
+                "end_time": "2026-02-16T01:00:01Z",
+                "duration_ms": 1000,
+            })
+        };
+        assert!(validate(&receipt(1, "ok")).unwrap().is_empty());
+        assert!(validate(&receipt(2, "missing_outputs")).unwrap().is_empty());
+        let problems = validate(&receipt(1, "missing_outputs")).unwrap();
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].pointer, "/status");
+        let problems = validate(&receipt(RECEIPT_VERSION + 1, "ok")).unwrap();
+        assert_eq!(problems[0].pointer, "/receipt_version");
+    }
+}
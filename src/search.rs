@@ -0,0 +1,121 @@
+use anyhow::Result;
+use std::fmt::Write as _;
+use std::path::Path;
+
+use crate::pattern::Pattern;
+use crate::receipt::Receipt;
+use crate::show::paint;
+
+/// How many lines are shown before and after each matching line unless
+/// --context says otherwise
+pub const DEFAULT_CONTEXT: usize = 2;
+
+/// What a line of output must contain
+#[derive(Debug, Clone)]
+pub enum Matcher {
+    /// This text, in any case if `ignore_case`
+    Text {
+        text: Vec<u8>,
+        ignore_case: bool,
+    },
+    Regex(Pattern),
+}
+
+impl Matcher {
+    /// A matcher for `pattern`: a regular expression if `regex`, else text
+    /// to look for as it is
+    pub fn new(pattern: &str, regex: bool, ignore_case: bool) -> Result<Self> {
+        Ok(match (regex, ignore_case) {
+            (true, false) => Matcher::Regex(Pattern::new(pattern)?),
+            (true, true) => Matcher::Regex(Pattern::new(&format!("(?i){}", pattern))?),
+            (false, _) => Matcher::Text {
+                text: match ignore_case {
+                    true => pattern.to_ascii_lowercase().into_bytes(),
+                    false => pattern.as_bytes().to_vec(),
+                },
+                ignore_case,
+            },
+        })
+    }
+
+    fn is_match(&self, line: &[u8]) -> bool {
+        match self {
+            Matcher::Text { text, ignore_case } => {
+                let line = match ignore_case {
+                    true => line.to_ascii_lowercase(),
+                    false => line.to_vec(),
+                };
+                text.is_empty() || line.windows(text.len()).any(|window| window == text)
+            }
+            Matcher::Regex(pattern) => pattern.find(line, 0).is_some(),
+        }
+    }
+}
+
+/// The lines of the receipt at `path`'s stdout and stderr that `matcher`
+/// matches, each with `context` lines around it, grep-style: `stream:line:`
+/// before a match and `stream-line-` before the lines around it. None if
+/// nothing matched
+pub fn search(
+    path: &Path,
+    receipt: &Receipt,
+    matcher: &Matcher,
+    context: usize,
+    color: bool,
+) -> Result<Option<String>> {
+    let mut found = String::new();
+    for stream in ["stdout", "stderr"] {
+        let output = receipt.written(stream, path)?;
+        let lines: Vec<&[u8]> = output.split(|&byte| byte == b'\n').collect();
+        // A last newline does not start another line
+        let lines = match lines.split_last() {
+            Some(([], rest)) => rest,
+            _ => &lines[..],
+        };
+        let hits: Vec<usize> = (0..lines.len())
+            .filter(|&n| matcher.is_match(lines[n]))
+            .collect();
+        let mut shown_to = 0;
+        for (i, &hit) in hits.iter().enumerate() {
+            let start = hit.saturating_sub(context).max(shown_to);
+            if i > 0 && start > shown_to {
+                let _ = writeln!(found, "  {}", paint(color, "2", "--"));
+            }
+            let end = (hit + context + 1).min(lines.len());
+            // The next match's context starts where this one's ends
+            let end = match hits.get(i + 1) {
+                Some(&next) if next.saturating_sub(context) < end => next,
+                _ => end,
+            };
+            for (n, line) in lines.iter().enumerate().take(end).skip(start) {
+                let text = String::from_utf8_lossy(line);
+                let _ = match n == hit {
+                    true => writeln!(
+                        found,
+                        "  {} {}",
+                        paint(color, "1;31", &format!("{}:{}:", stream, n + 1)),
+                        text
+                    ),
+                    false => writeln!(
+                        found,
+                        "  {} {}",
+                        paint(color, "2", &format!("{}-{}-", stream, n + 1)),
+                        text
+                    ),
+                };
+            }
+            shown_to = end;
+        }
+    }
+    if found.is_empty() {
+        return Ok(None);
+    }
+    let heading = format!(
+        "{}  {}  {}  {}",
+        path.display(),
+        receipt.start_time.format("%Y-%m-%dT%H:%M:%SZ"),
+        receipt.status.as_str(),
+        receipt.command_line()
+    );
+    Ok(Some(format!("{}\n{}", paint(color, "1", &heading), found)))
+}
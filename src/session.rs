@@ -0,0 +1,86 @@
+use anyhow::{bail, Result};
+use chrono::Utc;
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use crate::id;
+use crate::receipt::{json_files, read_receipt, Receipt, Status};
+
+/// Environment variable carrying the id of the session receipts belong to
+pub const SESSION_ENV: &str = "RCPT_SESSION";
+
+/// The session rcpt is running in, if one was started
+pub fn current() -> Option<String> {
+    std::env::var(SESSION_ENV)
+        .ok()
+        .filter(|session| !session.is_empty())
+}
+
+/// Mint a new session id
+pub fn start() -> String {
+    id::ulid(Utc::now())
+}
+
+/// Every receipt under `dirs` (searched recursively) stamped with
+/// `session_id`, in the order the runs started. Files that are not
+/// receipts are skipped.
+pub fn find(dirs: &[PathBuf], session_id: &str) -> Result<Vec<(PathBuf, Receipt)>> {
+    let mut receipts: Vec<(PathBuf, Receipt)> = json_files(dirs)?
+        .into_iter()
+        .filter_map(|path| {
+            let receipt = read_receipt(&path).ok()?;
+            (receipt.session_id.as_deref() == Some(session_id)).then_some((path, receipt))
+        })
+        .collect();
+    receipts.sort_by(|(_, a), (_, b)| (a.start_time, &a.id).cmp(&(b.start_time, &b.id)));
+    Ok(receipts)
+}
+
+/// One line per receipt in the session and a summary of them
+pub fn render(session_id: &str, dirs: &[PathBuf]) -> Result<String> {
+    let receipts = find(dirs, session_id)?;
+    if receipts.is_empty() {
+        bail!("No receipts found for session {}", session_id);
+    }
+    let mut out = String::new();
+    for (path, receipt) in &receipts {
+        let command = std::iter::once(receipt.command.as_str())
+            .chain(receipt.args.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let _ = writeln!(
+            out,
+            "{}  {:<15} {:>9}ms  {}  ({})",
+            receipt.start_time.format("%Y-%m-%dT%H:%M:%SZ"),
+            receipt.status.as_str(),
+            receipt.duration_ms,
+            command,
+            path.display()
+        );
+    }
+    let succeeded = receipts
+        .iter()
+        .filter(|(_, receipt)| receipt.status == Status::Ok)
+        .count();
+    let first = receipts.first().map(|(_, receipt)| receipt.start_time);
+    let last = receipts.iter().map(|(_, receipt)| receipt.end_time).max();
+    let span = first
+        .zip(last)
+        .map(|(first, last)| (last - first).num_milliseconds().max(0))
+        .unwrap_or_default();
+    let busy: u64 = receipts
+        .iter()
+        .map(|(_, receipt)| receipt.duration_ms)
+        .sum();
+    let _ = writeln!(
+        out,
+        "Session {}: {} receipts, {} succeeded, {} failed; {}ms running over {}ms",
+        session_id,
+        receipts.len(),
+        succeeded,
+        receipts.len() - succeeded,
+        busy,
+        span
+    );
+    Ok(out)
+}
@@ -0,0 +1,242 @@
+use anyhow::{Context, Result};
+use std::ffi::OsStr;
+use std::fmt::Write as _;
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::exec::resolve_executable;
+use crate::receipt::{Receipt, ResourceUsage, Status};
+use crate::units::format_size;
+
+/// How many of the last lines of each stream are shown unless --lines
+/// says otherwise
+pub const DEFAULT_LINES: usize = 20;
+
+/// The pager used when $PAGER is not set: quit if the text fits, keep
+/// colors and leave the text on the screen afterwards
+const DEFAULT_PAGER: &[&str] = &["less", "-FRX"];
+
+/// How a receipt is shown
+#[derive(Debug, Clone, Copy)]
+pub struct Style {
+    /// The last lines of each stream to show, or 0 for all of them
+    pub lines: usize,
+    /// Color the status and the stream headings
+    pub color: bool,
+}
+
+impl Style {
+    /// Colored as `use_color` says
+    pub fn detect(lines: usize) -> Self {
+        Style {
+            lines,
+            color: use_color(),
+        }
+    }
+
+    fn paint(&self, code: &str, text: &str) -> String {
+        paint(self.color, code, text)
+    }
+}
+
+/// Whether to color what is printed: if stdout is a terminal and NO_COLOR
+/// is not set
+pub fn use_color() -> bool {
+    std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+}
+
+/// `text` in the ANSI SGR `code`, e.g. `31` for red, if `color`
+pub fn paint(color: bool, code: &str, text: &str) -> String {
+    match color {
+        true => format!("\x1b[{}m{}\x1b[0m", code, text),
+        false => text.to_string(),
+    }
+}
+
+/// The receipt at `path` as a page to read: what ran, how it ended, what
+/// it used, and the last lines of what it wrote
+pub fn render(path: &Path, receipt: &Receipt, style: &Style) -> String {
+    let mut page = String::new();
+    let mut field = |label: &str, value: &str| {
+        let _ = writeln!(page, "{:<10} {}", format!("{}:", label), value);
+    };
+    field("Receipt", &receipt.id);
+    field("Path", &path.display().to_string());
+    field("Command", &receipt.command_line());
+    field("Status", &outcome(receipt, style));
+    field(
+        "Started",
+        &receipt
+            .start_time
+            .format("%Y-%m-%dT%H:%M:%S%.3fZ")
+            .to_string(),
+    );
+    field("Duration", &format!("{}ms", receipt.duration_ms));
+    if receipt.attempts.len() > 1 {
+        field("Attempts", &receipt.attempts.len().to_string());
+    }
+    field("Cwd", &receipt.cwd);
+    if let Some(hostname) = receipt
+        .host
+        .as_ref()
+        .and_then(|host| host.hostname.as_ref())
+    {
+        field("Host", hostname);
+    }
+    if !receipt.tags.is_empty() {
+        field("Tags", &receipt.tags.join(", "));
+    }
+    if let Some(session) = &receipt.session_id {
+        field("Session", session);
+    }
+    if let Some(parent) = &receipt.parent_id {
+        field("Parent", parent);
+    }
+    if receipt.imported {
+        let metadata = |key: &str| receipt.metadata.get(key).and_then(|value| value.as_str());
+        match (metadata("import_source"), metadata("import_format")) {
+            (Some(source), Some(format)) => {
+                field("Imported", &format!("from {} ({})", source, format))
+            }
+            _ => field("Imported", "yes"),
+        }
+    }
+    if let Some(resources) = &receipt.resources {
+        field("Resources", &resource_usage(resources));
+    }
+    let mut proofs = Vec::new();
+    if receipt.signature.is_some() {
+        proofs.push("signed");
+    }
+    if receipt.timestamp.is_some() {
+        proofs.push("timestamped");
+    }
+    if !proofs.is_empty() {
+        field("Proofs", &proofs.join(", "));
+    }
+
+    for stream in ["stdout", "stderr"] {
+        let _ = writeln!(page);
+        let heading = style.paint("1", &format!("── {} ", stream));
+        match receipt.written(stream, path) {
+            Ok(bytes) if bytes.is_empty() => {
+                let _ = writeln!(page, "{}{}", heading, style.paint("2", "(empty)"));
+            }
+            Ok(bytes) => {
+                let _ = writeln!(
+                    page,
+                    "{}{}",
+                    heading,
+                    style.paint("2", &format_size(bytes.len() as u64))
+                );
+                page.push_str(&tail(&String::from_utf8_lossy(&bytes), style));
+            }
+            Err(err) => {
+                let _ = writeln!(
+                    page,
+                    "{}{}",
+                    heading,
+                    style.paint("2", &format!("(unavailable: {:#})", err))
+                );
+            }
+        }
+    }
+    page
+}
+
+/// How the run ended, e.g. `failed (exit code 2)` or `interrupted (SIGINT)`
+fn outcome(receipt: &Receipt, style: &Style) -> String {
+    let color = match receipt.status {
+        Status::Ok => "32",
+        Status::Planned | Status::Running => "33",
+        _ => "31",
+    };
+    let mut outcome = style.paint(color, receipt.status.as_str());
+    if let Some(code) = receipt.exit_code {
+        outcome.push_str(&format!(" (exit code {})", code));
+    } else if let Some(signal) = &receipt.terminated_by {
+        outcome.push_str(&format!(" ({})", signal.name));
+    }
+    outcome
+}
+
+/// CPU time, then whichever of memory and I/O were measured
+fn resource_usage(resources: &ResourceUsage) -> String {
+    let mut parts = vec![format!(
+        "cpu {:.0}ms user, {:.0}ms system",
+        resources.cpu_user_ms, resources.cpu_system_ms
+    )];
+    if let Some(peak) = resources.memory_peak_bytes.or(resources.max_rss_bytes) {
+        parts.push(format!("memory peak {}", format_size(peak)));
+    }
+    let read = resources.io_read_bytes.or(resources.io_syscall_read_bytes);
+    let write = resources
+        .io_write_bytes
+        .or(resources.io_syscall_write_bytes);
+    if let (Some(read), Some(write)) = (read, write) {
+        parts.push(format!(
+            "io {} read, {} written",
+            format_size(read),
+            format_size(write)
+        ));
+    }
+    parts.join("; ")
+}
+
+/// The last `style.lines` lines of `text`, after a note of how many were
+/// left out
+fn tail(text: &str, style: &Style) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    let skip = match style.lines {
+        0 => 0,
+        shown => lines.len().saturating_sub(shown),
+    };
+    let mut out = String::new();
+    if skip > 0 {
+        let _ = writeln!(
+            out,
+            "{}",
+            style.paint("2", &format!("… {} earlier lines", skip))
+        );
+    }
+    for line in &lines[skip..] {
+        let _ = writeln!(out, "{}", line);
+    }
+    out
+}
+
+/// Show `page` through `pager`, returning false if it could not be started
+pub fn page(page: &str, mut pager: Command) -> Result<bool> {
+    let Ok(mut child) = pager.stdin(Stdio::piped()).spawn() else {
+        return Ok(false);
+    };
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    // The reader may quit the pager before reading it all
+    let _ = stdin.write_all(page.as_bytes());
+    drop(stdin);
+    child.wait().context("Failed to wait for the pager")?;
+    Ok(true)
+}
+
+/// The pager to show a page through when stdout is a terminal: $PAGER, or
+/// `less -FRX`, if it can be found
+pub fn pager() -> Option<Command> {
+    if !std::io::stdout().is_terminal() {
+        return None;
+    }
+    let pager = std::env::var("PAGER").unwrap_or_default();
+    let mut words: Vec<&str> = pager.split_whitespace().collect();
+    if words.is_empty() {
+        words = DEFAULT_PAGER.to_vec();
+    }
+    let cwd = std::env::current_dir().ok()?;
+    let program = resolve_executable(
+        OsStr::new(words[0]),
+        std::env::var_os("PATH").as_ref(),
+        &cwd,
+    )?;
+    let mut command = Command::new(program);
+    command.args(&words[1..]);
+    Some(command)
+}
@@ -0,0 +1,190 @@
+use std::process::ExitStatus;
+
+use crate::receipt::TerminatedBy;
+
+#[cfg(unix)]
+mod imp {
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+    use std::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+
+    /// Most recent signal received (0 if none)
+    static LAST: AtomicI32 = AtomicI32::new(0);
+    /// Number of signals received so far
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    extern "C" fn record(signal: libc::c_int) {
+        LAST.store(signal, Ordering::SeqCst);
+        COUNT.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn install() {
+        for signal in [libc::SIGINT, libc::SIGTERM] {
+            // SAFETY: the handler only stores to an atomic, which is
+            // async-signal-safe, and the sigaction struct is fully initialized
+            unsafe {
+                let mut action: libc::sigaction = std::mem::zeroed();
+                action.sa_sigaction = record as *const () as libc::sighandler_t;
+                action.sa_flags = libc::SA_RESTART;
+                libc::sigemptyset(&mut action.sa_mask);
+                libc::sigaction(signal, &action, std::ptr::null_mut());
+            }
+        }
+    }
+
+    pub fn count() -> usize {
+        COUNT.load(Ordering::SeqCst)
+    }
+
+    pub fn last() -> Option<i32> {
+        match LAST.load(Ordering::SeqCst) {
+            0 => None,
+            signal => Some(signal),
+        }
+    }
+
+    pub const SIGTERM: i32 = libc::SIGTERM;
+    pub const SIGKILL: i32 = libc::SIGKILL;
+
+    pub fn name(signal: i32) -> String {
+        let name = match signal {
+            libc::SIGHUP => "SIGHUP",
+            libc::SIGINT => "SIGINT",
+            libc::SIGQUIT => "SIGQUIT",
+            libc::SIGILL => "SIGILL",
+            libc::SIGTRAP => "SIGTRAP",
+            libc::SIGABRT => "SIGABRT",
+            libc::SIGBUS => "SIGBUS",
+            libc::SIGFPE => "SIGFPE",
+            libc::SIGKILL => "SIGKILL",
+            libc::SIGUSR1 => "SIGUSR1",
+            libc::SIGSEGV => "SIGSEGV",
+            libc::SIGUSR2 => "SIGUSR2",
+            libc::SIGPIPE => "SIGPIPE",
+            libc::SIGALRM => "SIGALRM",
+            libc::SIGTERM => "SIGTERM",
+            libc::SIGCHLD => "SIGCHLD",
+            libc::SIGCONT => "SIGCONT",
+            libc::SIGSTOP => "SIGSTOP",
+            libc::SIGTSTP => "SIGTSTP",
+            libc::SIGTTIN => "SIGTTIN",
+            libc::SIGTTOU => "SIGTTOU",
+            libc::SIGURG => "SIGURG",
+            libc::SIGXCPU => "SIGXCPU",
+            libc::SIGXFSZ => "SIGXFSZ",
+            libc::SIGVTALRM => "SIGVTALRM",
+            libc::SIGPROF => "SIGPROF",
+            libc::SIGWINCH => "SIGWINCH",
+            libc::SIGIO => "SIGIO",
+            libc::SIGSYS => "SIGSYS",
+            _ => return format!("signal {}", signal),
+        };
+        name.to_string()
+    }
+
+    pub fn termination(status: &ExitStatus) -> Option<(i32, bool)> {
+        Some((status.signal()?, status.core_dumped()))
+    }
+
+    pub fn terminal_interrupt(status: &ExitStatus) -> Option<i32> {
+        status
+            .signal()
+            .filter(|&signal| signal == libc::SIGINT || signal == libc::SIGQUIT)
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use std::process::ExitStatus;
+
+    pub fn install() {}
+
+    pub fn count() -> usize {
+        0
+    }
+
+    pub fn last() -> Option<i32> {
+        None
+    }
+
+    // Nominal numbers; without signals the only way to stop a command is
+    // TerminateProcess, and SIGTERM is never actually delivered
+    pub const SIGTERM: i32 = 15;
+    pub const SIGKILL: i32 = 9;
+
+    pub fn name(signal: i32) -> String {
+        match signal {
+            SIGKILL => "TerminateProcess".to_string(),
+            _ => format!("signal {}", signal),
+        }
+    }
+
+    pub fn termination(_status: &ExitStatus) -> Option<(i32, bool)> {
+        None
+    }
+
+    pub fn terminal_interrupt(_status: &ExitStatus) -> Option<i32> {
+        None
+    }
+}
+
+pub use imp::{SIGKILL, SIGTERM};
+
+/// Start intercepting SIGINT and SIGTERM (Unix only)
+pub fn install() {
+    imp::install()
+}
+
+/// Whether rcpt has received SIGINT or SIGTERM at any point
+pub fn received() -> bool {
+    imp::count() > 0
+}
+
+/// Observer of signals received after it was created. Every command being
+/// waited on has its own, so a single Ctrl-C reaches all of them.
+pub struct Watch {
+    seen: usize,
+}
+
+impl Default for Watch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Watch {
+    pub fn new() -> Self {
+        Watch { seen: imp::count() }
+    }
+
+    /// The latest signal received since the previous poll, if any
+    pub fn poll(&mut self) -> Option<i32> {
+        let count = imp::count();
+        if count == self.seen {
+            return None;
+        }
+        self.seen = count;
+        imp::last()
+    }
+}
+
+/// Conventional name of a signal, e.g. `SIGTERM`
+pub fn name(signal: i32) -> String {
+    imp::name(signal)
+}
+
+/// The signal that terminated a process, if it did not exit (Unix only)
+pub fn terminated_by(status: &ExitStatus) -> Option<TerminatedBy> {
+    let (signal, core_dumped) = imp::termination(status)?;
+    Some(TerminatedBy {
+        signal,
+        name: imp::name(signal),
+        core_dumped,
+    })
+}
+
+/// The keyboard signal (SIGINT/SIGQUIT) that terminated a command which held
+/// the terminal, i.e. an interrupt typed by the user that bypassed rcpt
+pub fn terminal_interrupt(status: &ExitStatus) -> Option<i32> {
+    imp::terminal_interrupt(status)
+}
@@ -0,0 +1,515 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::atomic;
+use crate::base64;
+use crate::canonical;
+use crate::digest;
+use crate::ed25519::{self, SigningKey};
+use crate::id;
+use crate::objects;
+use crate::receipt::{self, Encoding, Receipt};
+use crate::sigstore;
+use crate::sshsig::{self, SshKey};
+use crate::timestamp::{self, TimestampRecord};
+
+/// DER of a PKCS#8 Ed25519 private key, up to the 32-byte seed
+const PRIVATE_KEY_PREFIX: [u8; 16] = [
+    0x30, 0x2e, 0x02, 0x01, 0x00, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x04, 0x22, 0x04, 0x20,
+];
+
+/// DER of an Ed25519 SubjectPublicKeyInfo, up to the 32-byte key
+const PUBLIC_KEY_PREFIX: [u8; 12] = [
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+
+/// How a receipt was signed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureAlgorithm {
+    /// A raw Ed25519 signature, with --sign
+    Ed25519,
+    /// An SSH signature (`ssh-keygen -Y sign`), with --sign-ssh
+    Ssh,
+    /// A Sigstore keyless signature (`cosign sign-blob`), with
+    /// --sign-keyless
+    Sigstore,
+}
+
+impl fmt::Display for SignatureAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            SignatureAlgorithm::Ed25519 => "made with an Ed25519 key",
+            SignatureAlgorithm::Ssh => "made with an SSH key",
+            SignatureAlgorithm::Sigstore => "a Sigstore keyless signature",
+        })
+    }
+}
+
+/// A signature over a receipt, or over a bundle's manifest
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignatureRecord {
+    /// Signature scheme
+    pub algorithm: SignatureAlgorithm,
+    /// Identifies the public key: its SHA-256 for `ed25519`, its
+    /// `SHA256:...` fingerprint for `ssh`, the SHA-256 of the certificate
+    /// for `sigstore`
+    pub key_id: String,
+    /// The public key: base64 of the raw 32-byte key for `ed25519`, the
+    /// OpenSSH `<type> <base64>` form for `ssh`, base64 of the Fulcio
+    /// certificate for `sigstore`
+    pub public_key: String,
+    /// The signature over the receipt's canonical JSON without its
+    /// `signature`: base64 of the 64 bytes for `ed25519`, the armored
+    /// `SSH SIGNATURE` block (namespace `rcpt`) for `ssh`, base64 for
+    /// `sigstore`
+    pub signature: String,
+    /// Index of the signature's entry in the Rekor transparency log
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_index: Option<u64>,
+    /// cosign's bundle of the signature, certificate and Rekor entry, for
+    /// `sigstore`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bundle: Option<serde_json::Value>,
+}
+
+/// Who a receipt's signature must come from, for `rcpt verify`
+#[derive(Debug, Default)]
+pub struct TrustedSigners {
+    /// Ed25519 PEM public key
+    pub key: Option<PathBuf>,
+    /// SSH allowed_signers file
+    pub allowed_signers: Option<PathBuf>,
+    /// Identity in the Sigstore certificate (e.g. an email address or CI
+    /// workflow URL)
+    pub certificate_identity: Option<String>,
+    /// OIDC issuer that vouched for the identity
+    pub certificate_oidc_issuer: Option<String>,
+    /// CA certificates the timestamp's authority must chain to, instead of
+    /// the system's
+    pub tsa_ca_file: Option<PathBuf>,
+}
+
+/// What receipts are signed with
+enum Key {
+    Ed25519(SigningKey),
+    Ssh(SshKey),
+    /// An ephemeral key certified for the signer's OIDC identity
+    Keyless(PathBuf),
+}
+
+/// The key receipts are signed with, from --sign, --sign-ssh or
+/// --sign-keyless
+pub struct Signer {
+    key: Key,
+    /// Write the signature to `<receipt>.sig` instead of into the receipt
+    pub detached: bool,
+}
+
+impl fmt::Debug for Signer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let key_id = match &self.key {
+            Key::Ed25519(key) => key_id(&key.public_key()),
+            Key::Ssh(key) => key.fingerprint.clone(),
+            Key::Keyless(_) => "keyless".to_string(),
+        };
+        f.debug_struct("Signer")
+            .field("key_id", &key_id)
+            .field("detached", &self.detached)
+            .finish()
+    }
+}
+
+impl Signer {
+    /// Load the PEM private key at `path`, as written by `rcpt keygen` or
+    /// `openssl genpkey -algorithm ed25519`
+    pub fn load(path: &Path, detached: bool) -> Result<Self> {
+        let der = read_pem(path, "PRIVATE KEY")?;
+        let Some(seed) = der.strip_prefix(PRIVATE_KEY_PREFIX.as_slice()) else {
+            bail!("{} is not an Ed25519 private key", path.display());
+        };
+        let seed: [u8; 32] = seed
+            .try_into()
+            .context(format!("{} is not an Ed25519 private key", path.display()))?;
+        Ok(Signer {
+            key: Key::Ed25519(SigningKey::from_seed(seed)),
+            detached,
+        })
+    }
+
+    /// Sign without a long-lived key, through Sigstore's Fulcio and Rekor
+    pub fn keyless(detached: bool) -> Result<Self> {
+        Ok(Signer {
+            key: Key::Keyless(sigstore::cosign()?),
+            detached,
+        })
+    }
+
+    /// Sign with the SSH key at `path` through ssh-keygen: a private key
+    /// file, or a public key whose private half ssh-agent holds
+    pub fn load_ssh(path: &Path, detached: bool) -> Result<Self> {
+        Ok(Signer {
+            key: Key::Ssh(SshKey::load(path)?),
+            detached,
+        })
+    }
+
+    /// Sign the receipt that will be written to `out`: into its `signature`,
+    /// or into `<out>.sig` when detached
+    pub fn sign(&self, receipt: &mut Receipt, out: &Path, fsync: bool) -> Result<()> {
+        receipt.signature = None;
+        let message = canonical::to_string(&receipt::to_value(receipt)?);
+        let record = self.signature(message.as_bytes())?;
+        if self.detached {
+            let path = detached_path(out);
+            let json = canonical::to_string(&serde_json::to_value(&record)?);
+            atomic::write(&path, json.as_bytes(), fsync)
+                .context(format!("Failed to write {}", path.display()))?;
+        } else {
+            receipt.signature = Some(record);
+        }
+        Ok(())
+    }
+
+    /// A signature over `message`
+    pub fn signature(&self, message: &[u8]) -> Result<SignatureRecord> {
+        Ok(match &self.key {
+            Key::Ed25519(key) => SignatureRecord {
+                algorithm: SignatureAlgorithm::Ed25519,
+                key_id: key_id(&key.public_key()),
+                public_key: base64::encode(&key.public_key()),
+                signature: base64::encode(&key.sign(message)),
+                log_index: None,
+                bundle: None,
+            },
+            Key::Ssh(key) => SignatureRecord {
+                algorithm: SignatureAlgorithm::Ssh,
+                key_id: key.fingerprint.clone(),
+                public_key: key.public_key.clone(),
+                signature: key.sign(message)?,
+                log_index: None,
+                bundle: None,
+            },
+            Key::Keyless(cosign) => {
+                let signed = sigstore::sign(cosign, message)?;
+                SignatureRecord {
+                    algorithm: SignatureAlgorithm::Sigstore,
+                    key_id: certificate_id(&signed.certificate),
+                    public_key: signed.certificate,
+                    signature: signed.signature,
+                    log_index: signed.log_index,
+                    bundle: Some(signed.bundle),
+                }
+            }
+        })
+    }
+}
+
+/// Where a detached signature of the receipt at `receipt` is kept
+pub fn detached_path(receipt: &Path) -> PathBuf {
+    let mut path = OsString::from(receipt.as_os_str());
+    path.push(".sig");
+    PathBuf::from(path)
+}
+
+fn key_id(public: &[u8; 32]) -> String {
+    digest::sha256_hex(public)
+}
+
+/// SHA-256 of a base64 certificate
+fn certificate_id(certificate: &str) -> String {
+    let der = base64::decode(certificate).unwrap_or_else(|_| certificate.as_bytes().to_vec());
+    digest::sha256_hex(&der)
+}
+
+/// Write a new private key to `path` and its public key to `<path>.pub`,
+/// refusing to overwrite either
+pub fn keygen(path: &Path) -> Result<String> {
+    let mut seed = [0u8; 32];
+    id::secure_random(&mut seed)?;
+    let key = SigningKey::from_seed(seed);
+
+    let mut private = PRIVATE_KEY_PREFIX.to_vec();
+    private.extend_from_slice(key.seed());
+    let mut public = PUBLIC_KEY_PREFIX.to_vec();
+    public.extend_from_slice(&key.public_key());
+    let mut public_path = OsString::from(path.as_os_str());
+    public_path.push(".pub");
+    let public_path = PathBuf::from(public_path);
+    if public_path.exists() {
+        bail!("{} already exists", public_path.display());
+    }
+
+    write_new(path, &pem("PRIVATE KEY", &private), true)?;
+    write_new(&public_path, &pem("PUBLIC KEY", &public), false)?;
+    Ok(key_id(&key.public_key()))
+}
+
+/// Create `path` with `contents`, readable only by its owner if `secret`
+fn write_new(path: &Path, contents: &str, secret: bool) -> Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    if secret {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    #[cfg(not(unix))]
+    let _ = secret;
+    let mut file = options
+        .open(path)
+        .context(format!("Failed to create {}", path.display()))?;
+    file.write_all(contents.as_bytes())
+        .context(format!("Failed to write {}", path.display()))
+}
+
+fn pem(label: &str, der: &[u8]) -> String {
+    let encoded = base64::encode(der);
+    let mut pem = format!("-----BEGIN {}-----\n", label);
+    for line in encoded.as_bytes().chunks(64) {
+        pem.push_str(&String::from_utf8_lossy(line));
+        pem.push('\n');
+    }
+    pem.push_str(&format!("-----END {}-----\n", label));
+    pem
+}
+
+fn read_pem(path: &Path, label: &str) -> Result<Vec<u8>> {
+    let text = fs::read_to_string(path).context(format!("Failed to read {}", path.display()))?;
+    let begin = format!("-----BEGIN {}-----", label);
+    let end = format!("-----END {}-----", label);
+    let body = text
+        .split_once(&begin)
+        .and_then(|(_, rest)| rest.split_once(&end))
+        .map(|(body, _)| body)
+        .context(format!(
+            "{} is not a PEM {}",
+            path.display(),
+            label.to_lowercase()
+        ))?;
+    let encoded: String = body.split_whitespace().collect();
+    base64::decode(&encoded).context(format!("{} is not valid PEM", path.display()))
+}
+
+/// Load the PEM public key at `path`
+fn load_public_key(path: &Path) -> Result<[u8; 32]> {
+    let der = read_pem(path, "PUBLIC KEY")?;
+    der.strip_prefix(PUBLIC_KEY_PREFIX.as_slice())
+        .and_then(|key| key.try_into().ok())
+        .context(format!("{} is not an Ed25519 public key", path.display()))
+}
+
+/// Check the receipt at `path`: that its `receipt_digest` matches, that its
+/// timestamp is a valid token over the digest, that the output it keeps
+/// elsewhere matches its digests, and that its signature (embedded or in
+/// `<path>.sig`) is valid and, when `trusted` names a key, allowed signers
+/// or identity, made by them. Writes each check that passed to `report` and
+/// fails with the first that did not.
+pub fn verify(path: &Path, trusted: &TrustedSigners, report: &mut impl Write) -> Result<()> {
+    let contents = receipt::read_json(path)?;
+    let mut value: serde_json::Value = serde_json::from_str(&contents)
+        .context(format!("Failed to parse receipt {}", path.display()))?;
+
+    match value
+        .get("receipt_digest")
+        .and_then(|digest| digest.as_str())
+    {
+        None => bail!("receipt_digest: missing"),
+        Some(digest) if digest != receipt::receipt_digest(&value) => {
+            bail!("receipt_digest: does not match the receipt's contents")
+        }
+        Some(_) => writeln!(report, "receipt_digest: ok")?,
+    }
+
+    match value.get("timestamp") {
+        Some(record) => {
+            let record: TimestampRecord =
+                serde_json::from_value(record.clone()).context("timestamp: malformed")?;
+            let digest = receipt::receipt_digest(&value);
+            timestamp::verify(&record, &digest, trusted.tsa_ca_file.as_deref())
+                .context("timestamp: invalid")?;
+            writeln!(
+                report,
+                "timestamp: ok ({} from {})",
+                record.time, record.tsa
+            )?;
+        }
+        None if trusted.tsa_ca_file.is_some() => bail!("timestamp: missing"),
+        None => writeln!(report, "timestamp: none")?,
+    }
+
+    let parsed = receipt::parse_receipt(&contents)
+        .context(format!("Failed to parse receipt {}", path.display()))?;
+    // Objects are read back through their digests, which checks them
+    let store = parsed.object_dir(path).unwrap_or_default();
+    for (stream, encoding) in [
+        ("stdout", parsed.stdout_encoding),
+        ("stderr", parsed.stderr_encoding),
+    ] {
+        if encoding == Encoding::Object {
+            parsed
+                .output(stream, path)
+                .context(format!("{}: unreadable", stream))?;
+            writeln!(report, "{}: ok (object in {})", stream, store.display())?;
+        }
+    }
+    for output in parsed.outputs.iter().filter(|output| output.stored) {
+        objects::read(&store, &output.sha256)
+            .context(format!("output {}: unreadable", output.path))?;
+        writeln!(
+            report,
+            "output {}: ok (object in {})",
+            output.path,
+            store.display()
+        )?;
+    }
+    for (stream, compression) in [
+        ("stdout", &parsed.stdout_compression),
+        ("stderr", &parsed.stderr_compression),
+    ] {
+        let Some(compression) = compression else {
+            continue;
+        };
+        let output = parsed
+            .output(stream, path)
+            .context(format!("{}: unreadable", stream))?;
+        if digest::sha256_hex(&output) != compression.original_sha256 {
+            bail!("{}: does not match its original_sha256", stream);
+        }
+        writeln!(
+            report,
+            "{}: ok (decompressed {} bytes to {})",
+            stream, compression.compressed_bytes, compression.original_bytes
+        )?;
+    }
+    for sidecar in &parsed.sidecars {
+        let output = parsed
+            .output(&sidecar.stream, path)
+            .context(format!("sidecar {}: unreadable", sidecar.path))?;
+        if output.len() as u64 != sidecar.size_bytes
+            || digest::sha256_hex(&output) != sidecar.sha256
+        {
+            bail!("sidecar {}: does not match its sha256", sidecar.path);
+        }
+        writeln!(
+            report,
+            "sidecar {}: ok ({} bytes)",
+            sidecar.path, sidecar.size_bytes
+        )?;
+    }
+
+    let embedded = value
+        .as_object_mut()
+        .and_then(|object| object.remove("signature"));
+    let sig_path = detached_path(path);
+    let record: SignatureRecord = match embedded {
+        Some(record) => serde_json::from_value(record).context("signature: malformed")?,
+        None if sig_path.exists() => {
+            let json = fs::read_to_string(&sig_path)
+                .context(format!("Failed to read {}", sig_path.display()))?;
+            serde_json::from_str(&json)
+                .context(format!("signature: {} is malformed", sig_path.display()))?
+        }
+        None if trusted.key.is_some()
+            || trusted.allowed_signers.is_some()
+            || trusted.certificate_identity.is_some() =>
+        {
+            bail!("signature: missing")
+        }
+        None => {
+            writeln!(report, "signature: none")?;
+            return Ok(());
+        }
+    };
+    let message = canonical::to_string(&value);
+    let signer = check_signature(&record, message.as_bytes(), trusted)?;
+    writeln!(report, "signature: ok ({})", signer)?;
+    Ok(())
+}
+
+/// Check `record` is a valid signature over `message` and, when `trusted`
+/// names a key, allowed signers or identity, made by them, returning who
+/// made it
+pub fn check_signature(
+    record: &SignatureRecord,
+    message: &[u8],
+    trusted: &TrustedSigners,
+) -> Result<String> {
+    let wrong_check =
+        |flag: &str| anyhow::anyhow!("signature: {}; check it with {}", record.algorithm, flag);
+    let signer = match record.algorithm {
+        SignatureAlgorithm::Ed25519 => {
+            if trusted.allowed_signers.is_some() || trusted.certificate_identity.is_some() {
+                return Err(wrong_check("--key"));
+            }
+            verify_ed25519(record, message, trusted.key.as_deref())?;
+            format!("ed25519 key {}", record.key_id)
+        }
+        SignatureAlgorithm::Ssh => {
+            if trusted.key.is_some() || trusted.certificate_identity.is_some() {
+                return Err(wrong_check("--allowed-signers"));
+            }
+            let principal = sshsig::verify(
+                &record.signature,
+                message,
+                trusted.allowed_signers.as_deref(),
+            )
+            .context("signature: invalid")?;
+            match principal {
+                Some(principal) => format!("ssh key {}, signer {}", record.key_id, principal),
+                None => format!("ssh key {}", record.key_id),
+            }
+        }
+        SignatureAlgorithm::Sigstore => {
+            if trusted.key.is_some() || trusted.allowed_signers.is_some() {
+                return Err(wrong_check("--certificate-identity"));
+            }
+            let bundle = record.bundle.as_ref().context("signature: no bundle")?;
+            sigstore::verify(
+                bundle,
+                message,
+                trusted.certificate_identity.as_deref(),
+                trusted.certificate_oidc_issuer.as_deref(),
+            )
+            .context("signature: invalid")?;
+            let mut signer = format!("sigstore certificate {}", record.key_id);
+            if let Some(identity) = &trusted.certificate_identity {
+                signer.push_str(&format!(", identity {}", identity));
+            }
+            if let Some(index) = record.log_index {
+                signer.push_str(&format!(", rekor log index {}", index));
+            }
+            signer
+        }
+    };
+    Ok(signer)
+}
+
+fn verify_ed25519(record: &SignatureRecord, message: &[u8], key: Option<&Path>) -> Result<()> {
+    let public: [u8; 32] = base64::decode(&record.public_key)
+        .ok()
+        .and_then(|key| key.try_into().ok())
+        .context("signature: malformed public_key")?;
+    let signature: [u8; 64] = base64::decode(&record.signature)
+        .ok()
+        .and_then(|signature| signature.try_into().ok())
+        .context("signature: malformed signature")?;
+    if let Some(key) = key {
+        if load_public_key(key)? != public {
+            bail!(
+                "signature: made with key {}, not {}",
+                record.key_id,
+                key.display()
+            );
+        }
+    }
+    if !ed25519::verify(&public, message, &signature) || record.key_id != key_id(&public) {
+        bail!("signature: invalid");
+    }
+    Ok(())
+}
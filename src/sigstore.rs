@@ -0,0 +1,116 @@
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::exec::resolve_executable;
+use crate::tempfile::TempFile;
+
+/// Find cosign on PATH
+pub fn cosign() -> Result<PathBuf> {
+    let cwd = std::env::current_dir().context("Failed to get current directory")?;
+    resolve_executable(
+        OsStr::new("cosign"),
+        std::env::var_os("PATH").as_ref(),
+        &cwd,
+    )
+    .context("cosign not found; install it to sign or verify keyless signatures")
+}
+
+/// A keyless signature made through cosign: an ephemeral key certified by
+/// Fulcio for the signer's OIDC identity, logged in Rekor
+pub struct KeylessSignature {
+    /// Base64 of the signature
+    pub signature: String,
+    /// Base64 of the signing certificate
+    pub certificate: String,
+    /// Index of the entry in the Rekor transparency log
+    pub log_index: Option<u64>,
+    /// cosign's bundle, holding everything needed to verify offline
+    pub bundle: Value,
+}
+
+/// Sign `message` with `cosign sign-blob`, which gets the OIDC token
+/// (ambient in CI, or through the browser), the Fulcio certificate and the
+/// Rekor entry
+pub fn sign(cosign: &Path, message: &[u8]) -> Result<KeylessSignature> {
+    let blob = TempFile::create(".json", message)?;
+    let bundle_file = TempFile::create(".bundle", b"")?;
+    let status = Command::new(cosign)
+        .args(["sign-blob", "--yes", "--bundle"])
+        .arg(bundle_file.path())
+        .arg(blob.path())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .status()
+        .context(format!("Failed to run {}", cosign.display()))?;
+    if !status.success() {
+        bail!("cosign sign-blob failed");
+    }
+    let contents = std::fs::read_to_string(bundle_file.path())
+        .context("Failed to read the bundle cosign wrote")?;
+    let bundle: Value =
+        serde_json::from_str(&contents).context("cosign wrote a malformed bundle")?;
+
+    // cosign's original bundle format, or the Sigstore bundle it writes
+    // with --new-bundle-format
+    let text = |pointer: &str| bundle.pointer(pointer).and_then(Value::as_str);
+    let signature = text("/base64Signature").or_else(|| text("/messageSignature/signature"));
+    let certificate = text("/cert").or_else(|| text("/verificationMaterial/certificate/rawBytes"));
+    let log_index = bundle
+        .pointer("/rekorBundle/Payload/logIndex")
+        .and_then(Value::as_u64)
+        .or_else(|| {
+            text("/verificationMaterial/tlogEntries/0/logIndex")
+                .and_then(|index| index.parse().ok())
+        });
+    let (Some(signature), Some(certificate)) = (signature, certificate) else {
+        bail!("cosign's bundle has no signature or certificate");
+    };
+    Ok(KeylessSignature {
+        signature: signature.to_string(),
+        certificate: certificate.to_string(),
+        log_index,
+        bundle,
+    })
+}
+
+/// Check `bundle` is a valid signature of `message` with `cosign
+/// verify-blob`, by `identity` as issued by `issuer` where given, by any
+/// identity otherwise
+pub fn verify(
+    bundle: &Value,
+    message: &[u8],
+    identity: Option<&str>,
+    issuer: Option<&str>,
+) -> Result<()> {
+    let blob = TempFile::create(".json", message)?;
+    let bundle_file = TempFile::create(".bundle", bundle.to_string().as_bytes())?;
+    let cosign = cosign()?;
+    let mut command = Command::new(&cosign);
+    command
+        .args(["verify-blob", "--bundle"])
+        .arg(bundle_file.path());
+    if bundle.get("mediaType").is_some() {
+        command.arg("--new-bundle-format");
+    }
+    match identity {
+        Some(identity) => command.args(["--certificate-identity", identity]),
+        None => command.args(["--certificate-identity-regexp", ".*"]),
+    };
+    match issuer {
+        Some(issuer) => command.args(["--certificate-oidc-issuer", issuer]),
+        None => command.args(["--certificate-oidc-issuer-regexp", ".*"]),
+    };
+    let output = command
+        .arg(blob.path())
+        .stdin(Stdio::null())
+        .output()
+        .context(format!("Failed to run {}", cosign.display()))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("cosign verify-blob failed: {}", stderr.trim());
+    }
+    Ok(())
+}
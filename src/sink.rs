@@ -0,0 +1,361 @@
+use anyhow::{bail, Context, Result};
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::age::Recipient;
+use crate::atomic;
+use crate::config::Config;
+use crate::digest::sha256_hex;
+use crate::exec::resolve_executable;
+use crate::receipt::{self, Receipt};
+use crate::s3::Bucket;
+use crate::signing;
+use crate::syslog::SystemLog;
+use crate::webhook::Webhook;
+
+/// Where `--sink` keeps files it could not send until they can be,
+/// relative to the working directory
+pub const DEFAULT_SPOOL: &str = ".receipts/spool";
+
+/// How many times curl retries a delivery that failed for a reason that
+/// may pass, such as a refused connection or a 503, unless --sink-retries
+/// says otherwise
+pub const DEFAULT_RETRIES: u32 = 5;
+
+/// Longest one attempt at a delivery may take, unless --sink-timeout says
+/// otherwise
+pub const DEFAULT_TIMEOUT: &str = "30s";
+
+/// Longest curl waits to connect to a sink, on each attempt
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How hard curl tries to deliver a file
+#[derive(Debug, Clone, Copy)]
+pub struct Delivery {
+    /// How many times a failure that may pass is retried
+    pub retries: u32,
+    /// Longest each attempt may take, connecting included; an attempt that
+    /// takes longer fails, and may be retried
+    pub timeout: Duration,
+}
+
+/// Somewhere a finished receipt goes: its file, a ledger, an index, or a
+/// remote destination. Each is written every receipt a run makes, in
+/// order, then finalized once no more are coming
+pub trait ReceiptSink: fmt::Debug + Send + Sync {
+    /// Take `receipt`, whose file is `out`
+    fn write(&self, receipt: &Receipt, out: &Path) -> Result<()>;
+
+    /// Pass on whatever has been held back so far
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Finish up after the last receipt
+    fn finalize(&self) -> Result<()> {
+        self.flush()
+    }
+}
+
+/// Writes each receipt to its file, encrypted if there are recipients
+#[derive(Debug, Clone, Default)]
+pub struct FileSink {
+    pub encrypt_to: Vec<Recipient>,
+    /// Have the file on disk before counting it written
+    pub fsync: bool,
+}
+
+impl ReceiptSink for FileSink {
+    fn write(&self, receipt: &Receipt, out: &Path) -> Result<()> {
+        match self.encrypt_to.is_empty() {
+            true => receipt::write_receipt(out, receipt, self.fsync),
+            false => receipt::write_encrypted_receipt(out, receipt, &self.encrypt_to, self.fsync),
+        }
+    }
+}
+
+/// What to do when a file cannot be sent to a sink
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnFailure {
+    /// Fail the run
+    Fatal,
+    /// Warn and carry on
+    Log,
+    /// Warn, and keep the file in the spool for the next attempt
+    #[default]
+    Queue,
+}
+
+impl FromStr for OnFailure {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "fatal" => Ok(OnFailure::Fatal),
+            "log" => Ok(OnFailure::Log),
+            "queue" => Ok(OnFailure::Queue),
+            _ => bail!("expected fatal, log or queue, got '{}'", s),
+        }
+    }
+}
+
+/// Somewhere receipts are sent once they are written. Files that fail to
+/// arrive are dealt with as `on_failure` says
+#[derive(Debug, Clone)]
+pub struct RemoteSink {
+    url: String,
+    target: Target,
+    /// This sink's own directory in the spool
+    spool: PathBuf,
+    delivery: Delivery,
+    on_failure: OnFailure,
+}
+
+#[derive(Debug, Clone)]
+enum Target {
+    S3(Bucket),
+    Webhook(Webhook),
+    Log(SystemLog),
+    Dir(PathBuf),
+}
+
+impl RemoteSink {
+    /// The sink at `url`: an S3 bucket for `s3://bucket/prefix`, a
+    /// directory for `file:///path`, a system log, with the whole receipt
+    /// if `full`, for `syslog://` or `journald://`, else an HTTP endpoint
+    /// the receipt is POSTed to, also sent `headers`. Its spool is a
+    /// directory of its own in `spool`
+    pub fn new(
+        url: &str,
+        headers: &[String],
+        full: bool,
+        config: &Config,
+        spool: &Path,
+        delivery: Delivery,
+        on_failure: OnFailure,
+    ) -> Result<Self> {
+        let target = match url.split_once("://") {
+            Some(("s3", _)) => Target::S3(Bucket::new(url, &config.s3)?),
+            Some(("file", dir)) if dir.starts_with('/') => Target::Dir(PathBuf::from(dir)),
+            Some(("file", _)) => bail!("--sink must be a file:///path URL, not {}", url),
+            Some(("http" | "https", _)) => {
+                Target::Webhook(Webhook::new(url, headers, &config.webhook)?)
+            }
+            _ if is_system_log(url) => Target::Log(SystemLog::new(url, full)?),
+            _ => bail!(
+                "--sink must be an s3://, file://, http://, https://, syslog:// or journald:// URL, not {}",
+                url
+            ),
+        };
+        let cwd = std::env::current_dir().context("Failed to get current directory")?;
+        let url = url.trim_end_matches('/').to_string();
+        // Named for the URL, so each sink only ever sends its own files
+        let spool = cwd.join(spool).join(&sha256_hex(url.as_bytes())[..16]);
+        Ok(RemoteSink {
+            url,
+            target,
+            spool,
+            delivery,
+            on_failure,
+        })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Send the receipt at `receipt`, and to a bucket or directory the
+    /// `attached` files it refers to as well, before it, failing, warning
+    /// or spooling as `on_failure` says for whatever fails to arrive. Once
+    /// everything is sent, whatever was spooled before is tried again
+    pub fn send(&self, receipt: &Path, attached: &[PathBuf]) -> Result<()> {
+        let files = match self.target {
+            Target::S3(_) | Target::Dir(_) => attached.iter().map(PathBuf::as_path).collect(),
+            // An endpoint gets just the receipt, as JSON, and a log its summary
+            Target::Webhook(_) | Target::Log(_) => Vec::new(),
+        };
+        let mut failed = false;
+        for file in files.into_iter().chain([receipt]) {
+            let Some(name) = file.file_name() else {
+                continue;
+            };
+            let Err(err) = self.deliver(file, &name.to_string_lossy()) else {
+                continue;
+            };
+            failed = true;
+            match self.on_failure {
+                OnFailure::Fatal => return Err(err),
+                OnFailure::Log => eprintln!("rcpt: {:#}", err),
+                OnFailure::Queue => {
+                    let contents =
+                        fs::read(file).context(format!("Failed to read {}", file.display()))?;
+                    let spooled = self.spool.join(name);
+                    atomic::write(&spooled, &contents, false)
+                        .context(format!("Failed to spool {}", spooled.display()))?;
+                    eprintln!(
+                        "rcpt: {:#}; spooled to {} for the next run or `rcpt flush`",
+                        err,
+                        spooled.display()
+                    );
+                }
+            }
+        }
+        if !failed {
+            self.flush_spool()?;
+        }
+        Ok(())
+    }
+
+    /// Send every file in the spool, removing each once it has arrived.
+    /// Returns how many were sent and how many are left
+    pub fn flush_spool(&self) -> Result<(usize, usize)> {
+        let Ok(listing) = fs::read_dir(&self.spool) else {
+            return Ok((0, 0));
+        };
+        let (mut sent, mut left) = (0, 0);
+        for entry in listing.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            // Files being spooled, and locks
+            if name.starts_with('.') {
+                continue;
+            }
+            match self.deliver(&entry.path(), &name) {
+                Ok(()) => {
+                    // Another rcpt flushing the spool may have got there first
+                    let _ = fs::remove_file(entry.path());
+                    sent += 1;
+                }
+                Err(err) => {
+                    eprintln!("rcpt: {:#}", err);
+                    left += 1;
+                }
+            }
+        }
+        Ok((sent, left))
+    }
+
+    fn deliver(&self, file: &Path, name: &str) -> Result<()> {
+        match &self.target {
+            Target::S3(bucket) => bucket.upload(file, name, self.delivery),
+            Target::Webhook(webhook) => webhook.post(file, self.delivery),
+            Target::Log(log) => log.log(file),
+            Target::Dir(dir) => {
+                let copy = dir.join(name);
+                let contents =
+                    fs::read(file).context(format!("Failed to read {}", file.display()))?;
+                atomic::write(&copy, &contents, false).context(format!(
+                    "failed to copy {} to {}",
+                    file.display(),
+                    copy.display()
+                ))
+            }
+        }
+    }
+}
+
+impl ReceiptSink for RemoteSink {
+    /// Send the receipt with its sidecars and detached signature. The
+    /// receipt is on disk, so unless failures are fatal this only warns
+    fn write(&self, receipt: &Receipt, out: &Path) -> Result<()> {
+        let mut attached: Vec<PathBuf> = receipt
+            .sidecars
+            .iter()
+            .map(|sidecar| sidecar.locate(out))
+            .collect();
+        let signature = signing::detached_path(out);
+        if receipt.signature.is_none() && signature.exists() {
+            attached.push(signature);
+        }
+        match self.send(out, &attached) {
+            Err(err) if self.on_failure != OnFailure::Fatal => {
+                eprintln!(
+                    "rcpt: failed to send {} to {}: {:#}",
+                    out.display(),
+                    self.url,
+                    err
+                );
+                Ok(())
+            }
+            sent => sent,
+        }
+    }
+
+    fn flush(&self) -> Result<()> {
+        let (_, left) = self.flush_spool()?;
+        if left > 0 {
+            bail!("{} files are still spooled for {}", left, self.url);
+        }
+        Ok(())
+    }
+
+    /// The spool was tried after the last receipt that arrived; what is
+    /// left waits for the next run or `rcpt flush` rather than holding
+    /// this one up
+    fn finalize(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Whether `url` names a system log, which gets a summary of each receipt
+/// instead of the receipt as it was written
+pub fn is_system_log(url: &str) -> bool {
+    url.starts_with("syslog://") || url.starts_with("journald://")
+}
+
+/// Find curl on PATH
+pub fn find_curl(purpose: &str) -> Result<PathBuf> {
+    let cwd = std::env::current_dir().context("Failed to get current directory")?;
+    resolve_executable(OsStr::new("curl"), std::env::var_os("PATH").as_ref(), &cwd)
+        .context(format!("curl not found; install it to {}", purpose))
+}
+
+/// Run curl with `args`, reading `config`, in curl's config file syntax,
+/// from its stdin so secrets stay off its command line. curl retries
+/// failures that may pass, timeouts among them, as `delivery` says,
+/// waiting twice as long each time; a failure is its last complaint
+pub fn run_curl(curl: &Path, args: &[OsString], config: &str, delivery: Delivery) -> Result<()> {
+    let connect = delivery.timeout.min(CONNECT_TIMEOUT);
+    let mut child = Command::new(curl)
+        .args(["--silent", "--show-error", "--fail", "--retry-connrefused"])
+        .arg("--retry")
+        .arg(delivery.retries.to_string())
+        .arg("--connect-timeout")
+        .arg(connect.as_secs_f64().to_string())
+        .arg("--max-time")
+        .arg(delivery.timeout.as_secs_f64().to_string())
+        .args(["--config", "-"])
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context(format!("Failed to run {}", curl.display()))?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let _ = stdin.write_all(config.as_bytes());
+    drop(stdin);
+    let output = child
+        .wait_with_output()
+        .context(format!("Failed to run {}", curl.display()))?;
+    if !output.status.success() {
+        // curl reports each attempt; the last says how it ended
+        bail!(
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+                .lines()
+                .last()
+                .unwrap_or("curl failed")
+        );
+    }
+    Ok(())
+}
+
+/// `value` as a quoted string in a curl config file
+pub fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -0,0 +1,132 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::artifact;
+
+/// Most changes recorded in each list; any beyond are only counted
+const MAX_CHANGES: usize = 10_000;
+
+/// A file that was created, modified or deleted under the snapshot directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileChange {
+    /// Path relative to the snapshot directory
+    pub path: String,
+    /// SHA-256 after the run, or before it for a deleted file
+    pub sha256: String,
+    /// Size after the run, or before it for a deleted file
+    pub size_bytes: u64,
+    /// SHA-256 before the run, for a modified file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_sha256: Option<String>,
+}
+
+/// What the command changed under a directory, from `--snapshot`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotRecord {
+    /// Directory as given
+    pub dir: String,
+    pub created: Vec<FileChange>,
+    pub modified: Vec<FileChange>,
+    pub deleted: Vec<FileChange>,
+    /// Changes beyond the most that are recorded
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub omitted: u64,
+}
+
+fn is_zero(count: &u64) -> bool {
+    *count == 0
+}
+
+/// Every file under a directory with its digest, taken before the run
+pub struct Snapshot {
+    dir: PathBuf,
+    root: PathBuf,
+    files: BTreeMap<String, (String, u64)>,
+}
+
+impl Snapshot {
+    /// Fingerprint `dir`, relative to `cwd`
+    pub fn take(cwd: &Path, dir: &Path) -> Result<Self> {
+        let root = fs::canonicalize(cwd.join(dir))
+            .context(format!("Snapshot directory {} not found", dir.display()))?;
+        Ok(Snapshot {
+            dir: dir.to_path_buf(),
+            files: fingerprint(&root)?,
+            root,
+        })
+    }
+
+    /// Fingerprint the directory again and compare. `ignore` is a file rcpt
+    /// itself writes during the run, which is left out if it is inside.
+    pub fn finish(self, ignore: Option<&Path>) -> Result<SnapshotRecord> {
+        let mut after = fingerprint(&self.root)?;
+        if let Some(name) = ignore.and_then(|path| self.relative(path)) {
+            after.remove(&name);
+        }
+        let mut before = self.files;
+        let mut created = Vec::new();
+        let mut modified = Vec::new();
+        for (path, (sha256, size_bytes)) in after {
+            match before.remove(&path) {
+                None => created.push(FileChange {
+                    path,
+                    sha256,
+                    size_bytes,
+                    previous_sha256: None,
+                }),
+                Some((previous, _)) if previous != sha256 => modified.push(FileChange {
+                    path,
+                    sha256,
+                    size_bytes,
+                    previous_sha256: Some(previous),
+                }),
+                Some(_) => {}
+            }
+        }
+        let deleted: Vec<FileChange> = before
+            .into_iter()
+            .map(|(path, (sha256, size_bytes))| FileChange {
+                path,
+                sha256,
+                size_bytes,
+                previous_sha256: None,
+            })
+            .collect();
+        let omitted = [&created, &modified, &deleted]
+            .iter()
+            .map(|changes| changes.len().saturating_sub(MAX_CHANGES) as u64)
+            .sum();
+        let cap = |changes: Vec<FileChange>| changes.into_iter().take(MAX_CHANGES).collect();
+        Ok(SnapshotRecord {
+            dir: self.dir.to_string_lossy().to_string(),
+            created: cap(created),
+            modified: cap(modified),
+            deleted: cap(deleted),
+            omitted,
+        })
+    }
+
+    /// `path` relative to the snapshot root, as recorded, if it is inside
+    fn relative(&self, path: &Path) -> Option<String> {
+        let parent = fs::canonicalize(path.parent()?.join(".")).ok()?;
+        let path = parent.join(path.file_name()?);
+        let relative = path.strip_prefix(&self.root).ok()?;
+        let parts: Vec<String> = relative
+            .components()
+            .map(|part| part.as_os_str().to_string_lossy().to_string())
+            .collect();
+        Some(parts.join("/"))
+    }
+}
+
+fn fingerprint(root: &Path) -> Result<BTreeMap<String, (String, u64)>> {
+    let mut entries = Vec::new();
+    artifact::walk(root, "", &mut entries)?;
+    Ok(entries
+        .into_iter()
+        .map(|(path, sha256, size)| (path, (sha256, size)))
+        .collect())
+}
@@ -0,0 +1,146 @@
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::tempfile::TempFile;
+
+/// Namespace receipts are signed in, so their signatures cannot pass for
+/// signatures of anything else made with the same key
+const NAMESPACE: &str = "rcpt";
+
+/// An SSH key to sign with: a private key file, or a public key whose
+/// private half is held by ssh-agent
+#[derive(Debug)]
+pub struct SshKey {
+    path: PathBuf,
+    /// The public key, as `<type> <base64>`
+    pub public_key: String,
+    /// Fingerprint of the public key, as `SHA256:...`
+    pub fingerprint: String,
+}
+
+impl SshKey {
+    /// The key at `path`, with its public key read from `<path>.pub` unless
+    /// `path` is the public key itself
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents =
+            fs::read_to_string(path).context(format!("Failed to read {}", path.display()))?;
+        let (public, public_path) = if is_public_key(&contents) {
+            (contents, path.to_path_buf())
+        } else {
+            let mut public_path = path.as_os_str().to_owned();
+            public_path.push(".pub");
+            let public_path = PathBuf::from(public_path);
+            let public = fs::read_to_string(&public_path)
+                .context(format!("Failed to read {}", public_path.display()))?;
+            (public, public_path)
+        };
+        let public_key: Vec<&str> = public.split_whitespace().take(2).collect();
+        if public_key.len() < 2 || !is_public_key(&public) {
+            bail!("No SSH public key found for {}", path.display());
+        }
+        let output = ssh_keygen(&["-l", "-f"], Some(&public_path), None)?;
+        let fingerprint = output
+            .split_whitespace()
+            .nth(1)
+            .context(format!("Failed to fingerprint {}", path.display()))?
+            .to_string();
+        Ok(SshKey {
+            path: path.to_path_buf(),
+            public_key: public_key.join(" "),
+            fingerprint,
+        })
+    }
+
+    /// Armored SSH signature of `message`
+    pub fn sign(&self, message: &[u8]) -> Result<String> {
+        ssh_keygen(
+            &["-Y", "sign", "-n", NAMESPACE, "-f"],
+            Some(&self.path),
+            Some(message),
+        )
+    }
+}
+
+fn is_public_key(contents: &str) -> bool {
+    let key_type = contents.split_whitespace().next().unwrap_or("");
+    key_type.starts_with("ssh-") || key_type.starts_with("ecdsa-") || key_type.starts_with("sk-")
+}
+
+/// Check the armored `signature` of `message`, returning the principal
+/// from `allowed_signers` it belongs to, or None when no allowed signers
+/// file is given and only the signature itself is checked
+pub fn verify(
+    signature: &str,
+    message: &[u8],
+    allowed_signers: Option<&Path>,
+) -> Result<Option<String>> {
+    let file = TempFile::create(".sig", signature.as_bytes())?;
+    verify_file(file.path(), message, allowed_signers)
+}
+
+fn verify_file(
+    signature: &Path,
+    message: &[u8],
+    allowed_signers: Option<&Path>,
+) -> Result<Option<String>> {
+    let signature = signature.to_string_lossy();
+    let Some(allowed_signers) = allowed_signers else {
+        ssh_keygen(
+            &["-Y", "check-novalidate", "-n", NAMESPACE, "-s", &signature],
+            None,
+            Some(message),
+        )?;
+        return Ok(None);
+    };
+    let allowed = allowed_signers.to_string_lossy();
+    let principals = ssh_keygen(
+        &["-Y", "find-principals", "-s", &signature, "-f", &allowed],
+        None,
+        None,
+    )
+    .context(format!("key is not in {}", allowed_signers.display()))?;
+    let principal = principals.lines().next().unwrap_or_default().to_string();
+    ssh_keygen(
+        &[
+            "-Y", "verify", "-n", NAMESPACE, "-s", &signature, "-f", &allowed, "-I", &principal,
+        ],
+        None,
+        Some(message),
+    )?;
+    Ok(Some(principal))
+}
+
+/// Run ssh-keygen with `args` (then `file`, if given), feeding it `stdin`,
+/// and return its stdout. Fails with its stderr if it does.
+fn ssh_keygen(args: &[&str], file: Option<&Path>, stdin: Option<&[u8]>) -> Result<String> {
+    let mut command = Command::new("ssh-keygen");
+    command.args(args);
+    if let Some(file) = file {
+        command.arg(file);
+    }
+    let mut child = command
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run ssh-keygen")?;
+    if let (Some(mut pipe), Some(stdin)) = (child.stdin.take(), stdin) {
+        pipe.write_all(stdin)
+            .context("Failed to write to ssh-keygen")?;
+    }
+    let output = child
+        .wait_with_output()
+        .context("Failed to run ssh-keygen")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("ssh-keygen failed: {}", stderr.trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
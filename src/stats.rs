@@ -0,0 +1,240 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::list::{truncate, Summary};
+use crate::receipt::Status;
+
+/// The widest a group's name is shown in the table
+const GROUP_WIDTH: usize = 48;
+
+/// What runs are grouped by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GroupBy {
+    /// The command line
+    Cmd,
+    /// Each tag, so a run with several is counted in each
+    Tag,
+    /// The UTC day the run started
+    Day,
+}
+
+/// How a group of runs went
+#[derive(Debug, Clone, Serialize)]
+pub struct Group {
+    pub name: String,
+    pub runs: usize,
+    pub failed: usize,
+    pub p50_ms: u64,
+    pub p95_ms: u64,
+    pub max_ms: u64,
+}
+
+impl Group {
+    /// The share of runs that failed, from 0 to 1
+    pub fn failure_rate(&self) -> f64 {
+        self.failed as f64 / self.runs as f64
+    }
+}
+
+/// The finished runs among `receipts`, in groups by `group_by` (or all in
+/// one, named `all`), ordered by name
+pub fn aggregate(receipts: &[Summary], group_by: Option<GroupBy>) -> Vec<Group> {
+    let mut groups: BTreeMap<String, Vec<&Summary>> = BTreeMap::new();
+    for receipt in receipts {
+        // Runs that have not finished have no outcome or duration yet
+        if matches!(receipt.status, Status::Planned | Status::Running) {
+            continue;
+        }
+        let names = match group_by {
+            None => vec!["all".to_string()],
+            Some(GroupBy::Cmd) => vec![receipt.command_line()],
+            Some(GroupBy::Tag) if receipt.tags.is_empty() => vec!["(untagged)".to_string()],
+            Some(GroupBy::Tag) => receipt.tags.clone(),
+            Some(GroupBy::Day) => vec![receipt.start_time.format("%Y-%m-%d").to_string()],
+        };
+        for name in names {
+            groups.entry(name).or_default().push(receipt);
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(name, runs)| {
+            let mut durations: Vec<u64> = runs.iter().map(|run| run.duration_ms).collect();
+            durations.sort_unstable();
+            Group {
+                name,
+                runs: runs.len(),
+                failed: runs.iter().filter(|run| run.status != Status::Ok).count(),
+                p50_ms: percentile(&durations, 50),
+                p95_ms: percentile(&durations, 95),
+                max_ms: durations[durations.len() - 1],
+            }
+        })
+        .collect()
+}
+
+/// The `p`th percentile of `sorted`, by nearest rank: the smallest value
+/// at least `p`% of them are no greater than
+fn percentile(sorted: &[u64], p: usize) -> u64 {
+    let rank = (sorted.len() * p).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+/// A table of `groups`, one line each
+pub fn render_table(groups: &[Group], group_by: Option<GroupBy>) -> String {
+    let heading = match group_by {
+        None => "GROUP",
+        Some(GroupBy::Cmd) => "COMMAND",
+        Some(GroupBy::Tag) => "TAG",
+        Some(GroupBy::Day) => "DAY",
+    };
+    let width = groups
+        .iter()
+        .map(|group| group.name.chars().count())
+        .chain([heading.len()])
+        .max()
+        .unwrap_or_default()
+        .min(GROUP_WIDTH);
+    let mut out = format!(
+        "{:<width$}  {:>6} {:>6} {:>6} {:>10} {:>10} {:>10}\n",
+        heading, "RUNS", "FAILED", "FAIL%", "P50", "P95", "MAX"
+    );
+    for group in groups {
+        let _ = writeln!(
+            out,
+            "{:<width$}  {:>6} {:>6} {:>5.1}% {:>8}ms {:>8}ms {:>8}ms",
+            truncate(&group.name, width),
+            group.runs,
+            group.failed,
+            group.failure_rate() * 100.0,
+            group.p50_ms,
+            group.p95_ms,
+            group.max_ms
+        );
+    }
+    out
+}
+
+/// What `--json` prints of each group
+#[derive(Debug, Serialize)]
+struct Entry<'a> {
+    #[serde(flatten)]
+    group: &'a Group,
+    failure_rate: f64,
+}
+
+/// `groups` as a JSON array, each with its `failure_rate`
+pub fn render_json(groups: &[Group]) -> Result<String> {
+    let entries: Vec<Entry> = groups
+        .iter()
+        .map(|group| Entry {
+            group,
+            failure_rate: group.failure_rate(),
+        })
+        .collect();
+    Ok(serde_json::to_string_pretty(&entries)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use std::path::PathBuf;
+
+    fn run(argv: &str, status: Status, duration_ms: u64, day: u32, tags: &[&str]) -> Summary {
+        Summary {
+            path: PathBuf::from(format!("{}.json", duration_ms)),
+            id: String::new(),
+            argv: argv.split(' ').map(str::to_string).collect(),
+            status,
+            exit_code: None,
+            duration_ms,
+            start_time: Utc.with_ymd_and_hms(2026, 2, day, 12, 0, 0).unwrap(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn takes_percentiles_by_nearest_rank() {
+        let ten: Vec<u64> = (1..=10).collect();
+        assert_eq!(percentile(&ten, 50), 5);
+        assert_eq!(percentile(&ten, 95), 10);
+        let hundred: Vec<u64> = (1..=100).collect();
+        assert_eq!(percentile(&hundred, 50), 50);
+        assert_eq!(percentile(&hundred, 95), 95);
+        let twenty: Vec<u64> = (1..=20).collect();
+        assert_eq!(percentile(&twenty, 95), 19);
+        assert_eq!(percentile(&[1, 2, 3, 1000], 50), 2);
+        assert_eq!(percentile(&[1, 2, 3, 1000], 95), 1000);
+        // One run is every percentile of itself
+        for p in [0, 1, 50, 95, 100] {
+            assert_eq!(percentile(&[7], p), 7);
+        }
+    }
+
+    #[test]
+    fn groups_finished_runs() {
+        let runs = [
+            run("make test", Status::Ok, 100, 16, &["ci"]),
+            run("make test", Status::Failed, 300, 16, &[]),
+            run("make test", Status::TimedOut, 200, 17, &["ci", "nightly"]),
+            run("make lint", Status::Ok, 50, 17, &["ci"]),
+            run("make lint", Status::Running, 0, 17, &["ci"]),
+            run("make lint", Status::Planned, 0, 17, &[]),
+        ];
+        let all = aggregate(&runs, None);
+        assert_eq!(all.len(), 1);
+        assert_eq!(
+            (all[0].name.as_str(), all[0].runs, all[0].failed),
+            ("all", 4, 2)
+        );
+        assert_eq!(
+            (all[0].p50_ms, all[0].p95_ms, all[0].max_ms),
+            (100, 300, 300)
+        );
+        assert_eq!(all[0].failure_rate(), 0.5);
+
+        let named = |group_by| -> Vec<(String, usize, usize)> {
+            aggregate(&runs, Some(group_by))
+                .into_iter()
+                .map(|group| (group.name, group.runs, group.failed))
+                .collect()
+        };
+        let group = |name: &str, runs, failed| (name.to_string(), runs, failed);
+        assert_eq!(
+            named(GroupBy::Cmd),
+            [group("make lint", 1, 0), group("make test", 3, 2)]
+        );
+        assert_eq!(
+            named(GroupBy::Tag),
+            [
+                group("(untagged)", 1, 1),
+                group("ci", 3, 1),
+                group("nightly", 1, 1)
+            ]
+        );
+        assert_eq!(
+            named(GroupBy::Day),
+            [group("2026-02-16", 2, 1), group("2026-02-17", 2, 1)]
+        );
+        assert!(aggregate(&runs[4..], None).is_empty());
+    }
+
+    #[test]
+    fn renders_a_table() {
+        let runs = [
+            run("make test", Status::Ok, 100, 16, &[]),
+            run("make test", Status::Failed, 300, 16, &[]),
+            run("make test", Status::Ok, 200, 16, &[]),
+        ];
+        let table = render_table(&aggregate(&runs, Some(GroupBy::Cmd)), Some(GroupBy::Cmd));
+        assert_eq!(
+            table,
+            "COMMAND      RUNS FAILED  FAIL%        P50        P95        MAX\n\
+             make test       3      1  33.3%      200ms      300ms      300ms\n"
+        );
+    }
+}
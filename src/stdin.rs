@@ -0,0 +1,202 @@
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{Read, Seek, Write};
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::receipt::StdinRecord;
+
+/// Number of leading stdin bytes kept as a preview in the receipt
+const PREVIEW_BYTES: usize = 1024;
+
+/// Where the command's standard input comes from
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum StdinMode {
+    /// Share rcpt's own stdin with the command
+    #[default]
+    Inherit,
+    /// Give the command an empty, closed stdin
+    Null,
+    /// Forward rcpt's stdin to the command and record what was sent
+    Capture,
+    /// Feed the command the contents of a file
+    File(PathBuf),
+}
+
+impl FromStr for StdinMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "inherit" => Ok(StdinMode::Inherit),
+            "null" => Ok(StdinMode::Null),
+            "capture" => Ok(StdinMode::Capture),
+            _ => match s.strip_prefix("file:") {
+                Some(path) if !path.is_empty() => Ok(StdinMode::File(PathBuf::from(path))),
+                _ => bail!(
+                    "expected inherit, null, capture or file:<path>, got '{}'",
+                    s
+                ),
+            },
+        }
+    }
+}
+
+impl StdinMode {
+    /// The `Stdio` to hand to the child for this mode. In file mode this
+    /// also returns rcpt's own handle on the file, which shares the child's
+    /// read position and so shows how much of the file was consumed.
+    pub fn stdio(&self) -> Result<(Stdio, Option<File>)> {
+        Ok(match self {
+            StdinMode::Inherit => (Stdio::inherit(), None),
+            StdinMode::Null => (Stdio::null(), None),
+            StdinMode::Capture => (Stdio::piped(), None),
+            StdinMode::File(path) => {
+                let file = File::open(path)
+                    .context(format!("Failed to open stdin file {}", path.display()))?;
+                let ours = file
+                    .try_clone()
+                    .context(format!("Failed to open stdin file {}", path.display()))?;
+                (Stdio::from(file), Some(ours))
+            }
+        })
+    }
+
+    /// Receipt record once the command has finished, from the feeder that
+    /// forwarded captured stdin or the handle returned by `stdio`
+    pub fn finish(
+        &self,
+        feeder: Option<StdinFeeder>,
+        file: Option<File>,
+        grace: Duration,
+    ) -> StdinRecord {
+        match (self, feeder, file) {
+            (StdinMode::Capture, Some(feeder), _) => feeder.finish(grace),
+            (StdinMode::File(_), _, Some(file)) => {
+                let mut record = self.record();
+                // Provenance is best effort: a read error leaves just the path
+                if let Ok((consumed, sha256, size)) = consumed_digest(file) {
+                    record.bytes = Some(consumed);
+                    record.sha256 = Some(sha256);
+                    record.file_size = Some(size);
+                }
+                record
+            }
+            _ => self.record(),
+        }
+    }
+
+    /// Name of the mode as recorded in the receipt
+    fn name(&self) -> &'static str {
+        match self {
+            StdinMode::Inherit => "inherit",
+            StdinMode::Null => "null",
+            StdinMode::Capture => "capture",
+            StdinMode::File(_) => "file",
+        }
+    }
+
+    /// Receipt record for modes where rcpt does not see the bytes
+    pub fn record(&self) -> StdinRecord {
+        StdinRecord {
+            mode: self.name().to_string(),
+            path: match self {
+                StdinMode::File(path) => Some(path.to_string_lossy().to_string()),
+                _ => None,
+            },
+            file_size: None,
+            bytes: None,
+            sha256: None,
+            preview: None,
+        }
+    }
+}
+
+/// Number and SHA-256 digest of the bytes before the file's read position,
+/// plus the file's size
+fn consumed_digest(mut file: File) -> std::io::Result<(u64, String, u64)> {
+    let consumed = file.stream_position()?;
+    let size = file.metadata()?.len();
+    file.rewind()?;
+    let mut hasher = Sha256::new();
+    let mut chunk = [0u8; 8192];
+    let mut remaining = consumed;
+    while remaining > 0 {
+        let want = chunk.len().min(remaining as usize);
+        let n = file.read(&mut chunk[..want])?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&chunk[..n]);
+        remaining -= n as u64;
+    }
+    Ok((consumed, format!("{:x}", hasher.finalize()), size))
+}
+
+/// Running totals for the bytes forwarded to the child
+#[derive(Default)]
+struct FeedStats {
+    bytes: u64,
+    hasher: Sha256,
+    preview: Vec<u8>,
+}
+
+/// Background thread copying rcpt's stdin into the child while recording it
+pub struct StdinFeeder {
+    stats: Arc<Mutex<FeedStats>>,
+    handle: JoinHandle<()>,
+}
+
+impl StdinFeeder {
+    pub fn start<W: Write + Send + 'static>(mut child_stdin: W) -> Self {
+        let stats = Arc::new(Mutex::new(FeedStats::default()));
+        let handle = {
+            let stats = Arc::clone(&stats);
+            thread::spawn(move || {
+                let mut stdin = std::io::stdin().lock();
+                let mut chunk = [0u8; 8192];
+                loop {
+                    let n = match stdin.read(&mut chunk) {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => n,
+                    };
+                    // The child closing its stdin ends the feed
+                    if child_stdin.write_all(&chunk[..n]).is_err() {
+                        break;
+                    }
+                    let mut stats = stats.lock().unwrap();
+                    stats.bytes += n as u64;
+                    stats.hasher.update(&chunk[..n]);
+                    let room = PREVIEW_BYTES.saturating_sub(stats.preview.len());
+                    stats.preview.extend_from_slice(&chunk[..n.min(room)]);
+                }
+                // Dropping child_stdin closes the pipe so the child sees EOF
+            })
+        };
+        StdinFeeder { stats, handle }
+    }
+
+    /// Record what was forwarded. rcpt's own stdin may never reach EOF (a
+    /// terminal, say), so this waits at most `grace` for the feed to end.
+    pub fn finish(self, grace: Duration) -> StdinRecord {
+        let give_up = Instant::now() + grace;
+        while !self.handle.is_finished() && Instant::now() < give_up {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let stats = self.stats.lock().unwrap();
+        StdinRecord {
+            mode: StdinMode::Capture.name().to_string(),
+            path: None,
+            file_size: None,
+            bytes: Some(stats.bytes),
+            sha256: Some(format!("{:x}", stats.hasher.clone().finalize())),
+            preview: Some(String::from_utf8_lossy(&stats.preview).to_string()),
+        }
+    }
+}
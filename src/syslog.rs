@@ -0,0 +1,274 @@
+use anyhow::{bail, Context, Result};
+use std::io;
+use std::net::UdpSocket;
+use std::path::Path;
+
+use crate::canonical;
+use crate::host;
+use crate::receipt::{self, Receipt, Status};
+
+/// Sockets of the local syslog daemon: Linux's, then macOS's
+const SYSLOG_SOCKETS: &[&str] = &["/dev/log", "/var/run/syslog"];
+
+/// Socket of systemd-journald's native protocol
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// The `user` facility, as syslog numbers it
+const FACILITY_USER: u8 = 1;
+
+/// A system log that receipts are summarized to, one entry each
+#[derive(Debug, Clone)]
+pub struct SystemLog {
+    destination: Destination,
+    /// Also log the receipt itself, not just its summary
+    full: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Destination {
+    /// The local syslog daemon
+    Syslog,
+    /// A remote syslog server, over UDP
+    Remote(String),
+    Journal,
+}
+
+impl SystemLog {
+    /// The log for `url`: `syslog://` for the local syslog daemon,
+    /// `syslog://host[:port]` for a remote one, or `journald://`
+    pub fn new(url: &str, full: bool) -> Result<Self> {
+        let destination = match url.split_once("://") {
+            Some(("syslog", "")) => Destination::Syslog,
+            Some(("syslog", server)) => Destination::Remote(match server.contains(':') {
+                true => server.to_string(),
+                false => format!("{}:514", server),
+            }),
+            Some(("journald", "")) => Destination::Journal,
+            _ => bail!(
+                "--sink must be syslog://, syslog://host[:port] or journald://, not {}",
+                url
+            ),
+        };
+        if !cfg!(unix) && !matches!(destination, Destination::Remote(_)) {
+            bail!("--sink {} is only supported on Unix", url);
+        }
+        Ok(SystemLog { destination, full })
+    }
+
+    /// Log the receipt at `file`. A receipt too large for one message is
+    /// logged as its summary alone
+    pub fn log(&self, file: &Path) -> Result<()> {
+        let receipt = receipt::read_receipt(file)?;
+        let full = match self.full {
+            true => Some(canonical::to_string(&receipt::to_value(&receipt)?)),
+            false => None,
+        };
+        let sent = match self.send(&receipt, full.as_deref()) {
+            Err(err) if full.is_some() && too_large(&err) => {
+                eprintln!(
+                    "rcpt: receipt {} is too large for one log message; logging its summary",
+                    receipt.id
+                );
+                self.send(&receipt, None)
+            }
+            sent => sent,
+        };
+        let kind = match self.destination {
+            Destination::Journal => "the journal",
+            _ => "syslog",
+        };
+        sent.context(format!("failed to log receipt {} to {}", receipt.id, kind))
+    }
+
+    fn send(&self, receipt: &Receipt, full: Option<&str>) -> io::Result<()> {
+        match &self.destination {
+            Destination::Syslog => {
+                let message = syslog_message(receipt, full, None);
+                let mut last = io::Error::from(io::ErrorKind::NotFound);
+                for socket in SYSLOG_SOCKETS {
+                    match imp::send(Path::new(socket), message.as_bytes()) {
+                        Ok(()) => return Ok(()),
+                        Err(err) => last = err,
+                    }
+                }
+                Err(last)
+            }
+            Destination::Remote(server) => {
+                let hostname = receipt
+                    .host
+                    .as_ref()
+                    .and_then(|host| host.hostname.clone())
+                    .or_else(|| host::capture().hostname);
+                let message = syslog_message(receipt, full, hostname.as_deref());
+                let socket = UdpSocket::bind(match server.starts_with('[') {
+                    true => "[::]:0",
+                    false => "0.0.0.0:0",
+                })?;
+                socket.send_to(message.as_bytes(), server)?;
+                Ok(())
+            }
+            Destination::Journal => {
+                imp::send(Path::new(JOURNAL_SOCKET), &journal_entry(receipt, full))
+            }
+        }
+    }
+}
+
+/// Syslog severity for a run: informational if it succeeded or has not
+/// finished, a warning if it failed, an error if it was stopped
+fn severity(status: Status) -> u8 {
+    match status {
+        Status::Ok | Status::Planned | Status::Running => 6,
+        Status::Failed | Status::MissingOutputs | Status::Interrupted => 4,
+        Status::TimedOut | Status::OomKilled | Status::Lost => 3,
+    }
+}
+
+/// One line saying how the run went, e.g. `make test: failed with exit
+/// code 2 after 3204ms`
+fn summary(receipt: &Receipt) -> String {
+    let mut summary = format!("{}: {}", command_line(receipt), receipt.status.as_str());
+    if let Some(code) = receipt.exit_code {
+        summary.push_str(&format!(" with exit code {}", code));
+    }
+    summary.push_str(&format!(" after {}ms", receipt.duration_ms));
+    summary
+}
+
+fn command_line(receipt: &Receipt) -> String {
+    std::iter::once(receipt.command.as_str())
+        .chain(receipt.args.iter().map(String::as_str))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A BSD-style syslog message, which every syslog daemon reads, holding the
+/// summary's fields as `key=value` pairs and then, if given, `receipt=`
+/// and the receipt's JSON to the end of the message. `hostname` is for a
+/// remote server; the local daemon adds its own
+fn syslog_message(receipt: &Receipt, full: Option<&str>, hostname: Option<&str>) -> String {
+    let priority = FACILITY_USER * 8 + severity(receipt.status);
+    let mut message = format!(
+        "<{}>{} ",
+        priority,
+        chrono::Local::now().format("%b %e %H:%M:%S")
+    );
+    if let Some(hostname) = hostname {
+        message.push_str(hostname);
+        message.push(' ');
+    }
+    message.push_str(&format!("rcpt[{}]: ", std::process::id()));
+
+    let mut fields = vec![
+        ("id", receipt.id.clone()),
+        ("status", receipt.status.as_str().to_string()),
+    ];
+    if let Some(code) = receipt.exit_code {
+        fields.push(("exit_code", code.to_string()));
+    }
+    fields.push(("duration_ms", receipt.duration_ms.to_string()));
+    fields.push(("command", command_line(receipt)));
+    fields.push(("cwd", receipt.cwd.clone()));
+    let pairs: Vec<String> = fields
+        .into_iter()
+        .map(|(key, value)| format!("{}={}", key, logfmt_value(&value)))
+        .collect();
+    message.push_str(&pairs.join(" "));
+    if let Some(json) = full {
+        message.push_str(" receipt=");
+        message.push_str(json);
+    }
+    message
+}
+
+/// `value`, quoted if it is empty or has spaces, quotes or `=` in it
+fn logfmt_value(value: &str) -> String {
+    if !value.is_empty() && !value.contains([' ', '"', '=', '\\', '\n']) {
+        return value.to_string();
+    }
+    format!(
+        "\"{}\"",
+        value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+    )
+}
+
+/// A journal entry in journald's native protocol, its fields named
+/// `RCPT_*` and, if given, the receipt's JSON in `RCPT_RECEIPT`
+fn journal_entry(receipt: &Receipt, full: Option<&str>) -> Vec<u8> {
+    let mut fields = vec![
+        ("MESSAGE", summary(receipt)),
+        ("PRIORITY", severity(receipt.status).to_string()),
+        ("SYSLOG_IDENTIFIER", "rcpt".to_string()),
+        ("SYSLOG_PID", std::process::id().to_string()),
+        ("RCPT_ID", receipt.id.clone()),
+        ("RCPT_STATUS", receipt.status.as_str().to_string()),
+        ("RCPT_DURATION_MS", receipt.duration_ms.to_string()),
+        ("RCPT_COMMAND", command_line(receipt)),
+        ("RCPT_CWD", receipt.cwd.clone()),
+        ("RCPT_START_TIME", receipt.start_time.to_rfc3339()),
+    ];
+    if let Some(code) = receipt.exit_code {
+        fields.push(("RCPT_EXIT_CODE", code.to_string()));
+    }
+    if let Some(session) = &receipt.session_id {
+        fields.push(("RCPT_SESSION_ID", session.clone()));
+    }
+    if !receipt.tags.is_empty() {
+        fields.push(("RCPT_TAGS", receipt.tags.join(",")));
+    }
+    if let Some(json) = full {
+        fields.push(("RCPT_RECEIPT", json.to_string()));
+    }
+
+    let mut entry = Vec::new();
+    for (name, value) in fields {
+        entry.extend_from_slice(name.as_bytes());
+        // A value with a newline goes as its length and then its bytes
+        if value.contains('\n') {
+            entry.push(b'\n');
+            entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            entry.push(b'=');
+        }
+        entry.extend_from_slice(value.as_bytes());
+        entry.push(b'\n');
+    }
+    entry
+}
+
+/// Whether `err` says a message was too large to send in one datagram
+#[cfg(unix)]
+fn too_large(err: &io::Error) -> bool {
+    err.raw_os_error() == Some(libc::EMSGSIZE)
+}
+
+#[cfg(not(unix))]
+fn too_large(_err: &io::Error) -> bool {
+    false
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::io;
+    use std::os::unix::net::UnixDatagram;
+    use std::path::Path;
+
+    /// Send `message` as one datagram to the socket at `path`
+    pub fn send(path: &Path, message: &[u8]) -> io::Result<()> {
+        UnixDatagram::unbound()?.send_to(message, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use std::io;
+    use std::path::Path;
+
+    pub fn send(_path: &Path, _message: &[u8]) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
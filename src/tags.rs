@@ -0,0 +1,23 @@
+use anyhow::{bail, Result};
+
+/// Parse a `--tag` argument: any non-empty string without whitespace
+pub fn parse_tag(s: &str) -> Result<String> {
+    if s.is_empty() {
+        bail!("tag must not be empty");
+    }
+    if s.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        bail!("tag '{}' must not contain whitespace", s.escape_debug());
+    }
+    Ok(s.to_string())
+}
+
+/// Tags in the order first given, without repeats
+pub fn dedup(tags: &[String]) -> Vec<String> {
+    let mut unique: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        if !unique.contains(tag) {
+            unique.push(tag.clone());
+        }
+    }
+    unique
+}
@@ -0,0 +1,133 @@
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::ledger;
+use crate::receipt::{json_files, read_receipt, Receipt, Status};
+use crate::signals;
+
+/// How many of the latest receipts are printed first unless --lines says
+/// otherwise
+pub const DEFAULT_LINES: usize = 10;
+
+/// How often new receipts are looked for with --follow
+const POLL: Duration = Duration::from_millis(500);
+
+/// How often rcpt checks for a signal while waiting to look again
+const SIGNAL_CHECK: Duration = Duration::from_millis(50);
+
+/// Where receipts land
+#[derive(Debug, Clone)]
+pub enum Source {
+    /// Receipt files under these directories, searched recursively
+    Dirs(Vec<PathBuf>),
+    /// Entries appended to this ledger
+    Ledger(PathBuf),
+}
+
+/// What has been printed so far: the modification time and size of each
+/// file, and its status if it is a receipt, or how far into the ledger has
+/// been read
+#[derive(Debug, Default)]
+struct Seen {
+    files: HashMap<PathBuf, (Option<SystemTime>, u64, Option<Status>)>,
+    offset: u64,
+}
+
+/// Write a summary of each of the last `lines` receipts in `source` to
+/// `report`, then, with `follow`, of each new one as it lands until rcpt is
+/// interrupted. A receipt file rewritten with a new status, as a detached
+/// run's is when it finishes, is written again
+pub fn tail(source: &Source, lines: usize, follow: bool, report: &mut impl Write) -> Result<()> {
+    if let Source::Ledger(path) = source {
+        if !follow && !path.exists() {
+            bail!("No ledger at {}", path.display());
+        }
+    }
+    let mut seen = Seen::default();
+    let receipts = new_receipts(source, &mut seen)?;
+    for receipt in &receipts[receipts.len().saturating_sub(lines)..] {
+        writeln!(report, "{}", summary(receipt))?;
+    }
+    if !follow {
+        return Ok(());
+    }
+    loop {
+        let mut waited = Duration::ZERO;
+        while waited < POLL {
+            if signals::received() {
+                return Ok(());
+            }
+            thread::sleep(SIGNAL_CHECK);
+            waited += SIGNAL_CHECK;
+        }
+        for receipt in new_receipts(source, &mut seen)? {
+            writeln!(report, "{}", summary(&receipt))?;
+            report.flush()?;
+        }
+    }
+}
+
+/// One line for `receipt`: when it started, its id, status and duration,
+/// and the command line
+pub fn summary(receipt: &Receipt) -> String {
+    format!(
+        "{}  {}  {:<15} {:>8}ms  {}",
+        receipt.start_time.format("%Y-%m-%dT%H:%M:%SZ"),
+        receipt.id,
+        receipt.status.as_str(),
+        receipt.duration_ms,
+        receipt.command_line()
+    )
+}
+
+/// The receipts in `source` not seen before, or with a status that has
+/// changed since, in the order the runs started
+fn new_receipts(source: &Source, seen: &mut Seen) -> Result<Vec<Receipt>> {
+    let mut receipts = match source {
+        Source::Ledger(path) => {
+            // With --follow, a ledger that does not exist yet is waited for
+            let Ok(meta) = fs::metadata(path) else {
+                return Ok(Vec::new());
+            };
+            // A ledger replaced by a shorter one is read from its start
+            if meta.len() < seen.offset {
+                seen.offset = 0;
+            }
+            let (receipts, offset) = ledger::read_from(path, seen.offset)?;
+            seen.offset = offset;
+            receipts
+        }
+        Source::Dirs(dirs) => {
+            let mut receipts = Vec::new();
+            for path in json_files(dirs)? {
+                let Ok(meta) = fs::metadata(&path) else {
+                    continue;
+                };
+                let (modified, len) = (meta.modified().ok(), meta.len());
+                let before = seen.files.get(&path);
+                if before.is_some_and(|&(m, l, _)| (m, l) == (modified, len)) {
+                    continue;
+                }
+                // Not a receipt, or one that cannot be decrypted
+                let Ok(receipt) = read_receipt(&path) else {
+                    seen.files.insert(path, (modified, len, None));
+                    continue;
+                };
+                let changed = before.is_none_or(|&(_, _, status)| status != Some(receipt.status));
+                seen.files
+                    .insert(path, (modified, len, Some(receipt.status)));
+                if changed {
+                    receipts.push(receipt);
+                }
+            }
+            receipts
+        }
+    };
+    receipts.sort_by(|a, b| (a.start_time, &a.id).cmp(&(b.start_time, &b.id)));
+    Ok(receipts)
+}
@@ -0,0 +1,211 @@
+use anyhow::{bail, Context, Result};
+
+/// Size of a tar block, and of each header
+const BLOCK: usize = 512;
+
+/// Write `entries`, each a path and the contents of a regular file, as a
+/// POSIX ustar archive, every file modified at `mtime` (seconds since the
+/// Unix epoch)
+pub fn write(entries: &[(String, Vec<u8>)], mtime: u64) -> Result<Vec<u8>> {
+    let mut archive = Vec::new();
+    for (path, contents) in entries {
+        let mut header = [0u8; BLOCK];
+        let (prefix, name) = split_path(path)?;
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        octal(&mut header[100..108], 0o644);
+        octal(&mut header[108..116], 0);
+        octal(&mut header[116..124], 0);
+        octal(&mut header[124..136], contents.len() as u64);
+        octal(&mut header[136..148], mtime);
+        header[156] = b'0';
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+        // The checksum is over the header with its own field as spaces
+        header[148..156].fill(b' ');
+        let sum: u64 = header.iter().map(|&byte| byte as u64).sum();
+        header[148..154].copy_from_slice(format!("{:06o}", sum).as_bytes());
+        header[154] = 0;
+        archive.extend_from_slice(&header);
+        archive.extend_from_slice(contents);
+        archive.resize(archive.len().next_multiple_of(BLOCK), 0);
+    }
+    // Two empty blocks end the archive
+    archive.resize(archive.len() + 2 * BLOCK, 0);
+    Ok(archive)
+}
+
+/// The regular files in a tar archive, each a path and its contents,
+/// skipping the directories in it
+pub fn read(archive: &[u8]) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut entries = Vec::new();
+    let mut pos = 0;
+    while pos + BLOCK <= archive.len() {
+        let header = &archive[pos..pos + BLOCK];
+        if header.iter().all(|&byte| byte == 0) {
+            return Ok(entries);
+        }
+        let mut blank = header.to_vec();
+        blank[148..156].fill(b' ');
+        let sum: u64 = blank.iter().map(|&byte| byte as u64).sum();
+        if parse_octal(&header[148..156]) != Some(sum) {
+            bail!("the archive is corrupt at byte {}", pos);
+        }
+        let name = field(&header[..100]);
+        let path = match &header[257..262] == b"ustar" && header[345] != 0 {
+            true => format!("{}/{}", field(&header[345..500]), name),
+            false => name,
+        };
+        let size = parse_octal(&header[124..136])
+            .context(format!("{} in the archive has no size", path))? as usize;
+        pos += BLOCK;
+        if pos + size > archive.len() {
+            bail!("{} in the archive is cut short", path);
+        }
+        match header[156] {
+            b'0' | 0 => entries.push((path, archive[pos..pos + size].to_vec())),
+            b'5' => {}
+            kind => bail!(
+                "{} in the archive is not a regular file (type {})",
+                path,
+                kind as char
+            ),
+        }
+        pos += size.next_multiple_of(BLOCK);
+    }
+    bail!("the archive is cut short")
+}
+
+/// `path` as the ustar prefix and name fields hold it: all of it in the
+/// name if it fits in 100 bytes, or else split at a `/`
+fn split_path(path: &str) -> Result<(&str, &str)> {
+    if path.len() <= 100 {
+        return Ok(("", path));
+    }
+    path.char_indices()
+        .filter(|&(i, c)| c == '/' && i <= 155 && path.len() - i - 1 <= 100)
+        .map(|(i, _)| (&path[..i], &path[i + 1..]))
+        .next()
+        .context(format!("{} is too long a path for a tar archive", path))
+}
+
+/// Write `value` into `field` as zero-padded octal, ending in a NUL
+fn octal(field: &mut [u8], value: u64) {
+    let digits = format!("{:0width$o}", value, width = field.len() - 1);
+    field[..digits.len()].copy_from_slice(digits.as_bytes());
+    field[digits.len()] = 0;
+}
+
+fn parse_octal(field: &[u8]) -> Option<u64> {
+    let text = std::str::from_utf8(field).ok()?;
+    u64::from_str_radix(text.trim_matches(|c: char| c == '\0' || c == ' '), 8).ok()
+}
+
+/// A NUL-terminated text field
+fn field(bytes: &[u8]) -> String {
+    let end = bytes
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries() -> Vec<(String, Vec<u8>)> {
+        let long = format!("receipts/{}/receipt.json", "d".repeat(120));
+        vec![
+            ("manifest.json".to_string(), b"{}\n".to_vec()),
+            ("empty".to_string(), Vec::new()),
+            ("objects/block".to_string(), vec![7; BLOCK]),
+            (long, b"hello".to_vec()),
+        ]
+    }
+
+    /// `archive` with the header at `pos` changed by `edit`, and its
+    /// checksum fixed to match
+    fn edited(mut archive: Vec<u8>, pos: usize, edit: impl Fn(&mut [u8])) -> Vec<u8> {
+        let header = &mut archive[pos..pos + BLOCK];
+        edit(header);
+        header[148..156].fill(b' ');
+        let sum: u64 = header.iter().map(|&byte| byte as u64).sum();
+        header[148..154].copy_from_slice(format!("{:06o}", sum).as_bytes());
+        header[154] = 0;
+        archive
+    }
+
+    #[test]
+    fn reads_what_it_writes() {
+        let archive = write(&entries(), 1_771_204_509).unwrap();
+        assert_eq!(read(&archive).unwrap(), entries());
+    }
+
+    #[test]
+    fn writes_ustar_headers() {
+        let archive = write(&entries(), 1_771_204_509).unwrap();
+        // Each header and its contents padded to whole blocks (none for the
+        // empty file), then two empty blocks
+        assert_eq!(archive.len(), (2 + 1 + 2 + 2 + 2) * BLOCK);
+        assert_eq!(field(&archive[..100]), "manifest.json");
+        assert_eq!(&archive[124..136], b"00000000003\0");
+        assert_eq!(&archive[136..148], b"15144467635\0");
+        assert_eq!(&archive[257..265], b"ustar\x0000");
+        assert_eq!(&archive[BLOCK..BLOCK + 3], b"{}\n");
+        assert!(archive[BLOCK + 3..2 * BLOCK].iter().all(|&byte| byte == 0));
+
+        let long = 5 * BLOCK;
+        assert_eq!(
+            field(&archive[long + 345..long + 500]),
+            format!("receipts/{}", "d".repeat(120))
+        );
+        assert_eq!(field(&archive[long..long + 100]), "receipt.json");
+    }
+
+    #[test]
+    fn rejects_paths_it_cannot_split() {
+        let name = "n".repeat(101);
+        assert!(write(&[(name, Vec::new())], 0).is_err());
+        let dir = format!("{}/{}", "d".repeat(156), "name");
+        assert!(write(&[(dir, Vec::new())], 0).is_err());
+    }
+
+    #[test]
+    fn skips_directories() {
+        let archive = write(&entries(), 0).unwrap();
+        let archive = edited(archive, 2 * BLOCK, |header| {
+            header[..6].copy_from_slice(b"empty/");
+            header[156] = b'5';
+        });
+        let paths: Vec<String> = read(&archive)
+            .unwrap()
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(paths.len(), 3);
+        assert!(!paths.iter().any(|path| path.starts_with("empty")));
+    }
+
+    #[test]
+    fn rejects_other_kinds_of_file() {
+        let archive = edited(write(&entries(), 0).unwrap(), 0, |header| {
+            header[156] = b'2'
+        });
+        let err = read(&archive).unwrap_err().to_string();
+        assert!(err.contains("manifest.json"), "{}", err);
+        assert!(err.contains("type 2"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_corrupt_and_short_archives() {
+        let archive = write(&entries(), 0).unwrap();
+        let mut corrupt = archive.clone();
+        corrupt[BLOCK * 2 + 1] ^= 1;
+        let err = read(&corrupt).unwrap_err().to_string();
+        assert!(err.contains(&format!("byte {}", BLOCK * 2)), "{}", err);
+
+        assert!(read(&archive[..BLOCK + 1]).is_err());
+        assert!(read(&archive[..archive.len() - 2 * BLOCK]).is_err());
+    }
+}
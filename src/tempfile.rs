@@ -0,0 +1,29 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::id;
+
+/// A file in the temporary directory for handing data to another program,
+/// removed when dropped
+pub struct TempFile(PathBuf);
+
+impl TempFile {
+    /// Create a file holding `contents`, named `rcpt-<id><suffix>`
+    pub fn create(suffix: &str, contents: &[u8]) -> Result<Self> {
+        let name = format!("rcpt-{}{}", id::ulid(chrono::Utc::now()), suffix);
+        let path = std::env::temp_dir().join(name);
+        fs::write(&path, contents).context(format!("Failed to write {}", path.display()))?;
+        Ok(TempFile(path))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
@@ -0,0 +1,211 @@
+use anyhow::{bail, Result};
+use chrono::Utc;
+use std::path::{Path, PathBuf};
+
+use crate::id;
+use crate::receipt::{Receipt, Status};
+
+/// How receipts are named without --out-template
+pub const DEFAULT: &str = "receipt-{id}.json";
+
+/// What a receipt's file name can be made from
+#[derive(Debug, Clone)]
+pub struct Names<'a> {
+    pub id: &'a str,
+    /// The command as the receipt records it, or as it was given
+    pub command: &'a str,
+    pub tags: &'a [String],
+    /// How the command ended and its exit code, once it has
+    pub outcome: Option<(Status, Option<i32>)>,
+}
+
+impl<'a> Names<'a> {
+    pub fn of(receipt: &'a Receipt) -> Self {
+        Names {
+            id: &receipt.id,
+            command: &receipt.command,
+            tags: &receipt.tags,
+            outcome: Some((receipt.status, receipt.exit_code)),
+        }
+    }
+}
+
+/// The path `template` names, its placeholders filled in: `{id}`,
+/// `{date}` and `{time}` (UTC, when the id was made), `{command}` (the
+/// command's file name), `{status}`, `{exit_code}`, `{tag}` (the first
+/// tag) and `{tags}` (all of them, joined by `-`). A value that could
+/// leave its component of the path has the offending characters replaced
+pub fn render(template: &str, names: &Names) -> Result<PathBuf> {
+    let mut path = String::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        path.push_str(&rest[..open]);
+        let Some(close) = rest[open..].find('}') else {
+            bail!("--out-template {} has an unclosed {{", template);
+        };
+        let name = &rest[open + 1..open + close];
+        path.push_str(&sanitize(&value(name, names, template)?));
+        rest = &rest[open + close + 1..];
+    }
+    path.push_str(rest);
+    Ok(PathBuf::from(path))
+}
+
+/// Fail unless every placeholder in `template` is one `render` knows, so
+/// a mistake shows before the command runs rather than after
+pub fn check(template: &str) -> Result<()> {
+    render(
+        template,
+        &Names {
+            id: "",
+            command: "",
+            tags: &[],
+            outcome: Some((Status::Ok, None)),
+        },
+    )?;
+    Ok(())
+}
+
+fn value(name: &str, names: &Names, template: &str) -> Result<String> {
+    let time = id::time(names.id).unwrap_or_else(Utc::now);
+    Ok(match name {
+        "id" => names.id.to_string(),
+        "date" => time.format("%Y-%m-%d").to_string(),
+        "time" => time.format("%H%M%S").to_string(),
+        "command" => names
+            .command
+            .split_whitespace()
+            .next()
+            .and_then(|program| Path::new(program).file_name())
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        "status" => outcome(name, names)?.0.as_str().to_string(),
+        "exit_code" => match outcome(name, names)?.1 {
+            Some(code) => code.to_string(),
+            None => "none".to_string(),
+        },
+        "tag" => names
+            .tags
+            .first()
+            .cloned()
+            .unwrap_or_else(|| "untagged".to_string()),
+        "tags" => match names.tags.is_empty() {
+            true => "untagged".to_string(),
+            false => names.tags.join("-"),
+        },
+        _ => bail!(
+            "--out-template {} has an unknown placeholder {{{}}}; expected id, date, time, command, status, exit_code, tag or tags",
+            template,
+            name
+        ),
+    })
+}
+
+/// How the command ended, for the placeholder `name`, which cannot be
+/// filled in before then
+fn outcome(name: &str, names: &Names) -> Result<(Status, Option<i32>)> {
+    match names.outcome {
+        Some(outcome) => Ok(outcome),
+        None => bail!(
+            "--out-template cannot use {{{}}} here, where the receipt is named before the command finishes",
+            name
+        ),
+    }
+}
+
+/// `value` made safe as one component of a path
+fn sanitize(value: &str) -> String {
+    let safe: String = value
+        .chars()
+        .map(|c| match c {
+            'A'..='Z' | 'a'..='z' | '0'..='9' | '.' | '_' | '-' | '+' | '@' | ',' | '=' => c,
+            _ => '_',
+        })
+        .collect();
+    match safe.as_str() {
+        "" | "." | ".." => "_".to_string(),
+        _ => safe,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    /// The id of a run started at 2026-02-16T01:15:09Z
+    fn id() -> String {
+        id::ulid_from(Utc.with_ymd_and_hms(2026, 2, 16, 1, 15, 9).unwrap(), b"run")
+    }
+
+    fn names<'a>(id: &'a str, command: &'a str, tags: &'a [String]) -> Names<'a> {
+        Names {
+            id,
+            command,
+            tags,
+            outcome: Some((Status::Failed, Some(2))),
+        }
+    }
+
+    fn rendered(template: &str, names: &Names) -> String {
+        render(template, names)
+            .unwrap()
+            .to_string_lossy()
+            .to_string()
+    }
+
+    #[test]
+    fn fills_in_placeholders() {
+        let id = id();
+        let tags = ["nightly".to_string(), "arm64".to_string()];
+        let names = names(&id, "/usr/bin/make -j4", &tags);
+        assert_eq!(rendered(DEFAULT, &names), format!("receipt-{}.json", id));
+        assert_eq!(
+            rendered("{date}/{time}-{command}.json", &names),
+            "2026-02-16/011509-make.json"
+        );
+        assert_eq!(
+            rendered("{status}-{exit_code}-{tag}-{tags}", &names),
+            "failed-2-nightly-nightly-arm64"
+        );
+        assert_eq!(rendered("plain.json", &names), "plain.json");
+    }
+
+    #[test]
+    fn fills_in_what_is_missing() {
+        let id = id();
+        let mut names = names(&id, "", &[]);
+        names.outcome = Some((Status::TimedOut, None));
+        assert_eq!(
+            rendered("{command}{exit_code}-{tag}-{tags}", &names),
+            "_none-untagged-untagged"
+        );
+    }
+
+    #[test]
+    fn keeps_values_within_their_component() {
+        let id = id();
+        let tags = ["../etc".to_string(), "a b/c".to_string()];
+        let names = names(&id, "..", &tags);
+        assert_eq!(rendered("out/{tag}.json", &names), "out/.._etc.json");
+        assert_eq!(rendered("out/{tags}.json", &names), "out/.._etc-a_b_c.json");
+        assert_eq!(rendered("out/{command}/x", &names), "out/_/x");
+    }
+
+    #[test]
+    fn rejects_bad_templates() {
+        let id = id();
+        let names = names(&id, "make", &[]);
+        assert!(render("receipt-{id.json", &names).is_err());
+        let unknown = render("{host}.json", &names).unwrap_err();
+        assert!(unknown.to_string().contains("unknown placeholder {host}"));
+        assert!(check("{date}/{command}-{status}.json").is_ok());
+        assert!(check("{nope}").is_err());
+        let early = Names {
+            outcome: None,
+            ..names
+        };
+        assert!(render("{id}.json", &early).is_ok());
+        assert!(render("{status}.json", &early).is_err());
+    }
+}
@@ -0,0 +1,163 @@
+use anyhow::{bail, Context, Result};
+use chrono::{NaiveDateTime, SecondsFormat};
+use serde::{Deserialize, Serialize};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::base64;
+use crate::exec::resolve_executable;
+use crate::receipt::{self, Receipt};
+use crate::tempfile::TempFile;
+
+/// An RFC 3161 timestamp over a receipt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimestampRecord {
+    /// URL of the time-stamping authority
+    pub tsa: String,
+    /// Time the authority vouched for, from the token
+    pub time: String,
+    /// Base64 of the DER timestamp token, the authority's signature over
+    /// the `receipt_digest` and the time
+    pub token: String,
+}
+
+/// Seconds curl waits to connect to the authority
+const CONNECT_TIMEOUT: &str = "10";
+
+/// Seconds curl waits for the authority's whole response
+const MAX_TIME: &str = "30";
+
+/// A time-stamping authority, asked through openssl and curl
+#[derive(Debug)]
+pub struct Tsa {
+    url: String,
+    openssl: PathBuf,
+    curl: PathBuf,
+}
+
+/// Find `name` on PATH
+fn program(name: &str, purpose: &str) -> Result<PathBuf> {
+    let cwd = std::env::current_dir().context("Failed to get current directory")?;
+    resolve_executable(OsStr::new(name), std::env::var_os("PATH").as_ref(), &cwd)
+        .context(format!("{} not found; install it to {}", name, purpose))
+}
+
+impl Tsa {
+    /// The authority at `url`, e.g. https://freetsa.org/tsr
+    pub fn new(url: &str) -> Result<Self> {
+        Ok(Tsa {
+            url: url.to_string(),
+            openssl: program("openssl", "timestamp receipts")?,
+            curl: program("curl", "timestamp receipts")?,
+        })
+    }
+
+    /// Get a token over the receipt's digest into its `timestamp`
+    pub fn stamp(&self, receipt: &mut Receipt) -> Result<()> {
+        receipt.timestamp = None;
+        let digest = receipt::receipt_digest(&receipt::to_value(receipt)?);
+
+        let query = TempFile::create(".tsq", b"")?;
+        run(Command::new(&self.openssl)
+            .args(["ts", "-query", "-sha256", "-cert", "-digest", &digest])
+            .arg("-out")
+            .arg(query.path()))
+        .context("Failed to make the timestamp request")?;
+
+        let response = TempFile::create(".tsr", b"")?;
+        run(Command::new(&self.curl)
+            .args([
+                "--silent",
+                "--show-error",
+                "--fail",
+                "--connect-timeout",
+                CONNECT_TIMEOUT,
+                "--max-time",
+                MAX_TIME,
+                "--header",
+                "Content-Type: application/timestamp-query",
+                "--data-binary",
+            ])
+            .arg(format!("@{}", query.path().display()))
+            .arg("--output")
+            .arg(response.path())
+            .arg(&self.url))
+        .context(format!("Failed to get a timestamp from {}", self.url))?;
+
+        let text = run(Command::new(&self.openssl)
+            .args(["ts", "-reply", "-text", "-in"])
+            .arg(response.path()))
+        .context(format!("{} sent a malformed timestamp response", self.url))?;
+        if !text.lines().any(|line| line.trim() == "Status: Granted.") {
+            let status = text
+                .lines()
+                .find_map(|line| line.trim().strip_prefix("Status: "))
+                .unwrap_or("no status");
+            bail!("{} refused the timestamp: {}", self.url, status);
+        }
+        let time = text
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("Time stamp: "))
+            .and_then(parse_time)
+            .context(format!("{}'s timestamp has no readable time", self.url))?;
+
+        let token = TempFile::create(".der", b"")?;
+        run(Command::new(&self.openssl)
+            .args(["ts", "-reply", "-token_out", "-in"])
+            .arg(response.path())
+            .arg("-out")
+            .arg(token.path()))
+        .context(format!("{} sent a malformed timestamp response", self.url))?;
+        let token = std::fs::read(token.path()).context("Failed to read the timestamp token")?;
+
+        receipt.timestamp = Some(TimestampRecord {
+            tsa: self.url.clone(),
+            time,
+            token: base64::encode(&token),
+        });
+        Ok(())
+    }
+}
+
+/// Check `record` is a token over `digest` signed by an authority whose
+/// certificate chains to `ca_file`, or to the system's trusted roots
+pub fn verify(record: &TimestampRecord, digest: &str, ca_file: Option<&Path>) -> Result<()> {
+    let token = base64::decode(&record.token).context("malformed token")?;
+    let token = TempFile::create(".der", &token)?;
+    let mut command = Command::new(program("openssl", "verify timestamps")?);
+    command
+        .args(["ts", "-verify", "-digest", digest, "-token_in", "-in"])
+        .arg(token.path());
+    if let Some(ca_file) = ca_file {
+        command.arg("-CAfile").arg(ca_file);
+    }
+    run(&mut command)?;
+    Ok(())
+}
+
+/// openssl's `Oct 14 11:11:21 2026 GMT`, with optional fractional seconds,
+/// as RFC 3339
+fn parse_time(text: &str) -> Option<String> {
+    let text = text.strip_suffix(" GMT")?;
+    let time = NaiveDateTime::parse_from_str(text, "%b %e %H:%M:%S%.f %Y").ok()?;
+    Some(time.and_utc().to_rfc3339_opts(SecondsFormat::AutoSi, true))
+}
+
+/// Run a command to completion, failing with its stderr if it fails
+fn run(command: &mut Command) -> Result<String> {
+    let output = command
+        .stdin(Stdio::null())
+        .output()
+        .context(format!("Failed to run {:?}", command.get_program()))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let message = stderr
+            .lines()
+            .filter(|line| !line.starts_with("Using configuration from"))
+            .collect::<Vec<_>>()
+            .join("; ");
+        bail!("{}", message.trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
@@ -0,0 +1,359 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// How file accesses were observed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TraceMechanism {
+    Fanotify,
+}
+
+/// Files the command and its descendants opened, from `--trace-files`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileTrace {
+    pub mechanism: TraceMechanism,
+    /// Files opened only for reading, sorted
+    pub read: Vec<String>,
+    /// Files opened for writing or modified, sorted
+    pub written: Vec<String>,
+    /// Paths seen beyond the most that are recorded
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub omitted: u64,
+    /// Whether the kernel's event queue overflowed, losing accesses
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub events_lost: bool,
+}
+
+fn is_zero(count: &u64) -> bool {
+    *count == 0
+}
+
+/// A watch on file accesses across every mount, set up before the command
+/// starts so none of its accesses are missed
+pub struct Tracer(imp::Tracer);
+
+/// A tracer attributing accesses to the processes of a running command
+pub struct RunningTracer(imp::RunningTracer);
+
+impl Tracer {
+    /// Watch all mounts (Linux only; needs CAP_SYS_ADMIN)
+    pub fn open() -> Result<Self> {
+        Ok(Tracer(imp::Tracer::open()?))
+    }
+
+    /// Start recording the accesses of `roots` and their descendants
+    pub fn start(self, roots: &[u32]) -> RunningTracer {
+        RunningTracer(self.0.start(roots))
+    }
+}
+
+impl RunningTracer {
+    /// Collect the remaining events, once the command has exited
+    pub fn finish(self) -> FileTrace {
+        self.0.finish()
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use anyhow::{bail, Result};
+    use std::collections::{BTreeSet, HashMap};
+    use std::fs;
+    use std::io;
+    use std::mem;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread::{self, JoinHandle};
+
+    use super::FileTrace;
+    use crate::procfs;
+
+    /// How long a read of the event queue waits before checking for stop
+    const POLL_MS: libc::c_int = 50;
+
+    /// Most paths recorded in each list; any beyond are only counted
+    const MAX_PATHS: usize = 10_000;
+
+    pub struct Tracer {
+        fd: OwnedFd,
+    }
+
+    pub struct RunningTracer {
+        stop: Arc<AtomicBool>,
+        handle: JoinHandle<FileTrace>,
+    }
+
+    impl Tracer {
+        pub fn open() -> Result<Self> {
+            // SAFETY: fanotify_init has no memory-safety preconditions
+            let fd = unsafe {
+                libc::fanotify_init(
+                    libc::FAN_CLASS_NOTIF | libc::FAN_CLOEXEC | libc::FAN_NONBLOCK,
+                    (libc::O_RDONLY | libc::O_LARGEFILE | libc::O_CLOEXEC) as libc::c_uint,
+                )
+            };
+            if fd < 0 {
+                let err = io::Error::last_os_error();
+                if err.raw_os_error() == Some(libc::EPERM) {
+                    bail!("--trace-files needs root (CAP_SYS_ADMIN) to use fanotify");
+                }
+                bail!("Failed to start fanotify: {}", err);
+            }
+            // SAFETY: the descriptor was just opened and is owned here
+            let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+            let mask = libc::FAN_OPEN | libc::FAN_MODIFY | libc::FAN_CLOSE_WRITE;
+            let mut marked = 0;
+            for mount in mount_points() {
+                let Ok(path) = std::ffi::CString::new(mount) else {
+                    continue;
+                };
+                // SAFETY: the path is NUL-terminated and outlives the call
+                let rc = unsafe {
+                    libc::fanotify_mark(
+                        fd.as_raw_fd(),
+                        libc::FAN_MARK_ADD | libc::FAN_MARK_MOUNT,
+                        mask,
+                        libc::AT_FDCWD,
+                        path.as_ptr(),
+                    )
+                };
+                if rc == 0 {
+                    marked += 1;
+                }
+            }
+            if marked == 0 {
+                bail!(
+                    "Failed to watch any mount with fanotify: {}",
+                    io::Error::last_os_error()
+                );
+            }
+            Ok(Tracer { fd })
+        }
+
+        pub fn start(self, roots: &[u32]) -> RunningTracer {
+            let stop = Arc::new(AtomicBool::new(false));
+            let mut members = Members::new(roots);
+            let handle = {
+                let stop = Arc::clone(&stop);
+                thread::spawn(move || {
+                    let mut opened = BTreeSet::new();
+                    let mut written = BTreeSet::new();
+                    let mut events_lost = false;
+                    loop {
+                        // Checked before reading so the last read drains what
+                        // the command did before it exited
+                        let last = stop.load(Ordering::SeqCst);
+                        while let Some(events) = read_events(&self.fd, !last) {
+                            for event in events {
+                                events_lost |= event.mask & libc::FAN_Q_OVERFLOW != 0;
+                                let Some(path) = event.path else {
+                                    continue;
+                                };
+                                if !members.contains(event.pid) || is_own(&path) {
+                                    continue;
+                                }
+                                if event.mask & (libc::FAN_MODIFY | libc::FAN_CLOSE_WRITE) != 0 {
+                                    written.insert(path.clone());
+                                }
+                                if event.mask & libc::FAN_OPEN != 0 {
+                                    opened.insert(path);
+                                }
+                            }
+                        }
+                        if last {
+                            return summarize(opened, written, events_lost);
+                        }
+                    }
+                })
+            };
+            RunningTracer { stop, handle }
+        }
+    }
+
+    impl RunningTracer {
+        pub fn finish(self) -> FileTrace {
+            self.stop.store(true, Ordering::SeqCst);
+            self.handle
+                .join()
+                .unwrap_or_else(|_| summarize(BTreeSet::new(), BTreeSet::new(), true))
+        }
+    }
+
+    /// Whether the access was rcpt moving the command into its cgroup,
+    /// which happens in the child before it runs the command
+    fn is_own(path: &str) -> bool {
+        path.starts_with("/sys/fs/cgroup/") && path.ends_with("/cgroup.procs")
+    }
+
+    /// Sorted, deduplicated read and written lists
+    fn summarize(
+        opened: BTreeSet<String>,
+        written: BTreeSet<String>,
+        events_lost: bool,
+    ) -> FileTrace {
+        let read: Vec<String> = opened.difference(&written).cloned().collect();
+        let omitted =
+            read.len().saturating_sub(MAX_PATHS) + written.len().saturating_sub(MAX_PATHS);
+        FileTrace {
+            mechanism: super::TraceMechanism::Fanotify,
+            read: read.into_iter().take(MAX_PATHS).collect(),
+            written: written.into_iter().take(MAX_PATHS).collect(),
+            omitted: omitted as u64,
+            events_lost,
+        }
+    }
+
+    struct Event {
+        mask: u64,
+        pid: u32,
+        path: Option<String>,
+    }
+
+    /// The next batch of events, or None once the queue is empty (after
+    /// waiting a moment for more if `wait` is set)
+    fn read_events(fd: &OwnedFd, wait: bool) -> Option<Vec<Event>> {
+        if wait {
+            let mut poll = libc::pollfd {
+                fd: fd.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            // SAFETY: poll only writes to the pollfd we own
+            unsafe {
+                libc::poll(&mut poll, 1, POLL_MS);
+            }
+        }
+        // Aligned for the metadata structs read out of it
+        let mut buffer = vec![0u64; 8192];
+        let size = mem::size_of_val(buffer.as_slice());
+        // SAFETY: the buffer is writable for `size` bytes
+        let len = unsafe { libc::read(fd.as_raw_fd(), buffer.as_mut_ptr().cast(), size) };
+        if len <= 0 {
+            return None;
+        }
+        let bytes = &buffer[..];
+        let base = bytes.as_ptr() as *const u8;
+        let mut events = Vec::new();
+        let mut offset = 0usize;
+        let header = mem::size_of::<libc::fanotify_event_metadata>();
+        while offset + header <= len as usize {
+            // SAFETY: the kernel wrote a whole metadata struct at `offset`,
+            // which stays 8-byte aligned since event lengths are
+            let metadata = unsafe { &*(base.add(offset) as *const libc::fanotify_event_metadata) };
+            if metadata.vers != libc::FANOTIFY_METADATA_VERSION || metadata.event_len == 0 {
+                break;
+            }
+            let path = (metadata.fd >= 0).then(|| {
+                let path = fs::read_link(format!("/proc/self/fd/{}", metadata.fd));
+                // SAFETY: each event's descriptor is ours to close
+                unsafe {
+                    libc::close(metadata.fd);
+                }
+                path.ok().map(|path| path.to_string_lossy().to_string())
+            });
+            events.push(Event {
+                mask: metadata.mask,
+                pid: metadata.pid as u32,
+                path: path.flatten(),
+            });
+            offset += metadata.event_len as usize;
+        }
+        Some(events)
+    }
+
+    /// Which pids belong to the command: the roots, anything in their
+    /// process groups, and descendants of either
+    struct Members {
+        roots: Vec<u32>,
+        known: HashMap<u32, bool>,
+    }
+
+    impl Members {
+        fn new(roots: &[u32]) -> Self {
+            let mut known: HashMap<u32, bool> = roots.iter().map(|&pid| (pid, true)).collect();
+            known.insert(std::process::id(), false);
+            Members {
+                roots: roots.to_vec(),
+                known,
+            }
+        }
+
+        fn contains(&mut self, pid: u32) -> bool {
+            if let Some(&member) = self.known.get(&pid) {
+                return member;
+            }
+            let member = match parent_and_group(pid) {
+                Some((_, pgid)) if self.roots.contains(&pgid) => true,
+                Some((ppid, _)) if ppid > 1 => self.contains(ppid),
+                _ => false,
+            };
+            self.known.insert(pid, member);
+            member
+        }
+    }
+
+    fn parent_and_group(pid: u32) -> Option<(u32, u32)> {
+        let fields = procfs::stat_fields(pid)?;
+        Some((fields.get(1)?.parse().ok()?, fields.get(2)?.parse().ok()?))
+    }
+
+    /// Every mount point, from /proc/self/mountinfo
+    fn mount_points() -> Vec<String> {
+        let mountinfo = fs::read_to_string("/proc/self/mountinfo").unwrap_or_default();
+        mountinfo
+            .lines()
+            .filter_map(|line| line.split(' ').nth(4))
+            .map(unescape)
+            .collect()
+    }
+
+    /// Undo the octal escapes (`\040` for a space) mountinfo uses
+    fn unescape(field: &str) -> String {
+        let mut bytes = Vec::new();
+        let mut rest = field.as_bytes();
+        while let Some((&byte, tail)) = rest.split_first() {
+            let octal = tail
+                .get(..3)
+                .and_then(|digits| u8::from_str_radix(std::str::from_utf8(digits).ok()?, 8).ok());
+            match octal {
+                Some(value) if byte == b'\\' => {
+                    bytes.push(value);
+                    rest = &tail[3..];
+                }
+                _ => {
+                    bytes.push(byte);
+                    rest = tail;
+                }
+            }
+        }
+        String::from_utf8_lossy(&bytes).to_string()
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use anyhow::{bail, Result};
+
+    use super::FileTrace;
+
+    pub enum Tracer {}
+
+    pub enum RunningTracer {}
+
+    impl Tracer {
+        pub fn open() -> Result<Self> {
+            bail!("--trace-files is only supported on Linux");
+        }
+
+        pub fn start(self, _roots: &[u32]) -> RunningTracer {
+            match self {}
+        }
+    }
+
+    impl RunningTracer {
+        pub fn finish(self) -> FileTrace {
+            match self {}
+        }
+    }
+}
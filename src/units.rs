@@ -0,0 +1,37 @@
+use anyhow::{bail, Context, Result};
+
+/// Parse a byte size such as `4096`, `512K`, `256MiB` or `2G`. Suffixes are
+/// binary multiples (K = 1024) and case-insensitive.
+pub fn parse_size(s: &str) -> Result<u64> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, suffix) = s.split_at(split);
+    let number: u64 = number.parse().context(format!("invalid size '{}'", s))?;
+
+    let shift = match suffix.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 0,
+        "k" | "kb" | "kib" => 10,
+        "m" | "mb" | "mib" => 20,
+        "g" | "gb" | "gib" => 30,
+        "t" | "tb" | "tib" => 40,
+        _ => bail!("invalid size suffix in '{}' (expected K, M, G or T)", s),
+    };
+    number
+        .checked_mul(1 << shift)
+        .context(format!("size '{}' is too large", s))
+}
+
+/// `bytes` in the largest binary unit it reaches, e.g. `1.5 MiB`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{} B", bytes),
+        _ => format!("{:.1} {}", size, UNITS[unit]),
+    }
+}
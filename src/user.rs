@@ -0,0 +1,178 @@
+use crate::receipt::UserRecord;
+
+#[cfg(unix)]
+mod imp {
+    use anyhow::{bail, Context, Result};
+    use std::ffi::{CStr, CString};
+    use std::io;
+    use std::os::unix::process::CommandExt;
+    use std::process::Command;
+
+    use crate::receipt::UserRecord;
+
+    /// Account the command runs as, resolved before fork
+    #[derive(Debug, Clone)]
+    pub struct RunAs {
+        pub name: String,
+        pub uid: libc::uid_t,
+        pub gid: libc::gid_t,
+        pub home: String,
+        groups: Vec<libc::gid_t>,
+    }
+
+    struct Passwd {
+        name: String,
+        uid: libc::uid_t,
+        gid: libc::gid_t,
+        home: String,
+    }
+
+    fn lookup(name: Option<&CStr>, uid: libc::uid_t) -> Option<Passwd> {
+        let mut buffer = vec![0 as libc::c_char; 16 * 1024];
+        // SAFETY: getpwnam_r/getpwuid_r fill `entry` with pointers into
+        // `buffer`, which outlives every read below
+        unsafe {
+            let mut entry: libc::passwd = std::mem::zeroed();
+            let mut result = std::ptr::null_mut();
+            let rc = match name {
+                Some(name) => libc::getpwnam_r(
+                    name.as_ptr(),
+                    &mut entry,
+                    buffer.as_mut_ptr(),
+                    buffer.len(),
+                    &mut result,
+                ),
+                None => libc::getpwuid_r(
+                    uid,
+                    &mut entry,
+                    buffer.as_mut_ptr(),
+                    buffer.len(),
+                    &mut result,
+                ),
+            };
+            if rc != 0 || result.is_null() {
+                return None;
+            }
+            Some(Passwd {
+                name: CStr::from_ptr(entry.pw_name).to_string_lossy().to_string(),
+                uid: entry.pw_uid,
+                gid: entry.pw_gid,
+                home: CStr::from_ptr(entry.pw_dir).to_string_lossy().to_string(),
+            })
+        }
+    }
+
+    impl RunAs {
+        pub fn resolve(name: &str) -> Result<Self> {
+            // SAFETY: geteuid has no preconditions
+            if unsafe { libc::geteuid() } != 0 {
+                bail!("--user requires rcpt to run as root");
+            }
+            let c_name = CString::new(name).context("Invalid user name")?;
+            let Some(passwd) = lookup(Some(&c_name), 0) else {
+                bail!("Unknown user: {}", name);
+            };
+
+            // Resolve supplementary groups now: reading the group database
+            // is not safe between fork and exec
+            let mut groups: Vec<libc::gid_t> = vec![0; 64];
+            loop {
+                let mut count = groups.len() as libc::c_int;
+                // SAFETY: getgrouplist writes at most `count` entries
+                let rc = unsafe {
+                    libc::getgrouplist(
+                        c_name.as_ptr(),
+                        passwd.gid as _,
+                        groups.as_mut_ptr() as *mut _,
+                        &mut count,
+                    )
+                };
+                if rc >= 0 {
+                    groups.truncate(count as usize);
+                    break;
+                }
+                groups.resize(groups.len() * 2, 0);
+            }
+
+            Ok(RunAs {
+                name: passwd.name,
+                uid: passwd.uid,
+                gid: passwd.gid,
+                home: passwd.home,
+                groups,
+            })
+        }
+
+        pub fn apply(&self, command: &mut Command) {
+            let (uid, gid, groups) = (self.uid, self.gid, self.groups.clone());
+            // SAFETY: setgroups/setgid/setuid are async-signal-safe and the
+            // closure only reads values resolved before fork
+            unsafe {
+                command.pre_exec(move || {
+                    if libc::setgroups(groups.len() as _, groups.as_ptr()) != 0
+                        || libc::setgid(gid) != 0
+                        || libc::setuid(uid) != 0
+                    {
+                        return Err(io::Error::last_os_error());
+                    }
+                    Ok(())
+                });
+            }
+        }
+
+        pub fn record(&self) -> UserRecord {
+            UserRecord {
+                name: Some(self.name.clone()),
+                uid: self.uid,
+                gid: self.gid,
+            }
+        }
+    }
+
+    pub fn current() -> Option<UserRecord> {
+        // SAFETY: geteuid/getegid have no preconditions
+        let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+        Some(UserRecord {
+            name: lookup(None, uid).map(|passwd| passwd.name),
+            uid,
+            gid,
+        })
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use anyhow::{bail, Result};
+    use std::process::Command;
+
+    use crate::receipt::UserRecord;
+
+    #[derive(Debug, Clone)]
+    pub struct RunAs {
+        pub name: String,
+        pub home: String,
+    }
+
+    impl RunAs {
+        pub fn resolve(_name: &str) -> Result<Self> {
+            bail!("--user is only supported on Unix");
+        }
+
+        pub fn apply(&self, _command: &mut Command) {}
+
+        pub fn record(&self) -> UserRecord {
+            unreachable!("RunAs cannot be resolved on this platform")
+        }
+    }
+
+    pub fn current() -> Option<UserRecord> {
+        None
+    }
+}
+
+pub use imp::RunAs;
+
+/// The effective user rcpt itself runs as (Unix only)
+pub fn current() -> Option<UserRecord> {
+    imp::current()
+}
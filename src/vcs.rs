@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::digest::sha256_hex;
+
+/// Version control system holding the working directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum System {
+    Git,
+}
+
+/// State of the source tree the command ran in, as it was when it started
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VcsRecord {
+    /// Version control system of the working tree
+    pub system: System,
+    /// Top-level directory of the working tree
+    pub root: String,
+    /// Commit checked out (absent before the first commit)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    /// Branch checked out (absent when HEAD is detached)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// Whether there were uncommitted changes, including untracked files
+    pub dirty: bool,
+    /// SHA-256 digest of `git diff HEAD`, the uncommitted changes to tracked
+    /// files, when there were any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff_sha256: Option<String>,
+}
+
+/// The git state of `cwd`, or None if it is not in a git working tree or
+/// git is not installed
+pub fn capture(cwd: &Path) -> Option<VcsRecord> {
+    let root = git(cwd, &["rev-parse", "--show-toplevel"])?;
+    let root = String::from_utf8_lossy(&root).trim().to_string();
+    let text = |args: &[&str]| {
+        git(cwd, args)
+            .map(|output| String::from_utf8_lossy(&output).trim().to_string())
+            .filter(|value| !value.is_empty())
+    };
+    let commit = text(&["rev-parse", "--verify", "--quiet", "HEAD"]);
+    let branch = text(&["symbolic-ref", "--quiet", "--short", "HEAD"]);
+    let dirty = git(cwd, &["status", "--porcelain", "--untracked-files=normal"])
+        .is_some_and(|status| !status.is_empty());
+    let diff_sha256 = match commit {
+        Some(_) if dirty => git(
+            cwd,
+            &["diff", "HEAD", "--no-ext-diff", "--no-color", "--binary"],
+        )
+        .filter(|diff| !diff.is_empty())
+        .map(|diff| sha256_hex(&diff)),
+        _ => None,
+    };
+    Some(VcsRecord {
+        system: System::Git,
+        root,
+        commit,
+        branch,
+        dirty,
+        diff_sha256,
+    })
+}
+
+/// Stdout of a successful git command run in `cwd`
+fn git(cwd: &Path, args: &[&str]) -> Option<Vec<u8>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(cwd)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    output.status.success().then_some(output.stdout)
+}
@@ -0,0 +1,159 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::env::glob_match;
+use crate::exec::{execute_with_retries, save_new_receipt, ExecOptions, Invocation, RetryPolicy};
+use crate::receipt::{Trigger, TriggerReason};
+use crate::signals;
+use crate::template::{self, Names};
+
+/// How often rcpt checks for a signal while waiting for changes
+const SIGNAL_CHECK: Duration = Duration::from_millis(50);
+
+/// Names that are never watched
+const ALWAYS_IGNORED: &[&str] = &[".git"];
+
+/// What rcpt watches and where it records each run
+pub struct Watcher {
+    /// Files and directories (watched recursively) to check for changes
+    pub paths: Vec<PathBuf>,
+    /// Glob patterns of file and directory names to skip
+    pub ignore: Vec<String>,
+    /// How often to check for changes, and how long changes must settle
+    pub interval: Duration,
+    /// Directory receiving the receipt of each run, named by its id
+    pub out_dir: PathBuf,
+}
+
+/// Modification time and size of every watched file
+type Snapshot = BTreeMap<PathBuf, (Option<SystemTime>, u64)>;
+
+impl Watcher {
+    /// Run the command now and again after every change, until rcpt is
+    /// interrupted
+    pub fn run(
+        &self,
+        invocation: &Invocation,
+        opts: &ExecOptions,
+        policy: &RetryPolicy,
+        report: &mut impl Write,
+    ) -> Result<()> {
+        let mut trigger = Trigger::new(TriggerReason::Start);
+        for run in 1.. {
+            // Taken before running, so changes made meanwhile trigger the next run
+            let snapshot = self.scan()?;
+            let mut receipt = execute_with_retries(invocation, opts, policy)?;
+            receipt.trigger = Some(trigger);
+            let path = self
+                .out_dir
+                .join(template::render(template::DEFAULT, &Names::of(&receipt))?);
+            save_new_receipt(opts, &mut receipt, &path)?;
+            writeln!(
+                report,
+                "[run {}] {}; receipt written to: {}",
+                run,
+                receipt.status.as_str(),
+                opts.saved_to(&path).display()
+            )?;
+
+            match self.wait_for_change(snapshot)? {
+                Some(paths) => {
+                    trigger = Trigger {
+                        paths,
+                        ..Trigger::new(TriggerReason::Change)
+                    }
+                }
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Block until a watched file changes, returning the changed paths once
+    /// changes have settled, or None if rcpt was interrupted
+    fn wait_for_change(&self, before: Snapshot) -> Result<Option<Vec<String>>> {
+        let mut changed = Vec::new();
+        let mut latest = before.clone();
+        loop {
+            let mut waited = Duration::ZERO;
+            while waited < self.interval {
+                if signals::received() {
+                    return Ok(None);
+                }
+                thread::sleep(SIGNAL_CHECK.min(self.interval - waited));
+                waited += SIGNAL_CHECK;
+            }
+            let current = self.scan()?;
+            let settled = !changed.is_empty() && current == latest;
+            if settled {
+                return Ok(Some(changed));
+            }
+            changed = diff(&before, &current);
+            latest = current;
+        }
+    }
+
+    fn scan(&self) -> Result<Snapshot> {
+        let mut snapshot = Snapshot::new();
+        for path in &self.paths {
+            let meta = fs::symlink_metadata(path)
+                .context(format!("Failed to watch {}", path.display()))?;
+            self.scan_entry(path, &meta, &mut snapshot);
+        }
+        Ok(snapshot)
+    }
+
+    fn scan_entry(&self, path: &Path, meta: &fs::Metadata, snapshot: &mut Snapshot) {
+        if !meta.is_dir() {
+            snapshot.insert(path.to_path_buf(), (meta.modified().ok(), meta.len()));
+            return;
+        }
+        if self.is_out_dir(path) {
+            return;
+        }
+        let Ok(entries) = fs::read_dir(path) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if ALWAYS_IGNORED.contains(&name.as_ref())
+                || self.ignore.iter().any(|pattern| glob_match(pattern, &name))
+            {
+                continue;
+            }
+            // Files may disappear while scanning; the next scan sees that
+            if let Ok(meta) = entry.metadata() {
+                self.scan_entry(&entry.path(), &meta, snapshot);
+            }
+        }
+    }
+
+    /// Receipts are written into the out dir, which must not trigger runs
+    fn is_out_dir(&self, path: &Path) -> bool {
+        match (fs::canonicalize(path), fs::canonicalize(&self.out_dir)) {
+            (Ok(path), Ok(out_dir)) => path == out_dir,
+            _ => false,
+        }
+    }
+}
+
+/// Paths added, removed or modified between two snapshots
+fn diff(before: &Snapshot, after: &Snapshot) -> Vec<String> {
+    let mut paths: Vec<&PathBuf> = before
+        .iter()
+        .filter(|(path, state)| after.get(*path) != Some(state))
+        .map(|(path, _)| path)
+        .collect();
+    paths.extend(after.keys().filter(|path| !before.contains_key(*path)));
+    paths.sort();
+    paths
+        .into_iter()
+        .map(|path| path.to_string_lossy().to_string())
+        .collect()
+}
@@ -0,0 +1,64 @@
+use anyhow::{bail, Context, Result};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::age;
+use crate::config::WebhookConfig;
+use crate::sink::{self, Delivery};
+
+/// An HTTP endpoint that receipts are POSTed to through curl
+#[derive(Debug, Clone)]
+pub struct Webhook {
+    url: String,
+    /// `Name: value` headers sent with each receipt
+    headers: Vec<String>,
+    curl: PathBuf,
+}
+
+impl Webhook {
+    /// The endpoint at `url`, sent the headers in `config` and then
+    /// `headers`, each `Name: value`
+    pub fn new(url: &str, headers: &[String], config: &WebhookConfig) -> Result<Self> {
+        let headers: Vec<String> = config
+            .headers
+            .iter()
+            .map(|(name, value)| format!("{}: {}", name, value))
+            .chain(headers.iter().cloned())
+            .collect();
+        if let Some(header) = headers.iter().find(|header| !header.contains(':')) {
+            bail!("--sink-header must be `Name: value`, not {}", header);
+        }
+        Ok(Webhook {
+            url: url.to_string(),
+            headers,
+            curl: sink::find_curl("send receipts to a webhook")?,
+        })
+    }
+
+    /// POST the receipt at `file`, failing unless the endpoint answers with
+    /// a 2xx. Headers reach curl through its stdin, not its command line
+    pub fn post(&self, file: &Path, delivery: Delivery) -> Result<()> {
+        let contents = fs::read(file).context(format!("Failed to read {}", file.display()))?;
+        let content_type = match age::is_encrypted(&contents) {
+            true => "application/octet-stream",
+            false => "application/json",
+        };
+        let mut config = format!(
+            "header = {}\n",
+            sink::quote(&format!("Content-Type: {}", content_type))
+        );
+        for header in &self.headers {
+            config.push_str(&format!("header = {}\n", sink::quote(header)));
+        }
+        let mut data = OsString::from("@");
+        data.push(file);
+        sink::run_curl(
+            &self.curl,
+            &["--data-binary".into(), data, self.url.clone().into()],
+            &config,
+            delivery,
+        )
+        .context(format!("failed to post {} to {}", file.display(), self.url))
+    }
+}
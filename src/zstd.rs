@@ -0,0 +1,57 @@
+use anyhow::{bail, Context, Result};
+use std::ffi::OsStr;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+
+use crate::exec::resolve_executable;
+
+/// Find zstd on PATH
+pub fn program() -> Result<PathBuf> {
+    let cwd = std::env::current_dir().context("Failed to get current directory")?;
+    resolve_executable(OsStr::new("zstd"), std::env::var_os("PATH").as_ref(), &cwd)
+        .context("zstd not found; install it to compress or read compressed output")
+}
+
+/// Compress `bytes` into a zstd frame
+pub fn compress(program: &Path, bytes: &[u8]) -> Result<Vec<u8>> {
+    filter(program, &["--quiet", "--stdout", "-19"], bytes).context("zstd failed to compress")
+}
+
+/// Decompress a zstd frame
+pub fn decompress(bytes: &[u8]) -> Result<Vec<u8>> {
+    filter(&program()?, &["--quiet", "--stdout", "--decompress"], bytes)
+        .context("zstd failed to decompress")
+}
+
+/// Run zstd as a filter from `input` to what it writes
+fn filter(program: &Path, args: &[&str], input: &[u8]) -> Result<Vec<u8>> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context(format!("Failed to run {}", program.display()))?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    // Read while writing, so neither side blocks on a full pipe
+    let reader = thread::spawn(move || {
+        let mut output = Vec::new();
+        stdout.read_to_end(&mut output).map(|_| output)
+    });
+    let written = stdin.write_all(input);
+    drop(stdin);
+    let output = reader.join().expect("reader thread panicked")?;
+    let mut stderr = String::new();
+    if let Some(mut pipe) = child.stderr.take() {
+        let _ = pipe.read_to_string(&mut stderr);
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        bail!("{}", stderr.trim());
+    }
+    written?;
+    Ok(output)
+}
@@ -0,0 +1,83 @@
+#![cfg(unix)]
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn rcpt(dir: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_rcpt"));
+    command.current_dir(dir);
+    command
+}
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rcpt-test-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A PATH whose first directory, under `dir`, holds the shell `scripts`,
+/// as `(program, script)`
+fn fake_path(dir: &Path, scripts: &[(&str, &str)]) -> String {
+    let bin = dir.join("bin");
+    fs::create_dir_all(&bin).unwrap();
+    for (program, script) in scripts {
+        let path = bin.join(program);
+        fs::write(&path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    format!(
+        "{}:{}",
+        bin.display(),
+        std::env::var("PATH").unwrap_or_default()
+    )
+}
+
+fn read_json(path: &Path) -> serde_json::Value {
+    serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+}
+
+#[test]
+fn saves_the_receipt_when_the_timestamp_authority_fails() {
+    let dir = scratch("tsa-down");
+    let path = fake_path(
+        &dir,
+        &[
+            ("openssl", "exit 0"),
+            ("curl", "echo 'curl: (7) Failed to connect' >&2; exit 7"),
+        ],
+    );
+    let ran = rcpt(&dir)
+        .env("PATH", path)
+        .args(["run", "--timestamp-url", "http://127.0.0.1:9/tsa"])
+        .args(["-o", "r.json", "--", "true"])
+        .output()
+        .unwrap();
+    assert!(!ran.status.success());
+    let stderr = String::from_utf8_lossy(&ran.stderr);
+    assert!(stderr.contains("without a timestamp"), "{}", stderr);
+    let receipt = read_json(&dir.join("r.json"));
+    assert_eq!(receipt["status"], "ok");
+    assert!(receipt.get("timestamp").is_none());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn saves_the_receipt_when_cosign_fails() {
+    let dir = scratch("cosign-fails");
+    let path = fake_path(&dir, &[("cosign", "echo 'no OIDC token' >&2; exit 1")]);
+    let ran = rcpt(&dir)
+        .env("PATH", path)
+        .args(["run", "--sign-keyless", "-o", "r.json", "--", "true"])
+        .output()
+        .unwrap();
+    assert!(!ran.status.success());
+    let stderr = String::from_utf8_lossy(&ran.stderr);
+    assert!(stderr.contains("unsigned"), "{}", stderr);
+    let receipt = read_json(&dir.join("r.json"));
+    assert_eq!(receipt["status"], "ok");
+    assert!(receipt.get("signature").is_none());
+    fs::remove_dir_all(&dir).unwrap();
+}
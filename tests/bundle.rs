@@ -0,0 +1,69 @@
+#![cfg(unix)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn rcpt(dir: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_rcpt"));
+    command.current_dir(dir);
+    command
+}
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rcpt-test-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn read_json(path: &Path) -> serde_json::Value {
+    serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+}
+
+#[test]
+fn receipts_unpacked_from_a_bundle_find_their_objects() {
+    let dir = scratch("bundle-objects");
+    let made = dir.join("made");
+    fs::create_dir_all(&made).unwrap();
+    let ran = rcpt(&made)
+        .args([
+            "run",
+            "--objects-above",
+            "16",
+            "-o",
+            "r.json",
+            "--",
+            "seq",
+            "1000",
+        ])
+        .output()
+        .unwrap();
+    assert!(ran.status.success(), "{:?}", ran);
+    let receipt = read_json(&made.join("r.json"));
+    assert_eq!(receipt["stdout_encoding"], "object");
+    let id = receipt["id"].as_str().unwrap();
+    let bundled = rcpt(&made)
+        .args(["bundle", "--out", "../evidence.tar"])
+        .output()
+        .unwrap();
+    assert!(bundled.status.success(), "{:?}", bundled);
+    // The store the receipt names is gone, as on an auditor's machine
+    fs::remove_dir_all(&made).unwrap();
+
+    let unpacked = dir.join("unpacked");
+    fs::create_dir_all(&unpacked).unwrap();
+    let extracted = Command::new("tar")
+        .args(["-xf", "../evidence.tar"])
+        .current_dir(&unpacked)
+        .status()
+        .unwrap();
+    assert!(extracted.success());
+    let path = format!("receipts/{}/r.json", id);
+    let verified = rcpt(&unpacked).args(["verify", &path]).output().unwrap();
+    assert!(verified.status.success(), "{:?}", verified);
+    let shown = rcpt(&unpacked).args(["show", &path]).output().unwrap();
+    assert!(shown.status.success(), "{:?}", shown);
+    assert!(String::from_utf8_lossy(&shown.stdout).contains("999\n1000\n"));
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,74 @@
+#![cfg(unix)]
+
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+
+fn rcpt(dir: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_rcpt"));
+    command.current_dir(dir);
+    command
+}
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rcpt-test-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// The objects in `store`, aged past the hour gc keeps new ones for
+fn aged_objects(store: &Path) -> Vec<PathBuf> {
+    let objects: Vec<PathBuf> = fs::read_dir(store)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| !path.file_name().unwrap().to_string_lossy().starts_with('.'))
+        .collect();
+    for object in &objects {
+        File::options()
+            .write(true)
+            .open(object)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(3 * 60 * 60))
+            .unwrap();
+    }
+    objects
+}
+
+#[test]
+fn keeps_objects_referred_to_by_a_ledger_entry() {
+    let dir = scratch("gc-ledger");
+    let ran = rcpt(&dir)
+        .args([
+            "run",
+            "--ledger",
+            "receipts.jsonl",
+            "--ledger-only",
+            "--objects-above",
+            "1",
+            "--",
+            "echo",
+            "hello",
+        ])
+        .output()
+        .unwrap();
+    assert!(ran.status.success(), "{:?}", ran);
+    let objects = aged_objects(&dir.join(".receipts/objects"));
+    assert_eq!(objects.len(), 1);
+
+    let collected = rcpt(&dir)
+        .args(["gc", "--keep-last", "1", "--ledger", "receipts.jsonl"])
+        .output()
+        .unwrap();
+    assert!(collected.status.success(), "{:?}", collected);
+    assert!(objects[0].exists());
+
+    // Without the ledger nothing refers to it
+    let collected = rcpt(&dir)
+        .args(["gc", "--keep-last", "1"])
+        .output()
+        .unwrap();
+    assert!(collected.status.success(), "{:?}", collected);
+    assert!(!objects[0].exists());
+}
@@ -0,0 +1,113 @@
+#![cfg(unix)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn rcpt(dir: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_rcpt"));
+    command.current_dir(dir);
+    command
+}
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rcpt-test-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn has_sqlite() -> bool {
+    Command::new("sqlite3").arg("--version").output().is_ok()
+}
+
+/// The ids `rcpt list --json` prints with `args`
+fn listed(dir: &Path, args: &[&str]) -> Vec<String> {
+    let output = rcpt(dir)
+        .args(["list", "--json"])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let entries: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    entries
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| entry["id"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn filters_in_the_index_as_in_the_receipts() {
+    if !has_sqlite() {
+        eprintln!("skipping: sqlite3 not found");
+        return;
+    }
+    let dir = scratch("index-filters");
+    for (out, session, args) in [
+        ("r/one.json", "s1", &["--tag", "a", "--", "echo", "one"][..]),
+        (
+            "r/two.json",
+            "s2",
+            &["--tag", "b", "--", "sh", "-c", "exit 3"],
+        ),
+        (
+            "r/three.json",
+            "s1",
+            &["--tag", "a", "--tag", "b", "--", "true"],
+        ),
+    ] {
+        let ran = rcpt(&dir)
+            .env("RCPT_SESSION", session)
+            .args(["run", "--out", out])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(ran.status.code().is_some(), "{:?}", ran);
+    }
+    let indexed = rcpt(&dir).args(["index", "--dir", "r"]).output().unwrap();
+    assert!(indexed.status.success(), "{:?}", indexed);
+
+    for filter in [
+        &[][..],
+        &["--status", "fail"],
+        &["--status", "ok"],
+        &["--status", "ok", "--status", "failed"],
+        &["--tag", "a"],
+        &["--tag", "a", "--tag", "b"],
+        &["--cmd", "exit 3"],
+        &["--cmd", "on"],
+        &["--session", "s1"],
+        &["--session", "s1", "--status", "ok", "--tag", "b"],
+    ] {
+        let by_dir = listed(&dir, &[&["--dir", "r"][..], filter].concat());
+        let by_index = listed(&dir, &[&["--index"][..], filter].concat());
+        assert_eq!(by_dir, by_index, "{:?}", filter);
+    }
+    assert_eq!(listed(&dir, &["--index", "--session", "s1"]).len(), 2);
+}
+
+#[test]
+fn lists_from_the_index_without_reading_the_receipts() {
+    if !has_sqlite() {
+        eprintln!("skipping: sqlite3 not found");
+        return;
+    }
+    let dir = scratch("index-rows");
+    let ran = rcpt(&dir)
+        .args(["run", "--index", "--out", "r/one.json", "--", "echo", "one"])
+        .output()
+        .unwrap();
+    assert!(ran.status.success(), "{:?}", ran);
+    fs::write(dir.join("r/one.json"), "not a receipt").unwrap();
+
+    assert_eq!(listed(&dir, &["--index"]).len(), 1);
+    let stats = rcpt(&dir)
+        .args(["stats", "--index", "--json"])
+        .output()
+        .unwrap();
+    assert!(stats.status.success(), "{:?}", stats);
+    let groups: serde_json::Value = serde_json::from_slice(&stats.stdout).unwrap();
+    assert_eq!(groups[0]["runs"], 1);
+}
@@ -0,0 +1,77 @@
+#![cfg(unix)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn rcpt(dir: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_rcpt"));
+    command.current_dir(dir);
+    command
+}
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rcpt-test-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn read_json(path: &Path) -> serde_json::Value {
+    serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+}
+
+#[test]
+fn records_sidecars_relative_to_the_receipt() {
+    let dir = scratch("sidecar-path");
+    let ran = rcpt(&dir)
+        .args([
+            "run",
+            "--split-output",
+            "--out",
+            "receipts/job.json",
+            "--",
+            "echo",
+            "hi",
+        ])
+        .output()
+        .unwrap();
+    assert!(ran.status.success(), "{:?}", ran);
+    let receipt = read_json(&dir.join("receipts/job.json"));
+    assert_eq!(receipt["sidecars"][0]["path"], "job.json.stdout.log");
+    assert_eq!(receipt["sidecars"][1]["path"], "job.json.stderr.log");
+}
+
+#[test]
+fn resolves_sidecars_beside_the_receipt_from_another_directory() {
+    let dir = scratch("sidecar-elsewhere");
+    let ran = rcpt(&dir)
+        .args([
+            "run",
+            "--split-output",
+            "--out",
+            "receipts/job.json",
+            "--",
+            "echo",
+            "hi",
+        ])
+        .output()
+        .unwrap();
+    assert!(ran.status.success(), "{:?}", ran);
+
+    // A file at the same relative path under another directory is not it
+    let other = dir.join("other");
+    fs::create_dir_all(other.join("receipts")).unwrap();
+    fs::write(other.join("receipts/job.json.stdout.log"), "decoy\n").unwrap();
+    let verified = rcpt(&other)
+        .args(["verify", "../receipts/job.json"])
+        .output()
+        .unwrap();
+    assert!(verified.status.success(), "{:?}", verified);
+    let stdout = String::from_utf8_lossy(&verified.stdout);
+    assert!(
+        stdout.contains("sidecar job.json.stdout.log: ok (3 bytes)"),
+        "{}",
+        stdout
+    );
+}
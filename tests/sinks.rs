@@ -0,0 +1,113 @@
+#![cfg(unix)]
+
+use std::fs;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+fn rcpt(dir: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_rcpt"));
+    command.current_dir(dir);
+    command
+}
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rcpt-test-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn has_curl() -> bool {
+    Command::new("curl").arg("--version").output().is_ok()
+}
+
+/// Every file under `dir`, searched recursively
+fn files(dir: &Path) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+        match entry.path().is_dir() {
+            true => found.extend(files(&entry.path())),
+            false => found.push(entry.path()),
+        }
+    }
+    found
+}
+
+#[test]
+fn spools_the_receipt_when_a_sink_stalls() {
+    if !has_curl() {
+        return;
+    }
+    let dir = scratch("sink-stalls");
+    // Accepts connections and never answers
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/receipts", listener.local_addr().unwrap());
+    let started = Instant::now();
+    let ran = rcpt(&dir)
+        .args([
+            "run",
+            "--sink",
+            &url,
+            "--sink-timeout",
+            "1s",
+            "--sink-retries",
+            "0",
+        ])
+        .args(["-o", "r.json", "--", "true"])
+        .output()
+        .unwrap();
+    assert!(ran.status.success(), "{:?}", ran);
+    assert!(started.elapsed() < Duration::from_secs(20));
+    let spooled = files(&dir.join(".receipts/spool"));
+    assert_eq!(spooled.len(), 1, "{:?}", spooled);
+    assert_eq!(
+        fs::read(&spooled[0]).unwrap(),
+        fs::read(dir.join("r.json")).unwrap()
+    );
+    drop(listener);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// The ids `rcpt list --json` prints
+fn listed(dir: &Path) -> Vec<String> {
+    let output = rcpt(dir).args(["list", "--json"]).output().unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let entries: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    entries
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| entry["id"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn spooled_copies_are_not_listed_as_receipts() {
+    if !has_curl() {
+        return;
+    }
+    let dir = scratch("spool-listed");
+    // A port nothing listens on
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let url = format!("http://127.0.0.1:{}/receipts", port);
+    for out in ["a.json", "b.json"] {
+        let ran = rcpt(&dir)
+            .args(["run", "--sink", &url, "--sink-retries", "0"])
+            .args(["-o", out, "--", "true"])
+            .output()
+            .unwrap();
+        assert!(ran.status.success(), "{:?}", ran);
+    }
+    assert_eq!(files(&dir.join(".receipts/spool")).len(), 2);
+    let ids = listed(&dir);
+    assert_eq!(ids.len(), 2, "{:?}", ids);
+    let shown = rcpt(&dir).args(["show", &ids[0]]).output().unwrap();
+    assert!(shown.status.success(), "{:?}", shown);
+    fs::remove_dir_all(&dir).unwrap();
+}
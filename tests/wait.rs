@@ -0,0 +1,62 @@
+#![cfg(unix)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn rcpt(dir: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_rcpt"));
+    command.current_dir(dir);
+    command
+}
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rcpt-test-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn read_json(path: &Path) -> serde_json::Value {
+    serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+}
+
+#[test]
+fn waits_for_a_detached_run_by_id() {
+    let dir = scratch("wait-by-id");
+    let started = rcpt(&dir)
+        .args([
+            "run",
+            "--detach",
+            "--out",
+            "receipts/job.json",
+            "--",
+            "sleep",
+            "1",
+        ])
+        .output()
+        .unwrap();
+    assert!(started.status.success(), "{:?}", started);
+    let receipt = dir.join("receipts/job.json");
+    let id = read_json(&receipt)["id"].as_str().unwrap().to_string();
+
+    let waited = rcpt(&dir)
+        .args(["wait", &id[..16], "--dir", "receipts"])
+        .output()
+        .unwrap();
+    assert!(waited.status.success(), "{:?}", waited);
+    let stdout = String::from_utf8_lossy(&waited.stdout);
+    assert!(stdout.contains("receipts/job.json"), "{}", stdout);
+    assert_eq!(read_json(&receipt)["status"], "ok");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn fails_to_wait_for_an_unknown_id() {
+    let dir = scratch("wait-unknown");
+    let waited = rcpt(&dir).args(["wait", "01ZZZZ"]).output().unwrap();
+    assert!(!waited.status.success());
+    let stderr = String::from_utf8_lossy(&waited.stderr);
+    assert!(stderr.contains("No receipt 01ZZZZ found"), "{}", stderr);
+    fs::remove_dir_all(&dir).unwrap();
+}